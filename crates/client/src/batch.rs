@@ -0,0 +1,540 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use ferris_swarm_core::VideoEncodeError;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tracing::{error, info, warn};
+
+use crate::{cli::Cli, job::Job};
+
+/// Extensions of the files a directory, glob or inbox contributes as inputs
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mkv", "mp4", "m4v", "mov", "webm", "avi", "ts", "m2ts", "mts", "mxf", "mpg", "mpeg", "wmv",
+    "flv", "y4m",
+];
+
+/// Output path of a batch job from its input. `{stem}`, `{name}`, `{ext}` and
+/// `{parent}` stand for the input's file stem, file name, extension and
+/// directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate(String);
+
+impl OutputTemplate {
+    pub fn new(template: &str) -> Result<Self, VideoEncodeError> {
+        // Without the input's name, every job would write the same file
+        if !template.contains("{stem}") && !template.contains("{name}") {
+            return Err(VideoEncodeError::Config(format!(
+                "Output template '{}' must contain {{stem}} or {{name}}",
+                template
+            )));
+        }
+        Ok(Self(template.to_string()))
+    }
+
+    pub fn output_for(&self, input: &Path) -> PathBuf {
+        let part = |part: Option<&std::ffi::OsStr>| {
+            part.map(|part| part.to_string_lossy().into_owned()).unwrap_or_default()
+        };
+        let parent = input
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        PathBuf::from(
+            self.0
+                .replace("{stem}", &part(input.file_stem()))
+                .replace("{name}", &part(input.file_name()))
+                .replace("{ext}", &part(input.extension()))
+                .replace("{parent}", &parent.to_string_lossy()),
+        )
+    }
+}
+
+/// Where the jobs of a batch come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobSource {
+    /// The video files of a directory
+    Directory(PathBuf),
+    /// The files of `dir` whose name matches `pattern`
+    Glob { dir: PathBuf, pattern: String },
+    /// A job list file: one input per line, optionally followed by
+    /// `=> OUTPUT`
+    List(PathBuf),
+    /// New video files of an inbox directory, picked up until interrupted
+    Watch(PathBuf),
+}
+
+impl JobSource {
+    /// Job source of `--batch` or `--watch`, `None` for a single job
+    pub fn from_cli(cli: &Cli) -> Result<Option<Self>, VideoEncodeError> {
+        if let Some(inbox) = &cli.watch {
+            if !inbox.is_dir() {
+                return Err(VideoEncodeError::Config(format!(
+                    "Watch inbox {:?} is not a directory",
+                    inbox
+                )));
+            }
+            return Ok(Some(JobSource::Watch(inbox.clone())));
+        }
+        let Some(batch) = &cli.batch else {
+            return Ok(None);
+        };
+        Self::parse_batch(batch).map(Some)
+    }
+
+    /// A directory, a glob in the last path component, or a job list file
+    pub fn parse_batch(batch: &str) -> Result<Self, VideoEncodeError> {
+        let path = Path::new(batch);
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned());
+        if let Some(pattern) = name.filter(|name| name.contains(['*', '?'])) {
+            let dir = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            if dir.to_string_lossy().contains(['*', '?']) {
+                return Err(VideoEncodeError::Config(format!(
+                    "Only the file name of '{}' may contain wildcards",
+                    batch
+                )));
+            }
+            return Ok(JobSource::Glob {
+                dir: dir.to_path_buf(),
+                pattern,
+            });
+        }
+        if path.is_dir() {
+            Ok(JobSource::Directory(path.to_path_buf()))
+        } else if path.is_file() {
+            Ok(JobSource::List(path.to_path_buf()))
+        } else {
+            Err(VideoEncodeError::Config(format!(
+                "Batch '{}' is neither a directory, a glob nor a job list file",
+                batch
+            )))
+        }
+    }
+
+    /// The jobs of a batch, in name order for directories and globs
+    pub fn jobs(&self, template: Option<&OutputTemplate>) -> Result<Vec<Job>, VideoEncodeError> {
+        let inputs = match self {
+            JobSource::Directory(dir) => video_files(dir)?,
+            JobSource::Glob {
+                dir,
+                pattern,
+            } => video_files(dir)?
+                .into_iter()
+                .filter(|path| {
+                    path.file_name()
+                        .is_some_and(|name| glob_match(pattern, &name.to_string_lossy()))
+                })
+                .collect(),
+            JobSource::List(list) => {
+                let contents = fs::read_to_string(list)?;
+                let base_dir = list.parent().unwrap_or(Path::new("."));
+                return parse_job_list(&contents, base_dir, template);
+            },
+            JobSource::Watch(_) => Vec::new(),
+        };
+        let template = template.ok_or_else(|| {
+            VideoEncodeError::Config("A batch needs an --output-template".to_string())
+        })?;
+        let jobs: Vec<Job> = inputs
+            .into_iter()
+            .map(|input_file| Job {
+                output_file: template.output_for(&input_file),
+                input_file,
+            })
+            .collect();
+        check_distinct_outputs(&jobs)?;
+        Ok(jobs)
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters
+/// and `?` any one character
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and the name position it currently covers up to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                // Let the last `*` cover one more character
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            VIDEO_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+/// Video files directly in `dir`, hidden ones left out, by name
+fn video_files(dir: &Path) -> Result<Vec<PathBuf>, VideoEncodeError> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_video_file(path))
+        .filter(|path| {
+            !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads a job list: one input per line, optionally followed by `=> OUTPUT`.
+/// Blank lines and lines starting with `#` are skipped; relative paths on a
+/// line are relative to `base_dir`. Inputs without an output need
+/// `template`, which is applied to the resolved input as in any other batch:
+/// a relative template output is relative to the working directory.
+pub fn parse_job_list(
+    contents: &str,
+    base_dir: &Path,
+    template: Option<&OutputTemplate>,
+) -> Result<Vec<Job>, VideoEncodeError> {
+    let mut jobs = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (input, output) = match line.split_once("=>") {
+            Some((input, output)) => (input.trim(), Some(output.trim())),
+            None => (line, None),
+        };
+        if input.is_empty() || output.is_some_and(str::is_empty) {
+            return Err(VideoEncodeError::Config(format!(
+                "Line {} of the job list is not 'INPUT' or 'INPUT => OUTPUT': '{}'",
+                number + 1,
+                line
+            )));
+        }
+        let input_file = base_dir.join(input);
+        let output_file = match (output, template) {
+            (Some(output), _) => base_dir.join(output),
+            (None, Some(template)) => template.output_for(&input_file),
+            (None, None) => {
+                return Err(VideoEncodeError::Config(format!(
+                    "Line {} of the job list has no output and there is no --output-template",
+                    number + 1
+                )))
+            },
+        };
+        jobs.push(Job {
+            input_file,
+            output_file,
+        });
+    }
+    check_distinct_outputs(&jobs)?;
+    Ok(jobs)
+}
+
+fn check_distinct_outputs(jobs: &[Job]) -> Result<(), VideoEncodeError> {
+    let mut outputs = HashSet::new();
+    for job in jobs {
+        if !outputs.insert(&job.output_file) {
+            return Err(VideoEncodeError::Config(format!(
+                "Several inputs of the batch would be encoded to {:?}",
+                job.output_file
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Picks up the files of an inbox once they have stopped growing, each one
+/// once until it is released
+#[derive(Debug, Default)]
+pub struct InboxScanner {
+    /// Size of each file at the previous scan
+    sizes: HashMap<PathBuf, u64>,
+    /// Files queued or being encoded, and ignored outputs
+    taken: HashSet<PathBuf>,
+}
+
+impl InboxScanner {
+    /// Files of `listing` (path and size) the same size as at the previous
+    /// scan, which are taken from now on
+    pub fn ready(&mut self, listing: Vec<(PathBuf, u64)>) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        let mut sizes = HashMap::new();
+        for (path, size) in listing {
+            if self.taken.contains(&path) {
+                continue;
+            }
+            if self.sizes.get(&path) == Some(&size) {
+                self.taken.insert(path.clone());
+                ready.push(path);
+            } else {
+                sizes.insert(path, size);
+            }
+        }
+        // Files that went away are forgotten
+        self.sizes = sizes;
+        ready.sort();
+        ready
+    }
+
+    /// Never picks up `path`, e.g. an output written to the inbox
+    pub fn ignore(&mut self, path: &Path) {
+        self.taken.insert(path.to_path_buf());
+    }
+
+    /// Picks up `path` again once a new file appears there, e.g. after the
+    /// input taken from it has been moved away
+    pub fn release(&mut self, path: &Path) {
+        self.taken.remove(path);
+    }
+
+    /// Ready video files of `inbox`
+    pub fn scan(&mut self, inbox: &Path) -> Result<Vec<PathBuf>, VideoEncodeError> {
+        let listing = video_files(inbox)?
+            .into_iter()
+            .filter_map(|path| Some((fs::metadata(&path).ok()?.len(), path)))
+            .map(|(size, path)| (path, size))
+            .collect();
+        Ok(self.ready(listing))
+    }
+}
+
+/// Batch settings shared by every job
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub template:       Option<OutputTemplate>,
+    /// Where inputs are moved once encoded, left in place if `None`
+    pub done_dir:       Option<PathBuf>,
+    /// Where inputs are moved when their job fails, left in place if `None`
+    pub failed_dir:     Option<PathBuf>,
+    /// Jobs encoded at once; they share the nodes
+    pub parallel_jobs:  usize,
+    pub watch_interval: Duration,
+}
+
+impl BatchOptions {
+    /// An inbox has `done` and `failed` folders of its own unless set
+    pub fn from_cli(cli: &Cli, source: &JobSource) -> Result<Self, VideoEncodeError> {
+        let template = cli.output_template.as_deref().map(OutputTemplate::new).transpose()?;
+        if cli.parallel_jobs == 0 {
+            return Err(VideoEncodeError::Config(
+                "--parallel-jobs must be at least 1".to_string(),
+            ));
+        }
+        let (done_dir, failed_dir) = match source {
+            JobSource::Watch(inbox) => (
+                Some(cli.done_dir.clone().unwrap_or_else(|| inbox.join("done"))),
+                Some(cli.failed_dir.clone().unwrap_or_else(|| inbox.join("failed"))),
+            ),
+            _ => (cli.done_dir.clone(), cli.failed_dir.clone()),
+        };
+        if matches!(source, JobSource::Watch(_)) && template.is_none() {
+            return Err(VideoEncodeError::Config(
+                "Watching an inbox needs an --output-template".to_string(),
+            ));
+        }
+        Ok(Self {
+            template,
+            done_dir,
+            failed_dir,
+            parallel_jobs: cli.parallel_jobs,
+            watch_interval: Duration::from_secs_f64(cli.watch_interval.max(0.1)),
+        })
+    }
+}
+
+/// Inputs of a batch by outcome
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub succeeded: Vec<PathBuf>,
+    pub failed:    Vec<(PathBuf, String)>,
+}
+
+/// Moves `input` into `dir`, under a new name if the name is taken.
+/// Returns where it went.
+pub fn move_input(input: &Path, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let name = input.file_name().context("Input has no file name")?;
+    let mut target = dir.join(name);
+    let mut copy = 1;
+    while target.exists() {
+        let stem = input.file_stem().unwrap_or(name).to_string_lossy();
+        target = match input.extension() {
+            Some(extension) => {
+                dir.join(format!("{}.{}.{}", stem, copy, extension.to_string_lossy()))
+            },
+            None => dir.join(format!("{}.{}", stem, copy)),
+        };
+        copy += 1;
+    }
+    if fs::rename(input, &target).is_err() {
+        // Across file systems
+        fs::copy(input, &target)
+            .and_then(|_| fs::remove_file(input))
+            .with_context(|| format!("Failed to move {:?} to {:?}", input, target))?;
+    }
+    Ok(target)
+}
+
+/// Creates the directory `job` writes its output to
+pub(crate) fn create_output_dir(job: &Job) -> Result<()> {
+    match job.output_file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create output directory {:?}", dir)),
+        None => Ok(()),
+    }
+}
+
+/// Runs `job` and files its input under done or failed
+async fn run_one<F, Fut>(job: Job, options: &BatchOptions, run_job: &F) -> (Job, Result<()>)
+where
+    F: Fn(Job) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    info!("Starting job {:?} -> {:?}", job.input_file, job.output_file);
+    let mut result = create_output_dir(&job);
+    if result.is_ok() {
+        result = run_job(job.clone()).await;
+    }
+    let filed_under = match &result {
+        Ok(()) => options.done_dir.as_deref(),
+        Err(_) => options.failed_dir.as_deref(),
+    };
+    if let Some(dir) = filed_under {
+        match move_input(&job.input_file, dir) {
+            Ok(target) => info!("Moved {:?} to {:?}", job.input_file, target),
+            Err(e) => warn!("Failed to move {:?} to {:?}: {:#}", job.input_file, dir, e),
+        }
+    }
+    (job, result)
+}
+
+fn record(summary: &mut BatchSummary, (job, result): (Job, Result<()>)) {
+    match result {
+        Ok(()) => {
+            info!("Job {:?} finished: {:?}", job.input_file, job.output_file);
+            summary.succeeded.push(job.input_file);
+        },
+        Err(e) => {
+            error!("Job {:?} failed: {:#}", job.input_file, e);
+            summary.failed.push((job.input_file, format!("{:#}", e)));
+        },
+    }
+}
+
+/// Runs the jobs of `source` through `run_job`, up to
+/// `options.parallel_jobs` at once. A watched inbox is scanned every
+/// `options.watch_interval` until Ctrl-C, after which the running jobs are
+/// finished.
+pub async fn run_batch<F, Fut>(
+    source: &JobSource,
+    options: &BatchOptions,
+    run_job: F,
+) -> Result<BatchSummary>
+where
+    F: Fn(Job) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut summary = BatchSummary::default();
+    let mut running = FuturesUnordered::new();
+
+    let JobSource::Watch(inbox) = source else {
+        let jobs = source.jobs(options.template.as_ref())?;
+        info!("Batch of {} jobs queued", jobs.len());
+        for job in jobs {
+            if running.len() >= options.parallel_jobs {
+                if let Some(finished) = running.next().await {
+                    record(&mut summary, finished);
+                }
+            }
+            running.push(run_one(job, options, &run_job));
+        }
+        while let Some(finished) = running.next().await {
+            record(&mut summary, finished);
+        }
+        return Ok(summary);
+    };
+
+    let template = options
+        .template
+        .as_ref()
+        .context("Watching an inbox needs an output template")?;
+    info!("Watching {:?} for new inputs", inbox);
+    let mut scanner = InboxScanner::default();
+    let mut queue: VecDeque<PathBuf> = VecDeque::new();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    let mut interval = tokio::time::interval(options.watch_interval);
+    loop {
+        while running.len() < options.parallel_jobs {
+            let Some(input_file) = queue.pop_front() else {
+                break;
+            };
+            let job = Job {
+                output_file: template.output_for(&input_file),
+                input_file,
+            };
+            // An output written to the inbox is no new input
+            scanner.ignore(&job.output_file);
+            running.push(run_one(job, options, &run_job));
+        }
+        tokio::select! {
+            _ = &mut interrupted => {
+                info!(
+                    "Interrupted; finishing {} running jobs, {} queued jobs are left",
+                    running.len(),
+                    queue.len()
+                );
+                break;
+            },
+            Some(finished) = running.next(), if !running.is_empty() => {
+                // A file left in the inbox, e.g. as moving it failed, is not
+                // encoded again
+                if !finished.0.input_file.exists() {
+                    scanner.release(&finished.0.input_file);
+                }
+                record(&mut summary, finished);
+            },
+            _ = interval.tick() => {
+                match scanner.scan(inbox) {
+                    Ok(ready) => {
+                        for input in ready {
+                            info!("Queued new input {:?}", input);
+                            queue.push_back(input);
+                        }
+                    },
+                    Err(e) => warn!("Failed to scan inbox {:?}: {}", inbox, e),
+                }
+            },
+        }
+    }
+    while let Some(finished) = running.next().await {
+        record(&mut summary, finished);
+    }
+    Ok(summary)
+}
@@ -1,33 +1,40 @@
-use std::{path::PathBuf, sync::Arc};
+use std::io::IsTerminal;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use ferris_swarm_client::{
+    batch::{run_batch, BatchOptions, JobSource, OutputTemplate},
     cli::Cli,
-    comms::initialize_node_connections,
+    comms::NodeConnection,
     config::load_settings_with_cli_overrides,
-    tasks::{process_chunks_on_node_worker, EncodingTaskState},
+    daemon::{run_daemon, DaemonState},
+    job::{Job, JobBuilder, NodeSource},
+    preview::PreviewOptions,
+    progress::{progress_bars, watch_job, ProgressLogWriter},
 };
-use ferris_swarm_config::{job_config::create_job_temp_config, settings::ConcatenatorChoice};
-use ferris_swarm_core::chunk::convert_files_to_chunks;
-use ferris_swarm_logging::init_logging;
-use ferris_swarm_orchestration::split_video_into_segments;
-use ferris_swarm_video::{
-    concatenator::{concatenate_videos_ffmpeg, concatenate_videos_mkvmerge},
-    segmenter::extract_non_video_streams,
-    utils::{verify_ffmpeg, verify_mkvmerge},
-};
-use futures::stream::{FuturesUnordered, StreamExt};
-use tokio::sync::Mutex;
-use tracing::{debug, error, info, instrument, warn};
+use ferris_swarm_config::settings::{ConcatenatorChoice, Settings};
+use ferris_swarm_logging::{init_logging, init_logging_with_console};
+use ferris_swarm_video::utils::{verify_ffmpeg, verify_mkvmerge};
+use indicatif::MultiProgress;
+use tokio::net::TcpListener;
+use tracing::{debug, info, instrument};
 
 #[tokio::main]
 #[instrument]
 async fn main() -> Result<()> {
-    init_logging();
-    info!("Ferris Swarm Client: Starting video encoding process...");
-
     let cli_args = Cli::parse();
+    // Bars for a single job on a terminal, log lines otherwise
+    let bars = (!cli_args.no_progress
+        && cli_args.batch.is_none()
+        && cli_args.watch.is_none()
+        && cli_args.daemon.is_none()
+        && std::io::stdout().is_terminal())
+    .then(progress_bars);
+    match &bars {
+        Some(bars) => init_logging_with_console(ProgressLogWriter::new(bars.clone())),
+        None => init_logging(),
+    }
+    info!("Ferris Swarm Client: Starting video encoding process...");
     debug!("Parsed CLI arguments: {:?}", cli_args);
 
     let settings =
@@ -37,175 +44,73 @@ async fn main() -> Result<()> {
     verify_ffmpeg().context("FFmpeg verification failed")?;
     if settings.processing.concatenator == ConcatenatorChoice::Mkvmerge {
         verify_mkvmerge().context("mkvmerge verification failed")?;
-        info!("Using mkvmerge for concatenation.");
-    } else {
-        info!("Using FFmpeg for concatenation.");
-    }
-
-    let job_temp_config =
-        create_job_temp_config(&settings, &cli_args.input_file, &cli_args.output_file);
-    info!("Job temporary directory: {:?}", job_temp_config.base_dir);
-
-    let node_addresses_to_use = if !cli_args.nodes.is_empty() {
-        &cli_args.nodes
-    } else {
-        &settings.client.node_addresses
-    };
-    let node_slots_to_use = if !cli_args.slots.is_empty() {
-        cli_args.slots.clone()
-    } else if !node_addresses_to_use.is_empty() {
-        vec![1; node_addresses_to_use.len()]
-    } else {
-        Vec::new()
-    };
-
-    if node_addresses_to_use.is_empty() {
-        warn!("No nodes configured or specified. Encoding will not be distributed.");
-    }
-
-    let node_connections = initialize_node_connections(node_addresses_to_use, &node_slots_to_use)
-        .await
-        .context("Failed to initialize node connections")?;
-
-    if node_connections.is_empty() && !node_addresses_to_use.is_empty() {
-        return Err(anyhow::anyhow!(
-            "Nodes were specified, but no connections could be established."
-        ));
-    } else if node_connections.is_empty() {
-        return Err(anyhow::anyhow!(
-            "No worker nodes available. Cannot proceed with distributed encoding."
-        ));
-    }
-
-    info!("Splitting video into segments...");
-    let video_segments = split_video_into_segments(
-        &cli_args.input_file,
-        settings.processing.segment_duration,
-        &job_temp_config.segments_dir(),
-    )
-    .context("Failed to split video into segments")?;
-
-    info!("Extracting non-video streams...");
-    let non_video_streams_path =
-        extract_non_video_streams(&cli_args.input_file, &job_temp_config.base_dir)?;
-
-    let initial_chunks =
-        convert_files_to_chunks(video_segments, settings.client.encoder_params.clone())
-            .context("Failed to convert video segments to chunks")?;
-    let total_chunks_count = initial_chunks.len();
-    info!("Created {} chunks from video segments.", total_chunks_count);
-
-    if total_chunks_count == 0 {
-        warn!("No chunks were created from the video. Check video duration and segment settings.");
-        job_temp_config
-            .delete_job_temp_dirs()
-            .map_err(|e| warn!("Failed to clean up job temp dirs: {}", e))
-            .ok();
-        return Ok(());
     }
 
-    let encoding_task_state = Arc::new(Mutex::new(EncodingTaskState::new(initial_chunks)));
-    let mut node_worker_handles = FuturesUnordered::new();
+    // Every job of a batch or the daemon shares these nodes
+    let node_pool = NodeSource::from_cli(&cli_args).connect(&settings).await?;
 
-    info!(
-        "Dispatching encoding tasks to {} connected nodes...",
-        node_connections.len()
-    );
-    for node_conn in node_connections {
-        node_worker_handles.push(tokio::spawn(process_chunks_on_node_worker(
-            node_conn,
-            Arc::clone(&encoding_task_state),
-            job_temp_config.encoded_chunks_dir(),
-        )));
-    }
-
-    while let Some(result) = node_worker_handles.next().await {
-        if let Err(e) = result {
-            error!("A node worker task failed (joined with error): {}", e);
+    if let Some(address) = &cli_args.daemon {
+        if cli_args.parallel_jobs == 0 {
+            return Err(anyhow::anyhow!("--parallel-jobs must be at least 1"));
         }
+        let template = cli_args.output_template.as_deref().map(OutputTemplate::new).transpose()?;
+        let (state, queue) = DaemonState::new(template);
+        let listener = TcpListener::bind(address)
+            .await
+            .with_context(|| format!("Failed to bind the job API to {}", address))?;
+        return run_daemon(listener, state, queue, cli_args.parallel_jobs, |job| {
+            let (cli_args, settings, node_pool) = (&cli_args, &settings, &node_pool);
+            async move { run_job(cli_args, settings, node_pool, job, None).await }
+        })
+        .await;
     }
-    info!("All node workers have completed their processing loops.");
-
-    let final_state = encoding_task_state.lock().await;
-    if !final_state.pending_chunks.is_empty() {
-        warn!(
-            "{} chunks remain in pending state. Encoding may be incomplete.",
-            final_state.pending_chunks.len()
-        );
-        for chunk in &final_state.pending_chunks {
-            warn!(
-                "Pending chunk: index {}, source {:?}",
-                chunk.index, chunk.source_path
-            );
-        }
-    }
-
-    let mut successfully_encoded_chunks = final_state.completed_chunks.clone();
-    successfully_encoded_chunks.sort_by_key(|chunk| chunk.index);
 
+    let Some(source) = JobSource::from_cli(&cli_args)? else {
+        let job = Job::from_cli(&cli_args)?;
+        return run_job(&cli_args, &settings, &node_pool, job, bars).await;
+    };
+    let options = BatchOptions::from_cli(&cli_args, &source)?;
+    let summary = run_batch(&source, &options, |job| {
+        let (cli_args, settings, node_pool) = (&cli_args, &settings, &node_pool);
+        async move { run_job(cli_args, settings, node_pool, job, None).await }
+    })
+    .await?;
     info!(
-        "Total chunks processed: {}. Successfully encoded and received: {}.",
-        total_chunks_count,
-        successfully_encoded_chunks.len()
+        "Batch finished: {} jobs succeeded, {} failed.",
+        summary.succeeded.len(),
+        summary.failed.len()
     );
-
-    if successfully_encoded_chunks.len() != total_chunks_count {
-        error!(
-            "Encoding incomplete: Expected {} chunks, but only {} were successfully processed.",
-            total_chunks_count,
-            successfully_encoded_chunks.len()
-        );
-        job_temp_config
-            .delete_job_temp_dirs()
-            .map_err(|e| warn!("Failed to clean up job temp dirs: {}", e))
-            .ok();
+    if !summary.failed.is_empty() {
         return Err(anyhow::anyhow!(
-            "Encoding failed: Not all chunks were processed successfully."
+            "{} of {} jobs failed: {:?}",
+            summary.failed.len(),
+            summary.failed.len() + summary.succeeded.len(),
+            summary.failed.iter().map(|(input, _)| input).collect::<Vec<_>>()
         ));
     }
+    Ok(())
+}
 
-    info!(
-        "Concatenating {} encoded chunks using {:?}...",
-        successfully_encoded_chunks.len(),
-        settings.processing.concatenator
-    );
-    let encoded_chunk_paths: Vec<PathBuf> = successfully_encoded_chunks
-        .into_iter()
-        .map(|chunk| chunk.encoded_path.expect("Completed chunk must have an encoded_path"))
-        .collect();
-
-    let output_file_path = PathBuf::from(&cli_args.output_file);
-
-    match settings.processing.concatenator {
-        ConcatenatorChoice::Ffmpeg => {
-            concatenate_videos_ffmpeg(
-                encoded_chunk_paths,
-                &non_video_streams_path,
-                &output_file_path,
-                &job_temp_config.base_dir, // For the ffmpeg concat list file
-                total_chunks_count,
-            )?;
-        },
-        ConcatenatorChoice::Mkvmerge => {
-            concatenate_videos_mkvmerge(
-                encoded_chunk_paths,
-                &non_video_streams_path,
-                &output_file_path,
-                &job_temp_config.base_dir, // temp_dir not strictly needed by mkvmerge here
-                total_chunks_count,
-            )?;
-        },
-    }
-
-    info!(
-        "Video encoding completed successfully. Output: {}",
-        cli_args.output_file
-    );
-
-    job_temp_config
-        .delete_job_temp_dirs()
-        .map_err(|e| warn!("Failed to clean up job temporary directories: {}", e))
-        .ok();
-
+/// Encodes `job` on the nodes of `node_pool`, which other jobs of a batch
+/// or the daemon may be using at the same time. Its progress is drawn in
+/// `bars`, or logged without them.
+async fn run_job(
+    cli_args: &Cli,
+    settings: &Settings,
+    node_pool: &[NodeConnection],
+    job: Job,
+    bars: Option<MultiProgress>,
+) -> Result<()> {
+    let preview = PreviewOptions::from_cli(cli_args).context("Invalid preview options")?;
+    let label = job.input_file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut handle = JobBuilder::from_job(job)
+        .with_settings(settings.clone())
+        .with_nodes(NodeSource::Connected(node_pool.to_vec()))
+        .with_preview(preview)
+        .with_progressive(cli_args.progressive.clone())
+        .start()
+        .await?;
+    watch_job(&mut handle, &label, bars).await;
+    handle.wait().await?;
     Ok(())
 }
@@ -1,17 +1,65 @@
 use std::path::PathBuf;
 
 use clap::{builder::TypedValueParser, Parser};
+use ferris_swarm_core::StreamMetadata;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about = "Ferris Swarm Client: Distributes video encoding tasks.", long_about = None)]
 pub struct Cli {
     /// Input video file path
-    #[arg(short, long)]
-    pub input_file: PathBuf,
+    #[arg(short, long, required_unless_present_any = ["batch", "watch", "daemon"])]
+    pub input_file: Option<PathBuf>,
 
     /// Output video file path
-    #[arg(short, long)]
-    pub output_file: String,
+    #[arg(short, long, required_unless_present_any = ["batch", "watch", "daemon"])]
+    pub output_file: Option<String>,
+
+    /// Encode a batch instead of one input: a directory of videos, a glob
+    /// such as 'ingest/*.mov', or a job list file with one input per line,
+    /// optionally followed by '=> OUTPUT'.
+    #[arg(long, conflicts_with_all = ["input_file", "output_file", "watch", "preview", "progressive"])]
+    pub batch: Option<String>,
+
+    /// Watch this inbox directory and encode every video file dropped into
+    /// it once it has stopped growing, until interrupted. Encoded inputs are
+    /// moved to its done folder, failed ones to its failed folder.
+    #[arg(long, conflicts_with_all = ["input_file", "output_file", "preview", "progressive"])]
+    pub watch: Option<PathBuf>,
+
+    /// Stay up as a daemon serving a job API on this address (e.g.
+    /// 127.0.0.1:7900): POST /jobs with {"input_file", "output_file"} queues
+    /// a job, GET /jobs and GET /jobs/ID report them, DELETE /jobs/ID
+    /// cancels one. The node connections are kept between jobs.
+    #[arg(long, conflicts_with_all = ["input_file", "output_file", "batch", "watch", "preview", "progressive"])]
+    pub daemon: Option<String>,
+
+    /// Output path of each batch or watch job, and of daemon jobs submitted
+    /// without one; {stem}, {name}, {ext} and
+    /// {parent} stand for the input's file stem, file name, extension and
+    /// directory (e.g. 'encoded/{stem}.mkv').
+    #[arg(long)]
+    pub output_template: Option<String>,
+
+    /// Where batch or watch inputs are moved once encoded. Defaults to
+    /// INBOX/done when watching; batch inputs stay in place unless set.
+    #[arg(long)]
+    pub done_dir: Option<PathBuf>,
+
+    /// Where batch or watch inputs are moved when their job fails. Defaults
+    /// to INBOX/failed when watching; batch inputs stay in place unless set.
+    #[arg(long)]
+    pub failed_dir: Option<PathBuf>,
+
+    /// Batch, watch or daemon jobs encoded at once, sharing the nodes. More
+    /// than one keeps nodes busy while the next input is split; nodes need to
+    /// keep the chunks of concurrent jobs apart, as this version does.
+    #[arg(long, default_value_t = 1)]
+    pub parallel_jobs: usize,
+
+    /// Seconds between scans of the watched inbox. A file is picked up once
+    /// its size is unchanged between two scans.
+    #[arg(long, default_value_t = 5.0)]
+    pub watch_interval: f64,
 
     /// Path to the configuration file (e.g., config.toml)
     #[arg(long)]
@@ -32,20 +80,209 @@ pub struct Cli {
     #[arg(long, num_args = 1..)]
     pub encoder_params: Option<Vec<String>>,
 
+    /// Name of an encoder profile defined under [profiles] in the config
+    /// file. Takes precedence over encoder params.
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Encode every chunk in two passes. The node runs the analysis pass
+    /// with its own passlog, so `-pass` must not be set in encoder params.
+    /// Overrides pass_mode in config file if provided.
+    #[arg(long)]
+    pub two_pass: bool,
+
+    /// Encoder backend nodes run chunks through (e.g. 'ffmpeg', 'svt-av1',
+    /// 'aomenc', 'rav1e', 'x264', 'x265'). Encoder params are passed to the
+    /// selected backend's CLI. Overrides encoder_backend in config file if
+    /// provided.
+    #[arg(long)]
+    pub encoder_backend: Option<String>,
+
+    /// Audio codec to re-encode audio tracks with (e.g. 'libopus', 'aac').
+    /// Audio is stream copied unless set. Overrides audio.codec in config
+    /// file if provided.
+    #[arg(long)]
+    pub audio_codec: Option<String>,
+
+    /// Audio bitrate (e.g. '128k'), or one per track (e.g. '192k,96k').
+    /// Overrides audio.bitrate in config file if provided.
+    #[arg(long)]
+    pub audio_bitrate: Option<String>,
+
+    /// Audio channel layout to up/downmix to (e.g. 'stereo', '5.1').
+    /// Overrides audio.channel_layout in config file if provided.
+    #[arg(long)]
+    pub audio_channel_layout: Option<String>,
+
+    /// Audio tracks to keep, counted among audio streams (e.g. 0,2).
+    /// Overrides audio.tracks in config file if provided.
+    #[arg(long, value_delimiter = ',')]
+    pub audio_tracks: Vec<usize>,
+
+    /// Where audio is re-encoded ('local' or 'node').
+    /// Overrides audio.location in config file if provided.
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["local", "node"]).map(|s| s.to_lowercase()))]
+    pub audio_location: Option<String>,
+
+    /// Normalize audio loudness (EBU R128, two-pass loudnorm). Requires an
+    /// audio codec. Uses the [audio.loudness] targets from the config file,
+    /// -23 LUFS / -1 dBTP if none are set.
+    #[arg(long)]
+    pub loudnorm: bool,
+
+    /// Integrated loudness target in LUFS. Implies --loudnorm.
+    #[arg(long, allow_hyphen_values = true)]
+    pub loudnorm_target: Option<f64>,
+
+    /// True peak ceiling in dBTP. Implies --loudnorm.
+    #[arg(long, allow_hyphen_values = true)]
+    pub loudnorm_true_peak: Option<f64>,
+
+    /// Audio languages to keep (ISO 639-2, e.g. eng,jpn; 'und' for untagged).
+    /// Overrides processing.streams.audio.languages in config file if
+    /// provided.
+    #[arg(long, value_delimiter = ',')]
+    pub audio_languages: Vec<String>,
+
+    /// Audio codecs to keep (e.g. aac,opus).
+    /// Overrides processing.streams.audio.codecs in config file if provided.
+    #[arg(long, value_delimiter = ',')]
+    pub audio_codecs: Vec<String>,
+
+    /// Subtitle languages to keep (ISO 639-2, e.g. eng).
+    /// Overrides processing.streams.subtitle.languages in config file if
+    /// provided.
+    #[arg(long, value_delimiter = ',')]
+    pub subtitle_languages: Vec<String>,
+
+    /// Subtitle codecs to keep (e.g. subrip,ass).
+    /// Overrides processing.streams.subtitle.codecs in config file if
+    /// provided.
+    #[arg(long, value_delimiter = ',')]
+    pub subtitle_codecs: Vec<String>,
+
+    /// Keep only subtitles with one of these dispositions (e.g. forced).
+    /// Overrides processing.streams.subtitle.dispositions in config file if
+    /// provided.
+    #[arg(long, value_delimiter = ',')]
+    pub subtitle_dispositions: Vec<String>,
+
+    /// Drop audio and subtitle streams with any of these dispositions (e.g.
+    /// comment,hearing_impaired). Overrides exclude_dispositions of both in
+    /// config file if provided.
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_dispositions: Vec<String>,
+
+    /// Metadata for an output stream, e.g. "a:0,title=Main,language=eng,
+    /// default,forced=false". Can be repeated. Replaces
+    /// processing.streams.metadata in config file if provided.
+    #[arg(long)]
+    pub stream_metadata: Vec<StreamMetadata>,
+
+    /// Only produce these renditions of the [[renditions]] ladder in the
+    /// config file (e.g. 1080p,720p).
+    #[arg(long, value_delimiter = ',')]
+    pub renditions: Vec<String>,
+
     /// Temporary directory for client-side processing for this job.
     /// Overrides temp_dir in [processing] section of config file if provided.
     #[arg(long)]
     pub temp_dir: Option<PathBuf>,
 
+    /// Encode the input from this time on, in seconds or [HH:]MM:SS[.ms].
+    /// Stream copied video begins on the keyframe at or before it.
+    #[arg(long, conflicts_with = "range")]
+    pub start: Option<String>,
+
+    /// Encode the input up to this time, in seconds or [HH:]MM:SS[.ms].
+    #[arg(long, conflicts_with = "range")]
+    pub end: Option<String>,
+
+    /// Parts of the input to encode as START-END (e.g. 0:30-1:10,5:00-5:30),
+    /// joined back to back in the output. The last may leave out END.
+    /// Overrides processing.ranges in config file if provided.
+    #[arg(long, value_delimiter = ',')]
+    pub range: Vec<String>,
+
     /// Duration of each video segment in seconds.
     /// Overrides segment_duration in [processing] section of config file if
     /// provided.
     #[arg(long)]
     pub segment_duration: Option<f64>,
 
-    /// Concatenation tool to use ('ffmpeg' or 'mkvmerge').
+    /// Concatenation tool to use ('ffmpeg', 'mkvmerge' or 'native' for raw
+    /// bitstreams), 'auto' to pick one from the output extension, or
+    /// 'hls'/'dash' to package segments with a playlist or manifest at the
+    /// output path.
     /// Overrides 'concatenator' in [processing] section of config file if
     /// provided.
-    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["ffmpeg", "mkvmerge"]).map(|s| s.to_lowercase()))]
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["auto", "ffmpeg", "mkvmerge", "native", "hls", "dash"]).map(|s| s.to_lowercase()))]
     pub concatenator: Option<String>,
+
+    /// What to do about duration or A/V drift in the concatenated output
+    /// ('off', 'report', 'fail', or 'fix' to retime Matroska outputs with
+    /// mkvmerge). Overrides processing.sync_check.action in config file if
+    /// provided.
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["off", "report", "fail", "fix"]).map(|s| s.to_lowercase()))]
+    pub sync_check: Option<String>,
+
+    /// Drift in seconds tolerated by the sync check.
+    /// Overrides processing.sync_check.tolerance in config file if provided.
+    #[arg(long)]
+    pub sync_tolerance: Option<f64>,
+
+    /// What chunks are sent to the nodes as: 'source' segments (smallest;
+    /// nodes decode them), lossless 'ffv1' (several times larger) or raw
+    /// 'y4m' frames (far larger, constant frame rate only), decoded on the
+    /// client so nodes need no decoder for the source format.
+    /// Overrides processing.transport in config file if provided.
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(["source", "ffv1", "y4m"]).map(|s| s.to_lowercase()))]
+    pub transport: Option<String>,
+
+    /// TOML file of [[zones]] with encoder overrides for time ranges or
+    /// chunks, e.g. cheaper settings for the credits. Replaces the [[zones]]
+    /// of the config file if provided.
+    #[arg(long)]
+    pub zones: Option<PathBuf>,
+
+    /// Side file sent to the node with each chunk, as NAME=PATH. Encoder
+    /// parameters refer to it as {attachment:NAME}; PATH may contain {index}
+    /// for a file per chunk (e.g. grain/{index}.tbl). Replaces the
+    /// [[attachments]] of the config file if provided.
+    #[arg(long = "attach", value_name = "NAME=PATH")]
+    pub attachments: Vec<String>,
+
+    /// Hard cap on the output size, e.g. 700MB or 4GiB. Video bitrate is
+    /// budgeted per chunk by complexity, and chunks are re-encoded if the
+    /// output overshoots. Overrides processing.target_size.bytes in config
+    /// file if provided.
+    #[arg(long)]
+    pub target_size: Option<String>,
+
+    /// Also append chunks to this growing file (fragmented .mp4 or .mkv) as
+    /// soon as they complete in index order, so the start of a long encode
+    /// can be watched early. H.264 and HEVC only, even for .mkv, as chunks
+    /// pass through MPEG-TS; with renditions, the first one. The output file
+    /// is still muxed at the end.
+    #[arg(long, conflicts_with = "preview")]
+    pub progressive: Option<PathBuf>,
+
+    /// Log progress instead of drawing progress bars. Bars are only drawn
+    /// for a single job when stdout is a terminal.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Encode only this many segments as a preview and report their size,
+    /// quality, and the extrapolated total size and encoding time.
+    #[arg(long)]
+    pub preview: Option<usize>,
+
+    /// Segments a preview encodes: 'even' spaces them over the input,
+    /// 'complex' picks those with the highest source bitrate.
+    #[arg(long, requires = "preview", value_parser = clap::builder::PossibleValuesParser::new(["even", "complex"]).map(|s| s.to_lowercase()))]
+    pub preview_select: Option<String>,
+
+    /// Join the preview's encoded chunks into this file.
+    #[arg(long, requires = "preview")]
+    pub preview_output: Option<PathBuf>,
 }
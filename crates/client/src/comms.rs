@@ -1,22 +1,78 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::{Context, Result};
-use ferris_swarm_core::chunk::Chunk;
+use ferris_swarm_core::{
+    chunk::{Chunk, ChunkTransport, PassMode},
+    AudioOptions,
+    NodeCapabilities,
+    TrackLoudness,
+};
 use ferris_swarm_proto::protos::video_encoding::{
     video_encoding_service_client::VideoEncodingServiceClient,
+    CapabilitiesRequest,
+    ChunkAttachment,
+    ChunkTransport as ProtoChunkTransport,
+    EncodeAudioRequest,
+    EncodeChunkPart,
     EncodeChunkRequest,
+    EncodeChunkResponse,
 };
-use tokio::sync::Semaphore;
-use tonic::transport::Channel;
+use ferris_swarm_video::transport::transport_command;
+use tokio::{io::AsyncReadExt, sync::Semaphore};
+use tonic::{codec::CompressionEncoding, transport::Channel};
 use tracing::{debug, error, info, instrument, warn};
 
 const MAX_MESSAGE_SIZE_BYTES: usize = 1 * 1024 * 1024 * 1024; // 1 GB
 
 #[derive(Clone)]
 pub struct NodeConnection {
-    pub client:    VideoEncodingServiceClient<Channel>,
-    pub address:   String,
-    pub semaphore: Arc<Semaphore>, // Controls concurrent tasks for this specific node
+    pub client:       VideoEncodingServiceClient<Channel>,
+    pub address:      String,
+    pub semaphore:    Arc<Semaphore>, // Controls concurrent tasks for this specific node
+    /// Capabilities reported by the node, `None` if the node does not report
+    /// them
+    pub capabilities: Option<NodeCapabilities>,
+}
+
+impl NodeConnection {
+    /// Whether this node can encode with the given backend. Nodes that don't
+    /// report capabilities are assumed to only run ffmpeg.
+    pub fn supports_backend(&self, backend: &str) -> bool {
+        match &self.capabilities {
+            Some(capabilities) => capabilities.supported_backends.iter().any(|b| b == backend),
+            None => backend == ferris_swarm_core::DEFAULT_ENCODER_BACKEND,
+        }
+    }
+}
+
+/// Asks a node for its capabilities. Returns `None` for nodes that don't
+/// implement the call.
+async fn fetch_node_capabilities(
+    client: &mut VideoEncodingServiceClient<Channel>,
+    address: &str,
+) -> Result<Option<NodeCapabilities>> {
+    match client.get_capabilities(CapabilitiesRequest {}).await {
+        Ok(response) => Ok(Some(response.into_inner().into())),
+        Err(status) if status.code() == tonic::Code::Unimplemented => {
+            warn!(
+                "Node at {} does not report capabilities; assuming ffmpeg only.",
+                address
+            );
+            Ok(None)
+        },
+        Err(status) => Err(anyhow::anyhow!(
+            "Failed to query capabilities of node at {}: {}",
+            address,
+            status
+        )),
+    }
 }
 
 #[instrument(skip(node_addresses, node_slots))]
@@ -58,14 +114,18 @@ pub async fn initialize_node_connections(
             .await
             .with_context(|| format!("Failed to connect to node at {}", address_str))?;
 
-        let client = VideoEncodingServiceClient::new(channel)
+        let mut client = VideoEncodingServiceClient::new(channel)
             .max_decoding_message_size(MAX_MESSAGE_SIZE_BYTES)
             .max_encoding_message_size(MAX_MESSAGE_SIZE_BYTES);
 
+        let capabilities = fetch_node_capabilities(&mut client, address_str).await?;
+        debug!("Node {} capabilities: {:?}", address_str, capabilities);
+
         connections.push(NodeConnection {
             client,
             address: address_str.clone(),
             semaphore: Arc::new(Semaphore::new(slots)),
+            capabilities,
         });
         info!(
             "Successfully connected to node {} at {} with {} slots",
@@ -93,27 +153,52 @@ pub async fn send_chunk_for_encoding(
     // Returns a new Chunk with encoded_path set on client
     debug!("Preparing to send chunk {} for encoding.", chunk.index);
 
-    let chunk_source_data = tokio::fs::read(&chunk.source_path) // Use tokio::fs for async read
-        .await
-        .with_context(|| {
-            format!(
-                "Failed to read chunk source data from {:?}",
-                chunk.source_path
-            )
-        })?;
+    let mut attachments = Vec::with_capacity(chunk.attachments.len());
+    for attachment in &chunk.attachments {
+        attachments.push(ChunkAttachment {
+            name: attachment.name.clone(),
+            data: tokio::fs::read(&attachment.path).await.with_context(|| {
+                format!(
+                    "Failed to read attachment '{}' of chunk {} from {:?}",
+                    attachment.name, chunk.index, attachment.path
+                )
+            })?,
+        });
+    }
 
-    let request = tonic::Request::new(EncodeChunkRequest {
-        chunk_data:         chunk_source_data,
-        chunk_index:        chunk.index as i32,
+    let mut request = EncodeChunkRequest {
+        chunk_data: Vec::new(),
+        chunk_index: chunk.index as i32,
         encoder_parameters: chunk.encoder_parameters.clone(),
-    });
+        two_pass: chunk.pass_mode == PassMode::TwoPass,
+        encoder_backend: chunk.encoder_backend.clone(),
+        profile: chunk.profile.clone().map(Into::into),
+        color: chunk.color.clone().map(Into::into),
+        rendition: chunk.rendition.clone().unwrap_or_default(),
+        video_filter: chunk.video_filter.clone().unwrap_or_default(),
+        attachments,
+        transport: ProtoChunkTransport::from(chunk.transport) as i32,
+    };
 
-    debug!("Sending EncodeChunkRequest for chunk {}...", chunk.index);
-    let response = client
-        .encode_chunk(request)
-        .await
-        .with_context(|| format!("gRPC call to encode_chunk {} failed", chunk.index))?
-        .into_inner();
+    let response = match transport_command(&chunk.source_path, chunk.transport) {
+        Some(command) => stream_transported_chunk(&chunk, request, command, client).await?,
+        None => {
+            request.chunk_data = tokio::fs::read(&chunk.source_path) // Use tokio::fs for async read
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to read chunk source data from {:?}",
+                        chunk.source_path
+                    )
+                })?;
+            debug!("Sending EncodeChunkRequest for chunk {}...", chunk.index);
+            client
+                .encode_chunk(tonic::Request::new(request))
+                .await
+                .with_context(|| format!("gRPC call to encode_chunk {} failed", chunk.index))?
+                .into_inner()
+        },
+    };
 
     if response.success {
         info!(
@@ -123,7 +208,7 @@ pub async fn send_chunk_for_encoding(
         );
 
         let client_side_encoded_path =
-            client_side_encoded_chunk_dir.join(format!("encoded_chunk_{}.mkv", chunk.index)); // Standardized name
+            client_side_encoded_chunk_dir.join(format!("encoded_{}.mkv", chunk.file_stem())); // Standardized name
 
         tokio::fs::write(&client_side_encoded_path, response.encoded_chunk_data) // Use tokio::fs
             .await
@@ -138,12 +223,9 @@ pub async fn send_chunk_for_encoding(
             chunk.index, client_side_encoded_path
         );
 
-        Ok(Chunk {
-            source_path:        chunk.source_path, // Original source path (segment)
-            encoded_path:       Some(client_side_encoded_path), // Path to the file saved on client
-            index:              chunk.index,
-            encoder_parameters: chunk.encoder_parameters,
-        })
+        // Original source path (segment) is kept, encoded path points to the file
+        // saved on client
+        Ok(chunk.with_encoded_path(client_side_encoded_path))
     } else {
         error!(
             "Node failed to encode chunk {}: {}",
@@ -156,3 +238,168 @@ pub async fn send_chunk_for_encoding(
         ))
     }
 }
+
+/// Size of the data parts a transported chunk is streamed in
+const TRANSPORT_PART_BYTES: usize = 4 * 1024 * 1024;
+
+/// Decodes `chunk` with `command` and streams the intermediate to the node as
+/// it is written, after a first part carrying `request`. Raw y4m is gzip
+/// compressed on the wire.
+async fn stream_transported_chunk(
+    chunk: &Chunk,
+    request: EncodeChunkRequest,
+    command: Command,
+    client: VideoEncodingServiceClient<Channel>,
+) -> Result<EncodeChunkResponse> {
+    let mut child = tokio::process::Command::from(command)
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start ffmpeg to decode the chunk for transport")?;
+    let stdout = child.stdout.take().context("ffmpeg stdout was not piped")?;
+    let sent_bytes = Arc::new(AtomicU64::new(0));
+
+    let counter = Arc::clone(&sent_bytes);
+    let parts = futures::stream::unfold((Some(request), stdout), move |(request, mut stdout)| {
+        let counter = Arc::clone(&counter);
+        async move {
+            if let Some(request) = request {
+                let header = EncodeChunkPart {
+                    request: Some(request),
+                    data:    Vec::new(),
+                };
+                return Some((header, (None, stdout)));
+            }
+            let mut data = vec![0; TRANSPORT_PART_BYTES];
+            let mut filled = 0;
+            while filled < data.len() {
+                match stdout.read(&mut data[filled..]).await {
+                    Ok(0) => break,
+                    Ok(read) => filled += read,
+                    // ffmpeg's exit status tells what went wrong
+                    Err(_) => break,
+                }
+            }
+            if filled == 0 {
+                return None;
+            }
+            data.truncate(filled);
+            counter.fetch_add(filled as u64, Ordering::Relaxed);
+            Some((
+                EncodeChunkPart {
+                    request: None,
+                    data,
+                },
+                (None, stdout),
+            ))
+        }
+    });
+
+    let mut client = match chunk.transport {
+        ChunkTransport::Y4m => client.send_compressed(CompressionEncoding::Gzip),
+        _ => client,
+    };
+    debug!(
+        "Streaming chunk {} to the node as {:?}...",
+        chunk.index, chunk.transport
+    );
+    let response = client.encode_chunk_stream(tonic::Request::new(parts)).await;
+
+    // A failed call drops the stream, and ffmpeg then fails on the broken
+    // pipe; its status only means something when the call went through
+    let response = match response {
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            child.start_kill().ok();
+            if let Ok(output) = child.wait_with_output().await {
+                debug!(
+                    "ffmpeg decoding chunk {} for transport stopped: {}",
+                    chunk.index,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+            return Err(anyhow::Error::new(status).context(format!(
+                "gRPC call to encode_chunk_stream {} failed",
+                chunk.index
+            )));
+        },
+    };
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "ffmpeg failed to decode chunk {} for transport: {}",
+            chunk.index,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let source_bytes = tokio::fs::metadata(&chunk.source_path).await?.len();
+    let sent_bytes = sent_bytes.load(Ordering::Relaxed);
+    info!(
+        "Chunk {} sent as {:?}: {} bytes instead of {} ({:.1}x the source{})",
+        chunk.index,
+        chunk.transport,
+        sent_bytes,
+        source_bytes,
+        sent_bytes as f64 / source_bytes.max(1) as f64,
+        if chunk.transport == ChunkTransport::Y4m {
+            ", before gzip"
+        } else {
+            ""
+        }
+    );
+    Ok(response)
+}
+
+/// Sends the extracted audio tracks to a node for re-encoding and saves the
+/// result as `encoded_audio.mka` in `client_side_output_dir`. Returns the
+/// saved path with the node's loudness normalization results.
+#[instrument(skip(options, client, client_side_output_dir))]
+pub async fn send_audio_for_encoding(
+    audio_source_path: &Path,
+    options: &AudioOptions,
+    mut client: VideoEncodingServiceClient<Channel>,
+    client_side_output_dir: &Path,
+) -> Result<(PathBuf, Vec<TrackLoudness>)> {
+    let audio_data = tokio::fs::read(audio_source_path)
+        .await
+        .with_context(|| format!("Failed to read audio data from {:?}", audio_source_path))?;
+
+    let request = tonic::Request::new(EncodeAudioRequest {
+        audio_data,
+        codec: options.codec.clone().unwrap_or_default(),
+        bitrate: options.bitrate.clone().unwrap_or_default(),
+        channel_layout: options.channel_layout.clone().unwrap_or_default(),
+        loudness: options.loudness.map(Into::into),
+    });
+
+    debug!("Sending EncodeAudioRequest...");
+    let response = client
+        .encode_audio(request)
+        .await
+        .context("gRPC call to encode_audio failed")?
+        .into_inner();
+
+    if !response.success {
+        error!("Node failed to encode audio: {}", response.error_message);
+        return Err(anyhow::anyhow!(
+            "Node reported failure for audio: {}",
+            response.error_message
+        ));
+    }
+
+    info!(
+        "Audio successfully encoded by node. Received {} bytes.",
+        response.encoded_audio_data.len()
+    );
+    let client_side_encoded_path = client_side_output_dir.join("encoded_audio.mka");
+    tokio::fs::write(&client_side_encoded_path, response.encoded_audio_data)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to write received encoded audio to {:?}",
+                client_side_encoded_path
+            )
+        })?;
+    let loudness = response.loudness.into_iter().map(Into::into).collect();
+    Ok((client_side_encoded_path, loudness))
+}
@@ -1,5 +1,19 @@
+use std::path::Path;
+
 use anyhow::Result;
 use ferris_swarm_config::settings::{ConcatenatorChoice, Settings};
+use ferris_swarm_core::{
+    parse_size,
+    parse_time,
+    AttachmentSource,
+    AudioEncodeLocation,
+    ChunkTransport,
+    PassMode,
+    Rendition,
+    SyncAction,
+    TargetSize,
+    TimeRange,
+};
 use tracing::{debug, instrument, warn}; // Added warn
 
 use super::cli::Cli;
@@ -31,14 +45,122 @@ pub fn load_settings_with_cli_overrides(cli: &Cli) -> Result<Settings> {
             "Overriding encoder_params from CLI: {:?}",
             cli_encoder_params
         );
-        let mut params: Vec<String> = Vec::new();
-        cli_encoder_params.iter().for_each(|x| {
-            params.extend(x.split_whitespace().map(str::to_string).collect::<Vec<String>>());
-        });
-        if !params.contains(&"-y".to_string()) {
-            params.push("-y".to_string());
+        settings.client.encoder_params =
+            cli_encoder_params.iter().flat_map(|x| split_arguments(x)).collect();
+    }
+
+    if let Some(profile) = &cli.profile {
+        debug!("Overriding profile from CLI: {}", profile);
+        if cli.encoder_params.is_some() {
+            warn!("Both --profile and --encoder-params given; encoder params are ignored.");
         }
-        settings.client.encoder_params = params;
+        settings.client.profile = Some(profile.clone());
+    }
+
+    if cli.two_pass {
+        debug!("Overriding pass_mode from CLI: two_pass");
+        settings.client.pass_mode = PassMode::TwoPass;
+    }
+
+    if let Some(encoder_backend) = &cli.encoder_backend {
+        debug!("Overriding encoder_backend from CLI: {}", encoder_backend);
+        settings.client.encoder_backend = encoder_backend.clone();
+    }
+
+    if let Some(audio_codec) = &cli.audio_codec {
+        debug!("Overriding audio.codec from CLI: {}", audio_codec);
+        settings.audio.codec = Some(audio_codec.clone());
+    }
+
+    if let Some(audio_bitrate) = &cli.audio_bitrate {
+        debug!("Overriding audio.bitrate from CLI: {}", audio_bitrate);
+        settings.audio.bitrate = Some(audio_bitrate.clone());
+    }
+
+    if let Some(channel_layout) = &cli.audio_channel_layout {
+        debug!(
+            "Overriding audio.channel_layout from CLI: {}",
+            channel_layout
+        );
+        settings.audio.channel_layout = Some(channel_layout.clone());
+    }
+
+    if !cli.audio_tracks.is_empty() {
+        debug!("Overriding audio.tracks from CLI: {:?}", cli.audio_tracks);
+        settings.audio.tracks = cli.audio_tracks.clone();
+    }
+
+    if let Some(location) = &cli.audio_location {
+        debug!("Overriding audio.location from CLI: {}", location);
+        settings.audio.location = match location.as_str() {
+            "node" => AudioEncodeLocation::Node,
+            _ => AudioEncodeLocation::Local,
+        };
+    }
+
+    if cli.loudnorm || cli.loudnorm_target.is_some() || cli.loudnorm_true_peak.is_some() {
+        let loudness = settings.audio.loudness.get_or_insert_with(Default::default);
+        if let Some(integrated) = cli.loudnorm_target {
+            loudness.integrated = integrated;
+        }
+        if let Some(true_peak) = cli.loudnorm_true_peak {
+            loudness.true_peak = true_peak;
+        }
+        debug!("Overriding audio.loudness from CLI: {:?}", loudness);
+    }
+
+    if !settings.audio.reencodes()
+        && (settings.audio.bitrate.is_some() || settings.audio.channel_layout.is_some())
+    {
+        warn!("Audio bitrate/channel layout are ignored unless an audio codec is set.");
+    }
+
+    let streams = &mut settings.processing.streams;
+    let filter_overrides = [
+        (&mut streams.audio.languages, &cli.audio_languages),
+        (&mut streams.audio.codecs, &cli.audio_codecs),
+        (&mut streams.subtitle.languages, &cli.subtitle_languages),
+        (&mut streams.subtitle.codecs, &cli.subtitle_codecs),
+        (
+            &mut streams.subtitle.dispositions,
+            &cli.subtitle_dispositions,
+        ),
+        (
+            &mut streams.audio.exclude_dispositions,
+            &cli.exclude_dispositions,
+        ),
+        (
+            &mut streams.subtitle.exclude_dispositions,
+            &cli.exclude_dispositions,
+        ),
+    ];
+    for (setting, cli_value) in filter_overrides {
+        if !cli_value.is_empty() {
+            *setting = cli_value.clone();
+        }
+    }
+    if !cli.stream_metadata.is_empty() {
+        streams.metadata = cli.stream_metadata.clone();
+    }
+    debug!("Stream selection: {:?}", streams);
+
+    if !cli.renditions.is_empty() {
+        if let Some(unknown) = cli
+            .renditions
+            .iter()
+            .find(|name| !settings.renditions.iter().any(|r| &r.name == *name))
+        {
+            return Err(anyhow::anyhow!(
+                "Rendition '{}' is not defined in [[renditions]]",
+                unknown
+            ));
+        }
+        settings.renditions.retain(|rendition| cli.renditions.contains(&rendition.name));
+        debug!("Producing renditions from CLI: {:?}", cli.renditions);
+    }
+    // Batch jobs check their own outputs
+    if let Some(output_file) = &cli.output_file {
+        Rendition::validate_ladder(&settings.renditions, Path::new(output_file))?;
     }
 
     if let Some(temp_dir) = &cli.temp_dir {
@@ -56,6 +178,10 @@ pub fn load_settings_with_cli_overrides(cli: &Cli) -> Result<Settings> {
 
     if let Some(concat_choice_str) = &cli.concatenator {
         match concat_choice_str.as_str() {
+            "auto" => {
+                debug!("Overriding processing.concatenator from CLI: auto");
+                settings.processing.concatenator = ConcatenatorChoice::Auto;
+            },
             "ffmpeg" => {
                 debug!("Overriding processing.concatenator from CLI: ffmpeg");
                 settings.processing.concatenator = ConcatenatorChoice::Ffmpeg;
@@ -64,6 +190,18 @@ pub fn load_settings_with_cli_overrides(cli: &Cli) -> Result<Settings> {
                 debug!("Overriding processing.concatenator from CLI: mkvmerge");
                 settings.processing.concatenator = ConcatenatorChoice::Mkvmerge;
             },
+            "native" => {
+                debug!("Overriding processing.concatenator from CLI: native");
+                settings.processing.concatenator = ConcatenatorChoice::Native;
+            },
+            "hls" => {
+                debug!("Overriding processing.concatenator from CLI: hls");
+                settings.processing.concatenator = ConcatenatorChoice::Hls;
+            },
+            "dash" => {
+                debug!("Overriding processing.concatenator from CLI: dash");
+                settings.processing.concatenator = ConcatenatorChoice::Dash;
+            },
             _ => {
                 // This case should not be reached due to clap's value_parser
                 warn!(
@@ -74,6 +212,87 @@ pub fn load_settings_with_cli_overrides(cli: &Cli) -> Result<Settings> {
         }
     }
 
+    if let Some(action) = &cli.sync_check {
+        debug!(
+            "Overriding processing.sync_check.action from CLI: {}",
+            action
+        );
+        settings.processing.sync_check.action = match action.as_str() {
+            "off" => SyncAction::Off,
+            "fail" => SyncAction::Fail,
+            "fix" => SyncAction::Fix,
+            _ => SyncAction::Report,
+        };
+    }
+
+    if let Some(transport) = &cli.transport {
+        debug!("Overriding processing.transport from CLI: {}", transport);
+        settings.processing.transport = match transport.as_str() {
+            "ffv1" => ChunkTransport::Ffv1,
+            "y4m" => ChunkTransport::Y4m,
+            _ => ChunkTransport::Source,
+        };
+    }
+    if let Some(tolerance) = cli.sync_tolerance {
+        debug!(
+            "Overriding processing.sync_check.tolerance from CLI: {}",
+            tolerance
+        );
+        settings.processing.sync_check.tolerance = tolerance;
+    }
+
+    if !cli.range.is_empty() {
+        settings.processing.ranges = cli
+            .range
+            .iter()
+            .map(|range| TimeRange::parse(range))
+            .collect::<Result<_, _>>()?;
+        debug!(
+            "Overriding processing.ranges from CLI: {:?}",
+            settings.processing.ranges
+        );
+    } else if cli.start.is_some() || cli.end.is_some() {
+        let start = cli.start.as_deref().map(parse_time).transpose()?.unwrap_or(0.0);
+        let end = cli.end.as_deref().map(parse_time).transpose()?;
+        settings.processing.ranges = vec![TimeRange::new(start, end)];
+        debug!(
+            "Overriding processing.ranges from CLI: {:?}",
+            settings.processing.ranges
+        );
+    }
+
+    if let Some(zones_file) = &cli.zones {
+        settings.zones = Settings::zones_from_file(zones_file)
+            .map_err(|e| anyhow::anyhow!("Failed to load zones from {:?}: {}", zones_file, e))?;
+        debug!(
+            "Overriding zones from {:?}: {:?}",
+            zones_file, settings.zones
+        );
+    }
+
+    if !cli.attachments.is_empty() {
+        settings.attachments = cli
+            .attachments
+            .iter()
+            .map(|attachment| AttachmentSource::parse(attachment))
+            .collect::<Result<_, _>>()?;
+        debug!(
+            "Overriding attachments from CLI: {:?}",
+            settings.attachments
+        );
+    }
+
+    if let Some(size) = &cli.target_size {
+        let bytes = parse_size(size)?;
+        // Keeps the tolerance of the config file
+        settings.processing.target_size.get_or_insert(TargetSize::new(bytes)).bytes = bytes;
+        debug!(
+            "Overriding processing.target_size from CLI: {} bytes",
+            bytes
+        );
+    }
+    settings.validate()?;
+
     if !cli.nodes.is_empty() && cli.slots.is_empty() {
         return Err(anyhow::anyhow!(
             "If --nodes are provided via CLI, --slots must also be provided."
@@ -90,3 +309,37 @@ pub fn load_settings_with_cli_overrides(cli: &Cli) -> Result<Settings> {
     debug!("Final client settings: {:?}", settings);
     Ok(settings)
 }
+
+/// Splits an argument string on whitespace like a shell would, keeping single
+/// or double quoted parts (e.g. filter graphs with spaces) together.
+pub fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut in_argument = false;
+    let mut quote: Option<char> = None;
+
+    for c in arguments.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '\'' || c == '"' => {
+                quote = Some(c);
+                in_argument = true;
+            },
+            None if c.is_whitespace() => {
+                if in_argument {
+                    split.push(std::mem::take(&mut current));
+                    in_argument = false;
+                }
+            },
+            None => {
+                current.push(c);
+                in_argument = true;
+            },
+        }
+    }
+    if in_argument {
+        split.push(current);
+    }
+    split
+}
@@ -0,0 +1,340 @@
+/// Daemon mode: the client stays up with its node connections and encodes
+/// the jobs submitted over a local HTTP API, which also reports and cancels
+/// them.
+use std::{
+    collections::HashMap,
+    future::{Future, IntoFuture},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use ferris_swarm_core::VideoEncodeError;
+use futures::{
+    future::{AbortHandle, Abortable},
+    stream::{FuturesUnordered, StreamExt},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+    net::TcpListener,
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        RwLock,
+    },
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    batch::{create_output_dir, OutputTemplate},
+    job::Job,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DaemonJobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl DaemonJobStatus {
+    /// Whether the job still has its output to write
+    pub fn is_active(&self) -> bool {
+        matches!(self, DaemonJobStatus::Queued | DaemonJobStatus::Running)
+    }
+}
+
+/// A submitted job as the API reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonJob {
+    pub id:          Uuid,
+    pub input_file:  PathBuf,
+    pub output_file: PathBuf,
+    pub status:      DaemonJobStatus,
+    /// Why the job failed
+    pub error:       Option<String>,
+}
+
+/// Body of a job submission. Without an output, the daemon's output
+/// template names it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobSubmission {
+    pub input_file:  PathBuf,
+    pub output_file: Option<PathBuf>,
+}
+
+/// Jobs of the daemon, shared by the API and the loop running them
+#[derive(Debug, Clone)]
+pub struct DaemonState {
+    /// Every job submitted, in submission order
+    jobs:     Arc<RwLock<Vec<DaemonJob>>>,
+    /// Cancels the running jobs
+    running:  Arc<RwLock<HashMap<Uuid, AbortHandle>>>,
+    queue:    UnboundedSender<Uuid>,
+    template: Option<OutputTemplate>,
+}
+
+impl DaemonState {
+    /// The state and the queue of job ids `run_daemon` takes jobs from
+    pub fn new(template: Option<OutputTemplate>) -> (Self, UnboundedReceiver<Uuid>) {
+        let (queue, receiver) = mpsc::unbounded_channel();
+        let state = Self {
+            jobs: Arc::new(RwLock::new(Vec::new())),
+            running: Arc::new(RwLock::new(HashMap::new())),
+            queue,
+            template,
+        };
+        (state, receiver)
+    }
+
+    /// Queues a job, after checking that its input exists and that no
+    /// other active job writes its output
+    pub async fn submit(&self, submission: JobSubmission) -> Result<DaemonJob, VideoEncodeError> {
+        if !submission.input_file.is_file() {
+            return Err(VideoEncodeError::Config(format!(
+                "Input {:?} is not a file",
+                submission.input_file
+            )));
+        }
+        let output_file = match (submission.output_file, &self.template) {
+            (Some(output_file), _) => output_file,
+            (None, Some(template)) => template.output_for(&submission.input_file),
+            (None, None) => {
+                return Err(VideoEncodeError::Config(
+                    "The job has no output_file and the daemon has no --output-template"
+                        .to_string(),
+                ))
+            },
+        };
+        if output_file == submission.input_file {
+            return Err(VideoEncodeError::Config(format!(
+                "Output {:?} would overwrite the input",
+                output_file
+            )));
+        }
+
+        let mut jobs = self.jobs.write().await;
+        if jobs.iter().any(|job| job.status.is_active() && job.output_file == output_file) {
+            return Err(VideoEncodeError::Config(format!(
+                "Another queued or running job already writes {:?}",
+                output_file
+            )));
+        }
+        let job = DaemonJob {
+            id: Uuid::new_v4(),
+            input_file: submission.input_file,
+            output_file,
+            status: DaemonJobStatus::Queued,
+            error: None,
+        };
+        self.queue
+            .send(job.id)
+            .map_err(|_| VideoEncodeError::Config("The daemon is shutting down".to_string()))?;
+        jobs.push(job.clone());
+        info!(
+            "Queued job {}: {:?} -> {:?}",
+            job.id, job.input_file, job.output_file
+        );
+        Ok(job)
+    }
+
+    pub async fn job(&self, id: Uuid) -> Option<DaemonJob> {
+        self.jobs.read().await.iter().find(|job| job.id == id).cloned()
+    }
+
+    pub async fn jobs(&self) -> Vec<DaemonJob> {
+        self.jobs.read().await.clone()
+    }
+
+    /// Cancels a queued or running job; a finished one is left as it is.
+    /// A running job's future is dropped, which cancels its `JobHandle` and
+    /// so kills its ffmpeg processes. Returns the job afterwards, `None` if
+    /// there is no such job.
+    pub async fn cancel(&self, id: Uuid) -> Option<DaemonJob> {
+        let mut jobs = self.jobs.write().await;
+        let job = jobs.iter_mut().find(|job| job.id == id)?;
+        if job.status.is_active() {
+            if let Some(handle) = self.running.write().await.remove(&id) {
+                handle.abort();
+            }
+            job.status = DaemonJobStatus::Cancelled;
+            info!("Cancelled job {}", id);
+        }
+        Some(job.clone())
+    }
+
+    /// Marks a queued job as running, cancelled through `handle`. `None` if
+    /// it was cancelled while queued.
+    async fn start(&self, id: Uuid, handle: AbortHandle) -> Option<Job> {
+        let mut jobs = self.jobs.write().await;
+        let job = jobs
+            .iter_mut()
+            .find(|job| job.id == id && job.status == DaemonJobStatus::Queued)?;
+        job.status = DaemonJobStatus::Running;
+        self.running.write().await.insert(id, handle);
+        Some(Job {
+            input_file:  job.input_file.clone(),
+            output_file: job.output_file.clone(),
+        })
+    }
+
+    /// Records how a running job ended, `None` if it was cancelled. A job
+    /// cancelled as it finished stays cancelled.
+    async fn finish(&self, id: Uuid, result: Option<Result<()>>) {
+        self.running.write().await.remove(&id);
+        let mut jobs = self.jobs.write().await;
+        let Some(job) = jobs
+            .iter_mut()
+            .find(|job| job.id == id && job.status != DaemonJobStatus::Cancelled)
+        else {
+            return;
+        };
+        match result {
+            Some(Ok(())) => {
+                info!("Job {} finished: {:?}", id, job.output_file);
+                job.status = DaemonJobStatus::Completed;
+            },
+            Some(Err(e)) => {
+                error!("Job {} failed: {:#}", id, e);
+                job.status = DaemonJobStatus::Failed;
+                job.error = Some(format!("{:#}", e));
+            },
+            None => job.status = DaemonJobStatus::Cancelled,
+        }
+    }
+}
+
+async fn submit_job(
+    State(state): State<DaemonState>,
+    Json(submission): Json<JobSubmission>,
+) -> Result<(StatusCode, Json<DaemonJob>), (StatusCode, Json<serde_json::Value>)> {
+    match state.submit(submission).await {
+        Ok(job) => Ok((StatusCode::CREATED, Json(job))),
+        Err(e) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": e.to_string() })),
+        )),
+    }
+}
+
+async fn list_jobs(State(state): State<DaemonState>) -> Json<Vec<DaemonJob>> {
+    Json(state.jobs().await)
+}
+
+async fn get_job(
+    Path(id): Path<Uuid>,
+    State(state): State<DaemonState>,
+) -> Result<Json<DaemonJob>, StatusCode> {
+    state.job(id).await.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn cancel_job(
+    Path(id): Path<Uuid>,
+    State(state): State<DaemonState>,
+) -> Result<Json<DaemonJob>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(job) = state.cancel(id).await else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "No such job" })),
+        ));
+    };
+    match job.status {
+        DaemonJobStatus::Cancelled => Ok(Json(job)),
+        _ => Err((
+            StatusCode::CONFLICT,
+            Json(json!({ "error": "The job has already ended", "job": job })),
+        )),
+    }
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Routes of the job API:
+/// - `POST /jobs` with `{"input_file": ..., "output_file": ...}` queues a job
+/// - `GET /jobs` lists every job, `GET /jobs/:id` reports one
+/// - `DELETE /jobs/:id` cancels a queued or running job
+pub fn daemon_router(state: DaemonState) -> Router {
+    Router::new()
+        .route("/jobs", get(list_jobs).post(submit_job))
+        .route("/jobs/:id", get(get_job).delete(cancel_job))
+        .route("/health", get(health_check))
+        .with_state(state)
+}
+
+/// Serves the job API on `listener` and runs the queued jobs through
+/// `run_job`, up to `parallel_jobs` at once, until Ctrl-C. Running jobs are
+/// then finished; queued ones are dropped. Parallel jobs may send the same
+/// chunk to a node at once, which receives each request into files of its
+/// own.
+pub async fn run_daemon<F, Fut>(
+    listener: TcpListener,
+    state: DaemonState,
+    mut queue: UnboundedReceiver<Uuid>,
+    parallel_jobs: usize,
+    run_job: F,
+) -> Result<()>
+where
+    F: Fn(Job) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let address = listener.local_addr()?;
+    if !address.ip().is_loopback() {
+        warn!(
+            "The job API on {} has no authentication and is reachable from other machines",
+            address
+        );
+    }
+    info!("Job API listening on http://{}", address);
+    let mut server = Box::pin(axum::serve(listener, daemon_router(state.clone())).into_future());
+
+    let run_job = &run_job;
+    let mut running = FuturesUnordered::new();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        tokio::select! {
+            result = &mut server => {
+                return result.context("The job API server stopped");
+            },
+            _ = &mut interrupted => {
+                info!("Interrupted; finishing {} running jobs", running.len());
+                break;
+            },
+            Some((id, result)) = running.next(), if !running.is_empty() => {
+                state.finish(id, result).await;
+            },
+            Some(id) = queue.recv(), if running.len() < parallel_jobs => {
+                let (handle, registration) = AbortHandle::new_pair();
+                let Some(job) = state.start(id, handle).await else {
+                    continue;
+                };
+                info!("Starting job {}: {:?} -> {:?}", id, job.input_file, job.output_file);
+                running.push(async move {
+                    let encode = async move {
+                        create_output_dir(&job)?;
+                        run_job(job).await
+                    };
+                    (id, Abortable::new(encode, registration).await.ok())
+                });
+            },
+        }
+    }
+    drop(server);
+    while let Some((id, result)) = running.next().await {
+        state.finish(id, result).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,1806 @@
+/// Encoding jobs as a library: a `JobBuilder` takes the input, output,
+/// settings and nodes of a job and starts it, returning a `JobHandle` with
+/// progress events, cancellation and the final report.
+use std::{
+    collections::BTreeSet,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use ferris_swarm_config::{
+    job_config::{create_job_temp_config, JobTempConfig},
+    settings::{ConcatenatorChoice, Settings},
+};
+use ferris_swarm_core::{
+    allocate_bitrates,
+    chunk::Chunk,
+    parse_bitrate,
+    reencode_plan,
+    zones_covering,
+    AudioEncodeLocation,
+    AudioOptions,
+    ChunkTransport,
+    ColorMetadata,
+    EncoderProfile,
+    RateControl,
+    Rendition,
+    StreamKind,
+    StreamMetadata,
+    StreamTiming,
+    SyncAction,
+    SyncAudit,
+    SyncCheck,
+    TimeRange,
+    VideoEncodeError,
+};
+use ferris_swarm_orchestration::stream_video_segments;
+use ferris_swarm_video::{
+    audio::{encode_audio, extract_audio_streams},
+    chunk_encoder_arguments,
+    complexity::analyze_segments,
+    concatenator::{select_concatenator, Concatenator, MuxInputs},
+    encoder_backend as resolve_encoder_backend,
+    metadata::ContainerMetadata,
+    output_codec_family,
+    packager::{package_ffmpeg, PackageFormat, PackageVariant},
+    process::ProcessScope,
+    progressive::{ProgressiveFormat, ProgressiveWriter},
+    segmenter::{extract_container_metadata, extract_non_video_streams},
+    streams::{probe_color_metadata, probe_streams},
+    sync::{fix_sync_mkvmerge, probe_stream_timing},
+    utils::{frame_rate_value, probe_duration, probe_frame_rate, probe_keyframe_before},
+    validate_profile_for_node,
+};
+use futures::future::{self, FutureExt};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::{AbortHandle, JoinHandle},
+};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{
+    cli::Cli,
+    comms::{initialize_node_connections, send_audio_for_encoding, NodeConnection},
+    preview::{
+        estimate_encode_seconds,
+        preview_file_path,
+        select_preview_segments,
+        PreviewChunk,
+        PreviewOptions,
+        PreviewOutput,
+        PreviewReport,
+    },
+    report::{AudioReport, JobReport, RenditionReport, SizeReport, SyncReport, ZoneReport},
+    tasks::{encode_chunk_stream_on_nodes, encode_chunks_on_nodes, AbortOnDrop, ChunkProgress},
+};
+
+/// One input to encode and the output it is encoded to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub input_file:  PathBuf,
+    pub output_file: PathBuf,
+}
+
+impl Job {
+    /// The single job of `--input-file`/`--output-file`
+    pub fn from_cli(cli: &Cli) -> Result<Self, VideoEncodeError> {
+        match (&cli.input_file, &cli.output_file) {
+            (Some(input_file), Some(output_file)) => Ok(Self {
+                input_file:  input_file.clone(),
+                output_file: PathBuf::from(output_file),
+            }),
+            _ => Err(VideoEncodeError::Config(
+                "--input-file and --output-file are needed unless --batch, --watch or --daemon is \
+                 used"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+/// Nodes a job encodes on
+#[derive(Clone)]
+pub enum NodeSource {
+    /// Connects to `addresses`, or to the settings' `node_addresses` when
+    /// empty. `slots` are the chunks each node encodes at once, one each
+    /// when empty.
+    Addresses {
+        addresses: Vec<String>,
+        slots:     Vec<usize>,
+    },
+    /// Connections already made, e.g. shared by the jobs of a batch
+    Connected(Vec<NodeConnection>),
+}
+
+impl Default for NodeSource {
+    fn default() -> Self {
+        NodeSource::Addresses {
+            addresses: Vec::new(),
+            slots:     Vec::new(),
+        }
+    }
+}
+
+impl NodeSource {
+    /// The nodes of `--nodes` and `--slots`
+    pub fn from_cli(cli: &Cli) -> Self {
+        NodeSource::Addresses {
+            addresses: cli.nodes.clone(),
+            slots:     cli.slots.clone(),
+        }
+    }
+
+    /// Connects to the nodes, failing if none could be reached
+    pub async fn connect(&self, settings: &Settings) -> Result<Vec<NodeConnection>> {
+        let (addresses, slots) = match self {
+            NodeSource::Connected(connections) if connections.is_empty() => {
+                return Err(anyhow::anyhow!(
+                    "No worker nodes available. Cannot proceed with distributed encoding."
+                ))
+            },
+            NodeSource::Connected(connections) => return Ok(connections.clone()),
+            NodeSource::Addresses {
+                addresses,
+                slots,
+            } => (addresses, slots),
+        };
+        let addresses = if addresses.is_empty() {
+            &settings.client.node_addresses
+        } else {
+            addresses
+        };
+        let slots = if !slots.is_empty() {
+            slots.clone()
+        } else {
+            vec![1; addresses.len()]
+        };
+
+        if addresses.is_empty() {
+            warn!("No nodes configured or specified. Encoding will not be distributed.");
+        }
+        let node_connections = initialize_node_connections(addresses, &slots)
+            .await
+            .context("Failed to initialize node connections")?;
+        if node_connections.is_empty() && !addresses.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Nodes were specified, but no connections could be established."
+            ));
+        } else if node_connections.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No worker nodes available. Cannot proceed with distributed encoding."
+            ));
+        }
+        Ok(node_connections)
+    }
+}
+
+/// Progress of a running job
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobEvent {
+    /// Segment `index` was split off the input and became `chunks` chunks,
+    /// one per output
+    SegmentReady { index: usize, chunks: usize },
+    /// The input is split: `chunks` chunks of `segments` segments are
+    /// encoded in total
+    Segmented { segments: usize, chunks: usize },
+    /// Chunks are sent to `nodes`, by address with the chunks each encodes
+    /// at once. The input's `frame_rate` turns encoded seconds into frames.
+    Dispatching {
+        nodes:      Vec<(String, usize)>,
+        frame_rate: Option<f64>,
+    },
+    /// Chunk `index` of `rendition` was sent to `node`
+    ChunkAssigned {
+        index:     usize,
+        rendition: Option<String>,
+        node:      String,
+    },
+    /// `node` returned chunk `index` of `rendition` encoded, `seconds` after
+    /// it was sent. The chunk plays for `duration` seconds, if known.
+    ChunkEncoded {
+        index:         usize,
+        rendition:     Option<String>,
+        node:          String,
+        seconds:       f64,
+        duration:      Option<f64>,
+        source_bytes:  u64,
+        encoded_bytes: u64,
+    },
+    /// Chunk `index` of `rendition` failed on `node` and is retried
+    ChunkFailed {
+        index:     usize,
+        rendition: Option<String>,
+        node:      String,
+        error:     String,
+    },
+    /// The encoded chunks are being joined into `output`
+    Muxing { output: PathBuf },
+}
+
+/// How a job ended
+#[derive(Debug)]
+pub enum JobOutcome {
+    /// The output was written, as the report tells
+    Encoded(Box<JobReport>),
+    /// A preview encoded a sample of the segments
+    Preview(Box<PreviewReport>),
+    /// The input made no chunks, so nothing was written
+    Empty,
+}
+
+/// Builds a job: what to encode, with which settings, on which nodes
+pub struct JobBuilder {
+    job:         Job,
+    settings:    Settings,
+    profile:     Option<String>,
+    nodes:       NodeSource,
+    preview:     Option<PreviewOptions>,
+    progressive: Option<PathBuf>,
+}
+
+impl JobBuilder {
+    pub fn new(input_file: impl Into<PathBuf>, output_file: impl Into<PathBuf>) -> Self {
+        Self::from_job(Job {
+            input_file:  input_file.into(),
+            output_file: output_file.into(),
+        })
+    }
+
+    pub fn from_job(job: Job) -> Self {
+        Self {
+            job,
+            settings: Settings::default(),
+            profile: None,
+            nodes: NodeSource::default(),
+            preview: None,
+            progressive: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Encodes with the settings' profile of this name instead of the one
+    /// they select
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    pub fn with_nodes(mut self, nodes: NodeSource) -> Self {
+        self.nodes = nodes;
+        self
+    }
+
+    /// Encodes only a sample of the segments and reports on them
+    pub fn with_preview(mut self, preview: Option<PreviewOptions>) -> Self {
+        self.preview = preview;
+        self
+    }
+
+    /// Also appends chunks to this growing file as they complete
+    pub fn with_progressive(mut self, progressive: Option<PathBuf>) -> Self {
+        self.progressive = progressive;
+        self
+    }
+
+    /// Checks the settings, connects to the nodes and starts encoding in the
+    /// background
+    pub async fn start(self) -> Result<JobHandle> {
+        let mut settings = self.settings;
+        if let Some(profile) = self.profile {
+            settings.client.profile = Some(profile);
+        }
+        settings.validate()?;
+        let node_connections = self.nodes.connect(&settings).await?;
+        let (events, receiver) = mpsc::unbounded_channel();
+        let job = self.job;
+        let preview = self.preview;
+        let progressive = self.progressive;
+        let job_temp_config = create_job_temp_config(
+            &settings,
+            &job.input_file,
+            &job.output_file.to_string_lossy(),
+        );
+        info!("Job temporary directory: {:?}", job_temp_config.base_dir);
+        let workspace = JobWorkspace {
+            temp_config: job_temp_config,
+            processes:   ProcessScope::default(),
+        };
+        let job_task = {
+            let workspace = workspace.clone();
+            AbortOnDrop(tokio::spawn(async move {
+                run_job(
+                    &settings,
+                    node_connections,
+                    &job,
+                    &workspace,
+                    preview.as_ref(),
+                    progressive.as_deref(),
+                    &events,
+                )
+                .await
+            }))
+        };
+        let cancel = CancelOnDrop {
+            processes: workspace.processes.clone(),
+            job:       job_task.0.abort_handle(),
+        };
+        let task = tokio::spawn(async move {
+            // Dropped with the workspace only when the job is cancelled or
+            // panics
+            let mut cleanup = CleanupOnCancel(Some(workspace));
+            match job_task.await {
+                Ok(result) => {
+                    cleanup.0 = None;
+                    result
+                },
+                Err(e) => {
+                    // Waits for the blocking work off the runtime's threads
+                    let _ = tokio::task::spawn_blocking(move || drop(cleanup)).await;
+                    if e.is_cancelled() {
+                        Err(anyhow::anyhow!("The job was cancelled"))
+                    } else {
+                        Err(anyhow::anyhow!("The job task panicked: {}", e))
+                    }
+                },
+            }
+        });
+        Ok(JobHandle {
+            events: receiver,
+            task,
+            cancel,
+        })
+    }
+}
+
+/// A job encoding in the background. Dropping the handle cancels it.
+pub struct JobHandle {
+    events: UnboundedReceiver<JobEvent>,
+    /// Ends once the job has, after a cancelled job's cleanup
+    task:   JoinHandle<Result<JobOutcome>>,
+    cancel: CancelOnDrop,
+}
+
+impl JobHandle {
+    /// The next progress event, `None` once the job has ended
+    pub async fn next_event(&mut self) -> Option<JobEvent> {
+        self.events.recv().await
+    }
+
+    /// Stops the job, including the chunks on the nodes and its ffmpeg
+    /// processes. Its temporary files are removed once those have exited;
+    /// `wait` then fails.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Waits for the job to end. Dropping the future cancels the job.
+    pub async fn wait(self) -> Result<JobOutcome> {
+        let JobHandle {
+            task,
+            cancel: _cancel,
+            ..
+        } = self;
+        match task.await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Err(anyhow::anyhow!("The job was cancelled")),
+            Err(e) => Err(anyhow::anyhow!("The job task panicked: {}", e)),
+        }
+    }
+}
+
+/// Cancels a job when its handle is dropped
+struct CancelOnDrop {
+    processes: ProcessScope,
+    job:       AbortHandle,
+}
+
+impl CancelOnDrop {
+    fn cancel(&self) {
+        // Killed first, so the blocking work returns instead of writing on
+        self.processes.cancel();
+        self.job.abort();
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if !self.job.is_finished() {
+            self.cancel();
+        }
+    }
+}
+
+/// The temporary files of a job and the processes writing them
+#[derive(Clone)]
+struct JobWorkspace {
+    temp_config: JobTempConfig,
+    processes:   ProcessScope,
+}
+
+/// Removes the temporary files of a cancelled job, once the processes and
+/// blocking work using them have stopped
+struct CleanupOnCancel(Option<JobWorkspace>);
+
+impl Drop for CleanupOnCancel {
+    fn drop(&mut self) {
+        if let Some(workspace) = self.0.take() {
+            workspace.processes.cancel();
+            workspace.processes.wait_idle();
+            info!(
+                "Job cancelled; removing {:?}",
+                workspace.temp_config.base_dir
+            );
+            workspace
+                .temp_config
+                .delete_job_temp_dirs()
+                .map_err(|e| warn!("Failed to clean up job temp dirs: {}", e))
+                .ok();
+        }
+    }
+}
+
+/// An output of the job: one rendition of the ladder, or the job itself when
+/// it has none
+struct JobOutput<'a> {
+    rendition:    Option<&'a Rendition>,
+    profile:      Option<EncoderProfile>,
+    path:         PathBuf,
+    /// Joins the encoded chunks into `path`, `None` when they are packaged
+    concatenator: Option<Arc<dyn Concatenator>>,
+}
+
+impl JobOutput<'_> {
+    fn rendition_name(&self) -> Option<&str> {
+        self.rendition.map(|rendition| rendition.name.as_str())
+    }
+}
+
+/// The streams muxed into every output, owned so the muxing can run on the
+/// job's blocking threads
+#[derive(Default)]
+struct MuxStreams {
+    non_video_stream_file: Option<PathBuf>,
+    audio_file:            Option<PathBuf>,
+    stream_metadata:       Vec<StreamMetadata>,
+    container_metadata:    Option<ContainerMetadata>,
+    color_metadata:        Option<ColorMetadata>,
+}
+
+impl MuxStreams {
+    fn mux_inputs(&self) -> MuxInputs<'_> {
+        MuxInputs {
+            non_video_stream_file: self.non_video_stream_file.as_deref(),
+            audio_file:            self.audio_file.as_deref(),
+            stream_metadata:       &self.stream_metadata,
+            container_metadata:    self.container_metadata.as_ref(),
+            color_metadata:        self.color_metadata.as_ref(),
+        }
+    }
+}
+
+/// Turns each segment into a chunk for every output as segments are split
+/// off the input, in playback order
+struct SegmentChunker<'a> {
+    settings:        &'a Settings,
+    job_outputs:     &'a [JobOutput<'a>],
+    zone_profiles:   Vec<Option<&'a EncoderProfile>>,
+    encoder_backend: &'a str,
+    color_metadata:  Option<&'a ColorMetadata>,
+    transport:       ChunkTransport,
+    /// Told about each segment as it becomes chunks
+    events:          &'a UnboundedSender<JobEvent>,
+    /// Whether zones need each segment's duration
+    timed_zones:     bool,
+    /// Start of the next segment on the output's timeline
+    position:        f64,
+    segments:        Vec<PathBuf>,
+    /// Indices of the zones covering each segment
+    zone_chunks:     Vec<Vec<usize>>,
+    chunk_count:     usize,
+    /// Source properties the encoders can't carry, from the first chunks
+    warnings:        Vec<String>,
+}
+
+impl SegmentChunker<'_> {
+    fn add_segment(&mut self, segment: PathBuf) -> Result<Vec<Chunk>> {
+        let index = self.segments.len();
+        let duration = if self.timed_zones {
+            probe_duration(&segment)?
+        } else {
+            0.0
+        };
+        let zones = zones_covering(
+            &self.settings.zones,
+            index,
+            (self.position, self.position + duration),
+        );
+        self.position += duration;
+
+        // Every output is encoded from the same segment: one chunk per
+        // rendition
+        let mut chunks = Vec::with_capacity(self.job_outputs.len());
+        for job_output in self.job_outputs {
+            let mut chunk = Chunk::new(
+                segment.clone(),
+                index,
+                self.settings.client.encoder_params.clone(),
+            )?
+            .with_pass_mode(self.settings.client.pass_mode)
+            .with_encoder_backend(self.encoder_backend)
+            .with_profile(job_output.profile.clone())
+            .with_color(self.color_metadata.cloned())
+            .with_transport(self.transport)
+            .with_rendition(job_output.rendition_name().map(str::to_string))
+            .with_video_filter(job_output.rendition.and_then(|r| r.filter.clone()));
+            for position in &zones {
+                chunk.apply_zone(
+                    &self.settings.zones[*position],
+                    self.zone_profiles[*position],
+                );
+            }
+            chunk.attachments = self
+                .settings
+                .attachments
+                .iter()
+                .filter_map(|attachment| attachment.for_chunk(index).transpose())
+                .collect::<Result<_, _>>()?;
+            // Placeholders without an attachment would only fail on the node
+            let (backend, _, unsupported) = chunk_encoder_arguments(&chunk)
+                .with_context(|| format!("Invalid encoder arguments for chunk {}", index))?;
+            // The first chunk of each output stands for the rest
+            if index == 0 && self.color_metadata.is_some() && !unsupported.is_empty() {
+                let message = format!(
+                    "Encoder backend '{}' can't carry the source's {}; {} won't signal it",
+                    backend.name(),
+                    unsupported.join(", "),
+                    chunk.rendition.as_ref().map_or("the output".to_string(), |name| format!(
+                        "rendition '{}'",
+                        name
+                    ))
+                );
+                warn!("{}", message);
+                self.warnings.push(message);
+            }
+            chunks.push(chunk);
+        }
+        debug!(
+            "Segment {} ready as {} chunks: {:?}",
+            index,
+            chunks.len(),
+            segment
+        );
+        self.segments.push(segment);
+        self.zone_chunks.push(zones);
+        self.chunk_count += chunks.len();
+        // Listening is optional
+        let _ = self.events.send(JobEvent::SegmentReady {
+            index,
+            chunks: chunks.len(),
+        });
+        Ok(chunks)
+    }
+}
+
+/// Encodes `job` on `node_connections`, which other jobs of a batch or the
+/// daemon may be using at the same time.
+#[instrument(skip_all, fields(input = ?job.input_file))]
+async fn run_job(
+    settings: &Settings,
+    mut node_connections: Vec<NodeConnection>,
+    job: &Job,
+    workspace: &JobWorkspace,
+    preview: Option<&PreviewOptions>,
+    progressive: Option<&Path>,
+    events: &UnboundedSender<JobEvent>,
+) -> Result<JobOutcome> {
+    let JobWorkspace {
+        temp_config: job_temp_config,
+        processes,
+    } = workspace;
+    let package_format = match settings.processing.concatenator {
+        ConcatenatorChoice::Hls => Some(PackageFormat::Hls),
+        ConcatenatorChoice::Dash => Some(PackageFormat::Dash),
+        _ => None,
+    };
+    Rendition::validate_ladder(&settings.renditions, &job.output_file)?;
+
+    let encoder_backend = settings.client.encoder_backend.as_str();
+    let encoder_profile = settings.selected_profile().context("Invalid encoder profile")?.cloned();
+    if let Some(profile) = &encoder_profile {
+        info!("Using encoder profile: {:?}", profile);
+    }
+    let target_size = settings.processing.target_size;
+    if target_size.is_some() && encoder_profile.is_none() {
+        return Err(anyhow::anyhow!(
+            "A target size needs an encoder profile to set each chunk's bitrate."
+        ));
+    }
+    let transport = settings.processing.transport;
+    match transport {
+        ChunkTransport::Source => {},
+        ChunkTransport::Ffv1 => info!(
+            "Sending chunks as lossless FFV1: nodes need no source decoder, but chunks are \
+             typically several times the size of the source."
+        ),
+        ChunkTransport::Y4m => info!(
+            "Sending chunks as raw y4m frames: nodes need no source decoder, but chunks are many \
+             times the size of the source even gzip compressed, and variable frame rate timing is \
+             lost."
+        ),
+    }
+    let output_file_path = job.output_file.clone();
+    let mut job_outputs: Vec<JobOutput> = if settings.renditions.is_empty() {
+        vec![JobOutput {
+            rendition:    None,
+            profile:      encoder_profile.clone(),
+            path:         output_file_path.clone(),
+            concatenator: None,
+        }]
+    } else {
+        settings
+            .renditions
+            .iter()
+            .map(|rendition| {
+                let profile = settings.rendition_profile(rendition)?.cloned();
+                info!(
+                    "Rendition '{}': filter {:?}, profile {:?}",
+                    rendition.name, rendition.filter, profile
+                );
+                Ok(JobOutput {
+                    rendition: Some(rendition),
+                    profile,
+                    path: rendition.output_path(&output_file_path),
+                    concatenator: None,
+                })
+            })
+            .collect::<Result<_, VideoEncodeError>>()
+            .context("Invalid rendition")?
+    };
+    // Check every output can be written before anything is encoded
+    if let Some(format) = package_format {
+        format
+            .validate_output(&output_file_path)
+            .with_context(|| format!("Can't write output {:?}", output_file_path))?;
+    } else {
+        let backend = resolve_encoder_backend(encoder_backend)?;
+        for job_output in &mut job_outputs {
+            let codec_family = output_codec_family(
+                backend.as_ref(),
+                job_output.profile.as_ref(),
+                &settings.client.encoder_params,
+            );
+            let concatenator = select_concatenator(
+                settings.processing.concatenator.concatenator_name(),
+                &job_output.path,
+                codec_family.as_deref(),
+            )
+            .with_context(|| format!("Can't write output {:?}", job_output.path))?;
+            info!(
+                "Using {} to concatenate {:?}.",
+                concatenator.name(),
+                job_output.path
+            );
+            job_output.concatenator = Some(Arc::from(concatenator));
+        }
+    }
+    // The progressive output shows the first output while it is encoded
+    if let Some(progressive_path) = progressive {
+        let progressive_format = ProgressiveFormat::from_path(progressive_path)?;
+        if progressive_path == output_file_path {
+            return Err(anyhow::anyhow!(
+                "The progressive output must be a file of its own, not the output file."
+            ));
+        }
+        let backend = resolve_encoder_backend(encoder_backend)?;
+        let codec_family = output_codec_family(
+            backend.as_ref(),
+            job_outputs[0].profile.as_ref(),
+            &settings.client.encoder_params,
+        );
+        if let Some(family) = codec_family.as_deref() {
+            progressive_format.check_codec(family)?;
+        }
+    }
+    node_connections.retain(|node| {
+        if !node.supports_backend(encoder_backend) {
+            warn!(
+                "Node {} does not offer encoder backend '{}'; it will not receive chunks.",
+                node.address, encoder_backend
+            );
+            return false;
+        }
+        for profile in job_outputs.iter().filter_map(|output| output.profile.as_ref()) {
+            if let Err(e) =
+                validate_profile_for_node(profile, encoder_backend, node.capabilities.as_ref())
+            {
+                warn!(
+                    "Node {} cannot encode the selected profile; it will not receive chunks: {}",
+                    node.address, e
+                );
+                return false;
+            }
+        }
+        true
+    });
+    if node_connections.is_empty() {
+        return Err(anyhow::anyhow!(
+            "None of the connected nodes can encode with backend '{}' and the selected profile.",
+            encoder_backend
+        ));
+    }
+
+    // Stream copied cuts begin on a keyframe; every stream is cut there
+    let ranges = {
+        let input_file = job.input_file.clone();
+        let ranges = settings.processing.ranges.clone();
+        spawn_job_blocking(processes, move || {
+            ranges
+                .iter()
+                .map(|range| {
+                    let start = probe_keyframe_before(&input_file, range.start)?;
+                    if start != range.start {
+                        info!(
+                            "Time range starting at {}s begins on the keyframe at {}s",
+                            range.start, start
+                        );
+                    }
+                    Ok(TimeRange::new(start, range.end))
+                })
+                .collect::<Result<Vec<_>, VideoEncodeError>>()
+        })
+        .await
+        .context("Keyframe probe task panicked")??
+    };
+
+    // Segments are handed over as ffmpeg finishes them, so encoding overlaps
+    // with splitting the input
+    info!("Splitting video into segments...");
+    let (segment_sender, mut segment_receiver) = mpsc::unbounded_channel();
+    let segmenter = {
+        let input_file = job.input_file.clone();
+        let segment_duration = settings.processing.segment_duration;
+        let segments_dir = job_temp_config.segments_dir();
+        let ranges = ranges.clone();
+        spawn_job_blocking(processes, move || {
+            stream_video_segments(
+                &input_file,
+                segment_duration,
+                &segments_dir,
+                &ranges,
+                |segment| {
+                    // The receiver is only gone once the job has failed
+                    let _ = segment_sender.send(segment.to_path_buf());
+                },
+            )
+        })
+    };
+
+    // The other streams are extracted meanwhile. A preview encodes a sample
+    // of the segments and no other streams.
+    let non_video_extraction = {
+        let input_file = job.input_file.clone();
+        let base_dir = job_temp_config.base_dir.clone();
+        let audio = settings.audio.clone();
+        let streams = settings.processing.streams.clone();
+        let ranges = ranges.clone();
+        let extract = preview.is_none();
+        spawn_job_blocking(processes, move || {
+            if !extract {
+                return Ok(None);
+            }
+            info!("Extracting non-video streams...");
+            extract_non_video_streams(&input_file, &base_dir, &audio, &streams, &ranges)
+        })
+    };
+    // Audio is encoded as a task of its own, next to the video chunks
+    let audio_extraction = {
+        let input_file = job.input_file.clone();
+        let base_dir = job_temp_config.base_dir.clone();
+        let audio = settings.audio.clone();
+        let streams = settings.processing.streams.clone();
+        let ranges = ranges.clone();
+        let extract = settings.audio.reencodes() && preview.is_none();
+        spawn_job_blocking(processes, move || {
+            if !extract {
+                return Ok(None);
+            }
+            info!("Extracting audio tracks for re-encoding...");
+            extract_audio_streams(&input_file, &base_dir, &audio, &streams, &ranges)
+        })
+    };
+    let mut non_video_streams = extracted_stream(non_video_extraction).boxed();
+    let mut audio_source = extracted_stream(audio_extraction).boxed();
+
+    let (container_metadata, color_metadata) = {
+        let input_file = job.input_file.clone();
+        let base_dir = job_temp_config.base_dir.clone();
+        let ranges = ranges.clone();
+        spawn_job_blocking(processes, move || {
+            let mut container_metadata = extract_container_metadata(&input_file, &base_dir)?;
+            if !ranges.is_empty() {
+                container_metadata.trim_to_ranges(&ranges, probe_duration(&input_file)?);
+            }
+            let color_metadata =
+                Some(probe_color_metadata(&input_file)?).filter(|color| !color.is_empty());
+            Ok::<_, VideoEncodeError>((container_metadata, color_metadata))
+        })
+        .await
+        .context("Metadata probe task panicked")??
+    };
+
+    let zone_profiles = settings
+        .zones
+        .iter()
+        .map(|zone| zone.profile.as_deref().map(|name| settings.profile(name)).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut chunker = SegmentChunker {
+        settings,
+        job_outputs: &job_outputs,
+        zone_profiles,
+        encoder_backend,
+        color_metadata: color_metadata.as_ref(),
+        transport,
+        events,
+        timed_zones: settings.zones.iter().any(|zone| zone.is_timed()),
+        position: 0.0,
+        segments: Vec::new(),
+        zone_chunks: Vec::new(),
+        chunk_count: 0,
+        warnings: Vec::new(),
+    };
+
+    // A preview and a target size look at every segment before dispatching
+    // any chunk
+    let streaming = preview.is_none() && target_size.is_none();
+    let mut initial_chunks = Vec::new();
+    let segmenter = if streaming {
+        Some(segmenter)
+    } else {
+        while let Some(segment) = segment_receiver.recv().await {
+            initial_chunks.extend(chunker.add_segment(segment)?);
+        }
+        segmentation_finished(segmenter).await?;
+        None
+    };
+    let video_segments = chunker.segments.clone();
+
+    let preview_segments = match preview {
+        Some(options) => {
+            let (durations, selected) = select_preview_segments(&video_segments, options)?;
+            info!(
+                "Previewing {} of {} segments ({:?} selection): {:?}",
+                selected.len(),
+                video_segments.len(),
+                options.selection,
+                selected
+            );
+            let selected_set: BTreeSet<usize> = selected.iter().copied().collect();
+            initial_chunks.retain(|chunk| selected_set.contains(&chunk.index));
+            Some((durations, selected))
+        },
+        None => None,
+    };
+    if !streaming {
+        let _ = events.send(JobEvent::Segmented {
+            segments: video_segments.len(),
+            chunks:   initial_chunks.len(),
+        });
+    }
+
+    // With a target size, each chunk gets a share of what the other streams
+    // leave of it, by complexity
+    let mut size_report = match target_size {
+        Some(target) => {
+            let segments = video_segments.clone();
+            let complexity_dir = job_temp_config.base_dir.join("complexity");
+            let (durations, complexity) = spawn_job_blocking(processes, move || {
+                let durations = segments
+                    .iter()
+                    .map(|segment| probe_duration(segment))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let complexity = analyze_segments(&segments, &complexity_dir)?;
+                Ok::<_, VideoEncodeError>((durations, complexity))
+            })
+            .await
+            .context("Complexity analysis task panicked")??;
+            let non_video_streams_path = non_video_streams.await?;
+            let audio_source_path = audio_source.await?;
+            let reserved_bytes = reserved_stream_bytes(
+                non_video_streams_path.as_deref(),
+                audio_source_path.as_deref(),
+                &settings.audio,
+                durations.iter().sum(),
+            )?;
+            non_video_streams = future::ready(Ok(non_video_streams_path)).boxed();
+            audio_source = future::ready(Ok(audio_source_path)).boxed();
+            let video_bytes = target.video_bytes(reserved_bytes)?;
+            let budgets = allocate_bitrates(video_bytes, &durations, &complexity);
+            for chunk in &mut initial_chunks {
+                set_chunk_bitrate(chunk, budgets[chunk.index].bitrate);
+            }
+            info!(
+                "Target size {} bytes: {} bytes for video after {} bytes of other streams.",
+                target.bytes, video_bytes, reserved_bytes
+            );
+            Some(SizeReport {
+                target,
+                reserved_bytes,
+                video_bytes,
+                output_bytes: 0,
+                reencoded_chunks: Vec::new(),
+                chunks: budgets,
+            })
+        },
+        None => None,
+    };
+
+    let audio_task = {
+        let audio_options = settings.audio.clone();
+        let audio_output_dir = job_temp_config.base_dir.clone();
+        let node_conn = node_connections[0].clone();
+        let processes = processes.clone();
+        AbortOnDrop(tokio::spawn(async move {
+            let Some(audio_source_path) = audio_source.await? else {
+                return Ok(None);
+            };
+            let encoded = match audio_options.location {
+                AudioEncodeLocation::Local => {
+                    info!("Encoding audio locally while nodes encode video.");
+                    spawn_job_blocking(&processes, move || {
+                        let encoded_path = audio_output_dir.join("encoded_audio.mka");
+                        let loudness =
+                            encode_audio(&audio_source_path, &encoded_path, &audio_options)?;
+                        Ok::<_, anyhow::Error>((encoded_path, loudness))
+                    })
+                    .await
+                    .context("Audio encoding task panicked")??
+                },
+                AudioEncodeLocation::Node => {
+                    info!("Sending audio to node {} for encoding.", node_conn.address);
+                    // Audio takes one of the node's slots like a video chunk
+                    let _permit = node_conn.semaphore.clone().acquire_owned().await?;
+                    send_audio_for_encoding(
+                        &audio_source_path,
+                        &audio_options,
+                        node_conn.client,
+                        &audio_output_dir,
+                    )
+                    .await?
+                },
+            };
+            Ok::<_, anyhow::Error>(Some(encoded))
+        }))
+    };
+
+    let cluster_slots: usize =
+        node_connections.iter().map(|node| node.semaphore.available_permits()).sum();
+    let encoding_started = Instant::now();
+    info!(
+        "Dispatching encoding tasks to {} connected nodes...",
+        node_connections.len()
+    );
+    // Encoded chunks are appended to the progressive output in index order
+    let (progressive_sender, progressive_task) = match progressive {
+        Some(progressive_path) => {
+            let mut writer = ProgressiveWriter::start(progressive_path)?;
+            let rendition = job_outputs[0].rendition_name().map(str::to_string);
+            let (sender, mut receiver) = mpsc::unbounded_channel::<Chunk>();
+            let task = spawn_job_blocking(processes, move || {
+                while let Some(chunk) = receiver.blocking_recv() {
+                    if chunk.rendition != rendition {
+                        continue;
+                    }
+                    if let Some(encoded_path) = chunk.encoded_path {
+                        writer.add(chunk.index, encoded_path)?;
+                    }
+                }
+                writer.finish()
+            });
+            (Some(sender), Some(task))
+        },
+        None => (None, None),
+    };
+    let frame_rate = {
+        let input_file = job.input_file.clone();
+        spawn_job_blocking(processes, move || probe_frame_rate(&input_file))
+            .await
+            .context("Frame rate probe task panicked")?
+            .ok()
+            .and_then(|frame_rate| frame_rate_value(&frame_rate))
+    };
+    // Listening is optional
+    let _ = events.send(JobEvent::Dispatching {
+        nodes: node_connections
+            .iter()
+            .map(|node| (node.address.clone(), node.semaphore.available_permits()))
+            .collect(),
+        frame_rate,
+    });
+    let (progress_sender, progress_receiver) = mpsc::unbounded_channel();
+    let progress_forwarder = AbortOnDrop(tokio::spawn(forward_progress(
+        progress_receiver,
+        events.clone(),
+        progressive_sender,
+        processes.clone(),
+    )));
+    let final_state = if streaming {
+        let (chunk_sender, chunk_receiver) = mpsc::unbounded_channel();
+        let produce_chunks = async {
+            // Dropped on return, which lets the nodes finish
+            let chunk_sender = chunk_sender;
+            while let Some(segment) = segment_receiver.recv().await {
+                for chunk in chunker.add_segment(segment)? {
+                    // The receiver outlives this producer
+                    let _ = chunk_sender.send(chunk);
+                }
+            }
+            if let Some(segmenter) = segmenter {
+                segmentation_finished(segmenter).await?;
+            }
+            let _ = events.send(JobEvent::Segmented {
+                segments: chunker.segments.len(),
+                chunks:   chunker.chunk_count,
+            });
+            Ok::<_, anyhow::Error>(())
+        };
+        let encode = async {
+            Ok(encode_chunk_stream_on_nodes(
+                &node_connections,
+                chunk_receiver,
+                job_temp_config.encoded_chunks_dir(),
+                Some(progress_sender),
+            )
+            .await)
+        };
+        // A failed segmentation drops the encoding, stopping the chunks on
+        // the nodes, rather than waiting for them
+        let ((), state) = tokio::try_join!(produce_chunks, encode)?;
+        state
+    } else {
+        encode_chunks_on_nodes(
+            &node_connections,
+            initial_chunks,
+            job_temp_config.encoded_chunks_dir(),
+            Some(progress_sender),
+        )
+        .await
+    };
+    // Ends once the encoding is done with the sender
+    if let Err(e) = progress_forwarder.await {
+        error!(
+            "The chunk progress forwarder failed (joined with error): {}",
+            e
+        );
+    }
+
+    let segment_count = chunker.segments.len();
+    let total_chunks_count = match &preview_segments {
+        Some((_, selected)) => selected.len() * job_outputs.len(),
+        None => chunker.chunk_count,
+    };
+    info!(
+        "Created {} chunks from {} video segments for {} outputs.",
+        total_chunks_count,
+        segment_count,
+        job_outputs.len()
+    );
+
+    if total_chunks_count == 0 {
+        warn!("No chunks were created from the video. Check video duration and segment settings.");
+        job_temp_config
+            .delete_job_temp_dirs()
+            .map_err(|e| warn!("Failed to clean up job temp dirs: {}", e))
+            .ok();
+        return Ok(JobOutcome::Empty);
+    }
+
+    let mut job_report = JobReport::new(&job.input_file, &job.output_file);
+    job_report.total_chunks = total_chunks_count;
+    job_report.color = color_metadata.clone();
+    job_report.ranges = ranges.clone();
+    job_report.zones = settings
+        .zones
+        .iter()
+        .enumerate()
+        .map(|(position, zone)| ZoneReport {
+            name:   zone.label(position),
+            zone:   zone.clone(),
+            chunks: (0..chunker.zone_chunks.len())
+                .filter(|index| chunker.zone_chunks[*index].contains(&position))
+                .collect(),
+        })
+        .collect();
+    job_report.warnings = std::mem::take(&mut chunker.warnings);
+    for zone in job_report.zones.iter().filter(|zone| zone.chunks.is_empty()) {
+        let message = format!("Zone '{}' covers no chunk", zone.name);
+        warn!("{}", message);
+        job_report.warnings.push(message);
+    }
+    if let (Some(task), Some(progressive_path)) = (progressive_task, progressive) {
+        // Only a convenience; the output is muxed regardless
+        match task.await.context("Progressive output task panicked")? {
+            Ok(chunks) => info!(
+                "Progressive output {:?} holds {} chunks.",
+                progressive_path, chunks
+            ),
+            Err(e) => {
+                let message = format!(
+                    "Progressive output {} stopped early: {}",
+                    progressive_path.display(),
+                    e
+                );
+                warn!("{}", message);
+                job_report.warnings.push(message);
+            },
+        }
+    }
+
+    // From here on the report is written even if the job fails, so what was
+    // measured, like the audio loudness, isn't lost with it
+    let finished: Result<Option<PreviewReport>> = async {
+        let encoded_audio_path = match audio_task.await.context("Audio encoding task panicked")? {
+            Ok(Some((path, loudness))) => {
+                info!("Audio encoded to {:?}", path);
+                job_report.audio = Some(AudioReport::new(&settings.audio, loudness));
+                Some(path)
+            },
+            Ok(None) => None,
+            Err(e) => {
+                error!("Audio encoding failed: {:#}", e);
+                return Err(e.context("Audio encoding failed"));
+            },
+        };
+        let non_video_streams_path = non_video_streams.await?;
+
+        if !final_state.pending_chunks.is_empty() {
+            warn!(
+                "{} chunks remain in pending state. Encoding may be incomplete.",
+                final_state.pending_chunks.len()
+            );
+            for chunk in &final_state.pending_chunks {
+                warn!(
+                    "Pending chunk: index {}, source {:?}",
+                    chunk.index, chunk.source_path
+                );
+            }
+        }
+
+        let mut successfully_encoded_chunks = final_state.completed_chunks;
+        successfully_encoded_chunks.sort_by_key(|chunk| chunk.index);
+        job_report.encoded_chunks = successfully_encoded_chunks.len();
+
+        info!(
+            "Total chunks processed: {}. Successfully encoded and received: {}.",
+            total_chunks_count,
+            successfully_encoded_chunks.len()
+        );
+
+        if successfully_encoded_chunks.len() != total_chunks_count {
+            error!(
+                "Encoding incomplete: Expected {} chunks, but only {} were successfully processed.",
+                total_chunks_count,
+                successfully_encoded_chunks.len()
+            );
+            return Err(anyhow::anyhow!(
+                "Encoding failed: Not all chunks were processed successfully."
+            ));
+        }
+
+        if let (Some(options), Some((durations, selected))) = (preview, &preview_segments) {
+            let elapsed = encoding_started.elapsed().as_secs_f64();
+            let total_duration = durations.iter().sum::<f64>();
+            let mut preview_report = PreviewReport {
+                input_file: job.input_file.clone(),
+                output_file: output_file_path.clone(),
+                selection: options.selection,
+                total_segments: segment_count,
+                sampled_segments: selected.clone(),
+                sampled_duration: selected.iter().map(|index| durations[*index]).sum(),
+                total_duration,
+                elapsed_seconds: elapsed,
+                estimated_total_seconds: estimate_encode_seconds(
+                    elapsed,
+                    total_chunks_count,
+                    segment_count * job_outputs.len(),
+                    cluster_slots,
+                ),
+                outputs: Vec::new(),
+            };
+            let backend = resolve_encoder_backend(encoder_backend)?;
+            for job_output in &job_outputs {
+                let chunks: Vec<_> = successfully_encoded_chunks
+                    .iter()
+                    .filter(|chunk| chunk.rendition.as_deref() == job_output.rendition_name())
+                    .collect();
+                let measured = chunks
+                    .iter()
+                    .map(|chunk| PreviewChunk::measure(chunk, durations[chunk.index]))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut output = PreviewOutput::new(
+                    job_output.rendition_name().map(str::to_string),
+                    measured,
+                    total_duration,
+                );
+                if let Some(preview_path) = &options.output {
+                    let path = preview_file_path(preview_path, job_output.rendition_name());
+                    let codec_family = output_codec_family(
+                        backend.as_ref(),
+                        job_output.profile.as_ref(),
+                        &settings.client.encoder_params,
+                    );
+                    let concatenator = select_concatenator(None, &path, codec_family.as_deref())
+                        .with_context(|| format!("Can't write preview {:?}", path))?;
+                    concatenate_output(
+                        processes,
+                        Arc::from(concatenator),
+                        chunks
+                            .iter()
+                            .map(|chunk| {
+                                chunk
+                                    .encoded_path
+                                    .clone()
+                                    .expect("Completed chunk must have an encoded_path")
+                            })
+                            .collect(),
+                        &Arc::new(MuxStreams::default()),
+                        &path,
+                        &job_temp_config.base_dir,
+                        chunks.len(),
+                    )
+                    .await?;
+                    info!("Preview written to {:?}", path);
+                    output.preview_file = Some(path);
+                }
+                info!(
+                    "Preview of {}: {} bytes for {:.1}s, about {} bytes in total, quality {:?}",
+                    job_output.rendition_name().unwrap_or("the output"),
+                    output.encoded_bytes,
+                    preview_report.sampled_duration,
+                    output.estimated_total_bytes,
+                    output.quality
+                );
+                preview_report.outputs.push(output);
+            }
+            info!(
+                "Preview took {:.1}s; the full encode should take about {:.0}s.",
+                elapsed, preview_report.estimated_total_seconds
+            );
+            preview_report.write()?;
+            return Ok(Some(preview_report));
+        }
+
+        let mux_streams = Arc::new(MuxStreams {
+            non_video_stream_file: non_video_streams_path,
+            audio_file:            encoded_audio_path,
+            stream_metadata:       settings.processing.streams.metadata.clone(),
+            container_metadata:    Some(container_metadata),
+            color_metadata:        color_metadata.clone(),
+        });
+        let sync_check = settings.processing.sync_check;
+        let source_timing = match sync_check.action {
+            SyncAction::Off => None,
+            _ if package_format.is_some() => {
+                info!("Packaged outputs are not audited for A/V sync.");
+                None
+            },
+            _ => {
+                let input_file = job.input_file.clone();
+                let timing =
+                    spawn_job_blocking(processes, move || probe_stream_timing(&input_file))
+                        .await
+                        .context("Timing probe task panicked")??;
+                Some(trimmed_timing(timing, &ranges))
+            },
+        };
+        let mut drifted_outputs = Vec::new();
+        let mut package_variants = Vec::new();
+        for job_output in &job_outputs {
+            let encoded_chunk_paths: Vec<PathBuf> = successfully_encoded_chunks
+                .iter()
+                .filter(|chunk| chunk.rendition.as_deref() == job_output.rendition_name())
+                .map(|chunk| {
+                    chunk.encoded_path.clone().expect("Completed chunk must have an encoded_path")
+                })
+                .collect();
+            if let Some(rendition) = job_output.rendition {
+                job_report.renditions.push(RenditionReport {
+                    name:           rendition.name.clone(),
+                    // Packaged renditions are variants of the one manifest
+                    output_file:    match package_format {
+                        Some(_) => output_file_path.clone(),
+                        None => job_output.path.clone(),
+                    },
+                    encoded_chunks: encoded_chunk_paths.len(),
+                });
+            }
+
+            match &job_output.concatenator {
+                Some(concatenator) => {
+                    let _ = events.send(JobEvent::Muxing {
+                        output: job_output.path.clone(),
+                    });
+                    info!(
+                        "Concatenating {} encoded chunks into {:?} using {}...",
+                        encoded_chunk_paths.len(),
+                        job_output.path,
+                        concatenator.name()
+                    );
+                    concatenate_output(
+                        processes,
+                        concatenator.clone(),
+                        encoded_chunk_paths,
+                        &mux_streams,
+                        &job_output.path,
+                        &job_temp_config.base_dir, // For list, chapter and tag files
+                        segment_count,
+                    )
+                    .await?;
+                    if let Some(size_report) = &mut size_report {
+                        reconcile_target_size(
+                            size_report,
+                            &mut successfully_encoded_chunks,
+                            &node_connections,
+                            &job_temp_config.encoded_chunks_dir(),
+                            |chunk_paths| {
+                                concatenate_output(
+                                    processes,
+                                    concatenator.clone(),
+                                    chunk_paths,
+                                    &mux_streams,
+                                    &job_output.path,
+                                    &job_temp_config.base_dir,
+                                    segment_count,
+                                )
+                            },
+                            &job_output.path,
+                        )
+                        .await?;
+                        if !size_report.met() {
+                            let message = format!(
+                                "{} is {} bytes, over its target of {} bytes",
+                                job_output.path.display(),
+                                size_report.output_bytes,
+                                size_report.target.bytes
+                            );
+                            warn!("{}", message);
+                            job_report.warnings.push(message);
+                        }
+                    }
+                    if let Some(source_timing) = &source_timing {
+                        let source_timing = source_timing.clone();
+                        let output_file = job_output.path.clone();
+                        let temp_dir = job_temp_config.base_dir.clone();
+                        let sync_report = spawn_job_blocking(processes, move || {
+                            audit_output(&source_timing, &output_file, sync_check, &temp_dir)
+                        })
+                        .await
+                        .context("Sync audit task panicked")??;
+                        for problem in &sync_report.problems {
+                            warn!("A/V sync of {:?}: {}", job_output.path, problem);
+                            job_report.warnings.push(format!(
+                                "{}: {}",
+                                job_output.path.display(),
+                                problem
+                            ));
+                        }
+                        if !sync_report.problems.is_empty() {
+                            drifted_outputs.push(job_output.path.clone());
+                        }
+                        job_report.sync.push(sync_report);
+                    }
+                },
+                None => {
+                    package_variants.push(PackageVariant {
+                        name:          job_output.rendition_name().unwrap_or("video").to_string(),
+                        segment_paths: encoded_chunk_paths,
+                    });
+                },
+            }
+        }
+        if let Some(format) = package_format {
+            let _ = events.send(JobEvent::Muxing {
+                output: output_file_path.clone(),
+            });
+            info!(
+                "Packaging {} variants as {:?} into {:?}...",
+                package_variants.len(),
+                format,
+                output_file_path
+            );
+            let output_file = output_file_path.clone();
+            let temp_dir = job_temp_config.base_dir.clone(); // For the concat list files
+            let segment_duration = settings.processing.segment_duration;
+            spawn_job_blocking(processes, move || {
+                package_ffmpeg(
+                    format,
+                    &package_variants,
+                    &mux_streams.mux_inputs(),
+                    &output_file,
+                    &temp_dir,
+                    segment_count,
+                    segment_duration,
+                )
+            })
+            .await
+            .context("Packaging task panicked")??;
+        }
+
+        job_report.size = size_report;
+
+        if sync_check.action == SyncAction::Fail && !drifted_outputs.is_empty() {
+            return Err(anyhow::anyhow!(
+                "A/V sync check failed for {:?}; see the job report for details.",
+                drifted_outputs
+            ));
+        }
+
+        Ok(None)
+    }
+    .await;
+
+    let outcome = match finished {
+        Ok(Some(preview_report)) => Ok(JobOutcome::Preview(Box::new(preview_report))),
+        Ok(None) => {
+            info!(
+                "Video encoding completed successfully. Output: {}",
+                job.output_file.display()
+            );
+            if let Err(e) = job_report.write() {
+                warn!("Failed to write job report: {:#}", e);
+            }
+            Ok(JobOutcome::Encoded(Box::new(job_report)))
+        },
+        Err(e) => {
+            // A preview has a report of its own
+            if preview.is_none() {
+                job_report.error = Some(format!("{:#}", e));
+                if let Err(e) = job_report.write() {
+                    warn!("Failed to write job report: {:#}", e);
+                }
+            }
+            Err(e)
+        },
+    };
+    job_temp_config
+        .delete_job_temp_dirs()
+        .map_err(|e| warn!("Failed to clean up job temporary directories: {}", e))
+        .ok();
+    outcome
+}
+
+/// Runs `work` on the blocking threads within the job's `processes`, so
+/// cancelling the job kills the ffmpeg processes it starts
+fn spawn_job_blocking<T, F>(processes: &ProcessScope, work: F) -> JoinHandle<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let processes = processes.clone();
+    tokio::task::spawn_blocking(move || processes.run(work))
+}
+
+/// Joins `segment_paths` into `output_file` on the job's blocking threads
+async fn concatenate_output(
+    processes: &ProcessScope,
+    concatenator: Arc<dyn Concatenator>,
+    segment_paths: Vec<PathBuf>,
+    mux_streams: &Arc<MuxStreams>,
+    output_file: &Path,
+    temp_dir: &Path,
+    expected_segments: usize,
+) -> Result<()> {
+    let mux_streams = mux_streams.clone();
+    let output_file = output_file.to_path_buf();
+    let temp_dir = temp_dir.to_path_buf();
+    spawn_job_blocking(processes, move || {
+        concatenator.concatenate(
+            segment_paths,
+            &mux_streams.mux_inputs(),
+            &output_file,
+            &temp_dir,
+            expected_segments,
+        )
+    })
+    .await
+    .context("Concatenation task panicked")??;
+    Ok(())
+}
+
+/// Turns the progress of the chunks on the nodes into job events, and hands
+/// encoded chunks on to the progressive output
+async fn forward_progress(
+    mut progress: UnboundedReceiver<ChunkProgress>,
+    events: UnboundedSender<JobEvent>,
+    progressive_sender: Option<UnboundedSender<Chunk>>,
+    processes: ProcessScope,
+) {
+    let file_size = |path: &Path| fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+    while let Some(update) = progress.recv().await {
+        let event = match update {
+            ChunkProgress::Assigned {
+                index,
+                rendition,
+                node,
+            } => JobEvent::ChunkAssigned {
+                index,
+                rendition,
+                node,
+            },
+            ChunkProgress::Failed {
+                index,
+                rendition,
+                node,
+                error,
+            } => JobEvent::ChunkFailed {
+                index,
+                rendition,
+                node,
+                error,
+            },
+            ChunkProgress::Encoded {
+                chunk,
+                node,
+                seconds,
+            } => {
+                let encoded_path = chunk.encoded_path.clone().unwrap_or_default();
+                let duration = {
+                    let encoded_path = encoded_path.clone();
+                    spawn_job_blocking(&processes, move || probe_duration(&encoded_path).ok())
+                        .await
+                        .ok()
+                        .flatten()
+                };
+                let event = JobEvent::ChunkEncoded {
+                    index: chunk.index,
+                    rendition: chunk.rendition.clone(),
+                    node,
+                    seconds,
+                    duration,
+                    source_bytes: file_size(&chunk.source_path),
+                    encoded_bytes: file_size(&encoded_path),
+                };
+                if let Some(sender) = &progressive_sender {
+                    let _ = sender.send(*chunk);
+                }
+                event
+            },
+        };
+        // Listening is optional
+        let _ = events.send(event);
+    }
+}
+
+/// Waits for the blocking segmentation task to end, failing if it did
+async fn segmentation_finished(
+    segmenter: JoinHandle<Result<Vec<PathBuf>, VideoEncodeError>>,
+) -> Result<()> {
+    segmenter
+        .await
+        .context("Segmentation task panicked")?
+        .context("Failed to split video into segments")?;
+    Ok(())
+}
+
+/// Path a blocking stream extraction task has extracted to, if any
+async fn extracted_stream(
+    task: JoinHandle<Result<Option<PathBuf>, VideoEncodeError>>,
+) -> Result<Option<PathBuf>> {
+    Ok(task.await.context("Stream extraction task panicked")??)
+}
+
+/// Bytes the streams other than video are expected to take in the output:
+/// the stream copied ones as extracted, re-encoded audio at each track's
+/// bitrate, or as extracted when a track has none.
+fn reserved_stream_bytes(
+    non_video_streams: Option<&Path>,
+    audio_source: Option<&Path>,
+    audio: &AudioOptions,
+    duration: f64,
+) -> Result<u64> {
+    let file_size = |path: Option<&Path>| -> Result<u64> {
+        Ok(match path {
+            Some(path) => fs::metadata(path)?.len(),
+            None => 0,
+        })
+    };
+    let audio_bytes = match audio_source {
+        Some(audio_source) if audio.bitrate.is_some() => {
+            let tracks = probe_streams(audio_source)?
+                .iter()
+                .filter(|stream| stream.kind == StreamKind::Audio)
+                .count();
+            audio
+                .track_bitrates(tracks)
+                .into_iter()
+                .map(|bitrate| {
+                    bitrate
+                        .and_then(parse_bitrate)
+                        .map(|bits| (bits as f64 * duration / 8.0) as u64)
+                })
+                .sum::<Option<u64>>()
+                .map_or_else(|| file_size(Some(audio_source)), Ok)?
+        },
+        audio_source => file_size(audio_source)?,
+    };
+    Ok(file_size(non_video_streams)? + audio_bytes)
+}
+
+/// Has `chunk` encoded at `bitrate` kbit/s
+fn set_chunk_bitrate(chunk: &mut Chunk, bitrate: u32) {
+    if let Some(profile) = &mut chunk.profile {
+        profile.rate_control = RateControl::Bitrate(bitrate);
+    }
+}
+
+/// Measures the concatenated output against its target size. When it
+/// overshoots, the chunks furthest over their budget are encoded again at a
+/// lower bitrate and the output is concatenated anew, once. If any of them
+/// fails, the output is left as it was.
+async fn reconcile_target_size<F, Fut>(
+    size_report: &mut SizeReport,
+    encoded_chunks: &mut [Chunk],
+    node_connections: &[NodeConnection],
+    encoded_chunks_dir: &Path,
+    concatenate: F,
+    output_file: &Path,
+) -> Result<()>
+where
+    F: Fn(Vec<PathBuf>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    size_report.output_bytes = fs::metadata(output_file)?.len();
+    let Some(overshoot) = size_report.output_bytes.checked_sub(size_report.target.bytes) else {
+        info!(
+            "{:?} is {} bytes, within its target of {} bytes.",
+            output_file, size_report.output_bytes, size_report.target.bytes
+        );
+        return Ok(());
+    };
+    let overshoot = overshoot.max(1);
+
+    let chunk_bytes = |chunks: &[Chunk], index: usize| -> Result<u64> {
+        let chunk = chunks
+            .iter()
+            .find(|chunk| chunk.index == index)
+            .context("Target size budget for a chunk that wasn't encoded")?;
+        let encoded_path =
+            chunk.encoded_path.as_ref().expect("Completed chunk must have an encoded_path");
+        Ok(fs::metadata(encoded_path)?.len())
+    };
+    let actual_bytes = size_report
+        .chunks
+        .iter()
+        .map(|budget| chunk_bytes(encoded_chunks, budget.index))
+        .collect::<Result<Vec<_>>>()?;
+    let plan = reencode_plan(
+        &size_report.chunks,
+        &actual_bytes,
+        overshoot,
+        size_report.target.tolerance,
+    );
+    warn!(
+        "{:?} is {} bytes over its target; re-encoding {} chunks at a lower bitrate.",
+        output_file,
+        overshoot,
+        plan.len()
+    );
+
+    let reencode: Vec<Chunk> = plan
+        .iter()
+        .filter_map(|(index, bitrate)| {
+            let mut chunk = encoded_chunks.iter().find(|chunk| chunk.index == *index)?.clone();
+            chunk.encoded_path = None;
+            set_chunk_bitrate(&mut chunk, *bitrate);
+            Some(chunk)
+        })
+        .collect();
+    let reencode_count = reencode.len();
+    // The first encodes stay in place until every chunk has a replacement
+    let reencoded_dir = encoded_chunks_dir.join("target_size");
+    fs::create_dir_all(&reencoded_dir)
+        .with_context(|| format!("Failed to create {:?}", reencoded_dir))?;
+    let state = encode_chunks_on_nodes(node_connections, reencode, reencoded_dir, None).await;
+    if state.completed_chunks.len() != reencode_count {
+        warn!(
+            "Re-encoding for the target size failed: {} of {} chunks were encoded. {:?} is kept \
+             as it is.",
+            state.completed_chunks.len(),
+            reencode_count,
+            output_file
+        );
+        return Ok(());
+    }
+    for reencoded in state.completed_chunks {
+        if let Some(budget) = size_report.chunks.iter_mut().find(|b| b.index == reencoded.index) {
+            if let Some(RateControl::Bitrate(bitrate)) =
+                reencoded.profile.as_ref().map(|profile| profile.rate_control)
+            {
+                budget.bitrate = bitrate;
+            }
+        }
+        size_report.reencoded_chunks.push(reencoded.index);
+        if let Some(chunk) = encoded_chunks.iter_mut().find(|chunk| chunk.index == reencoded.index)
+        {
+            *chunk = reencoded;
+        }
+    }
+    size_report.reencoded_chunks.sort_unstable();
+
+    concatenate(
+        encoded_chunks
+            .iter()
+            .map(|chunk| {
+                chunk.encoded_path.clone().expect("Completed chunk must have an encoded_path")
+            })
+            .collect(),
+    )
+    .await?;
+    size_report.output_bytes = fs::metadata(output_file)?.len();
+    info!(
+        "{:?} is {} bytes after re-encoding, target {} bytes.",
+        output_file, size_report.output_bytes, size_report.target.bytes
+    );
+    Ok(())
+}
+
+/// Source timing as the output should have it when only `ranges` of the
+/// source are encoded: every stream starts at zero and lasts as long as the
+/// ranges together. Frame counts are unknown.
+fn trimmed_timing(mut timing: Vec<StreamTiming>, ranges: &[TimeRange]) -> Vec<StreamTiming> {
+    if ranges.is_empty() {
+        return timing;
+    }
+    let source_duration = timing
+        .iter()
+        .find(|stream| stream.kind == StreamKind::Video)
+        .and_then(|video| video.duration);
+    let duration = ranges.iter().try_fold(0.0, |total, range| {
+        let end = range.end.or(source_duration)?.min(source_duration.unwrap_or(f64::INFINITY));
+        Some(total + (end - range.start).max(0.0))
+    });
+    for stream in &mut timing {
+        stream.start_time = Some(0.0);
+        stream.duration = duration;
+        stream.frames = None;
+    }
+    timing
+}
+
+/// Compares the timing of a concatenated output against the source. With
+/// `SyncAction::Fix`, drift is corrected by retiming the video track and the
+/// output is audited again.
+fn audit_output(
+    source_timing: &[StreamTiming],
+    output_file: &Path,
+    sync_check: SyncCheck,
+    temp_dir: &Path,
+) -> Result<SyncReport> {
+    let mut audit = SyncAudit::from_timing(source_timing, &probe_stream_timing(output_file)?);
+    let mut problems = audit.problems(sync_check.tolerance);
+    let mut fixed = false;
+    if sync_check.action == SyncAction::Fix && !problems.is_empty() {
+        match fix_sync_mkvmerge(output_file, &audit, sync_check.tolerance, temp_dir) {
+            Ok(true) => {
+                fixed = true;
+                audit = SyncAudit::from_timing(source_timing, &probe_stream_timing(output_file)?);
+                problems = audit.problems(sync_check.tolerance);
+            },
+            Ok(false) => {},
+            Err(e) => warn!("Could not fix the timing of {:?}: {}", output_file, e),
+        }
+    }
+    Ok(SyncReport {
+        output_file: output_file.to_path_buf(),
+        audit,
+        problems,
+        fixed,
+    })
+}
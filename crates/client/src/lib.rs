@@ -1,9 +1,21 @@
+pub mod batch;
 pub mod cli;
 pub mod comms;
 pub mod config;
+pub mod daemon;
+pub mod job;
+pub mod preview;
+pub mod progress;
+pub mod report;
 pub mod tasks;
 
+pub use batch::*;
 pub use cli::*;
 pub use comms::*;
 pub use config::*;
+pub use daemon::*;
+pub use job::*;
+pub use preview::*;
+pub use progress::*;
+pub use report::*;
 pub use tasks::*;
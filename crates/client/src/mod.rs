@@ -1,4 +1,10 @@
+pub mod batch;
 pub mod cli;
 pub mod comms;
 pub mod config;
+pub mod daemon;
+pub mod job;
+pub mod preview;
+pub mod progress;
+pub mod report;
 pub mod tasks;
@@ -0,0 +1,249 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use ferris_swarm_core::{chunk::Chunk, QualityMetrics, VideoEncodeError};
+use ferris_swarm_video::{quality::measure_quality, utils::probe_duration};
+use serde::Serialize;
+use tracing::info;
+
+use crate::cli::Cli;
+
+/// How a preview picks the segments it encodes
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewSelection {
+    /// Evenly spaced over the input
+    Even,
+    /// Highest source bitrate, the hardest to encode
+    Complex,
+}
+
+/// A preview encodes a few segments instead of the whole input
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewOptions {
+    pub chunks:    usize,
+    pub selection: PreviewSelection,
+    /// Where the sampled chunks are joined into a short preview
+    pub output:    Option<PathBuf>,
+}
+
+impl PreviewOptions {
+    /// Preview requested on the command line, `None` for a full encode.
+    pub fn from_cli(cli: &Cli) -> Result<Option<Self>, VideoEncodeError> {
+        let Some(chunks) = cli.preview else {
+            return Ok(None);
+        };
+        if chunks == 0 {
+            return Err(VideoEncodeError::Config(
+                "A preview needs at least one chunk".to_string(),
+            ));
+        }
+        let selection = match cli.preview_select.as_deref() {
+            None | Some("even") => PreviewSelection::Even,
+            Some("complex") => PreviewSelection::Complex,
+            Some(other) => {
+                return Err(VideoEncodeError::Config(format!(
+                    "Unknown preview selection '{}'",
+                    other
+                )))
+            },
+        };
+        Ok(Some(Self {
+            chunks,
+            selection,
+            output: cli.preview_output.clone(),
+        }))
+    }
+}
+
+/// `count` indices spread evenly over `0..total`, each in the middle of its
+/// share. All of them when `count` covers `total`.
+pub fn evenly_spaced(count: usize, total: usize) -> Vec<usize> {
+    if count >= total {
+        return (0..total).collect();
+    }
+    (0..count).map(|i| (2 * i + 1) * total / (2 * count)).collect()
+}
+
+/// Indices of the `count` highest `complexity` values, in index order
+pub fn most_complex(complexity: &[f64], count: usize) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..complexity.len()).collect();
+    indices.sort_by(|a, b| complexity[*b].total_cmp(&complexity[*a]));
+    indices.truncate(count);
+    indices.sort_unstable();
+    indices
+}
+
+/// Duration of every segment, and the indices of the segments to preview.
+/// Complexity is the stream copied segment's bitrate, which needs no decode.
+pub fn select_preview_segments(
+    segments: &[PathBuf],
+    options: &PreviewOptions,
+) -> Result<(Vec<f64>, Vec<usize>), VideoEncodeError> {
+    let durations = segments
+        .iter()
+        .map(|segment| probe_duration(segment))
+        .collect::<Result<Vec<_>, _>>()?;
+    let selected = match options.selection {
+        PreviewSelection::Even => evenly_spaced(options.chunks, segments.len()),
+        PreviewSelection::Complex => {
+            let complexity = segments
+                .iter()
+                .zip(&durations)
+                .map(|(segment, duration)| {
+                    let bytes = fs::metadata(segment)?.len() as f64;
+                    Ok(if *duration > 0.0 {
+                        bytes * 8.0 / duration
+                    } else {
+                        0.0
+                    })
+                })
+                .collect::<Result<Vec<_>, VideoEncodeError>>()?;
+            most_complex(&complexity, options.chunks)
+        },
+    };
+    Ok((durations, selected))
+}
+
+/// Scales what `sampled_duration` seconds produced up to `total_duration`
+pub fn extrapolate(sampled: f64, sampled_duration: f64, total_duration: f64) -> f64 {
+    if sampled_duration <= 0.0 {
+        return 0.0;
+    }
+    sampled * total_duration / sampled_duration
+}
+
+/// Wall time of a full encode, from the time the preview took. Chunks run in
+/// waves of `slots` at a time.
+pub fn estimate_encode_seconds(
+    elapsed: f64,
+    sampled_chunks: usize,
+    total_chunks: usize,
+    slots: usize,
+) -> f64 {
+    let waves = |chunks: usize| chunks.div_ceil(slots.max(1)).max(1) as f64;
+    elapsed * waves(total_chunks) / waves(sampled_chunks)
+}
+
+/// Preview file of a rendition: `preview.mkv` becomes `preview.720p.mkv`
+pub fn preview_file_path(path: &Path, rendition: Option<&str>) -> PathBuf {
+    let Some(rendition) = rendition else {
+        return path.to_path_buf();
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, rendition, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, rendition),
+    };
+    path.with_file_name(file_name)
+}
+
+/// One encoded sample chunk
+#[derive(Debug, Serialize)]
+pub struct PreviewChunk {
+    pub index:           usize,
+    pub source_duration: f64,
+    pub encoded_bytes:   u64,
+    pub quality:         QualityMetrics,
+}
+
+impl PreviewChunk {
+    /// Measures the encoded `chunk` against its source segment.
+    pub fn measure(chunk: &Chunk, source_duration: f64) -> Result<Self> {
+        let encoded_path = chunk
+            .encoded_path
+            .as_deref()
+            .context("Completed chunk must have an encoded_path")?;
+        let encoded_bytes = fs::metadata(encoded_path)
+            .with_context(|| format!("Failed to read encoded chunk {:?}", encoded_path))?
+            .len();
+        let quality = measure_quality(
+            encoded_path,
+            &chunk.source_path,
+            chunk.video_filter.as_deref(),
+        )
+        .with_context(|| format!("Could not measure the quality of chunk {}", chunk.index))?;
+        Ok(Self {
+            index: chunk.index,
+            source_duration,
+            encoded_bytes,
+            quality,
+        })
+    }
+}
+
+/// Samples of one output and what they predict for the full encode
+#[derive(Debug, Serialize)]
+pub struct PreviewOutput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendition:             Option<String>,
+    pub encoded_bytes:         u64,
+    pub estimated_total_bytes: u64,
+    /// Mean over the sampled chunks
+    pub quality:               QualityMetrics,
+    pub chunks:                Vec<PreviewChunk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview_file:          Option<PathBuf>,
+}
+
+impl PreviewOutput {
+    pub fn new(rendition: Option<String>, chunks: Vec<PreviewChunk>, total_duration: f64) -> Self {
+        let encoded_bytes = chunks.iter().map(|chunk| chunk.encoded_bytes).sum::<u64>();
+        let sampled_duration = chunks.iter().map(|chunk| chunk.source_duration).sum::<f64>();
+        let quality =
+            QualityMetrics::mean(&chunks.iter().map(|chunk| chunk.quality).collect::<Vec<_>>());
+        Self {
+            rendition,
+            encoded_bytes,
+            estimated_total_bytes: extrapolate(
+                encoded_bytes as f64,
+                sampled_duration,
+                total_duration,
+            )
+            .round() as u64,
+            quality,
+            chunks,
+            preview_file: None,
+        }
+    }
+}
+
+/// Summary of a preview, written next to the output as
+/// `<output>.preview.json`.
+#[derive(Debug, Serialize)]
+pub struct PreviewReport {
+    pub input_file:              PathBuf,
+    pub output_file:             PathBuf,
+    pub selection:               PreviewSelection,
+    pub total_segments:          usize,
+    pub sampled_segments:        Vec<usize>,
+    pub sampled_duration:        f64,
+    pub total_duration:          f64,
+    /// Seconds the sampled chunks took to encode across the cluster
+    pub elapsed_seconds:         f64,
+    pub estimated_total_seconds: f64,
+    pub outputs:                 Vec<PreviewOutput>,
+}
+
+impl PreviewReport {
+    /// Path the preview report for `output_file` is written to.
+    pub fn report_path(output_file: &Path) -> PathBuf {
+        let mut path = output_file.as_os_str().to_owned();
+        path.push(".preview.json");
+        PathBuf::from(path)
+    }
+
+    /// Writes the report next to the output file and returns its path.
+    pub fn write(&self) -> Result<PathBuf> {
+        let path = Self::report_path(&self.output_file);
+        let json =
+            serde_json::to_string_pretty(self).context("Failed to serialize preview report")?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write preview report to {:?}", path))?;
+        info!("Preview report written to {:?}", path);
+        Ok(path)
+    }
+}
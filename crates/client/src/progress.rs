@@ -0,0 +1,378 @@
+/// Live progress of a job: overall progress with ETA, throughput, retries and
+/// the chunks each node is encoding, drawn as terminal bars or logged
+/// periodically when stdout is not a terminal.
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use tracing::info;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::job::{JobEvent, JobHandle};
+
+/// How often the bars are redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+/// How often progress is logged without bars
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Chunks a node is encoding and has encoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeProgress {
+    pub address: String,
+    pub slots:   usize,
+    /// Indices of the chunks on the node now, with their rendition
+    pub active:  Vec<(usize, Option<String>)>,
+    pub encoded: usize,
+    pub failed:  usize,
+}
+
+impl NodeProgress {
+    fn new(address: String, slots: usize) -> Self {
+        Self {
+            address,
+            slots,
+            active: Vec::new(),
+            encoded: 0,
+            failed: 0,
+        }
+    }
+
+    fn finished(&mut self, index: usize, rendition: &Option<String>) {
+        if let Some(position) = self
+            .active
+            .iter()
+            .position(|active| active.0 == index && &active.1 == rendition)
+        {
+            self.active.remove(position);
+        }
+    }
+
+    pub fn line(&self) -> String {
+        let active: Vec<String> = self.active.iter().map(|(index, _)| index.to_string()).collect();
+        format!(
+            "{}: {}/{} slots busy [{}], {} encoded, {} failed",
+            self.address,
+            self.active.len(),
+            self.slots,
+            active.join(", "),
+            self.encoded,
+            self.failed
+        )
+    }
+}
+
+/// Progress of a job, gathered from its events
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobProgress {
+    pub nodes:           Vec<NodeProgress>,
+    /// Chunks made so far; all of them once `segmented`
+    pub total_chunks:    usize,
+    pub segmented:       bool,
+    pub encoded_chunks:  usize,
+    /// Failed attempts, each chunk queued again
+    pub retries:         usize,
+    /// Playing time of the encoded chunks
+    pub encoded_seconds: f64,
+    pub source_bytes:    u64,
+    pub encoded_bytes:   u64,
+    frame_rate:          Option<f64>,
+    /// Output being muxed, once encoding is over
+    pub muxing:          Option<String>,
+}
+
+impl JobProgress {
+    pub fn apply(&mut self, event: &JobEvent) {
+        match event {
+            JobEvent::SegmentReady {
+                chunks, ..
+            } => {
+                if !self.segmented {
+                    self.total_chunks += chunks;
+                }
+            },
+            JobEvent::Segmented {
+                chunks, ..
+            } => {
+                self.total_chunks = *chunks;
+                self.segmented = true;
+            },
+            JobEvent::Dispatching {
+                nodes,
+                frame_rate,
+            } => {
+                self.nodes = nodes
+                    .iter()
+                    .map(|(address, slots)| NodeProgress::new(address.clone(), *slots))
+                    .collect();
+                self.frame_rate = *frame_rate;
+            },
+            JobEvent::ChunkAssigned {
+                index,
+                rendition,
+                node,
+            } => {
+                self.node(node).active.push((*index, rendition.clone()));
+            },
+            JobEvent::ChunkEncoded {
+                index,
+                rendition,
+                node,
+                duration,
+                source_bytes,
+                encoded_bytes,
+                ..
+            } => {
+                let node = self.node(node);
+                node.finished(*index, rendition);
+                node.encoded += 1;
+                self.encoded_chunks += 1;
+                self.encoded_seconds += duration.unwrap_or(0.0);
+                self.source_bytes += source_bytes;
+                self.encoded_bytes += encoded_bytes;
+            },
+            JobEvent::ChunkFailed {
+                index,
+                rendition,
+                node,
+                ..
+            } => {
+                let node = self.node(node);
+                node.finished(*index, rendition);
+                node.failed += 1;
+                self.retries += 1;
+            },
+            JobEvent::Muxing {
+                output,
+            } => self.muxing = Some(output.display().to_string()),
+        }
+    }
+
+    fn node(&mut self, address: &str) -> &mut NodeProgress {
+        let position = match self.nodes.iter().position(|node| node.address == address) {
+            Some(position) => position,
+            None => {
+                self.nodes.push(NodeProgress::new(address.to_string(), 0));
+                self.nodes.len() - 1
+            },
+        };
+        &mut self.nodes[position]
+    }
+
+    /// Frames encoded per second, when the frame rate is known
+    pub fn fps(&self, elapsed: f64) -> Option<f64> {
+        let frame_rate = self.frame_rate?;
+        (elapsed > 0.0).then(|| self.encoded_seconds * frame_rate / elapsed)
+    }
+
+    /// Megabytes of source encoded per second
+    pub fn megabytes_per_second(&self, elapsed: f64) -> f64 {
+        if elapsed > 0.0 {
+            self.source_bytes as f64 / 1_000_000.0 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Seconds left at the pace so far, once every chunk is known
+    pub fn eta(&self, elapsed: f64) -> Option<f64> {
+        if !self.segmented || self.encoded_chunks == 0 {
+            return None;
+        }
+        let left = self.total_chunks.saturating_sub(self.encoded_chunks);
+        Some(elapsed / self.encoded_chunks as f64 * left as f64)
+    }
+
+    /// One line about the whole job
+    pub fn summary(&self, elapsed: f64) -> String {
+        if let Some(output) = &self.muxing {
+            return format!("{} chunks encoded, muxing {}", self.encoded_chunks, output);
+        }
+        let total = match self.segmented {
+            true => self.total_chunks.to_string(),
+            false => format!("{}+", self.total_chunks),
+        };
+        let mut summary = format!("{}/{} chunks", self.encoded_chunks, total);
+        if self.segmented && self.total_chunks > 0 {
+            summary += &format!(
+                " ({:.0}%)",
+                self.encoded_chunks as f64 * 100.0 / self.total_chunks as f64
+            );
+        }
+        if let Some(fps) = self.fps(elapsed) {
+            summary += &format!(", {:.1} fps", fps);
+        }
+        summary += &format!(", {:.1} MB/s", self.megabytes_per_second(elapsed));
+        if self.retries > 0 {
+            summary += &format!(", {} retries", self.retries);
+        }
+        summary += &match self.eta(elapsed) {
+            Some(eta) => format!(", ETA {}", format_seconds(eta)),
+            None => ", ETA unknown".to_string(),
+        };
+        summary
+    }
+}
+
+/// `95` as `1m 35s`
+pub fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
+
+/// Shows a job's progress as bars, or as periodic log lines without them
+pub struct ProgressDisplay {
+    /// Names the job in log lines, which jobs running at once share
+    label:    String,
+    progress: JobProgress,
+    started:  Instant,
+    bars:     Option<Bars>,
+    logged:   Instant,
+}
+
+struct Bars {
+    multi:   MultiProgress,
+    overall: ProgressBar,
+    nodes:   Vec<ProgressBar>,
+}
+
+impl ProgressDisplay {
+    /// Draws bars in `multi`, or logs lines naming the job by `label` when
+    /// `None`
+    pub fn new(label: impl Into<String>, multi: Option<MultiProgress>) -> Self {
+        let bars = multi.map(|multi| {
+            let overall = multi.add(ProgressBar::new(0));
+            overall.set_style(
+                ProgressStyle::with_template("[{elapsed_precise}] [{bar:40}] {msg}")
+                    .expect("Valid progress template")
+                    .progress_chars("=> "),
+            );
+            Bars {
+                multi,
+                overall,
+                nodes: Vec::new(),
+            }
+        });
+        Self {
+            label: label.into(),
+            progress: JobProgress::default(),
+            started: Instant::now(),
+            bars,
+            logged: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, event: &JobEvent) {
+        self.progress.apply(event);
+        if let JobEvent::ChunkFailed {
+            index,
+            node,
+            error,
+            ..
+        } = event
+        {
+            if let Some(bars) = &self.bars {
+                bars.multi
+                    .println(format!("Chunk {} failed on {}: {}", index, node, error))
+                    .ok();
+            }
+        }
+    }
+
+    /// Redraws the bars, or logs if it is time to
+    pub fn tick(&mut self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        match &mut self.bars {
+            Some(bars) => {
+                bars.overall.set_length(self.progress.total_chunks as u64);
+                bars.overall.set_position(self.progress.encoded_chunks as u64);
+                bars.overall.set_message(self.progress.summary(elapsed));
+                while bars.nodes.len() < self.progress.nodes.len() {
+                    let bar = bars.multi.add(ProgressBar::new_spinner());
+                    bar.set_style(
+                        ProgressStyle::with_template("  {msg}").expect("Valid progress template"),
+                    );
+                    bars.nodes.push(bar);
+                }
+                for (bar, node) in bars.nodes.iter().zip(&self.progress.nodes) {
+                    bar.set_message(node.line());
+                }
+            },
+            None => {
+                if self.logged.elapsed() >= LOG_INTERVAL {
+                    self.logged = Instant::now();
+                    info!(
+                        "Progress of {}: {}",
+                        self.label,
+                        self.progress.summary(elapsed)
+                    );
+                }
+            },
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.tick();
+        if let Some(bars) = &self.bars {
+            for bar in &bars.nodes {
+                bar.finish_and_clear();
+            }
+            bars.overall.finish();
+        }
+    }
+}
+
+/// Shows the progress of `job`, named `label`, until it ends
+pub async fn watch_job(job: &mut JobHandle, label: &str, multi: Option<MultiProgress>) {
+    let mut display = ProgressDisplay::new(label, multi);
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+    loop {
+        tokio::select! {
+            event = job.next_event() => match event {
+                Some(event) => display.update(&event),
+                None => break,
+            },
+            _ = redraw.tick() => display.tick(),
+        }
+    }
+    display.finish();
+}
+
+/// Bars drawn to stdout, where the log lines go too
+pub fn progress_bars() -> MultiProgress {
+    MultiProgress::with_draw_target(ProgressDrawTarget::stdout())
+}
+
+/// Writes log lines to stdout above the bars of `MultiProgress`
+#[derive(Clone)]
+pub struct ProgressLogWriter(MultiProgress);
+
+impl ProgressLogWriter {
+    pub fn new(multi: MultiProgress) -> Self {
+        Self(multi)
+    }
+}
+
+impl Write for ProgressLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stdout().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for ProgressLogWriter {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
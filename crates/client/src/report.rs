@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ferris_swarm_core::{
+    AudioEncodeLocation,
+    AudioOptions,
+    ChunkBudget,
+    ColorMetadata,
+    LoudnessTarget,
+    SyncAudit,
+    TargetSize,
+    TimeRange,
+    TrackLoudness,
+    Zone,
+};
+use serde::Serialize;
+use tracing::info;
+
+/// Summary of an encoding job, written next to the output as
+/// `<output>.report.json`.
+#[derive(Debug, Serialize)]
+pub struct JobReport {
+    pub input_file:     PathBuf,
+    pub output_file:    PathBuf,
+    pub total_chunks:   usize,
+    pub encoded_chunks: usize,
+    /// Parts of the input that were encoded, starts moved to keyframes
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ranges:         Vec<TimeRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio:          Option<AudioReport>,
+    /// Color description and HDR10 metadata passed through from the source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color:          Option<ColorMetadata>,
+    /// Outputs of an ABR ladder, empty for a job with a single output
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub renditions:     Vec<RenditionReport>,
+    /// Timing audit of each concatenated output
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sync:           Vec<SyncReport>,
+    /// Output size against the target and the bitrate budget of each chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size:           Option<SizeReport>,
+    /// Encoder overrides and the chunks they were applied to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub zones:          Vec<ZoneReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings:       Vec<String>,
+    /// Why the job failed; the report is written either way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:          Option<String>,
+}
+
+/// One rendition of an ABR ladder
+#[derive(Debug, Serialize)]
+pub struct RenditionReport {
+    pub name:           String,
+    pub output_file:    PathBuf,
+    pub encoded_chunks: usize,
+}
+
+/// Timing of one output against the source after concatenation
+#[derive(Debug, Serialize)]
+pub struct SyncReport {
+    pub output_file: PathBuf,
+    pub audit:       SyncAudit,
+    /// Drift found beyond the tolerance
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems:    Vec<String>,
+    /// Whether the video track was retimed to correct the drift
+    pub fixed:       bool,
+}
+
+/// Chunks a zone's encoder overrides were applied to
+#[derive(Debug, Serialize)]
+pub struct ZoneReport {
+    pub name:   String,
+    pub zone:   Zone,
+    pub chunks: Vec<usize>,
+}
+
+/// Output size of a job with a target size
+#[derive(Debug, Serialize)]
+pub struct SizeReport {
+    pub target:           TargetSize,
+    /// Estimated bytes of the other streams, kept free of the video budget
+    pub reserved_bytes:   u64,
+    pub video_bytes:      u64,
+    pub output_bytes:     u64,
+    /// Chunks encoded a second time at a lower bitrate after the output
+    /// overshot the target
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reencoded_chunks: Vec<usize>,
+    pub chunks:           Vec<ChunkBudget>,
+}
+
+impl SizeReport {
+    /// Whether the output stayed within the target
+    pub fn met(&self) -> bool {
+        self.output_bytes <= self.target.bytes
+    }
+}
+
+/// How the audio of a job was re-encoded
+#[derive(Debug, Serialize)]
+pub struct AudioReport {
+    pub codec:           Option<String>,
+    pub bitrate:         Option<String>,
+    pub channel_layout:  Option<String>,
+    pub location:        AudioEncodeLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness_target: Option<LoudnessTarget>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub loudness:        Vec<TrackLoudness>,
+}
+
+impl AudioReport {
+    pub fn new(options: &AudioOptions, loudness: Vec<TrackLoudness>) -> Self {
+        Self {
+            codec: options.codec.clone(),
+            bitrate: options.bitrate.clone(),
+            channel_layout: options.channel_layout.clone(),
+            location: options.location,
+            loudness_target: options.loudness,
+            loudness,
+        }
+    }
+}
+
+impl JobReport {
+    pub fn new(input_file: &Path, output_file: &Path) -> Self {
+        Self {
+            input_file:     input_file.to_path_buf(),
+            output_file:    output_file.to_path_buf(),
+            total_chunks:   0,
+            encoded_chunks: 0,
+            ranges:         Vec::new(),
+            audio:          None,
+            color:          None,
+            renditions:     Vec::new(),
+            sync:           Vec::new(),
+            size:           None,
+            zones:          Vec::new(),
+            warnings:       Vec::new(),
+            error:          None,
+        }
+    }
+
+    /// Path the report for `output_file` is written to.
+    pub fn report_path(output_file: &Path) -> PathBuf {
+        let mut path = output_file.as_os_str().to_owned();
+        path.push(".report.json");
+        PathBuf::from(path)
+    }
+
+    /// Writes the report next to the output file and returns its path.
+    pub fn write(&self) -> Result<PathBuf> {
+        let path = Self::report_path(&self.output_file);
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize job report")?;
+        std::fs::write(&path, json)
+            .with_context(|| format!("Failed to write job report to {:?}", path))?;
+        info!("Job report written to {:?}", path);
+        Ok(path)
+    }
+}
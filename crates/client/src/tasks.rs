@@ -1,13 +1,69 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
 
 use anyhow::Result;
 use ferris_swarm_core::chunk::Chunk;
 use futures::stream::{FuturesUnordered, StreamExt};
-use tokio::sync::Mutex;
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        Mutex,
+        Notify,
+    },
+    task::{JoinError, JoinHandle},
+};
 use tracing::{debug, error, info, instrument};
 
 use super::comms::{send_chunk_for_encoding, NodeConnection};
 
+/// A spawned task that is aborted once its handle is dropped, so cancelling
+/// a job also stops the node work it spawned
+pub(crate) struct AbortOnDrop<T>(pub(crate) JoinHandle<T>);
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// What happened to a chunk on a node
+#[derive(Debug, Clone)]
+pub enum ChunkProgress {
+    /// Sent to `node` for encoding
+    Assigned {
+        index:     usize,
+        rendition: Option<String>,
+        node:      String,
+    },
+    /// Returned encoded by `node`, `seconds` after it was sent
+    Encoded {
+        chunk:   Box<Chunk>,
+        node:    String,
+        seconds: f64,
+    },
+    /// Failed on `node` and was queued again
+    Failed {
+        index:     usize,
+        rendition: Option<String>,
+        node:      String,
+        error:     String,
+    },
+}
+
 /// Manages the state of chunks during the encoding process.
 #[derive(Debug)]
 pub struct EncodingTaskState {
@@ -15,15 +71,119 @@ pub struct EncodingTaskState {
     pub completed_chunks: Vec<Chunk>, /* Chunks successfully encoded and saved locally
                                        * Chunks that failed could be moved to a separate list if
                                        * retry logic is complex */
+    /// More chunks are still to come, e.g. while the input is segmented
+    pub producing:        bool,
+    /// Wakes workers waiting for chunks while `producing`
+    pub chunks_added:     Arc<Notify>,
+    /// Told about every chunk as it is assigned, encoded or fails
+    pub progress_sender:  Option<UnboundedSender<ChunkProgress>>,
 }
 
 impl EncodingTaskState {
-    pub fn new(initial_chunks: Vec<Chunk>) -> Self {
+    /// Chunks are handed out earliest first, so the output fills in from the
+    /// start
+    pub fn new(mut initial_chunks: Vec<Chunk>) -> Self {
+        initial_chunks.sort_by_key(|chunk| std::cmp::Reverse(chunk.index));
         Self {
             pending_chunks:   initial_chunks,
             completed_chunks: Vec::new(),
+            producing:        false,
+            chunks_added:     Arc::new(Notify::new()),
+            progress_sender:  None,
+        }
+    }
+}
+
+/// Encodes `chunks` on `node_connections`, one worker per node, and returns
+/// the state once every worker has finished. The progress of each chunk is
+/// also sent to `progress_sender`, if any.
+pub async fn encode_chunks_on_nodes(
+    node_connections: &[NodeConnection],
+    chunks: Vec<Chunk>,
+    client_side_encoded_chunk_dir: PathBuf,
+    progress_sender: Option<UnboundedSender<ChunkProgress>>,
+) -> EncodingTaskState {
+    let mut state = EncodingTaskState::new(chunks);
+    state.progress_sender = progress_sender;
+    let task_state = Arc::new(Mutex::new(state));
+    run_node_workers(node_connections, &task_state, client_side_encoded_chunk_dir).await;
+    take_state(&task_state).await
+}
+
+/// Encodes chunks on `node_connections` as they arrive on `chunks`, so
+/// encoding starts before all of them exist. Returns the state once the
+/// sender is gone and every worker has finished.
+pub async fn encode_chunk_stream_on_nodes(
+    node_connections: &[NodeConnection],
+    mut chunks: UnboundedReceiver<Chunk>,
+    client_side_encoded_chunk_dir: PathBuf,
+    progress_sender: Option<UnboundedSender<ChunkProgress>>,
+) -> EncodingTaskState {
+    let mut state = EncodingTaskState::new(Vec::new());
+    state.producing = true;
+    state.progress_sender = progress_sender;
+    let task_state = Arc::new(Mutex::new(state));
+
+    let feeder_state = Arc::clone(&task_state);
+    let feeder = AbortOnDrop(tokio::spawn(async move {
+        while let Some(chunk) = chunks.recv().await {
+            debug!("Chunk {} queued for encoding", chunk.index);
+            let mut state_guard = feeder_state.lock().await;
+            state_guard.pending_chunks.insert(0, chunk); // Popped from the back
+            state_guard.chunks_added.notify_waiters();
+        }
+        let mut state_guard = feeder_state.lock().await;
+        state_guard.producing = false;
+        state_guard.chunks_added.notify_waiters();
+        debug!("All chunks queued for encoding");
+    }));
+
+    run_node_workers(node_connections, &task_state, client_side_encoded_chunk_dir).await;
+    // Without nodes, nothing waits for the last chunks otherwise
+    if let Err(e) = feeder.await {
+        error!("The chunk feeder task failed (joined with error): {}", e);
+    }
+    take_state(&task_state).await
+}
+
+/// Runs one worker per node on `task_state` until all of them are done
+async fn run_node_workers(
+    node_connections: &[NodeConnection],
+    task_state: &Arc<Mutex<EncodingTaskState>>,
+    client_side_encoded_chunk_dir: PathBuf,
+) {
+    let mut node_worker_handles: FuturesUnordered<_> = node_connections
+        .iter()
+        .map(|node_connection| {
+            AbortOnDrop(tokio::spawn(process_chunks_on_node_worker(
+                node_connection.clone(),
+                Arc::clone(task_state),
+                client_side_encoded_chunk_dir.clone(),
+            )))
+        })
+        .collect();
+
+    while let Some(result) = node_worker_handles.next().await {
+        if let Err(e) = result {
+            error!("A node worker task failed (joined with error): {}", e);
         }
     }
+    info!("All node workers have completed their processing loops.");
+}
+
+/// Takes the chunks out of `task_state` once every worker and its sub-tasks
+/// are done with it
+async fn take_state(task_state: &Mutex<EncodingTaskState>) -> EncodingTaskState {
+    let mut state = task_state.lock().await;
+    // Dropped, so whoever listens learns that no more chunks follow
+    state.progress_sender = None;
+    EncodingTaskState {
+        pending_chunks:   std::mem::take(&mut state.pending_chunks),
+        completed_chunks: std::mem::take(&mut state.completed_chunks),
+        producing:        state.producing,
+        chunks_added:     Arc::clone(&state.chunks_added),
+        progress_sender:  None,
+    }
 }
 
 /// Processes chunks on a given node, respecting its concurrency limit
@@ -42,18 +202,11 @@ pub async fn process_chunks_on_node_worker(
         // Try to acquire a permit to send a task to this node
         let permit = match node_connection.semaphore.clone().try_acquire_owned() {
             Ok(p) => p,
+            // Other jobs sharing the node hold every slot; wait for one
+            Err(_) if active_node_tasks.is_empty() => {
+                node_connection.semaphore.clone().acquire_owned().await?
+            },
             Err(_) => {
-                // Semaphore full or closed
-                if active_node_tasks.is_empty()
-                    && node_connection.semaphore.available_permits() == 0
-                {
-                    // If no active tasks for this node and semaphore is full (or closed),
-                    // this worker might be done if no more pending chunks globally.
-                    // However, the main loop should handle global pending chunks.
-                    // This worker yields if it can't get a permit.
-                    tokio::task::yield_now().await;
-                    continue;
-                }
                 // Wait for an active task on this node to complete to free up a slot
                 if let Some(task_result) = active_node_tasks.next().await {
                     if let Err(e) = task_result {
@@ -64,21 +217,36 @@ pub async fn process_chunks_on_node_worker(
                     }
                 }
                 // Try to acquire permit again after a task potentially finished
-                match node_connection.semaphore.clone().try_acquire_owned() {
-                    Ok(p) => p,
-                    Err(_) => {
-                        tokio::task::yield_now().await; // Yield if still can't get permit
-                        continue;
-                    },
-                }
+                continue;
             },
         };
 
-        let chunk_to_process = {
+        let (chunk_to_process, chunks_added) = {
             let mut state_guard = task_state.lock().await;
-            state_guard.pending_chunks.pop() // Get a chunk from the global
-                                             // pending list
+            // Get a chunk from the global pending list
+            let chunk = state_guard.pending_chunks.pop();
+            if let (Some(chunk), Some(sender)) = (&chunk, &state_guard.progress_sender) {
+                // Listening is optional
+                let _ = sender.send(ChunkProgress::Assigned {
+                    index:     chunk.index,
+                    rendition: chunk.rendition.clone(),
+                    node:      node_connection.address.clone(),
+                });
+            }
+            // Registered under the lock, so no chunk added after the check is missed
+            let chunks_added = (chunk.is_none() && state_guard.producing)
+                .then(|| Arc::clone(&state_guard.chunks_added).notified_owned());
+            (chunk, chunks_added)
         };
+        if let Some(notified) = chunks_added {
+            drop(permit);
+            debug!(
+                "Worker on node {} waiting for more chunks.",
+                node_connection.address
+            );
+            notified.await;
+            continue;
+        }
 
         match chunk_to_process {
             Some(current_chunk) => {
@@ -91,7 +259,8 @@ pub async fn process_chunks_on_node_worker(
                 let dir_clone = client_side_encoded_chunk_dir.clone();
                 let node_addr_clone = node_connection.address.clone();
 
-                active_node_tasks.push(tokio::spawn(async move {
+                active_node_tasks.push(AbortOnDrop(tokio::spawn(async move {
+                    let started = Instant::now();
                     let result =
                         send_chunk_for_encoding(current_chunk.clone(), node_client, &dir_clone)
                             .await;
@@ -104,6 +273,14 @@ pub async fn process_chunks_on_node_worker(
                                 "Chunk {} successfully processed by node {} and saved locally.",
                                 encoded_chunk.index, node_addr_clone,
                             );
+                            if let Some(sender) = &state_guard.progress_sender {
+                                // Listening is optional
+                                let _ = sender.send(ChunkProgress::Encoded {
+                                    chunk:   Box::new(encoded_chunk.clone()),
+                                    node:    node_addr_clone.clone(),
+                                    seconds: started.elapsed().as_secs_f64(),
+                                });
+                            }
                             state_guard.completed_chunks.push(encoded_chunk);
                         },
                         Err(e) => {
@@ -112,12 +289,20 @@ pub async fn process_chunks_on_node_worker(
                                  list.",
                                 current_chunk.index, node_addr_clone, e
                             );
+                            if let Some(sender) = &state_guard.progress_sender {
+                                let _ = sender.send(ChunkProgress::Failed {
+                                    index:     current_chunk.index,
+                                    rendition: current_chunk.rendition.clone(),
+                                    node:      node_addr_clone.clone(),
+                                    error:     format!("{:#}", e),
+                                });
+                            }
                             // Simple retry: add back to pending. More sophisticated retry needed
                             // for production.
                             state_guard.pending_chunks.push(current_chunk);
                         },
                     }
-                }));
+                })));
             },
             None => {
                 // No more chunks in the global pending list for this worker to pick up
@@ -1,30 +1,63 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use config::{Config, ConfigError, File};
+use ferris_swarm_core::{
+    AttachmentSource,
+    AudioOptions,
+    ChunkTransport,
+    EncoderProfile,
+    PassMode,
+    Rendition,
+    StreamSelection,
+    SyncCheck,
+    TargetSize,
+    TimeRange,
+    VideoEncodeError,
+    Zone,
+    DEFAULT_ENCODER_BACKEND,
+};
 use serde::Deserialize;
 use tracing::debug;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ClientSettings {
-    pub node_addresses: Vec<String>,
-    pub encoder_params: Vec<String>,
+    pub node_addresses:  Vec<String>,
+    pub encoder_params:  Vec<String>,
+    #[serde(default)] // Single pass unless requested
+    pub pass_mode: PassMode,
+    #[serde(default = "default_encoder_backend")]
+    pub encoder_backend: String,
+    /// Name of an entry in `[profiles]`. Takes precedence over
+    /// `encoder_params` when set.
+    #[serde(default)]
+    pub profile:         Option<String>,
+}
+
+fn default_encoder_backend() -> String {
+    DEFAULT_ENCODER_BACKEND.to_string()
 }
 
 impl Default for ClientSettings {
     fn default() -> Self {
         Self {
-            node_addresses: vec!["127.0.0.1:50051".to_string()],
-            encoder_params: vec![
+            node_addresses:  vec!["127.0.0.1:50051".to_string()],
+            encoder_params:  vec![
                 "-c:v".to_string(),
                 "libx264".to_string(),
                 "-crf".to_string(),
                 "23".to_string(),
             ],
+            pass_mode:       PassMode::default(),
+            encoder_backend: default_encoder_backend(),
+            profile:         None,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct NodeSettings {
     pub address:  String,
     pub temp_dir: PathBuf,
@@ -42,23 +75,59 @@ impl Default for NodeSettings {
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")] // Ensures "ffmpeg" or "mkvmerge" in config maps correctly
 pub enum ConcatenatorChoice {
+    /// The first installed concatenator that can write the output file
+    Auto,
     Ffmpeg,
     Mkvmerge,
+    /// Joins the raw video bitstreams into an IVF, OBU or Annex-B file
+    Native,
+    /// fMP4 segments with HLS playlists instead of a single file
+    Hls,
+    /// fMP4 segments with a DASH manifest instead of a single file
+    Dash,
 }
 
 impl Default for ConcatenatorChoice {
     fn default() -> Self {
-        ConcatenatorChoice::Ffmpeg // Default to ffmpeg if not specified in
-                                   // config
+        ConcatenatorChoice::Auto // Picked from the output extension if not
+                                 // specified in config
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl ConcatenatorChoice {
+    /// Name of the concatenator this choice forces, `None` for automatic
+    /// selection and for packaging
+    pub fn concatenator_name(&self) -> Option<&'static str> {
+        match self {
+            ConcatenatorChoice::Ffmpeg => Some("ffmpeg"),
+            ConcatenatorChoice::Mkvmerge => Some("mkvmerge"),
+            ConcatenatorChoice::Native => Some("native"),
+            ConcatenatorChoice::Auto | ConcatenatorChoice::Hls | ConcatenatorChoice::Dash => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct ProcessingSettings {
     pub segment_duration: f64,
     pub temp_dir:         PathBuf,
     #[serde(default)] // Uses ConcatenatorChoice::default() if missing from config
     pub concatenator: ConcatenatorChoice,
+    /// Which audio/subtitle streams reach the output and their metadata
+    #[serde(default)]
+    pub streams:          StreamSelection,
+    /// Duration and A/V drift audit of the concatenated output
+    #[serde(default)]
+    pub sync_check:       SyncCheck,
+    /// Parts of the input to encode, in order; all of it when empty
+    #[serde(default)]
+    pub ranges:           Vec<TimeRange>,
+    /// Hard cap on the output size; video bitrate is then budgeted per chunk
+    #[serde(default)]
+    pub target_size:      Option<TargetSize>,
+    /// What chunks are sent to the nodes as
+    #[serde(default)]
+    pub transport:        ChunkTransport,
 }
 
 impl Default for ProcessingSettings {
@@ -67,31 +136,192 @@ impl Default for ProcessingSettings {
             segment_duration: 10.0, // 10 seconds per segment
             temp_dir:         std::env::temp_dir().join("ferris_swarm_processing"),
             concatenator:     ConcatenatorChoice::default(),
+            streams:          StreamSelection::default(),
+            sync_check:       SyncCheck::default(),
+            ranges:           Vec::new(),
+            target_size:      None,
+            transport:        ChunkTransport::default(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
     #[serde(default)]
-    pub client:     ClientSettings,
+    pub client:      ClientSettings,
+    #[serde(default)]
+    pub node:        NodeSettings,
+    #[serde(default)]
+    pub processing:  ProcessingSettings,
+    /// Named encoder presets, e.g. `[profiles.av1-hq]`
     #[serde(default)]
-    pub node:       NodeSettings,
+    pub profiles:    HashMap<String, EncoderProfile>,
+    /// Audio track selection and re-encoding, stream copy by default
     #[serde(default)]
-    pub processing: ProcessingSettings,
+    pub audio:       AudioOptions,
+    /// ABR ladder, e.g. `[[renditions]]`. Each rendition is encoded from the
+    /// same segments into an output of its own; without any the job has a
+    /// single output.
+    #[serde(default)]
+    pub renditions:  Vec<Rendition>,
+    /// Encoder overrides for parts of the output, e.g. `[[zones]]`
+    #[serde(default)]
+    pub zones:       Vec<Zone>,
+    /// Side files sent along with chunks, e.g. `[[attachments]]`
+    #[serde(default)]
+    pub attachments: Vec<AttachmentSource>,
+}
+
+/// A zones file holds `[[zones]]` entries only
+#[derive(Debug, Deserialize)]
+struct ZonesFile {
+    #[serde(default)]
+    zones: Vec<Zone>,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            client:     ClientSettings::default(),
-            node:       NodeSettings::default(),
-            processing: ProcessingSettings::default(),
+            client:      ClientSettings::default(),
+            node:        NodeSettings::default(),
+            processing:  ProcessingSettings::default(),
+            profiles:    HashMap::new(),
+            audio:       AudioOptions::default(),
+            renditions:  Vec::new(),
+            zones:       Vec::new(),
+            attachments: Vec::new(),
         }
     }
 }
 
 impl Settings {
+    /// Returns the encoder profile selected by `client.profile`, if any.
+    pub fn selected_profile(&self) -> Result<Option<&EncoderProfile>, VideoEncodeError> {
+        match &self.client.profile {
+            Some(name) => self.profile(name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the encoder profile `rendition` is encoded with: its own if it
+    /// names one, otherwise the job's.
+    pub fn rendition_profile(
+        &self,
+        rendition: &Rendition,
+    ) -> Result<Option<&EncoderProfile>, VideoEncodeError> {
+        match &rendition.profile {
+            Some(name) => self.profile(name).map(Some),
+            None => self.selected_profile(),
+        }
+    }
+
+    /// Looks up and validates the `[profiles]` entry `name`.
+    pub fn profile(&self, name: &str) -> Result<&EncoderProfile, VideoEncodeError> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            let mut known: Vec<&String> = self.profiles.keys().collect();
+            known.sort();
+            VideoEncodeError::Config(format!(
+                "Encoder profile '{}' is not defined. Known profiles: {:?}",
+                name, known
+            ))
+        })?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// Checks the settings as a whole, once every override is applied. Both
+    /// the CLI and `JobBuilder::start` run this before a job starts.
+    pub fn validate(&self) -> Result<(), VideoEncodeError> {
+        self.audio.validate()?;
+        self.processing.streams.validate()?;
+        for rendition in &self.renditions {
+            self.rendition_profile(rendition)?;
+        }
+        let tolerance = self.processing.sync_check.tolerance;
+        if !tolerance.is_finite() || tolerance < 0.0 {
+            return Err(VideoEncodeError::Config(format!(
+                "Sync check tolerance must be a non-negative number of seconds, got {}",
+                tolerance
+            )));
+        }
+        TimeRange::validate_ranges(&self.processing.ranges)?;
+        self.validate_zones()?;
+        AttachmentSource::validate_all(&self.attachments)?;
+        if let Some(target_size) = &self.processing.target_size {
+            target_size.validate()?;
+            if !self.renditions.is_empty() {
+                return Err(VideoEncodeError::Config(
+                    "A target size applies to a single output and can't be combined with \
+                     renditions."
+                        .to_string(),
+                ));
+            }
+            if matches!(
+                self.processing.concatenator,
+                ConcatenatorChoice::Hls | ConcatenatorChoice::Dash
+            ) {
+                return Err(VideoEncodeError::Config(
+                    "A target size can't be combined with HLS or DASH packaging.".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the `[[zones]]` of a zones file.
+    pub fn zones_from_file(path: &Path) -> Result<Vec<Zone>, ConfigError> {
+        let config = Config::builder().add_source(File::from(path)).build()?;
+        Ok(config.try_deserialize::<ZonesFile>()?.zones)
+    }
+
+    /// Checks every zone, that the profiles zones name or override exist, and
+    /// that nothing a zone sets would be ignored.
+    pub fn validate_zones(&self) -> Result<(), VideoEncodeError> {
+        let output_profiles = match self.renditions.is_empty() {
+            true => vec![self.selected_profile()?.is_some()],
+            false => self
+                .renditions
+                .iter()
+                .map(|rendition| Ok(self.rendition_profile(rendition)?.is_some()))
+                .collect::<Result<Vec<_>, VideoEncodeError>>()?,
+        };
+        let outputs_have_profiles = output_profiles.iter().all(|has_profile| *has_profile);
+        let any_output_has_profile = output_profiles.iter().any(|has_profile| *has_profile);
+        for (position, zone) in self.zones.iter().enumerate() {
+            zone.validate(position)?;
+            // Chunks encoded with a profile take no raw encoder parameters
+            if !zone.encoder_params.is_empty() && (zone.profile.is_some() || any_output_has_profile)
+            {
+                return Err(VideoEncodeError::Config(format!(
+                    "Zone '{}' sets encoder_params, which chunks encoded with a profile ignore; \
+                     use extra_args instead",
+                    zone.label(position)
+                )));
+            }
+            if zone.rate_control.is_some() && self.processing.target_size.is_some() {
+                return Err(VideoEncodeError::Config(format!(
+                    "Zone '{}' sets rate_control, but the target size sets the bitrate of every \
+                     chunk",
+                    zone.label(position)
+                )));
+            }
+            match &zone.profile {
+                Some(name) => {
+                    self.profile(name)?;
+                },
+                None if zone.overrides_profile() && !outputs_have_profiles => {
+                    return Err(VideoEncodeError::Config(format!(
+                        "Zone '{}' overrides encoder profile settings, but the job has no \
+                         profile; use encoder_params instead",
+                        zone.label(position)
+                    )));
+                },
+                None => {},
+            }
+        }
+        Ok(())
+    }
+
     pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
         let config = Config::builder().add_source(File::from(path)).build()?;
         config.try_deserialize()
@@ -164,6 +164,7 @@ impl AutoRegister {
                             "h264".to_string(),
                             "hevc".to_string(),
                         ],
+                        supported_backends:    vec!["ffmpeg".to_string(), "svt-av1".to_string()],
                         cpu_cores:             16,
                         memory_gb:             32,
                     },
@@ -181,6 +182,7 @@ impl AutoRegister {
                     capabilities: NodeCapabilities {
                         max_concurrent_chunks: 4,
                         supported_encoders:    vec!["h264".to_string(), "hevc".to_string()],
+                        supported_backends:    vec!["ffmpeg".to_string()],
                         cpu_cores:             8,
                         memory_gb:             16,
                     },
@@ -202,6 +204,7 @@ impl AutoRegister {
                             "h264".to_string(),
                             "hevc".to_string(),
                         ],
+                        supported_backends:    vec!["ffmpeg".to_string()],
                         cpu_cores:             32,
                         memory_gb:             64,
                     },
@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VideoEncodeError;
+
+/// Side file sent along with a chunk, e.g. a film grain table or an x265
+/// qpfile. Encoder arguments refer to it as `{attachment:<name>}`, which the
+/// node replaces with the path it wrote the file to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChunkAttachment {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// A job's side file. `path` may contain `{index}`, replaced by the chunk
+/// index, for files that differ per chunk.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AttachmentSource {
+    pub name:   String,
+    pub path:   String,
+    /// Chunk indices to attach the file to, all chunks when empty
+    #[serde(default)]
+    pub chunks: Vec<usize>,
+}
+
+impl AttachmentSource {
+    /// Parses `name=path` as given on the command line
+    pub fn parse(attachment: &str) -> Result<Self, VideoEncodeError> {
+        let (name, path) = attachment.split_once('=').ok_or_else(|| {
+            VideoEncodeError::Config(format!(
+                "Attachment '{}' must be written as name=path",
+                attachment
+            ))
+        })?;
+        Ok(Self {
+            name:   name.trim().to_string(),
+            path:   path.trim().to_string(),
+            chunks: Vec::new(),
+        })
+    }
+
+    /// The attachment of chunk `index`, `None` if it isn't attached to it.
+    /// Fails when the file doesn't exist.
+    pub fn for_chunk(&self, index: usize) -> Result<Option<ChunkAttachment>, VideoEncodeError> {
+        if !self.chunks.is_empty() && !self.chunks.contains(&index) {
+            return Ok(None);
+        }
+        let path = PathBuf::from(self.path.replace("{index}", &index.to_string()));
+        if !path.is_file() {
+            return Err(VideoEncodeError::Config(format!(
+                "Attachment '{}' of chunk {} does not exist: {:?}",
+                self.name, index, path
+            )));
+        }
+        Ok(Some(ChunkAttachment {
+            name: self.name.clone(),
+            path,
+        }))
+    }
+
+    /// Checks that attachment names are unique and usable in file names.
+    pub fn validate_all(attachments: &[AttachmentSource]) -> Result<(), VideoEncodeError> {
+        for (i, attachment) in attachments.iter().enumerate() {
+            validate_attachment_name(&attachment.name)?;
+            if attachments[..i].iter().any(|other| other.name == attachment.name) {
+                return Err(VideoEncodeError::Config(format!(
+                    "Attachment name '{}' is used twice",
+                    attachment.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Attachment names end up in file names on the node
+pub fn validate_attachment_name(name: &str) -> Result<(), VideoEncodeError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(VideoEncodeError::Config(format!(
+            "Attachment name '{}' may only contain letters, digits, '-', '_' and '.'",
+            name
+        )))
+    }
+}
+
+/// Placeholder encoder arguments use for the attachment `name`
+pub fn attachment_placeholder(name: &str) -> String {
+    format!("{{attachment:{}}}", name)
+}
+
+/// Replaces every `{attachment:<name>}` in `argument` with the path of that
+/// attachment. Fails on a placeholder no attachment matches.
+pub fn substitute_attachments(
+    argument: &str,
+    attachments: &[ChunkAttachment],
+) -> Result<String, VideoEncodeError> {
+    const PREFIX: &str = "{attachment:";
+    let mut substituted = String::with_capacity(argument.len());
+    let mut rest = argument;
+    while let Some(start) = rest.find(PREFIX) {
+        let name_start = start + PREFIX.len();
+        let Some(length) = rest[name_start..].find('}') else {
+            break;
+        };
+        let name = &rest[name_start..name_start + length];
+        let attachment =
+            attachments.iter().find(|attachment| attachment.name == name).ok_or_else(|| {
+                VideoEncodeError::Config(format!(
+                    "Encoder argument '{}' refers to attachment '{}', which the chunk doesn't \
+                     carry",
+                    argument, name
+                ))
+            })?;
+        substituted.push_str(&rest[..start]);
+        substituted.push_str(&attachment.path.to_string_lossy());
+        rest = &rest[name_start + length + 1..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{budget::parse_bitrate, error::VideoEncodeError};
+
+/// Where the audio encode of a job runs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioEncodeLocation {
+    /// On the client, while the nodes encode video chunks
+    #[default]
+    Local,
+    /// On one of the nodes, sharing its slots with video chunks
+    Node,
+}
+
+/// Audio handling for a job. Without a codec, audio is stream copied.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AudioOptions {
+    /// Audio encoder as ffmpeg names it (e.g. `libopus`, `aac`)
+    #[serde(default)]
+    pub codec:          Option<String>,
+    /// Target bitrate in ffmpeg notation (e.g. `128k`), or one per encoded
+    /// track separated by commas (e.g. `192k,96k`). The last one applies to
+    /// any further tracks.
+    #[serde(default)]
+    pub bitrate:        Option<String>,
+    /// Output channel layout (e.g. `stereo`, `5.1`)
+    #[serde(default)]
+    pub channel_layout: Option<String>,
+    /// Source audio tracks to keep, counted among audio streams only. Empty
+    /// keeps all tracks.
+    #[serde(default)]
+    pub tracks:         Vec<usize>,
+    #[serde(default)]
+    pub location:       AudioEncodeLocation,
+    /// Two-pass EBU R128 loudness normalization, off unless set
+    #[serde(default)]
+    pub loudness:       Option<LoudnessTarget>,
+}
+
+impl AudioOptions {
+    /// Whether audio is re-encoded rather than stream copied
+    pub fn reencodes(&self) -> bool {
+        self.codec.as_deref().is_some_and(|codec| codec != "copy")
+    }
+
+    /// Bitrate of each of `tracks` encoded tracks, `None` when none is set
+    pub fn track_bitrates(&self, tracks: usize) -> Vec<Option<&str>> {
+        let bitrates: Vec<&str> = self
+            .bitrate
+            .as_deref()
+            .map(|bitrate| bitrate.split(',').map(str::trim).collect())
+            .unwrap_or_default();
+        (0..tracks)
+            .map(|track| bitrates.get(track).or(bitrates.last()).copied())
+            .collect()
+    }
+
+    /// Checks that the options can be applied together.
+    pub fn validate(&self) -> Result<(), VideoEncodeError> {
+        if let Some(bitrate) = &self.bitrate {
+            if let Some(invalid) =
+                bitrate.split(',').find(|bitrate| parse_bitrate(bitrate).is_none())
+            {
+                return Err(VideoEncodeError::Config(format!(
+                    "Invalid audio bitrate '{}'",
+                    invalid.trim()
+                )));
+            }
+        }
+        if self.loudness.is_some() && !self.reencodes() {
+            return Err(VideoEncodeError::Config(
+                "Loudness normalization re-encodes audio; an audio codec must be set".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Loudness targets for ffmpeg's `loudnorm` filter. Defaults follow EBU R128.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct LoudnessTarget {
+    /// Integrated loudness in LUFS
+    #[serde(default = "default_integrated_loudness")]
+    pub integrated:     f64,
+    /// Maximum true peak in dBTP
+    #[serde(default = "default_true_peak")]
+    pub true_peak:      f64,
+    /// Loudness range in LU
+    #[serde(default = "default_loudness_range")]
+    pub loudness_range: f64,
+}
+
+fn default_integrated_loudness() -> f64 {
+    -23.0
+}
+
+fn default_true_peak() -> f64 {
+    -1.0
+}
+
+fn default_loudness_range() -> f64 {
+    7.0
+}
+
+impl Default for LoudnessTarget {
+    fn default() -> Self {
+        Self {
+            integrated:     default_integrated_loudness(),
+            true_peak:      default_true_peak(),
+            loudness_range: default_loudness_range(),
+        }
+    }
+}
+
+/// Loudness statistics as `loudnorm` reports them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct LoudnessMeasurement {
+    /// Integrated loudness in LUFS
+    pub integrated:     f64,
+    /// True peak in dBTP
+    pub true_peak:      f64,
+    /// Loudness range in LU
+    pub loudness_range: f64,
+    /// Gating threshold in LUFS
+    pub threshold:      f64,
+    pub target_offset:  f64,
+}
+
+impl LoudnessMeasurement {
+    /// Whether the track is silent: `loudnorm` measures it at `-inf` LUFS,
+    /// which gives nothing to normalize against
+    pub fn is_silent(&self) -> bool {
+        !self.integrated.is_finite()
+    }
+}
+
+/// Loudness normalization result of one audio track
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TrackLoudness {
+    /// Track index among the encoded audio tracks
+    pub track:              usize,
+    /// Measurement pass result
+    pub input:              LoudnessMeasurement,
+    /// Loudness after normalization, if `loudnorm` reported it
+    pub output:             Option<LoudnessMeasurement>,
+    /// `linear` or `dynamic`; `loudnorm` falls back to dynamic when the
+    /// target can't be reached without exceeding the true peak. `none` for a
+    /// silent track, which is left as it is.
+    pub normalization_type: String,
+}
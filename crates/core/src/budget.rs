@@ -0,0 +1,235 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::VideoEncodeError;
+
+/// Share of a target size kept free for container overhead
+pub const CONTAINER_OVERHEAD: f64 = 0.005;
+
+/// Hard cap on the size of a job's output. Video bitrate is budgeted per
+/// chunk from what the other streams leave of it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TargetSize {
+    /// Bytes, as a number or a string such as `"700MB"` or `"4GiB"`
+    #[serde(deserialize_with = "deserialize_size")]
+    pub bytes:     u64,
+    /// Share a chunk may exceed its budget by before it counts as an outlier
+    /// when the output overshoots
+    #[serde(default = "default_size_tolerance")]
+    pub tolerance: f64,
+}
+
+fn default_size_tolerance() -> f64 {
+    0.05
+}
+
+impl TargetSize {
+    pub fn new(bytes: u64) -> Self {
+        Self {
+            bytes,
+            tolerance: default_size_tolerance(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), VideoEncodeError> {
+        if self.bytes == 0 {
+            return Err(VideoEncodeError::Config(
+                "Target size must be greater than 0 bytes".to_string(),
+            ));
+        }
+        if !self.tolerance.is_finite() || self.tolerance < 0.0 {
+            return Err(VideoEncodeError::Config(format!(
+                "Target size tolerance must be a non-negative share, got {}",
+                self.tolerance
+            )));
+        }
+        Ok(())
+    }
+
+    /// Bytes left for video once `reserved_bytes` of other streams and the
+    /// container overhead are taken out
+    pub fn video_bytes(&self, reserved_bytes: u64) -> Result<u64, VideoEncodeError> {
+        let available = (self.bytes as f64 * (1.0 - CONTAINER_OVERHEAD)) as u64;
+        available.checked_sub(reserved_bytes).filter(|bytes| *bytes > 0).ok_or_else(|| {
+            VideoEncodeError::Config(format!(
+                "Target size of {} bytes leaves no room for video next to {} bytes of other \
+                 streams",
+                self.bytes, reserved_bytes
+            ))
+        })
+    }
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(bytes),
+        Size::Text(text) => parse_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Parses a size in bytes with an optional decimal (`KB`, `MB`, `GB`, `TB`)
+/// or binary (`KiB`, `MiB`, ...) unit, e.g. `700MB` or `4.7 GB`.
+pub fn parse_size(size: &str) -> Result<u64, VideoEncodeError> {
+    let invalid = || VideoEncodeError::Config(format!("Invalid size '{}'", size));
+    let size = size.trim();
+    let split = size.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let unit = unit.trim().to_ascii_lowercase();
+    let unit = unit.strip_suffix('b').unwrap_or(&unit);
+    let (prefix, base) = match unit.strip_suffix('i') {
+        Some(prefix) => (prefix, 1024.0_f64),
+        None => (unit, 1000.0_f64),
+    };
+    let power = match prefix {
+        "" if base == 1000.0 => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return Err(invalid()),
+    };
+    let bytes = number * base.powi(power);
+    if !bytes.is_finite() || bytes < 1.0 {
+        return Err(invalid());
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Parses an ffmpeg bitrate such as `128k` or `1.5M` into bit/s
+pub fn parse_bitrate(bitrate: &str) -> Option<u64> {
+    let bitrate = bitrate.trim();
+    let (number, factor) = match bitrate.chars().last()? {
+        'k' | 'K' => (&bitrate[..bitrate.len() - 1], 1e3),
+        'm' | 'M' => (&bitrate[..bitrate.len() - 1], 1e6),
+        _ => (bitrate, 1.0),
+    };
+    let bits = number.parse::<f64>().ok()? * factor;
+    (bits.is_finite() && bits > 0.0).then(|| bits.round() as u64)
+}
+
+/// Bitrate a chunk is encoded at to stay within the job's target size
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ChunkBudget {
+    pub index:         usize,
+    /// Seconds of source video
+    pub duration:      f64,
+    /// Relative cost of encoding a second of the chunk
+    pub complexity:    f64,
+    /// Average bitrate in kbit/s
+    pub bitrate:       u32,
+    pub planned_bytes: u64,
+}
+
+/// Splits `video_bytes` over chunks of `durations` seconds in proportion to
+/// their `complexity` per second. Chunks without a measurable complexity get
+/// the average.
+pub fn allocate_bitrates(
+    video_bytes: u64,
+    durations: &[f64],
+    complexity: &[f64],
+) -> Vec<ChunkBudget> {
+    let measured: Vec<f64> =
+        complexity.iter().copied().filter(|c| c.is_finite() && *c > 0.0).collect();
+    let fallback = match measured.len() {
+        0 => 1.0,
+        n => measured.iter().sum::<f64>() / n as f64,
+    };
+    let complexity: Vec<f64> = durations
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            complexity
+                .get(i)
+                .copied()
+                .filter(|c| c.is_finite() && *c > 0.0)
+                .unwrap_or(fallback)
+        })
+        .collect();
+    let weight: f64 = durations.iter().zip(&complexity).map(|(d, c)| d.max(0.0) * c).sum();
+
+    durations
+        .iter()
+        .zip(&complexity)
+        .enumerate()
+        .map(|(index, (duration, complexity))| {
+            let share = if weight > 0.0 {
+                duration.max(0.0) * complexity / weight
+            } else {
+                0.0
+            };
+            let planned_bytes = (video_bytes as f64 * share) as u64;
+            let bitrate = match *duration > 0.0 {
+                true => (planned_bytes as f64 * 8.0 / duration / 1000.0).floor().max(1.0) as u32,
+                false => 1,
+            };
+            ChunkBudget {
+                index,
+                duration: *duration,
+                complexity: *complexity,
+                bitrate,
+                planned_bytes,
+            }
+        })
+        .collect()
+}
+
+/// New bitrates for the chunks to re-encode when the output came out
+/// `overshoot` bytes over its target. Chunks more than `tolerance` over their
+/// budget go first, the worst first, and are brought back to it. When that
+/// doesn't recover enough, every other chunk is scaled down by the share
+/// still missing. `actual_bytes` is indexed like `budgets`.
+pub fn reencode_plan(
+    budgets: &[ChunkBudget],
+    actual_bytes: &[u64],
+    overshoot: u64,
+    tolerance: f64,
+) -> Vec<(usize, u32)> {
+    let scaled = |budget: &ChunkBudget, factor: f64| {
+        ((budget.bitrate as f64 * factor).floor() as u32).max(1)
+    };
+    let mut outliers: Vec<(&ChunkBudget, u64)> = budgets
+        .iter()
+        .zip(actual_bytes)
+        .filter(|(budget, actual)| {
+            **actual as f64 > budget.planned_bytes as f64 * (1.0 + tolerance)
+        })
+        .map(|(budget, actual)| (budget, *actual))
+        .collect();
+    outliers.sort_by_key(|(budget, actual)| std::cmp::Reverse(actual - budget.planned_bytes));
+
+    let mut plan = Vec::new();
+    let mut recovered = 0;
+    for (budget, actual) in outliers {
+        if recovered >= overshoot {
+            break;
+        }
+        plan.push((
+            budget.index,
+            scaled(budget, budget.planned_bytes as f64 / actual as f64),
+        ));
+        recovered += actual - budget.planned_bytes;
+    }
+    if recovered < overshoot {
+        let others: Vec<(&ChunkBudget, u64)> = budgets
+            .iter()
+            .zip(actual_bytes)
+            .filter(|(budget, _)| !plan.iter().any(|(index, _)| *index == budget.index))
+            .map(|(budget, actual)| (budget, *actual))
+            .collect();
+        let others_bytes: u64 = others.iter().map(|(_, actual)| actual).sum();
+        if others_bytes > 0 {
+            let factor = 1.0 - (overshoot - recovered) as f64 / others_bytes as f64;
+            // Bitrate isn't hit exactly; keep some of each chunk
+            let factor = factor.max(0.1);
+            plan.extend(others.iter().map(|(budget, _)| (budget.index, scaled(budget, factor))));
+        }
+    }
+    plan.sort_unstable();
+    plan
+}
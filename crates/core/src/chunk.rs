@@ -2,7 +2,56 @@ use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
-use crate::error::VideoEncodeError;
+use crate::{
+    attachment::ChunkAttachment,
+    color::ColorMetadata,
+    error::VideoEncodeError,
+    profile::EncoderProfile,
+    zone::Zone,
+};
+
+/// Number of encoder passes to run for each chunk
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PassMode {
+    #[default]
+    OnePass,
+    /// Analysis pass followed by the final encode, using a passlog private to
+    /// the chunk
+    TwoPass,
+}
+
+/// What a chunk is sent to the node as
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkTransport {
+    /// The stream copied source segment, smallest; the node decodes it
+    #[default]
+    Source,
+    /// Lossless FFV1 decoded on the client, so the node needs no source
+    /// decoder. Several times the size of the source.
+    Ffv1,
+    /// Raw yuv4mpeg frames decoded on the client, the largest. Carries a
+    /// constant frame rate only.
+    Y4m,
+}
+
+impl ChunkTransport {
+    /// Extension of the file the node writes the chunk data to
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChunkTransport::Source | ChunkTransport::Ffv1 => "mkv",
+            ChunkTransport::Y4m => "y4m",
+        }
+    }
+}
+
+/// Encoder backend used when a job does not select one
+pub const DEFAULT_ENCODER_BACKEND: &str = "ffmpeg";
+
+fn default_encoder_backend() -> String {
+    DEFAULT_ENCODER_BACKEND.to_string()
+}
 
 /// Represents a video chunk for processing
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -11,6 +60,32 @@ pub struct Chunk {
     pub encoded_path:       Option<PathBuf>,
     pub index:              usize,
     pub encoder_parameters: Vec<String>,
+    #[serde(default)]
+    pub pass_mode:          PassMode,
+    #[serde(default = "default_encoder_backend")]
+    pub encoder_backend:    String,
+    /// Typed encoder settings. When set, the node renders them instead of
+    /// using `encoder_parameters`.
+    #[serde(default)]
+    pub profile:            Option<EncoderProfile>,
+    /// Color description and HDR10 metadata of the source, forwarded to the
+    /// encoder
+    #[serde(default)]
+    pub color:              Option<ColorMetadata>,
+    /// Name of the rendition this chunk is encoded for, `None` for a job
+    /// with a single output
+    #[serde(default)]
+    pub rendition:          Option<String>,
+    /// ffmpeg video filter applied ahead of the encoder, e.g. the
+    /// rendition's scale
+    #[serde(default)]
+    pub video_filter:       Option<String>,
+    /// Side files the encoder arguments refer to by placeholder
+    #[serde(default)]
+    pub attachments:        Vec<ChunkAttachment>,
+    /// What the chunk data is sent to the node as
+    #[serde(default)]
+    pub transport:          ChunkTransport,
 }
 
 impl Chunk {
@@ -31,9 +106,82 @@ impl Chunk {
             encoded_path: None,
             index,
             encoder_parameters,
+            pass_mode: PassMode::default(),
+            encoder_backend: default_encoder_backend(),
+            profile: None,
+            color: None,
+            rendition: None,
+            video_filter: None,
+            attachments: Vec::new(),
+            transport: ChunkTransport::default(),
         })
     }
 
+    /// Sets the number of encoder passes for this chunk
+    pub fn with_pass_mode(mut self, pass_mode: PassMode) -> Self {
+        self.pass_mode = pass_mode;
+        self
+    }
+
+    /// Sets the encoder backend this chunk is encoded with
+    pub fn with_encoder_backend(mut self, encoder_backend: impl Into<String>) -> Self {
+        self.encoder_backend = encoder_backend.into();
+        self
+    }
+
+    /// Sets the encoder profile this chunk is encoded with
+    pub fn with_profile(mut self, profile: Option<EncoderProfile>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Sets the source color metadata the encoder should carry
+    pub fn with_color(mut self, color: Option<ColorMetadata>) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Sets the rendition this chunk is encoded for
+    pub fn with_rendition(mut self, rendition: Option<String>) -> Self {
+        self.rendition = rendition;
+        self
+    }
+
+    /// Sets the video filter applied ahead of the encoder
+    pub fn with_video_filter(mut self, video_filter: Option<String>) -> Self {
+        self.video_filter = video_filter;
+        self
+    }
+
+    /// Sets the side files sent along with this chunk
+    pub fn with_attachments(mut self, attachments: Vec<ChunkAttachment>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    /// Sets what the chunk data is sent to the node as
+    pub fn with_transport(mut self, transport: ChunkTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Applies `zone` on top of the chunk's settings. `profile` is the
+    /// `[profiles]` entry the zone names, if it names one.
+    pub fn apply_zone(&mut self, zone: &Zone, profile: Option<&EncoderProfile>) {
+        if let Some(profile) = profile {
+            self.profile = Some(profile.clone());
+        }
+        if let Some(profile) = &mut self.profile {
+            zone.merge_into(profile);
+        }
+        self.encoder_parameters.extend(zone.encoder_params.iter().cloned());
+    }
+
+    /// Stem for files holding this chunk, unique across renditions
+    pub fn file_stem(&self) -> String {
+        chunk_file_stem(self.index, self.rendition.as_deref())
+    }
+
     /// Sets the encoded path for this chunk
     pub fn set_encoded_path(&mut self, encoded_path: PathBuf) {
         self.encoded_path = Some(encoded_path);
@@ -46,18 +194,40 @@ impl Chunk {
             encoded_path:       Some(encoded_path),
             index:              self.index,
             encoder_parameters: self.encoder_parameters.clone(),
+            pass_mode:          self.pass_mode,
+            encoder_backend:    self.encoder_backend.clone(),
+            profile:            self.profile.clone(),
+            color:              self.color.clone(),
+            rendition:          self.rendition.clone(),
+            video_filter:       self.video_filter.clone(),
+            attachments:        self.attachments.clone(),
+            transport:          self.transport,
         }
     }
 }
 
+/// Stem for files holding chunk `index` of `rendition`
+pub fn chunk_file_stem(index: usize, rendition: Option<&str>) -> String {
+    match rendition {
+        Some(rendition) => format!("chunk_{}_{}", rendition, index),
+        None => format!("chunk_{}", index),
+    }
+}
+
 pub fn convert_files_to_chunks(
     segments: Vec<PathBuf>,
     encoder_params: Vec<String>,
+    pass_mode: PassMode,
+    encoder_backend: &str,
+    profile: Option<&EncoderProfile>,
 ) -> Result<Vec<Chunk>, VideoEncodeError> {
     let mut chunks = Vec::new();
 
     for (index, path) in segments.into_iter().enumerate() {
-        let chunk = Chunk::new(path, index, encoder_params.clone())?;
+        let chunk = Chunk::new(path, index, encoder_params.clone())?
+            .with_pass_mode(pass_mode)
+            .with_encoder_backend(encoder_backend)
+            .with_profile(profile.cloned());
         chunks.push(chunk);
     }
 
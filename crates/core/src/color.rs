@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+/// SMPTE ST 2086 mastering display color volume
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct MasteringDisplay {
+    /// CIE 1931 xy chromaticity of the red, green and blue primaries and the
+    /// white point
+    pub red:           (f64, f64),
+    pub green:         (f64, f64),
+    pub blue:          (f64, f64),
+    pub white_point:   (f64, f64),
+    /// Luminance in cd/m²
+    pub min_luminance: f64,
+    pub max_luminance: f64,
+}
+
+/// CTA-861.3 content light level
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Maximum content light level in cd/m²
+    pub max_cll:  u32,
+    /// Maximum frame-average light level in cd/m²
+    pub max_fall: u32,
+}
+
+/// Color description and HDR10 static metadata of a video stream. Color
+/// fields use ffmpeg's names (e.g. `bt2020`, `smpte2084`, `bt2020nc`, `tv`).
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ColorMetadata {
+    #[serde(default)]
+    pub color_primaries:   Option<String>,
+    #[serde(default)]
+    pub transfer:          Option<String>,
+    #[serde(default)]
+    pub matrix:            Option<String>,
+    #[serde(default)]
+    pub range:             Option<String>,
+    #[serde(default)]
+    pub mastering_display: Option<MasteringDisplay>,
+    #[serde(default)]
+    pub content_light:     Option<ContentLightLevel>,
+}
+
+impl ColorMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &ColorMetadata::default()
+    }
+
+    /// Whether mastering display or content light level metadata is present
+    pub fn has_hdr10(&self) -> bool {
+        self.mastering_display.is_some() || self.content_light.is_some()
+    }
+
+    /// ITU-T H.273 code of the color primaries
+    pub fn primaries_code(&self) -> Option<u8> {
+        Some(match self.color_primaries.as_deref()? {
+            "bt709" => 1,
+            "bt470m" => 4,
+            "bt470bg" => 5,
+            "smpte170m" => 6,
+            "smpte240m" => 7,
+            "film" => 8,
+            "bt2020" => 9,
+            "smpte428" => 10,
+            "smpte431" => 11,
+            "smpte432" => 12,
+            "jedec-p22" => 22,
+            _ => return None,
+        })
+    }
+
+    /// ITU-T H.273 code of the transfer characteristics
+    pub fn transfer_code(&self) -> Option<u8> {
+        Some(match self.transfer.as_deref()? {
+            "bt709" => 1,
+            "gamma22" => 4,
+            "gamma28" => 5,
+            "smpte170m" => 6,
+            "smpte240m" => 7,
+            "linear" => 8,
+            "log100" => 9,
+            "log316" => 10,
+            "iec61966-2-4" => 11,
+            "bt1361e" => 12,
+            "iec61966-2-1" => 13,
+            "bt2020-10" => 14,
+            "bt2020-12" => 15,
+            "smpte2084" => 16,
+            "smpte428" => 17,
+            "arib-std-b67" => 18,
+            _ => return None,
+        })
+    }
+
+    /// ITU-T H.273 code of the matrix coefficients
+    pub fn matrix_code(&self) -> Option<u8> {
+        Some(match self.matrix.as_deref()? {
+            "gbr" => 0,
+            "bt709" => 1,
+            "fcc" => 4,
+            "bt470bg" => 5,
+            "smpte170m" => 6,
+            "smpte240m" => 7,
+            "ycgco" => 8,
+            "bt2020nc" => 9,
+            "bt2020c" => 10,
+            "smpte2085" => 11,
+            "chroma-derived-nc" => 12,
+            "chroma-derived-c" => 13,
+            "ictcp" => 14,
+            _ => return None,
+        })
+    }
+
+    /// Whether the range is full (`pc`) rather than limited (`tv`)
+    pub fn full_range(&self) -> Option<bool> {
+        match self.range.as_deref()? {
+            "pc" | "full" | "jpeg" => Some(true),
+            "tv" | "limited" | "mpeg" => Some(false),
+            _ => None,
+        }
+    }
+}
@@ -31,6 +31,9 @@ pub enum VideoEncodeError {
 
     #[error("Chunk processing error: {0}")]
     ChunkProcessing(String),
+
+    #[error("Unsupported encoder backend: {0}")]
+    UnsupportedBackend(String),
 }
 
 pub type VideoEncodeResult<T> = Result<T, VideoEncodeError>;
@@ -1,7 +1,48 @@
+pub mod attachment;
+pub mod audio;
+pub mod budget;
 pub mod chunk;
+pub mod color;
 pub mod error;
 pub mod models;
+pub mod profile;
+pub mod quality;
+pub mod rendition;
+pub mod streams;
+pub mod sync;
+pub mod trim;
+pub mod zone;
 
-pub use chunk::Chunk;
+pub use attachment::{
+    attachment_placeholder,
+    substitute_attachments,
+    validate_attachment_name,
+    AttachmentSource,
+    ChunkAttachment,
+};
+pub use audio::{
+    AudioEncodeLocation,
+    AudioOptions,
+    LoudnessMeasurement,
+    LoudnessTarget,
+    TrackLoudness,
+};
+pub use budget::{
+    allocate_bitrates,
+    parse_bitrate,
+    parse_size,
+    reencode_plan,
+    ChunkBudget,
+    TargetSize,
+};
+pub use chunk::{Chunk, ChunkTransport, PassMode, DEFAULT_ENCODER_BACKEND};
+pub use color::{ColorMetadata, ContentLightLevel, MasteringDisplay};
 pub use error::VideoEncodeError;
 pub use models::*;
+pub use profile::{EncoderProfile, RateControl};
+pub use quality::QualityMetrics;
+pub use rendition::Rendition;
+pub use streams::{StreamFilter, StreamInfo, StreamKind, StreamMetadata, StreamSelection};
+pub use sync::{StreamDrift, StreamTiming, SyncAction, SyncAudit, SyncCheck};
+pub use trim::{parse_time, TimeRange};
+pub use zone::{chunk_spans, zone_assignments, zones_covering, Zone};
@@ -7,6 +7,8 @@ use serde::{Deserialize, Serialize};
 pub struct NodeCapabilities {
     pub max_concurrent_chunks: u32,
     pub supported_encoders:    Vec<String>,
+    #[serde(default)] // Nodes predating encoder backends only ran ffmpeg
+    pub supported_backends: Vec<String>,
     pub cpu_cores:             u32,
     pub memory_gb:             u32,
 }
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{error::VideoEncodeError, NodeCapabilities};
+
+/// Rate control mode of an encoder profile together with its value
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "mode", content = "value", rename_all = "snake_case")]
+pub enum RateControl {
+    /// Constant rate factor / constant quality level
+    Crf(f32),
+    /// Constant quantizer
+    Qp(u32),
+    /// Average bitrate in kbit/s
+    Bitrate(u32),
+}
+
+/// Typed description of how video is encoded. Rendered to encoder specific
+/// arguments on the node by the selected encoder backend.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EncoderProfile {
+    /// Encoder as ffmpeg names it (e.g. `libsvtav1`, `libx265`)
+    pub codec:        String,
+    pub rate_control: RateControl,
+    #[serde(default)]
+    pub preset:       Option<String>,
+    #[serde(default)]
+    pub pixel_format: Option<String>,
+    /// Maximum distance between keyframes in frames
+    #[serde(default)]
+    pub keyint:       Option<u32>,
+    /// Arguments appended verbatim after the rendered ones
+    #[serde(default)]
+    pub extra_args:   Vec<String>,
+}
+
+impl EncoderProfile {
+    /// Codec family this encoder produces, as nodes advertise it in
+    /// `NodeCapabilities::supported_encoders`.
+    pub fn codec_family(&self) -> &str {
+        codec_family(&self.codec)
+    }
+
+    /// Checks the profile for values no encoder would accept.
+    pub fn validate(&self) -> Result<(), VideoEncodeError> {
+        if self.codec.trim().is_empty() {
+            return Err(VideoEncodeError::Config(
+                "Encoder profile has no codec".to_string(),
+            ));
+        }
+        match self.rate_control {
+            RateControl::Crf(value) if !value.is_finite() || value < 0.0 => {
+                Err(VideoEncodeError::Config(format!(
+                    "Encoder profile CRF must be a non-negative number, got {}",
+                    value
+                )))
+            },
+            RateControl::Bitrate(0) => Err(VideoEncodeError::Config(
+                "Encoder profile bitrate must be greater than 0 kbit/s".to_string(),
+            )),
+            _ => Ok(()),
+        }?;
+        if self.keyint == Some(0) {
+            return Err(VideoEncodeError::Config(
+                "Encoder profile keyint must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that a node advertising `capabilities` can encode this profile.
+    pub fn validate_against(
+        &self,
+        capabilities: &NodeCapabilities,
+    ) -> Result<(), VideoEncodeError> {
+        let encoders = &capabilities.supported_encoders;
+        // Nodes list ffmpeg encoder names next to codec families. Only nodes
+        // that list families alone (older or hand configured nodes) are matched
+        // by family, since e.g. `av1` doesn't tell libsvtav1 from libaom-av1.
+        let lists_encoder_names = encoders.iter().any(|e| codec_family(e) != e);
+        let supported = encoders.iter().any(|encoder| encoder == &self.codec)
+            || (!lists_encoder_names && encoders.iter().any(|e| e == self.codec_family()));
+        if supported {
+            Ok(())
+        } else {
+            Err(VideoEncodeError::Config(format!(
+                "Encoder '{}' ({}) is not among the node's encoders {:?}",
+                self.codec,
+                self.codec_family(),
+                capabilities.supported_encoders
+            )))
+        }
+    }
+}
+
+/// Maps an ffmpeg encoder name to the codec family it produces. Unknown
+/// encoders are their own family.
+pub fn codec_family(codec: &str) -> &str {
+    match codec {
+        "libx264" | "h264" | "x264" => "h264",
+        "libx265" | "hevc" | "h265" | "x265" => "hevc",
+        "libaom-av1" | "libsvtav1" | "librav1e" | "av1" => "av1",
+        "libvpx-vp9" | "vp9" => "vp9",
+        "libvpx" | "vp8" => "vp8",
+        other => other,
+    }
+}
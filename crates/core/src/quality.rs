@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Full-reference quality of an encode against its source
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct QualityMetrics {
+    /// SSIM over all planes, 1.0 for identical frames
+    pub ssim: Option<f64>,
+    /// Average PSNR in dB
+    pub psnr: Option<f64>,
+}
+
+impl QualityMetrics {
+    /// Mean of each metric over the samples that have it
+    pub fn mean(samples: &[QualityMetrics]) -> QualityMetrics {
+        let mean = |values: Vec<f64>| {
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+        QualityMetrics {
+            ssim: mean(samples.iter().filter_map(|sample| sample.ssim).collect()),
+            psnr: mean(samples.iter().filter_map(|sample| sample.psnr).collect()),
+        }
+    }
+}
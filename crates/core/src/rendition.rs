@@ -0,0 +1,82 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VideoEncodeError;
+
+/// One rung of an ABR ladder: the source filtered (usually scaled) and
+/// encoded with its own profile into an output file of its own.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Rendition {
+    /// Identifies the rendition in logs and intermediate file names
+    pub name:    String,
+    /// ffmpeg video filter applied before encoding, e.g. `scale=-2:720`
+    #[serde(default)]
+    pub filter:  Option<String>,
+    /// Name of the `[profiles]` entry to encode with. The job's encoder
+    /// settings are used when unset.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Output file. Defaults to the job output with `.<name>` inserted before
+    /// the extension.
+    #[serde(default)]
+    pub output:  Option<PathBuf>,
+}
+
+impl Rendition {
+    /// Output file of this rendition for a job writing to `job_output`
+    pub fn output_path(&self, job_output: &Path) -> PathBuf {
+        if let Some(output) = &self.output {
+            return output.clone();
+        }
+        let stem = job_output.file_stem().unwrap_or_default().to_string_lossy();
+        let file_name = match job_output.extension() {
+            Some(extension) => {
+                format!("{}.{}.{}", stem, self.name, extension.to_string_lossy())
+            },
+            None => format!("{}.{}", stem, self.name),
+        };
+        job_output.with_file_name(file_name)
+    }
+
+    /// Checks that rendition names are unique and usable in file names, and
+    /// that no two renditions write the same output.
+    pub fn validate_ladder(
+        renditions: &[Rendition],
+        job_output: &Path,
+    ) -> Result<(), VideoEncodeError> {
+        let mut names = HashSet::new();
+        let mut outputs = HashSet::new();
+        for rendition in renditions {
+            if rendition.name.is_empty()
+                || !rendition
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                return Err(VideoEncodeError::Config(format!(
+                    "Rendition name '{}' must be non-empty and contain only letters, digits, '-', \
+                     '_' and '.'",
+                    rendition.name
+                )));
+            }
+            if !names.insert(rendition.name.as_str()) {
+                return Err(VideoEncodeError::Config(format!(
+                    "Rendition '{}' is defined more than once",
+                    rendition.name
+                )));
+            }
+            let output = rendition.output_path(job_output);
+            if !outputs.insert(output.clone()) {
+                return Err(VideoEncodeError::Config(format!(
+                    "Rendition '{}' writes to {:?}, which another rendition already uses",
+                    rendition.name, output
+                )));
+            }
+        }
+        Ok(())
+    }
+}
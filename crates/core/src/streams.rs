@@ -0,0 +1,215 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VideoEncodeError;
+
+/// Stream type as ffprobe reports it in `codec_type`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+}
+
+impl StreamKind {
+    pub fn from_codec_type(codec_type: &str) -> Self {
+        match codec_type {
+            "video" => StreamKind::Video,
+            "audio" => StreamKind::Audio,
+            "subtitle" => StreamKind::Subtitle,
+            "attachment" => StreamKind::Attachment,
+            _ => StreamKind::Data,
+        }
+    }
+
+    /// Stream specifier letter ffmpeg uses for this kind (`a` in `0:a:1`)
+    pub fn specifier(&self) -> &'static str {
+        match self {
+            StreamKind::Video => "v",
+            StreamKind::Audio => "a",
+            StreamKind::Subtitle => "s",
+            StreamKind::Data => "d",
+            StreamKind::Attachment => "t",
+        }
+    }
+}
+
+/// A stream of a media file as probed with ffprobe
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamInfo {
+    /// Index among all streams of the file
+    pub index:        usize,
+    pub kind:         StreamKind,
+    pub codec:        String,
+    pub language:     Option<String>,
+    pub title:        Option<String>,
+    /// Disposition flags that are set (e.g. `default`, `comment`, `forced`)
+    pub dispositions: Vec<String>,
+}
+
+impl StreamInfo {
+    /// ISO 639-2 language, `und` if the stream is untagged
+    pub fn language_or_und(&self) -> &str {
+        self.language.as_deref().unwrap_or("und")
+    }
+
+    pub fn has_disposition(&self, disposition: &str) -> bool {
+        self.dispositions.iter().any(|d| d.eq_ignore_ascii_case(disposition))
+    }
+}
+
+/// Which streams of one kind to keep. Empty lists don't filter.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct StreamFilter {
+    /// ISO 639-2 languages to keep; untagged streams count as `und`
+    #[serde(default)]
+    pub languages:            Vec<String>,
+    /// Codec names to keep (e.g. `aac`, `subrip`)
+    #[serde(default)]
+    pub codecs:               Vec<String>,
+    /// Keep only streams with at least one of these dispositions
+    #[serde(default)]
+    pub dispositions:         Vec<String>,
+    /// Drop streams with any of these dispositions (e.g. `comment`)
+    #[serde(default)]
+    pub exclude_dispositions: Vec<String>,
+}
+
+impl StreamFilter {
+    pub fn matches(&self, stream: &StreamInfo) -> bool {
+        let language = stream.language_or_und();
+        (self.languages.is_empty()
+            || self.languages.iter().any(|l| l.eq_ignore_ascii_case(language)))
+            && (self.codecs.is_empty()
+                || self.codecs.iter().any(|c| c.eq_ignore_ascii_case(&stream.codec)))
+            && (self.dispositions.is_empty()
+                || self.dispositions.iter().any(|d| stream.has_disposition(d)))
+            && !self.exclude_dispositions.iter().any(|d| stream.has_disposition(d))
+    }
+}
+
+/// Stream selection and metadata for the output mux. Streams other than audio
+/// and subtitles are always kept.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct StreamSelection {
+    #[serde(default)]
+    pub audio:    StreamFilter,
+    #[serde(default)]
+    pub subtitle: StreamFilter,
+    /// Metadata and disposition overrides for output streams
+    #[serde(default)]
+    pub metadata: Vec<StreamMetadata>,
+}
+
+impl StreamSelection {
+    /// Non-video streams of `streams` to keep. `audio_tracks` further limits
+    /// audio to the given indices among audio streams; empty keeps all.
+    pub fn select<'a>(
+        &self,
+        streams: &'a [StreamInfo],
+        audio_tracks: &[usize],
+    ) -> Vec<&'a StreamInfo> {
+        let mut audio_index = 0;
+        streams
+            .iter()
+            .filter(|stream| match stream.kind {
+                StreamKind::Video => false,
+                StreamKind::Audio => {
+                    let track = audio_index;
+                    audio_index += 1;
+                    (audio_tracks.is_empty() || audio_tracks.contains(&track))
+                        && self.audio.matches(stream)
+                },
+                StreamKind::Subtitle => self.subtitle.matches(stream),
+                StreamKind::Data | StreamKind::Attachment => true,
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), VideoEncodeError> {
+        self.metadata.iter().try_for_each(StreamMetadata::validate)
+    }
+}
+
+/// Metadata to set on one output stream, addressed by kind and index among
+/// output streams of that kind (`a:0` is the first audio track).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamMetadata {
+    pub kind:     StreamKind,
+    pub index:    usize,
+    #[serde(default)]
+    pub title:    Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub default:  Option<bool>,
+    #[serde(default)]
+    pub forced:   Option<bool>,
+}
+
+impl StreamMetadata {
+    pub fn validate(&self) -> Result<(), VideoEncodeError> {
+        match self.kind {
+            StreamKind::Audio | StreamKind::Subtitle => Ok(()),
+            kind => Err(VideoEncodeError::Config(format!(
+                "Stream metadata can only be set on audio and subtitle streams, not {:?}",
+                kind
+            ))),
+        }
+    }
+}
+
+/// Parses `a:0,title=Main,language=eng,default,forced=false`. A bare flag name
+/// sets it to true.
+impl FromStr for StreamMetadata {
+    type Err = VideoEncodeError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| {
+            VideoEncodeError::Config(format!("Invalid stream metadata '{}': {}", spec, reason))
+        };
+        let mut parts = spec.split(',');
+        let stream = parts.next().unwrap_or_default().trim();
+        let (kind, index) = stream
+            .split_once(':')
+            .ok_or_else(|| invalid("expected a stream like 'a:0' first"))?;
+        let kind = match kind {
+            "a" => StreamKind::Audio,
+            "s" => StreamKind::Subtitle,
+            _ => return Err(invalid("stream kind must be 'a' or 's'")),
+        };
+        let index = index.parse().map_err(|_| invalid("stream index must be a number"))?;
+
+        let mut metadata = StreamMetadata {
+            kind,
+            index,
+            title: None,
+            language: None,
+            default: None,
+            forced: None,
+        };
+        for part in parts {
+            let (key, value) = match part.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (part.trim(), None),
+            };
+            let flag = |value: Option<&str>| match value {
+                None | Some("true") | Some("1") | Some("yes") => Ok(true),
+                Some("false") | Some("0") | Some("no") => Ok(false),
+                Some(_) => Err(invalid("flags must be true or false")),
+            };
+            match key {
+                "title" => metadata.title = value.map(str::to_string),
+                "language" => metadata.language = value.map(str::to_string),
+                "default" => metadata.default = Some(flag(value)?),
+                "forced" => metadata.forced = Some(flag(value)?),
+                _ => return Err(invalid(&format!("unknown key '{}'", key))),
+            }
+        }
+        Ok(metadata)
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use crate::streams::StreamKind;
+
+/// What a job does when the concatenated output drifts from the source
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncAction {
+    /// No audit
+    Off,
+    /// Audit and report drift as warnings
+    #[default]
+    Report,
+    /// Fail the job on drift beyond the tolerance
+    Fail,
+    /// Retime the video track with mkvmerge `--sync`, Matroska outputs only
+    Fix,
+}
+
+/// Post-concatenation audit of durations, frame counts and A/V offsets
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct SyncCheck {
+    #[serde(default)]
+    pub action:    SyncAction,
+    /// Drift in seconds tolerated before the audit reports a problem
+    #[serde(default = "default_sync_tolerance")]
+    pub tolerance: f64,
+}
+
+fn default_sync_tolerance() -> f64 {
+    0.1
+}
+
+impl Default for SyncCheck {
+    fn default() -> Self {
+        Self {
+            action:    SyncAction::default(),
+            tolerance: default_sync_tolerance(),
+        }
+    }
+}
+
+/// Timing of one stream as ffprobe reports it, all in seconds
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamTiming {
+    pub index:      usize,
+    pub kind:       StreamKind,
+    pub start_time: Option<f64>,
+    pub duration:   Option<f64>,
+    /// Packets read, i.e. frames for video
+    pub frames:     Option<u64>,
+}
+
+/// Offset of a non-video output stream against the output video, less the
+/// offset the source already had between its first audio stream and video
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StreamDrift {
+    pub index:          usize,
+    pub kind:           StreamKind,
+    /// Seconds the stream starts later than it should
+    pub start_offset:   Option<f64>,
+    /// Seconds the stream ends later than it should
+    pub duration_drift: Option<f64>,
+}
+
+/// Output video and audio timing compared against the source
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct SyncAudit {
+    pub source_duration:    Option<f64>,
+    pub output_duration:    Option<f64>,
+    pub source_frames:      Option<u64>,
+    pub output_frames:      Option<u64>,
+    /// Stream index of the output video, the track a fix retimes
+    pub output_video_index: Option<usize>,
+    pub streams:            Vec<StreamDrift>,
+}
+
+impl SyncAudit {
+    /// Compares the first video stream and the audio streams of `output`
+    /// against `source`.
+    pub fn from_timing(source: &[StreamTiming], output: &[StreamTiming]) -> Self {
+        let first = |streams: &[StreamTiming], kind: StreamKind| {
+            streams.iter().find(|stream| stream.kind == kind).cloned()
+        };
+        let source_video = first(source, StreamKind::Video);
+        let output_video = first(output, StreamKind::Video);
+        let source_audio = first(source, StreamKind::Audio);
+
+        // What the source already had between audio and video is not drift
+        let difference = |a: Option<f64>, b: Option<f64>| Some(a? - b?);
+        let source_video_start = source_video.as_ref().and_then(|video| video.start_time);
+        let source_video_duration = source_video.as_ref().and_then(|video| video.duration);
+        let baseline_start = source_audio
+            .as_ref()
+            .and_then(|audio| difference(audio.start_time, source_video_start))
+            .unwrap_or(0.0);
+        let baseline_duration = source_audio
+            .as_ref()
+            .and_then(|audio| difference(audio.duration, source_video_duration))
+            .unwrap_or(0.0);
+
+        let output_video_start = output_video.as_ref().and_then(|video| video.start_time);
+        let output_video_duration = output_video.as_ref().and_then(|video| video.duration);
+        let streams = output
+            .iter()
+            .filter(|stream| stream.kind == StreamKind::Audio)
+            .map(|stream| StreamDrift {
+                index:          stream.index,
+                kind:           stream.kind,
+                start_offset:   difference(stream.start_time, output_video_start)
+                    .map(|offset| offset - baseline_start),
+                duration_drift: difference(stream.duration, output_video_duration)
+                    .map(|drift| drift - baseline_duration),
+            })
+            .collect();
+
+        Self {
+            source_duration: source_video_duration,
+            output_duration: output_video_duration,
+            source_frames: source_video.and_then(|video| video.frames),
+            output_frames: output_video.as_ref().and_then(|video| video.frames),
+            output_video_index: output_video.map(|video| video.index),
+            streams,
+        }
+    }
+
+    /// Seconds the output video is longer than the source video
+    pub fn duration_drift(&self) -> Option<f64> {
+        Some(self.output_duration? - self.source_duration?)
+    }
+
+    /// Frames the output video has more than the source video
+    pub fn frame_difference(&self) -> Option<i64> {
+        Some(self.output_frames? as i64 - self.source_frames? as i64)
+    }
+
+    /// Human readable findings beyond `tolerance` seconds. Frame counts
+    /// aren't compared, as filters changing the frame rate change them
+    /// without any drift.
+    pub fn problems(&self, tolerance: f64) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(drift) = self.duration_drift().filter(|drift| drift.abs() > tolerance) {
+            problems.push(format!(
+                "Output video lasts {:.3}s, the source {:.3}s ({:+.3}s)",
+                self.output_duration.unwrap_or_default(),
+                self.source_duration.unwrap_or_default(),
+                drift
+            ));
+        }
+        for stream in &self.streams {
+            if let Some(offset) = stream.start_offset.filter(|offset| offset.abs() > tolerance) {
+                problems.push(format!(
+                    "Stream {} starts {:+.3}s off the video",
+                    stream.index, offset
+                ));
+            }
+            if let Some(drift) = stream.duration_drift.filter(|drift| drift.abs() > tolerance) {
+                problems.push(format!(
+                    "Stream {} drifts {:+.3}s against the video by its end",
+                    stream.index, drift
+                ));
+            }
+        }
+        problems
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::VideoEncodeError;
+
+/// A part of the input to encode, in seconds from the start of the input
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    #[serde(default)]
+    pub start: f64,
+    /// `None` runs to the end of the input
+    #[serde(default)]
+    pub end:   Option<f64>,
+}
+
+impl TimeRange {
+    pub fn new(start: f64, end: Option<f64>) -> Self {
+        Self {
+            start,
+            end,
+        }
+    }
+
+    /// Parses `start-end`, `start-` or `-end`, with times as
+    /// [`parse_time`] reads them.
+    pub fn parse(range: &str) -> Result<Self, VideoEncodeError> {
+        let (start, end) = range.split_once('-').ok_or_else(|| {
+            VideoEncodeError::Config(format!(
+                "Time range '{}' must be written as start-end",
+                range
+            ))
+        })?;
+        let start = match start.trim() {
+            "" => 0.0,
+            start => parse_time(start)?,
+        };
+        let end = match end.trim() {
+            "" => None,
+            end => Some(parse_time(end)?),
+        };
+        Ok(Self::new(start, end))
+    }
+
+    /// Length in seconds, `None` for a range open to the end of the input
+    pub fn duration(&self) -> Option<f64> {
+        self.end.map(|end| end - self.start)
+    }
+
+    /// Checks that `ranges` are in order, don't overlap and that only the
+    /// last one is open-ended.
+    pub fn validate_ranges(ranges: &[TimeRange]) -> Result<(), VideoEncodeError> {
+        for (i, range) in ranges.iter().enumerate() {
+            if !range.start.is_finite() || range.start < 0.0 {
+                return Err(VideoEncodeError::Config(format!(
+                    "Time range start must be a non-negative number of seconds, got {}",
+                    range.start
+                )));
+            }
+            match range.end {
+                Some(end) if !end.is_finite() || end <= range.start => {
+                    return Err(VideoEncodeError::Config(format!(
+                        "Time range end {} must come after its start {}",
+                        end, range.start
+                    )));
+                },
+                None if i + 1 < ranges.len() => {
+                    return Err(VideoEncodeError::Config(
+                        "Only the last time range may run to the end of the input".to_string(),
+                    ));
+                },
+                _ => {},
+            }
+            if let Some(next) = ranges.get(i + 1) {
+                if range.end.is_some_and(|end| next.start < end) {
+                    return Err(VideoEncodeError::Config(format!(
+                        "Time ranges must be in order and not overlap: {} starts before {} ends",
+                        next.start,
+                        range.end.unwrap_or_default()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a time in seconds (`90.5`) or as `[HH:]MM:SS[.fraction]`.
+pub fn parse_time(value: &str) -> Result<f64, VideoEncodeError> {
+    let invalid = || VideoEncodeError::Config(format!("Invalid time '{}'", value));
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    let mut seconds = 0.0;
+    for (i, part) in parts.iter().enumerate() {
+        let number: f64 = part.parse().map_err(|_| invalid())?;
+        // Only the seconds may have a fraction, minutes and seconds stay below 60
+        let last = i + 1 == parts.len();
+        if !number.is_finite() || number < 0.0 || (!last && number.fract() != 0.0) {
+            return Err(invalid());
+        }
+        if i > 0 && number >= 60.0 {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + number;
+    }
+    Ok(seconds)
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::VideoEncodeError,
+    profile::{EncoderProfile, RateControl},
+};
+
+/// Encoder settings for part of the output, e.g. cheaper ones for credits.
+/// A zone covers the chunks overlapping its time range and the chunks it
+/// lists; where zones overlap, the later one is merged last.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Zone {
+    /// Identifies the zone in the job report
+    #[serde(default)]
+    pub name:           Option<String>,
+    /// Seconds on the output's timeline, i.e. after trimming to time ranges
+    #[serde(default)]
+    pub start:          Option<f64>,
+    /// `None` runs to the end of the output when `start` is set
+    #[serde(default)]
+    pub end:            Option<f64>,
+    /// Chunk indices covered next to the time range
+    #[serde(default)]
+    pub chunks:         Vec<usize>,
+    /// Name of a `[profiles]` entry replacing the output's profile
+    #[serde(default)]
+    pub profile:        Option<String>,
+    #[serde(default)]
+    pub rate_control:   Option<RateControl>,
+    #[serde(default)]
+    pub preset:         Option<String>,
+    #[serde(default)]
+    pub keyint:         Option<u32>,
+    /// Appended to the profile's extra arguments
+    #[serde(default)]
+    pub extra_args:     Vec<String>,
+    /// Appended to the raw encoder parameters of jobs without a profile
+    #[serde(default)]
+    pub encoder_params: Vec<String>,
+}
+
+impl Zone {
+    /// Name of the zone, or its position among `zones` when it has none
+    pub fn label(&self, position: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("zone {}", position))
+    }
+
+    /// Whether the zone has a time range
+    pub fn is_timed(&self) -> bool {
+        self.start.is_some() || self.end.is_some()
+    }
+
+    /// Whether the zone changes anything the encoder profile holds
+    pub fn overrides_profile(&self) -> bool {
+        self.profile.is_some()
+            || self.rate_control.is_some()
+            || self.preset.is_some()
+            || self.keyint.is_some()
+            || !self.extra_args.is_empty()
+    }
+
+    /// Whether the zone covers chunk `index`, spanning `start` to `end`
+    /// seconds of the output
+    pub fn covers(&self, index: usize, start: f64, end: f64) -> bool {
+        if self.chunks.contains(&index) {
+            return true;
+        }
+        if !self.is_timed() {
+            return false;
+        }
+        let zone_start = self.start.unwrap_or(0.0);
+        let zone_end = self.end.unwrap_or(f64::INFINITY);
+        start < zone_end && zone_start < end
+    }
+
+    /// Checks that the zone covers something and changes something.
+    pub fn validate(&self, position: usize) -> Result<(), VideoEncodeError> {
+        let label = self.label(position);
+        if !self.is_timed() && self.chunks.is_empty() {
+            return Err(VideoEncodeError::Config(format!(
+                "Zone '{}' has neither a time range nor chunks",
+                label
+            )));
+        }
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            if end <= start {
+                return Err(VideoEncodeError::Config(format!(
+                    "Zone '{}' ends at {} before it starts at {}",
+                    label, end, start
+                )));
+            }
+        }
+        if [self.start, self.end]
+            .iter()
+            .flatten()
+            .any(|time| !time.is_finite() || *time < 0.0)
+        {
+            return Err(VideoEncodeError::Config(format!(
+                "Zone '{}' times must be non-negative numbers of seconds",
+                label
+            )));
+        }
+        if !self.overrides_profile() && self.encoder_params.is_empty() {
+            return Err(VideoEncodeError::Config(format!(
+                "Zone '{}' doesn't override any encoder setting",
+                label
+            )));
+        }
+        Ok(())
+    }
+
+    /// Merges the zone's overrides onto `profile`
+    pub fn merge_into(&self, profile: &mut EncoderProfile) {
+        if let Some(rate_control) = self.rate_control {
+            profile.rate_control = rate_control;
+        }
+        if let Some(preset) = &self.preset {
+            profile.preset = Some(preset.clone());
+        }
+        if let Some(keyint) = self.keyint {
+            profile.keyint = Some(keyint);
+        }
+        profile.extra_args.extend(self.extra_args.iter().cloned());
+    }
+}
+
+/// Indices of the zones covering each chunk, in zone order. `spans` holds
+/// each chunk's start and end on the output's timeline.
+pub fn zone_assignments(zones: &[Zone], spans: &[(f64, f64)]) -> Vec<Vec<usize>> {
+    spans
+        .iter()
+        .enumerate()
+        .map(|(index, span)| zones_covering(zones, index, *span))
+        .collect()
+}
+
+/// Indices of the zones covering chunk `index` spanning `span`, in zone
+/// order; for chunks assigned one at a time
+pub fn zones_covering(zones: &[Zone], index: usize, (start, end): (f64, f64)) -> Vec<usize> {
+    zones
+        .iter()
+        .enumerate()
+        .filter(|(_, zone)| zone.covers(index, start, end))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+/// Start and end of each chunk on the output's timeline, from their durations
+pub fn chunk_spans(durations: &[f64]) -> Vec<(f64, f64)> {
+    durations
+        .iter()
+        .scan(0.0, |start, duration| {
+            let span = (*start, *start + duration);
+            *start += duration;
+            Some(span)
+        })
+        .collect()
+}
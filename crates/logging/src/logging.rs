@@ -1,7 +1,7 @@
 use std::env;
 
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use tracing_subscriber::{fmt, fmt::MakeWriter, prelude::*, EnvFilter};
 
 /// Initialize the logging system for the application.
 ///
@@ -17,6 +17,19 @@ use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 ///
 /// This function will panic if it fails to initialize the global logger.
 pub fn init_logging() {
+    init_logging_with_console(std::io::stdout);
+}
+
+/// Like [`init_logging`], with console lines written through `console`, e.g.
+/// to keep them clear of progress bars.
+///
+/// # Panics
+///
+/// This function will panic if it fails to initialize the global logger.
+pub fn init_logging_with_console<W>(console: W)
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
     let rust_log = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
 
     // Set up daily rotating file appender
@@ -28,7 +41,7 @@ pub fn init_logging() {
         .with(EnvFilter::new(rust_log))
         .with(
             fmt::Layer::new()
-                .with_writer(std::io::stdout)
+                .with_writer(console)
                 .with_ansi(true)
                 .with_file(true)
                 .with_line_number(true)
@@ -3,6 +3,7 @@ use std::{net::SocketAddr, time::Duration};
 use anyhow::{anyhow, Result};
 use ferris_swarm_core::{NodeCapabilities, NodeRegistration};
 use ferris_swarm_discovery::DiscoveryService;
+use ferris_swarm_video::available_encoder_backends;
 use serde::{Deserialize, Serialize};
 use tokio::time::{interval, sleep};
 use tracing::{debug, error, info, warn};
@@ -252,6 +253,7 @@ pub fn detect_node_capabilities(
     memory_gb_override: Option<u32>,
     max_chunks_override: Option<u32>,
     encoders_override: Option<String>,
+    backends_override: Option<String>,
 ) -> Result<NodeCapabilities> {
     let cpu_cores = cpu_cores_override.unwrap_or_else(|| num_cpus::get() as u32);
 
@@ -263,11 +265,18 @@ pub fn detect_node_capabilities(
         detect_available_encoders()
     };
 
+    let supported_backends = if let Some(backends_str) = backends_override {
+        backends_str.split(',').map(|s| s.trim().to_string()).collect()
+    } else {
+        available_encoder_backends()
+    };
+
     let max_concurrent_chunks = max_chunks_override.unwrap_or_else(|| (cpu_cores / 2).max(1));
 
     Ok(NodeCapabilities {
         max_concurrent_chunks,
         supported_encoders,
+        supported_backends,
         cpu_cores,
         memory_gb,
     })
@@ -301,12 +310,18 @@ fn detect_available_encoders() -> Vec<String> {
         ("libx264", "h264"),
         ("libx265", "hevc"),
         ("libaom-av1", "av1"),
+        ("libsvtav1", "av1"),
+        ("librav1e", "av1"),
         ("libvpx-vp9", "vp9"),
     ];
 
     for (ffmpeg_name, encoder_name) in potential_encoders {
         if check_encoder_available(ffmpeg_name) {
-            encoders.push(encoder_name.to_string());
+            // Families let clients match profiles by codec, names by exact encoder
+            if !encoders.iter().any(|e| e == encoder_name) {
+                encoders.push(encoder_name.to_string());
+            }
+            encoders.push(ffmpeg_name.to_string());
         }
     }
 
@@ -11,7 +11,7 @@ use ferris_swarm_node::{
 };
 use ferris_swarm_proto::protos::video_encoding::video_encoding_service_server::VideoEncodingServiceServer;
 use ferris_swarm_video::utils::verify_ffmpeg;
-use tonic::transport::Server;
+use tonic::{codec::CompressionEncoding, transport::Server};
 use tracing::{debug, error, info, instrument, warn};
 
 const MAX_MESSAGE_SIZE_BYTES: usize = 1 * 1024 * 1024 * 1024; // 1 GB
@@ -30,23 +30,28 @@ async fn main() -> Result<()> {
 
     verify_ffmpeg()?;
 
+    let capabilities = detect_node_capabilities(
+        cli_args.cpu_cores,
+        cli_args.memory_gb,
+        cli_args.max_chunks,
+        cli_args.encoders.clone(),
+        cli_args.backends.clone(),
+    )?;
+    info!(
+        "Encoder backends available on this node: {:?}",
+        capabilities.supported_backends
+    );
+
     // Handle auto-registration (enabled by default)
     let mut auto_register_handle = None;
     if cli_args.should_auto_register() {
         info!("Auto-registration enabled (default behavior)");
 
-        let capabilities = detect_node_capabilities(
-            cli_args.cpu_cores,
-            cli_args.memory_gb,
-            cli_args.max_chunks,
-            cli_args.encoders.clone(),
-        )?;
-
         // Use discovery with fallback to manual URL
         let auto_register_config = NodeAutoRegister::create_with_discovery_fallback(
             cli_args.constellation_url.clone(),
             cli_args.node_name.clone(),
-            capabilities,
+            capabilities.clone(),
             Duration::from_secs(cli_args.heartbeat_interval),
         )
         .await?;
@@ -102,11 +107,13 @@ async fn main() -> Result<()> {
     }
     // The NodeEncodingService now takes the temp_dir path directly.
     // This temp_dir comes from the node's specific configuration.
-    let node_service = NodeEncodingService::new(settings.node.temp_dir.clone());
+    let node_service = NodeEncodingService::new(settings.node.temp_dir.clone(), capabilities);
 
     let grpc_service = VideoEncodingServiceServer::new(node_service)
         .max_encoding_message_size(MAX_MESSAGE_SIZE_BYTES)
-        .max_decoding_message_size(MAX_MESSAGE_SIZE_BYTES);
+        .max_decoding_message_size(MAX_MESSAGE_SIZE_BYTES)
+        // Clients compress raw y4m chunks
+        .accept_compressed(CompressionEncoding::Gzip);
 
     let listen_address = settings.node.address.parse()?;
     info!(
@@ -59,6 +59,11 @@ pub struct Cli {
     #[arg(long, help = "Supported encoders", env = "NODE_ENCODERS")]
     pub encoders: Option<String>,
 
+    /// Encoder backends offered to clients (comma-separated, e.g.
+    /// ffmpeg,svt-av1). Detected from installed executables if not set.
+    #[arg(long, help = "Supported encoder backends", env = "NODE_BACKENDS")]
+    pub backends: Option<String>,
+
     /// Disable heartbeat service to constellation
     #[arg(long, help = "Disable heartbeat to constellation")]
     pub no_heartbeat: bool,
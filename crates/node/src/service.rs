@@ -1,13 +1,26 @@
 use std::{fs, path::PathBuf};
 
-use ferris_swarm_core::chunk::Chunk;
+use ferris_swarm_core::{
+    chunk::{chunk_file_stem, Chunk, ChunkTransport, PassMode},
+    validate_attachment_name,
+    AudioOptions,
+    ChunkAttachment,
+    NodeCapabilities,
+    DEFAULT_ENCODER_BACKEND,
+};
 use ferris_swarm_proto::protos::video_encoding::{
     video_encoding_service_server::VideoEncodingService,
+    CapabilitiesRequest,
+    CapabilitiesResponse,
+    EncodeAudioRequest,
+    EncodeAudioResponse,
+    EncodeChunkPart,
     EncodeChunkRequest,
     EncodeChunkResponse,
 };
-use ferris_swarm_video::ChunkEncoder;
-use tonic::{Request, Response, Status};
+use ferris_swarm_video::{encode_audio, ChunkEncoder};
+use tokio::io::AsyncWriteExt;
+use tonic::{Request, Response, Status, Streaming};
 use tracing::{debug, error, info, instrument, warn};
 
 /// Implements the gRPC VideoEncodingService for a node.
@@ -15,10 +28,12 @@ use tracing::{debug, error, info, instrument, warn};
 pub struct NodeEncodingService {
     /// Base temporary directory for this node's operations.
     node_temp_dir: PathBuf,
+    /// Capabilities reported to clients before they dispatch work.
+    capabilities:  NodeCapabilities,
 }
 
 impl NodeEncodingService {
-    pub fn new(node_temp_dir: PathBuf) -> Self {
+    pub fn new(node_temp_dir: PathBuf, capabilities: NodeCapabilities) -> Self {
         // Ensure the base temp directory for the node exists
         if let Err(e) = fs::create_dir_all(&node_temp_dir) {
             // Panicking here as node cannot function without its temp directory
@@ -33,6 +48,7 @@ impl NodeEncodingService {
         );
         Self {
             node_temp_dir,
+            capabilities,
         }
     }
 
@@ -44,18 +60,19 @@ impl NodeEncodingService {
     fn get_locally_encoded_dir(&self) -> PathBuf {
         self.node_temp_dir.join("locally_encoded")
     }
-}
 
-#[tonic::async_trait]
-impl VideoEncodingService for NodeEncodingService {
-    #[instrument(skip(self, request), fields(chunk_index = request.get_ref().chunk_index))]
-    async fn encode_chunk(
-        &self,
-        request: Request<EncodeChunkRequest>,
-    ) -> Result<Response<EncodeChunkResponse>, Status> {
-        let req = request.into_inner();
-        info!("Received encode request for chunk {}", req.chunk_index);
+    fn get_audio_dir(&self) -> PathBuf {
+        self.node_temp_dir.join("audio")
+    }
 
+    /// Creates the working directories and returns the path the data of the
+    /// chunk `req` describes is received into, named by `chunk_stem`.
+    #[allow(clippy::result_large_err)] // Status is what tonic answers with
+    fn prepare_received_input(
+        &self,
+        req: &EncodeChunkRequest,
+        chunk_stem: &str,
+    ) -> Result<PathBuf, Status> {
         let received_chunks_dir = self.get_received_chunks_dir();
         let locally_encoded_dir = self.get_locally_encoded_dir();
 
@@ -75,22 +92,61 @@ impl VideoEncodingService for NodeEncodingService {
             Status::internal("Node temporary directory error")
         })?;
 
-        let temp_input_path =
-            received_chunks_dir.join(format!("chunk_{}_received.mkv", req.chunk_index));
+        let transport: ChunkTransport = req.transport().into();
+        Ok(received_chunks_dir.join(format!("{}_received.{}", chunk_stem, transport.extension())))
+    }
+
+    /// Stem of the files of one request. Renditions of the same chunk, and
+    /// the same chunk of jobs sharing the node, may be encoded side by side.
+    fn chunk_stem(req: &EncodeChunkRequest) -> String {
+        let rendition = Some(req.rendition.as_str()).filter(|name| !name.is_empty());
+        format!(
+            "{}_{}",
+            chunk_file_stem(req.chunk_index as usize, rendition),
+            uuid::Uuid::new_v4().simple()
+        )
+    }
+
+    /// Encodes the chunk `req` describes from the data received into
+    /// `temp_input_path` and answers with the encoded data.
+    #[allow(clippy::result_large_err)] // Status is what tonic answers with
+    fn encode_received_chunk(
+        &self,
+        req: EncodeChunkRequest,
+        chunk_stem: &str,
+        temp_input_path: PathBuf,
+    ) -> Result<Response<EncodeChunkResponse>, Status> {
+        let received_chunks_dir = self.get_received_chunks_dir();
+        let rendition = Some(req.rendition.as_str()).filter(|name| !name.is_empty());
         let temp_output_path =
-            locally_encoded_dir.join(format!("chunk_{}_encoded.mkv", req.chunk_index));
+            self.get_locally_encoded_dir().join(format!("{}_encoded.mkv", chunk_stem));
 
-        debug!(
-            "Writing received chunk {} data to temp file: {:?}",
-            req.chunk_index, temp_input_path
-        );
-        fs::write(&temp_input_path, &req.chunk_data).map_err(|e| {
-            error!(
-                "Node: Failed to write chunk {} data to temp file {:?}: {}",
-                req.chunk_index, temp_input_path, e
-            );
-            Status::internal("Failed to write received chunk data to file")
-        })?;
+        // Side files go into a directory of the chunk's own
+        let attachments_dir = received_chunks_dir.join(format!("{}_attachments", chunk_stem));
+        let mut attachments = Vec::with_capacity(req.attachments.len());
+        for attachment in &req.attachments {
+            validate_attachment_name(&attachment.name).map_err(|e| {
+                error!(
+                    "Node: Rejected attachment of chunk {}: {}",
+                    req.chunk_index, e
+                );
+                Status::invalid_argument(e.to_string())
+            })?;
+            let path = attachments_dir.join(&attachment.name);
+            fs::create_dir_all(&attachments_dir)
+                .and_then(|_| fs::write(&path, &attachment.data))
+                .map_err(|e| {
+                    error!(
+                        "Node: Failed to write attachment '{}' of chunk {} to {:?}: {}",
+                        attachment.name, req.chunk_index, path, e
+                    );
+                    Status::internal("Failed to write chunk attachment to file")
+                })?;
+            attachments.push(ChunkAttachment {
+                name: attachment.name.clone(),
+                path,
+            });
+        }
 
         let chunk_to_encode = Chunk::new(
             temp_input_path.clone(), // Path to the data just saved by node
@@ -100,9 +156,33 @@ impl VideoEncodingService for NodeEncodingService {
         .map_err(|e| {
             error!("Node: Failed to create chunk: {}", e);
             Status::internal("Failed to create chunk")
-        })?;
+        })?
+        .with_pass_mode(if req.two_pass {
+            PassMode::TwoPass
+        } else {
+            PassMode::OnePass
+        })
+        .with_encoder_backend(if req.encoder_backend.is_empty() {
+            DEFAULT_ENCODER_BACKEND
+        } else {
+            req.encoder_backend.as_str()
+        })
+        .with_profile(req.profile.clone().map(Into::into))
+        .with_color(req.color.clone().map(Into::into))
+        .with_rendition(rendition.map(str::to_string))
+        .with_video_filter(Some(req.video_filter.clone()).filter(|filter| !filter.is_empty()))
+        .with_attachments(attachments);
 
-        match chunk_to_encode.encode(temp_output_path.clone()) {
+        let encode_result = chunk_to_encode.encode(temp_output_path.clone());
+        if attachments_dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&attachments_dir) {
+                warn!(
+                    "Node: Failed to remove attachments dir {:?}: {}",
+                    attachments_dir, e
+                );
+            }
+        }
+        match encode_result {
             Ok(encoded_chunk_info) => {
                 let final_encoded_path = encoded_chunk_info.encoded_path.ok_or_else(|| {
                     error!(
@@ -176,3 +256,157 @@ impl VideoEncodingService for NodeEncodingService {
         }
     }
 }
+
+#[tonic::async_trait]
+impl VideoEncodingService for NodeEncodingService {
+    #[instrument(skip(self, request), fields(chunk_index = request.get_ref().chunk_index))]
+    async fn encode_chunk(
+        &self,
+        request: Request<EncodeChunkRequest>,
+    ) -> Result<Response<EncodeChunkResponse>, Status> {
+        let mut req = request.into_inner();
+        info!("Received encode request for chunk {}", req.chunk_index);
+
+        let chunk_stem = Self::chunk_stem(&req);
+        let temp_input_path = self.prepare_received_input(&req, &chunk_stem)?;
+        debug!(
+            "Writing received chunk {} data to temp file: {:?}",
+            req.chunk_index, temp_input_path
+        );
+        fs::write(&temp_input_path, std::mem::take(&mut req.chunk_data)).map_err(|e| {
+            error!(
+                "Node: Failed to write chunk {} data to temp file {:?}: {}",
+                req.chunk_index, temp_input_path, e
+            );
+            Status::internal("Failed to write received chunk data to file")
+        })?;
+
+        self.encode_received_chunk(req, &chunk_stem, temp_input_path)
+    }
+
+    #[instrument(skip(self, request))]
+    async fn encode_chunk_stream(
+        &self,
+        request: Request<Streaming<EncodeChunkPart>>,
+    ) -> Result<Response<EncodeChunkResponse>, Status> {
+        let mut parts = request.into_inner();
+        let req =
+            parts.message().await?.and_then(|part| part.request).ok_or_else(|| {
+                Status::invalid_argument("First chunk part must carry the request")
+            })?;
+        info!(
+            "Receiving streamed chunk {} as {:?}",
+            req.chunk_index,
+            req.transport()
+        );
+
+        let chunk_stem = Self::chunk_stem(&req);
+        let temp_input_path = self.prepare_received_input(&req, &chunk_stem)?;
+        let write_error = |e: std::io::Error| {
+            error!(
+                "Node: Failed to write chunk {} data to temp file {:?}: {}",
+                req.chunk_index, temp_input_path, e
+            );
+            Status::internal("Failed to write received chunk data to file")
+        };
+        // Written as it arrives; raw intermediates don't fit in memory
+        let mut file = tokio::fs::File::create(&temp_input_path).await.map_err(write_error)?;
+        let mut received_bytes = 0;
+        while let Some(part) = parts.message().await? {
+            file.write_all(&part.data).await.map_err(write_error)?;
+            received_bytes += part.data.len();
+        }
+        file.flush().await.map_err(write_error)?;
+        drop(file);
+        debug!(
+            "Received {} bytes of chunk {} into {:?}",
+            received_bytes, req.chunk_index, temp_input_path
+        );
+
+        self.encode_received_chunk(req, &chunk_stem, temp_input_path)
+    }
+
+    #[instrument(skip(self, _request))]
+    async fn get_capabilities(
+        &self,
+        _request: Request<CapabilitiesRequest>,
+    ) -> Result<Response<CapabilitiesResponse>, Status> {
+        debug!("Reporting capabilities: {:?}", self.capabilities);
+        Ok(Response::new(self.capabilities.clone().into()))
+    }
+
+    #[instrument(skip(self, request))]
+    async fn encode_audio(
+        &self,
+        request: Request<EncodeAudioRequest>,
+    ) -> Result<Response<EncodeAudioResponse>, Status> {
+        let req = request.into_inner();
+        info!(
+            "Received audio encode request ({} bytes, codec {})",
+            req.audio_data.len(),
+            req.codec
+        );
+
+        let audio_dir = self.get_audio_dir();
+        fs::create_dir_all(&audio_dir).map_err(|e| {
+            error!("Node: Failed to create audio dir {:?}: {}", audio_dir, e);
+            Status::internal("Node temporary directory error")
+        })?;
+
+        // Audio requests carry no index, so a unique name keeps jobs apart
+        let request_id = uuid::Uuid::new_v4();
+        let temp_input_path = audio_dir.join(format!("audio_{}_received.mka", request_id));
+        let temp_output_path = audio_dir.join(format!("audio_{}_encoded.mka", request_id));
+        fs::write(&temp_input_path, &req.audio_data).map_err(|e| {
+            error!(
+                "Node: Failed to write audio data to temp file {:?}: {}",
+                temp_input_path, e
+            );
+            Status::internal("Failed to write received audio data to file")
+        })?;
+
+        let options = AudioOptions {
+            codec: Some(req.codec),
+            bitrate: Some(req.bitrate).filter(|bitrate| !bitrate.is_empty()),
+            channel_layout: Some(req.channel_layout).filter(|layout| !layout.is_empty()),
+            loudness: req.loudness.map(Into::into),
+            ..AudioOptions::default()
+        };
+        let result =
+            encode_audio(&temp_input_path, &temp_output_path, &options).and_then(|loudness| {
+                fs::read(&temp_output_path).map(|data| (data, loudness)).map_err(Into::into)
+            });
+
+        for path in [&temp_input_path, &temp_output_path] {
+            if path.exists() {
+                if let Err(e) = fs::remove_file(path) {
+                    warn!("Node: Failed to remove temp audio file {:?}: {}", path, e);
+                }
+            }
+        }
+
+        match result {
+            Ok((encoded_audio_data, loudness)) => {
+                info!(
+                    "Node: Successfully encoded audio, size of encoded data: {} bytes",
+                    encoded_audio_data.len()
+                );
+                Ok(Response::new(EncodeAudioResponse {
+                    encoded_audio_data,
+                    success: true,
+                    error_message: String::new(),
+                    loudness: loudness.into_iter().map(Into::into).collect(),
+                }))
+            },
+            Err(e) => {
+                error!("Node: Failed to encode audio: {}", e);
+                Ok(Response::new(EncodeAudioResponse {
+                    encoded_audio_data: Vec::new(),
+                    success:            false,
+                    error_message:      e.to_string(),
+                    loudness:           Vec::new(),
+                }))
+            },
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use ferris_swarm_core::error::VideoEncodeError;
+use ferris_swarm_core::{error::VideoEncodeError, TimeRange};
 use ferris_swarm_video as ffmpeg;
 use tracing::{debug, info, instrument};
 
@@ -8,16 +8,54 @@ use tracing::{debug, info, instrument};
 pub fn split_video_into_segments(
     input_path: &Path,
     segment_duration: f64,
-    segment_dir: &Path, // This is a subdirectory within the JobTempConfig
+    segment_dir: &Path,   // This is a subdirectory within the JobTempConfig
+    ranges: &[TimeRange], // Empty for the whole input
 ) -> Result<Vec<PathBuf>, VideoEncodeError> {
     debug!(
-        "Orchestrating video split: input={:?}, duration={}, segment_output_dir={:?}",
-        input_path, segment_duration, segment_dir
+        "Orchestrating video split: input={:?}, duration={}, segment_output_dir={:?}, ranges={:?}",
+        input_path, segment_duration, segment_dir, ranges
     );
 
     // The segment_dir path is already prepared by JobTempConfig
     let segmented_files =
-        ffmpeg::segmenter::segment_video(input_path, segment_duration, segment_dir)?;
+        ffmpeg::segmenter::segment_video_ranges(input_path, segment_duration, segment_dir, ranges)?;
+
+    info!(
+        "Video segmentation complete: {} segments created in {:?}",
+        segmented_files.len(),
+        segment_dir
+    );
+
+    Ok(segmented_files)
+}
+
+/// Splits the video like `split_video_into_segments`, handing each segment
+/// to `on_segment` as soon as it is complete so it can be dispatched while
+/// the rest of the input is still being split.
+#[instrument(skip(on_segment))]
+pub fn stream_video_segments<F>(
+    input_path: &Path,
+    segment_duration: f64,
+    segment_dir: &Path,
+    ranges: &[TimeRange],
+    on_segment: F,
+) -> Result<Vec<PathBuf>, VideoEncodeError>
+where
+    F: FnMut(&Path),
+{
+    debug!(
+        "Orchestrating streaming video split: input={:?}, duration={}, segment_output_dir={:?}, \
+         ranges={:?}",
+        input_path, segment_duration, segment_dir, ranges
+    );
+
+    let segmented_files = ffmpeg::segmenter::segment_video_streaming(
+        input_path,
+        segment_duration,
+        segment_dir,
+        ranges,
+        on_segment,
+    )?;
 
     info!(
         "Video segmentation complete: {} segments created in {:?}",
@@ -0,0 +1,217 @@
+//! Conversions between protocol messages and core types.
+use ferris_swarm_core::{NodeCapabilities, RateControl};
+
+use crate::protos::video_encoding::{
+    CapabilitiesResponse,
+    ChunkTransport,
+    ColorMetadata,
+    ContentLightLevel,
+    EncoderProfile,
+    LoudnessMeasurement,
+    LoudnessTarget,
+    MasteringDisplay,
+    RateControlMode,
+    TrackLoudness,
+};
+
+impl From<NodeCapabilities> for CapabilitiesResponse {
+    fn from(capabilities: NodeCapabilities) -> Self {
+        Self {
+            max_concurrent_chunks: capabilities.max_concurrent_chunks,
+            supported_encoders:    capabilities.supported_encoders,
+            supported_backends:    capabilities.supported_backends,
+            cpu_cores:             capabilities.cpu_cores,
+            memory_gb:             capabilities.memory_gb,
+        }
+    }
+}
+
+impl From<CapabilitiesResponse> for NodeCapabilities {
+    fn from(response: CapabilitiesResponse) -> Self {
+        Self {
+            max_concurrent_chunks: response.max_concurrent_chunks,
+            supported_encoders:    response.supported_encoders,
+            supported_backends:    response.supported_backends,
+            cpu_cores:             response.cpu_cores,
+            memory_gb:             response.memory_gb,
+        }
+    }
+}
+
+impl From<ferris_swarm_core::ChunkTransport> for ChunkTransport {
+    fn from(transport: ferris_swarm_core::ChunkTransport) -> Self {
+        match transport {
+            ferris_swarm_core::ChunkTransport::Source => ChunkTransport::Source,
+            ferris_swarm_core::ChunkTransport::Ffv1 => ChunkTransport::Ffv1,
+            ferris_swarm_core::ChunkTransport::Y4m => ChunkTransport::Y4m,
+        }
+    }
+}
+
+impl From<ChunkTransport> for ferris_swarm_core::ChunkTransport {
+    fn from(transport: ChunkTransport) -> Self {
+        match transport {
+            ChunkTransport::Source => ferris_swarm_core::ChunkTransport::Source,
+            ChunkTransport::Ffv1 => ferris_swarm_core::ChunkTransport::Ffv1,
+            ChunkTransport::Y4m => ferris_swarm_core::ChunkTransport::Y4m,
+        }
+    }
+}
+
+impl From<ferris_swarm_core::EncoderProfile> for EncoderProfile {
+    fn from(profile: ferris_swarm_core::EncoderProfile) -> Self {
+        let (mode, value) = match profile.rate_control {
+            RateControl::Crf(value) => (RateControlMode::Crf, value as f64),
+            RateControl::Qp(value) => (RateControlMode::Qp, value as f64),
+            RateControl::Bitrate(kbps) => (RateControlMode::Bitrate, kbps as f64),
+        };
+        Self {
+            codec:              profile.codec,
+            rate_control_mode:  mode as i32,
+            rate_control_value: value,
+            preset:             profile.preset.unwrap_or_default(),
+            pixel_format:       profile.pixel_format.unwrap_or_default(),
+            keyint:             profile.keyint.unwrap_or_default(),
+            extra_args:         profile.extra_args,
+        }
+    }
+}
+
+impl From<EncoderProfile> for ferris_swarm_core::EncoderProfile {
+    fn from(profile: EncoderProfile) -> Self {
+        let rate_control = match profile.rate_control_mode() {
+            RateControlMode::Crf => RateControl::Crf(profile.rate_control_value as f32),
+            RateControlMode::Qp => RateControl::Qp(profile.rate_control_value as u32),
+            RateControlMode::Bitrate => RateControl::Bitrate(profile.rate_control_value as u32),
+        };
+        Self {
+            codec: profile.codec,
+            rate_control,
+            preset: Some(profile.preset).filter(|preset| !preset.is_empty()),
+            pixel_format: Some(profile.pixel_format).filter(|format| !format.is_empty()),
+            keyint: Some(profile.keyint).filter(|keyint| *keyint > 0),
+            extra_args: profile.extra_args,
+        }
+    }
+}
+
+impl From<ferris_swarm_core::LoudnessTarget> for LoudnessTarget {
+    fn from(target: ferris_swarm_core::LoudnessTarget) -> Self {
+        Self {
+            integrated:     target.integrated,
+            true_peak:      target.true_peak,
+            loudness_range: target.loudness_range,
+        }
+    }
+}
+
+impl From<LoudnessTarget> for ferris_swarm_core::LoudnessTarget {
+    fn from(target: LoudnessTarget) -> Self {
+        Self {
+            integrated:     target.integrated,
+            true_peak:      target.true_peak,
+            loudness_range: target.loudness_range,
+        }
+    }
+}
+
+impl From<ferris_swarm_core::LoudnessMeasurement> for LoudnessMeasurement {
+    fn from(measurement: ferris_swarm_core::LoudnessMeasurement) -> Self {
+        Self {
+            integrated:     measurement.integrated,
+            true_peak:      measurement.true_peak,
+            loudness_range: measurement.loudness_range,
+            threshold:      measurement.threshold,
+            target_offset:  measurement.target_offset,
+        }
+    }
+}
+
+impl From<LoudnessMeasurement> for ferris_swarm_core::LoudnessMeasurement {
+    fn from(measurement: LoudnessMeasurement) -> Self {
+        Self {
+            integrated:     measurement.integrated,
+            true_peak:      measurement.true_peak,
+            loudness_range: measurement.loudness_range,
+            threshold:      measurement.threshold,
+            target_offset:  measurement.target_offset,
+        }
+    }
+}
+
+impl From<ferris_swarm_core::TrackLoudness> for TrackLoudness {
+    fn from(loudness: ferris_swarm_core::TrackLoudness) -> Self {
+        Self {
+            track:              loudness.track as u32,
+            input:              Some(loudness.input.into()),
+            output:             loudness.output.map(Into::into),
+            normalization_type: loudness.normalization_type,
+        }
+    }
+}
+
+impl From<TrackLoudness> for ferris_swarm_core::TrackLoudness {
+    fn from(loudness: TrackLoudness) -> Self {
+        Self {
+            track:              loudness.track as usize,
+            input:              loudness.input.map(Into::into).unwrap_or_default(),
+            output:             loudness.output.map(Into::into),
+            normalization_type: loudness.normalization_type,
+        }
+    }
+}
+
+impl From<ferris_swarm_core::ColorMetadata> for ColorMetadata {
+    fn from(color: ferris_swarm_core::ColorMetadata) -> Self {
+        Self {
+            color_primaries:   color.color_primaries.unwrap_or_default(),
+            transfer:          color.transfer.unwrap_or_default(),
+            matrix:            color.matrix.unwrap_or_default(),
+            range:             color.range.unwrap_or_default(),
+            mastering_display: color.mastering_display.map(|display| MasteringDisplay {
+                red_x:         display.red.0,
+                red_y:         display.red.1,
+                green_x:       display.green.0,
+                green_y:       display.green.1,
+                blue_x:        display.blue.0,
+                blue_y:        display.blue.1,
+                white_point_x: display.white_point.0,
+                white_point_y: display.white_point.1,
+                min_luminance: display.min_luminance,
+                max_luminance: display.max_luminance,
+            }),
+            content_light:     color.content_light.map(|light| ContentLightLevel {
+                max_cll:  light.max_cll,
+                max_fall: light.max_fall,
+            }),
+        }
+    }
+}
+
+impl From<ColorMetadata> for ferris_swarm_core::ColorMetadata {
+    fn from(color: ColorMetadata) -> Self {
+        let specified = |value: String| Some(value).filter(|value| !value.is_empty());
+        Self {
+            color_primaries:   specified(color.color_primaries),
+            transfer:          specified(color.transfer),
+            matrix:            specified(color.matrix),
+            range:             specified(color.range),
+            mastering_display: color.mastering_display.map(|display| {
+                ferris_swarm_core::MasteringDisplay {
+                    red:           (display.red_x, display.red_y),
+                    green:         (display.green_x, display.green_y),
+                    blue:          (display.blue_x, display.blue_y),
+                    white_point:   (display.white_point_x, display.white_point_y),
+                    min_luminance: display.min_luminance,
+                    max_luminance: display.max_luminance,
+                }
+            }),
+            content_light:     color.content_light.map(|light| {
+                ferris_swarm_core::ContentLightLevel {
+                    max_cll:  light.max_cll,
+                    max_fall: light.max_fall,
+                }
+            }),
+        }
+    }
+}
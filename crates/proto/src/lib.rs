@@ -1,3 +1,4 @@
+pub mod convert;
 pub mod protos;
 
 pub use protos::video_encoding::*;
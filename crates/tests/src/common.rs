@@ -25,6 +25,108 @@ pub mod mock_data {
         Chunk::new(temp_path, 0, vec!["test_param".to_string()])
             .expect("Failed to create test chunk")
     }
+
+    /// ffprobe stream listing of a file with video, three audio tracks (one
+    /// commentary), two subtitles and a font attachment
+    pub const PROBED_STREAMS_JSON: &str = r#"{
+    "streams": [
+        { "index": 0, "codec_name": "h264", "codec_type": "video",
+          "disposition": { "default": 1, "comment": 0 } },
+        { "index": 1, "codec_name": "aac", "codec_type": "audio",
+          "disposition": { "default": 1, "comment": 0 }, "tags": { "language": "eng" } },
+        { "index": 2, "codec_name": "ac3", "codec_type": "audio",
+          "disposition": { "default": 0, "comment": 1 },
+          "tags": { "language": "eng", "title": "Commentary" } },
+        { "index": 3, "codec_name": "aac", "codec_type": "audio",
+          "disposition": { "default": 0, "comment": 0 }, "tags": { "language": "jpn" } },
+        { "index": 4, "codec_name": "subrip", "codec_type": "subtitle",
+          "disposition": { "default": 0, "forced": 1 }, "tags": { "language": "eng" } },
+        { "index": 5, "codec_name": "ass", "codec_type": "subtitle",
+          "disposition": { "default": 0, "forced": 0 }, "tags": { "language": "ger" } },
+        { "index": 6, "codec_name": "ttf", "codec_type": "attachment" }
+    ]
+    }"#;
+
+    /// ffprobe stream and first frame of an HDR10 HEVC source. The container
+    /// leaves the range unspecified, the frame carries it and the side data.
+    pub const PROBED_HDR10_JSON: &str = r#"{
+    "frames": [
+        { "media_type": "video", "color_range": "tv", "color_space": "bt2020nc",
+          "color_primaries": "bt2020", "color_transfer": "smpte2084",
+          "side_data_list": [
+            { "side_data_type": "Mastering display metadata",
+              "red_x": "34000/50000", "red_y": "16000/50000",
+              "green_x": "13250/50000", "green_y": "34500/50000",
+              "blue_x": "7500/50000", "blue_y": "3000/50000",
+              "white_point_x": "15635/50000", "white_point_y": "16450/50000",
+              "min_luminance": "50/10000", "max_luminance": "10000000/10000" },
+            { "side_data_type": "Content light level metadata",
+              "max_content": 1000, "max_average": 400 }
+          ] }
+    ],
+    "streams": [
+        { "index": 0, "codec_name": "hevc", "codec_type": "video",
+          "color_range": "unknown", "color_space": "bt2020nc",
+          "color_transfer": "smpte2084", "color_primaries": "bt2020" }
+    ]
+    }"#;
+
+    /// ffprobe stream timing of a Matroska output: durations only in tags,
+    /// audio starting 20 ms after the video
+    pub const PROBED_TIMING_JSON: &str = r#"{
+    "streams": [
+        { "index": 0, "codec_type": "video", "start_time": "0.000000",
+          "nb_read_packets": "1500", "tags": { "DURATION": "00:01:00.040000000" } },
+        { "index": 1, "codec_type": "audio", "start_time": "0.020000",
+          "nb_read_packets": "2813", "tags": { "DURATION-eng": "00:01:00.020000000" } },
+        { "index": 2, "codec_type": "subtitle", "start_time": "0.000000",
+          "nb_read_packets": "12" }
+    ],
+    "format": { "duration": "60.040000" }
+    }"#;
+
+    /// `mkvmerge -J` of a Matroska file with its audio track ahead of the
+    /// video
+    pub const MKVMERGE_IDENTIFY_JSON: &str = r#"{
+    "container": { "recognized": true, "supported": true, "type": "Matroska" },
+    "tracks": [
+        { "codec": "Opus", "id": 0, "type": "audio" },
+        { "codec": "AVC/H.264/MPEG-4p10", "id": 1, "type": "video" },
+        { "codec": "SubRip/SRT", "id": 2, "type": "subtitles" }
+    ],
+    "attachments": [
+        { "file_name": "font.ttf", "id": 1 }
+    ]
+    }"#;
+
+    /// Writes an IVF file with one-byte frames at `timestamps`
+    pub fn write_test_ivf(path: &std::path::Path, fourcc: &[u8; 4], timestamps: &[u64]) {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DKIF");
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&32u16.to_le_bytes());
+        data.extend_from_slice(fourcc);
+        data.extend_from_slice(&64u16.to_le_bytes());
+        data.extend_from_slice(&48u16.to_le_bytes());
+        data.extend_from_slice(&25u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&(timestamps.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            data.extend_from_slice(&1u32.to_le_bytes());
+            data.extend_from_slice(&timestamp.to_le_bytes());
+            data.push(i as u8);
+        }
+        std::fs::write(path, data).unwrap();
+    }
+
+    /// ffmpeg log of the ssim and psnr filters at the end of a comparison
+    pub const QUALITY_LOG: &str = "\
+[Parsed_ssim_4 @ 0x55d0c8a3b2c0] SSIM Y:0.978123 (16.600000) U:0.990001 (20.000000) V:0.991234 \
+                                   (20.500000) All:0.982345 (17.525000)
+[Parsed_psnr_5 @ 0x55d0c8a3c3d0] PSNR y:39.876543 u:44.123456 v:44.654321 average:41.234567 \
+                                   min:35.000000 max:48.000000
+";
 }
 
 /// Network testing utilities
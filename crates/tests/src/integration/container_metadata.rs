@@ -0,0 +1,147 @@
+// Chapters, attachments and global tags through segmenting and concatenation
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use ferris_swarm_core::{AudioOptions, StreamSelection};
+use ferris_swarm_video::{
+    concatenate_videos_ffmpeg,
+    concatenate_videos_mkvmerge,
+    extract_container_metadata,
+    extract_non_video_streams,
+    segment_video,
+    verify_ffmpeg,
+    verify_mkvmerge,
+    ContainerMetadata,
+    MuxInputs,
+};
+
+use crate::common::{create_temp_dir, init_test_logging};
+
+pub const SOURCE_METADATA: &str = ";FFMETADATA1
+title=Round Trip
+comment=Kept through the pipeline
+[CHAPTER]
+TIMEBASE=1/1000
+START=0
+END=2000
+title=Opening
+[CHAPTER]
+TIMEBASE=1/1000
+START=2000
+END=4000
+title=Ending
+";
+
+/// Four seconds of test video and audio with two chapters, global tags and
+/// a font attachment.
+pub fn create_source(dir: &Path) -> PathBuf {
+    let metadata_path = dir.join("source_metadata.txt");
+    std::fs::write(&metadata_path, SOURCE_METADATA).unwrap();
+    let font_path = dir.join("subtitle_font.ttf");
+    std::fs::write(&font_path, b"not really a font").unwrap();
+
+    let source_path = dir.join("source.mkv");
+    let status = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "lavfi", "-i", "testsrc=duration=4:size=160x120:rate=25"])
+        .args(["-f", "lavfi", "-i", "sine=duration=4"])
+        .args(["-f", "ffmetadata", "-i"])
+        .arg(&metadata_path)
+        .arg("-attach")
+        .arg(&font_path)
+        .args(["-metadata:s:t", "mimetype=application/x-truetype-font"])
+        .args(["-map", "0", "-map", "1", "-map_metadata", "2", "-map_chapters", "2"])
+        .args(["-c:v", "mpeg4", "-g", "25", "-c:a", "aac"])
+        .arg(&source_path)
+        .status()
+        .expect("Failed to run ffmpeg");
+    assert!(status.success(), "Failed to create test source");
+    source_path
+}
+
+pub fn assert_metadata_preserved(output: &Path, scratch_dir: &Path) {
+    let metadata: ContainerMetadata = extract_container_metadata(output, scratch_dir).unwrap();
+
+    assert_eq!(metadata.title(), Some("Round Trip"));
+    assert!(metadata
+        .tags
+        .iter()
+        .any(|(key, value)| key.eq_ignore_ascii_case("comment")
+            && value == "Kept through the pipeline"));
+
+    let titles: Vec<_> = metadata.chapters.iter().map(|c| c.title.as_deref()).collect();
+    assert_eq!(titles, vec![Some("Opening"), Some("Ending")]);
+    assert!((metadata.chapters[1].start - 2.0).abs() < 0.01);
+
+    assert_eq!(metadata.attachments.len(), 1);
+    assert_eq!(metadata.attachments[0].filename, "subtitle_font.ttf");
+    assert_eq!(
+        metadata.attachments[0].mimetype,
+        "application/x-truetype-font"
+    );
+    assert_eq!(
+        std::fs::read(&metadata.attachments[0].path).unwrap(),
+        b"not really a font"
+    );
+}
+
+#[test]
+fn test_container_metadata_round_trip() {
+    init_test_logging();
+
+    if verify_ffmpeg().is_err() {
+        println!("FFmpeg not found, skipping container metadata round trip");
+        return;
+    }
+
+    let temp_dir = create_temp_dir();
+    let source = create_source(temp_dir.path());
+
+    let mut segments = segment_video(&source, 2.0, &temp_dir.path().join("segments")).unwrap();
+    segments.sort();
+    let non_video = extract_non_video_streams(
+        &source,
+        temp_dir.path(),
+        &AudioOptions::default(),
+        &StreamSelection::default(),
+        &[],
+    )
+    .unwrap();
+    let container_metadata = extract_container_metadata(&source, temp_dir.path()).unwrap();
+    assert_eq!(container_metadata.chapters.len(), 2);
+    assert_eq!(container_metadata.attachments.len(), 1);
+
+    let mux_inputs = MuxInputs {
+        non_video_stream_file: non_video.as_deref(),
+        container_metadata: Some(&container_metadata),
+        ..MuxInputs::default()
+    };
+
+    let ffmpeg_output = temp_dir.path().join("ffmpeg_output.mkv");
+    concatenate_videos_ffmpeg(
+        segments.clone(),
+        &mux_inputs,
+        &ffmpeg_output,
+        temp_dir.path(),
+        segments.len(),
+    )
+    .unwrap();
+    assert_metadata_preserved(&ffmpeg_output, &temp_dir.path().join("ffmpeg_check"));
+
+    if verify_mkvmerge().is_err() {
+        println!("mkvmerge not found, skipping mkvmerge round trip");
+        return;
+    }
+    let mkvmerge_output = temp_dir.path().join("mkvmerge_output.mkv");
+    concatenate_videos_mkvmerge(
+        segments.clone(),
+        &mux_inputs,
+        &mkvmerge_output,
+        temp_dir.path(),
+        segments.len(),
+    )
+    .unwrap();
+    assert_metadata_preserved(&mkvmerge_output, &temp_dir.path().join("mkvmerge_check"));
+}
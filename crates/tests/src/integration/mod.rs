@@ -1,4 +1,5 @@
 // Integration tests for the complete system
+pub mod container_metadata;
 pub mod distributed_encoding;
 pub mod end_to_end;
 pub mod service_communication;
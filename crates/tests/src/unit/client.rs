@@ -1,5 +1,36 @@
 // Client service unit tests
-use crate::common::init_test_logging;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ferris_swarm_client::{
+    batch::{glob_match, parse_job_list, InboxScanner, JobSource, OutputTemplate},
+    comms::NodeConnection,
+    config::split_arguments,
+    daemon::{run_daemon, DaemonJobStatus, DaemonState, JobSubmission},
+    job::{JobBuilder, JobEvent, NodeSource},
+    preview::{
+        estimate_encode_seconds,
+        evenly_spaced,
+        extrapolate,
+        most_complex,
+        preview_file_path,
+    },
+    progress::{format_seconds, JobProgress},
+    tasks::{encode_chunk_stream_on_nodes, EncodingTaskState},
+};
+use ferris_swarm_config::settings::Settings;
+use ferris_swarm_core::TimeRange;
+use ferris_swarm_proto::video_encoding_service_client::VideoEncodingServiceClient;
+use tokio::sync::Semaphore;
+use tonic::transport::Endpoint;
+
+use crate::common::{create_temp_dir, init_test_logging, mock_data};
 
 #[test]
 fn test_client_service_placeholder() {
@@ -8,3 +39,376 @@ fn test_client_service_placeholder() {
     // For example: CLI parsing, configuration validation, etc.
     assert!(true);
 }
+
+#[test]
+fn test_split_arguments_keeps_quoted_values() {
+    init_test_logging();
+
+    assert_eq!(
+        split_arguments(r#" -c:v libx264  -vf "drawtext=text='a b'" -x264-params 'ref=4' "#),
+        vec!["-c:v", "libx264", "-vf", "drawtext=text='a b'", "-x264-params", "ref=4"]
+    );
+    assert!(split_arguments("   ").is_empty());
+}
+
+#[test]
+fn test_preview_selection_and_estimates() {
+    init_test_logging();
+
+    assert_eq!(evenly_spaced(3, 10), vec![1, 5, 8]);
+    assert_eq!(evenly_spaced(1, 4), vec![2]);
+    assert_eq!(evenly_spaced(5, 3), vec![0, 1, 2]);
+    assert_eq!(most_complex(&[1.0, 9.0, 3.0, 7.0, 5.0], 3), vec![1, 3, 4]);
+    assert_eq!(most_complex(&[2.0, 1.0], 4), vec![0, 1]);
+
+    assert_eq!(extrapolate(1_000.0, 20.0, 600.0), 30_000.0);
+    assert_eq!(extrapolate(1_000.0, 0.0, 600.0), 0.0);
+    // 4 chunks on 4 slots run in one wave, 30 chunks in 8
+    assert_eq!(estimate_encode_seconds(10.0, 4, 30, 4), 80.0);
+    assert_eq!(estimate_encode_seconds(10.0, 3, 3, 0), 10.0);
+
+    assert_eq!(
+        preview_file_path(Path::new("/tmp/preview.mkv"), Some("720p")),
+        Path::new("/tmp/preview.720p.mkv")
+    );
+    assert_eq!(
+        preview_file_path(Path::new("/tmp/preview.mkv"), None),
+        Path::new("/tmp/preview.mkv")
+    );
+}
+
+#[tokio::test]
+async fn test_chunk_stream_queue() {
+    init_test_logging();
+
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    for index in 0..3 {
+        let mut chunk = mock_data::create_test_chunk();
+        chunk.index = index;
+        sender.send(chunk).unwrap();
+    }
+    drop(sender);
+
+    // Without nodes every streamed chunk stays pending, oldest popped first
+    let state = encode_chunk_stream_on_nodes(&[], receiver, std::env::temp_dir(), None).await;
+    assert!(!state.producing);
+    assert!(state.completed_chunks.is_empty());
+    let popped: Vec<usize> = state.pending_chunks.iter().rev().map(|chunk| chunk.index).collect();
+    assert_eq!(popped, vec![0, 1, 2]);
+}
+
+#[test]
+fn test_earliest_chunks_first() {
+    init_test_logging();
+
+    let chunks = [2, 0, 3, 1]
+        .into_iter()
+        .map(|index| {
+            let mut chunk = mock_data::create_test_chunk();
+            chunk.index = index;
+            chunk
+        })
+        .collect();
+    let state = EncodingTaskState::new(chunks);
+    let popped: Vec<usize> = state.pending_chunks.iter().rev().map(|chunk| chunk.index).collect();
+    assert_eq!(popped, vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_batch_jobs() {
+    init_test_logging();
+
+    let template = OutputTemplate::new("{parent}/encoded/{stem}.av1.{ext}").unwrap();
+    assert_eq!(
+        template.output_for(Path::new("videos/clip.mp4")),
+        PathBuf::from("videos/encoded/clip.av1.mp4")
+    );
+    assert!(OutputTemplate::new("out.mkv").is_err());
+
+    assert!(glob_match("*.mkv", "episode 01.mkv"));
+    assert!(glob_match("ep??_*.mp4", "ep01_final.mp4"));
+    assert!(!glob_match("*.mkv", "episode.mp4"));
+    assert!(matches!(
+        JobSource::parse_batch("videos/*.mkv").unwrap(),
+        JobSource::Glob { pattern, .. } if pattern == "*.mkv"
+    ));
+
+    let list = "# tonight\nraw/a.mov => out/a.mkv\n\nraw/b.mov\n";
+    let template = OutputTemplate::new("out/{stem}.mkv").unwrap();
+    let jobs = parse_job_list(list, Path::new("/jobs"), Some(&template)).unwrap();
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].output_file, PathBuf::from("/jobs/out/a.mkv"));
+    assert_eq!(jobs[1].input_file, PathBuf::from("/jobs/raw/b.mov"));
+    // Template outputs are relative to the working directory, as in any batch
+    assert_eq!(jobs[1].output_file, PathBuf::from("out/b.mkv"));
+    // Both inputs would be written to out/a.mkv
+    assert!(parse_job_list(
+        "a.mov\nb/a.mov => out/a.mkv\n",
+        Path::new(""),
+        Some(&template)
+    )
+    .is_err());
+    assert!(parse_job_list("a.mov\n", Path::new("."), None).is_err());
+}
+
+#[test]
+fn test_inbox_picks_up_finished_files_once() {
+    init_test_logging();
+
+    let mut scanner = InboxScanner::default();
+    let file = PathBuf::from("inbox/a.mp4");
+    // Still growing between scans
+    assert!(scanner.ready(vec![(file.clone(), 10)]).is_empty());
+    assert!(scanner.ready(vec![(file.clone(), 20)]).is_empty());
+    assert_eq!(scanner.ready(vec![(file.clone(), 20)]), vec![file.clone()]);
+    assert!(scanner.ready(vec![(file.clone(), 20)]).is_empty());
+    // Once the input is moved away, a new file of the same name is an input
+    scanner.release(&file);
+    assert!(scanner.ready(vec![(file.clone(), 30)]).is_empty());
+    assert_eq!(scanner.ready(vec![(file.clone(), 30)]), vec![file.clone()]);
+
+    let output = PathBuf::from("inbox/a.out.mp4");
+    scanner.ignore(&output);
+    assert!(scanner.ready(vec![(output.clone(), 5)]).is_empty());
+    assert!(scanner.ready(vec![(output, 5)]).is_empty());
+}
+
+#[tokio::test]
+async fn test_daemon_jobs() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let input_file = temp_dir.path().join("clip.mp4");
+    std::fs::write(&input_file, b"video").unwrap();
+    let submission = |output: &str| JobSubmission {
+        input_file:  input_file.clone(),
+        output_file: Some(temp_dir.path().join(output)),
+    };
+
+    let (state, queue) = DaemonState::new(None);
+    let finished = state.submit(submission("out/finished.mkv")).await.unwrap();
+    let stuck = state.submit(submission("stuck.mkv")).await.unwrap();
+    assert_eq!(stuck.status, DaemonJobStatus::Queued);
+    // Already written by a queued job
+    assert!(state.submit(submission("stuck.mkv")).await.is_err());
+    // Neither an output nor a template
+    assert!(state
+        .submit(JobSubmission {
+            input_file:  input_file.clone(),
+            output_file: None,
+        })
+        .await
+        .is_err());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stuck_output = stuck.output_file.clone();
+    // Set once the stuck job's future is dropped, which cancels the
+    // `JobHandle` of a real job
+    let stuck_dropped = Arc::new(AtomicBool::new(false));
+    struct SetOnDrop(Arc<AtomicBool>);
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+    let daemon = {
+        let stuck_dropped = stuck_dropped.clone();
+        tokio::spawn(run_daemon(listener, state.clone(), queue, 2, move |job| {
+            let stuck = job.output_file == stuck_output;
+            let dropped = stuck.then(|| SetOnDrop(stuck_dropped.clone()));
+            async move {
+                if stuck {
+                    let _dropped = dropped;
+                    std::future::pending::<()>().await;
+                }
+                Ok(())
+            }
+        }))
+    };
+
+    let status_of = |id| {
+        let state = state.clone();
+        async move { state.job(id).await.unwrap().status }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while status_of(finished.id).await != DaemonJobStatus::Completed
+            || status_of(stuck.id).await != DaemonJobStatus::Running
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("jobs did not start");
+    assert!(temp_dir.path().join("out").is_dir());
+
+    let cancelled = state.cancel(stuck.id).await.unwrap();
+    assert_eq!(cancelled.status, DaemonJobStatus::Cancelled);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !stuck_dropped.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the cancelled job kept running");
+    assert_eq!(status_of(stuck.id).await, DaemonJobStatus::Cancelled);
+    // A finished job stays as it is
+    assert_eq!(
+        state.cancel(finished.id).await.unwrap().status,
+        DaemonJobStatus::Completed
+    );
+    let (other_state, _other_queue) = DaemonState::new(None);
+    let unknown = other_state.submit(submission("other.mkv")).await.unwrap();
+    assert!(state.cancel(unknown.id).await.is_none());
+    // Its output is free again
+    assert!(state.submit(submission("stuck.mkv")).await.is_ok());
+    daemon.abort();
+}
+
+#[tokio::test]
+async fn test_job_builder_needs_nodes() {
+    init_test_logging();
+
+    let settings = ferris_swarm_config::settings::Settings::default();
+    assert!(NodeSource::Connected(Vec::new()).connect(&settings).await.is_err());
+    let started = JobBuilder::new("input.mkv", "output.mkv")
+        .with_settings(settings)
+        .with_profile("av1-hq")
+        .with_nodes(NodeSource::Connected(Vec::new()))
+        .start()
+        .await;
+    assert!(started.is_err());
+}
+
+#[tokio::test]
+async fn test_job_handle() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let mut settings = Settings::default();
+    settings.processing.temp_dir = temp_dir.path().to_path_buf();
+    // Never reached: the jobs end before sending chunks
+    let node = NodeConnection {
+        client:       VideoEncodingServiceClient::new(
+            Endpoint::from_static("http://127.0.0.1:1").connect_lazy(),
+        ),
+        address:      "http://127.0.0.1:1".to_string(),
+        semaphore:    Arc::new(Semaphore::new(1)),
+        capabilities: None,
+    };
+    let start = |output: &str| {
+        JobBuilder::new(
+            temp_dir.path().join("missing.mkv"),
+            temp_dir.path().join(output),
+        )
+        .with_settings(settings.clone())
+        .with_nodes(NodeSource::Connected(vec![node.clone()]))
+        .start()
+    };
+    let jobs_dir = temp_dir.path().join("ferris_swarm_jobs");
+
+    // Settings are checked before anything starts
+    let mut invalid = settings.clone();
+    invalid.processing.ranges = vec![TimeRange::new(10.0, Some(5.0))];
+    let rejected = JobBuilder::new(temp_dir.path().join("missing.mkv"), "rejected.mkv")
+        .with_settings(invalid)
+        .with_nodes(NodeSource::Connected(vec![node.clone()]))
+        .start()
+        .await;
+    assert!(rejected.is_err());
+    assert!(!jobs_dir.exists());
+    let job_dirs = || std::fs::read_dir(&jobs_dir).unwrap().count();
+
+    // A cancelled job ends its events and removes its temporary files
+    let mut cancelled = start("cancelled.mkv").await.unwrap();
+    assert_eq!(job_dirs(), 1);
+    cancelled.cancel();
+    assert_eq!(cancelled.next_event().await, None);
+    let error = cancelled.wait().await.unwrap_err();
+    assert!(error.to_string().contains("cancelled"), "{:#}", error);
+    assert_eq!(job_dirs(), 0);
+
+    // So does dropping its handle, in the background
+    drop(start("dropped.mkv").await.unwrap());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while job_dirs() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the dropped job kept its temporary files");
+
+    // A failed job ends its events too, and reports the error instead of
+    // an outcome
+    let mut failed = start("failed.mkv").await.unwrap();
+    while failed.next_event().await.is_some() {}
+    assert!(failed.wait().await.is_err());
+}
+
+#[test]
+fn test_job_progress() {
+    init_test_logging();
+
+    let node = "http://10.0.0.2:50051".to_string();
+    let mut progress = JobProgress::default();
+    progress.apply(&JobEvent::Dispatching {
+        nodes:      vec![(node.clone(), 2)],
+        frame_rate: ferris_swarm_video::utils::frame_rate_value("24000/1001"),
+    });
+    for index in 0..2 {
+        progress.apply(&JobEvent::SegmentReady {
+            index,
+            chunks: 1,
+        });
+        progress.apply(&JobEvent::ChunkAssigned {
+            index,
+            rendition: None,
+            node: node.clone(),
+        });
+    }
+    assert_eq!(progress.nodes[0].active.len(), 2);
+    // Still splitting, so the total isn't known yet
+    assert!(progress.summary(10.0).starts_with("0/2+ chunks"));
+    assert_eq!(progress.eta(10.0), None);
+
+    progress.apply(&JobEvent::ChunkFailed {
+        index:     1,
+        rendition: None,
+        node:      node.clone(),
+        error:     "connection reset".to_string(),
+    });
+    progress.apply(&JobEvent::ChunkEncoded {
+        index:         0,
+        rendition:     None,
+        node:          node.clone(),
+        seconds:       4.0,
+        duration:      Some(10.01),
+        source_bytes:  20_000_000,
+        encoded_bytes: 2_000_000,
+    });
+    progress.apply(&JobEvent::Segmented {
+        segments: 4,
+        chunks:   4,
+    });
+    assert!(progress.nodes[0].active.is_empty());
+    assert_eq!(
+        (progress.nodes[0].encoded, progress.nodes[0].failed),
+        (1, 1)
+    );
+    assert_eq!(progress.retries, 1);
+    // 10.01s at 23.976 fps in 10s
+    assert!((progress.fps(10.0).unwrap() - 24.0).abs() < 0.01);
+    assert_eq!(progress.megabytes_per_second(10.0), 2.0);
+    assert_eq!(progress.eta(10.0), Some(30.0));
+    assert_eq!(
+        progress.summary(10.0),
+        "1/4 chunks (25%), 24.0 fps, 2.0 MB/s, 1 retries, ETA 30s"
+    );
+
+    assert_eq!(format_seconds(95.0), "1m 35s");
+    assert_eq!(format_seconds(7260.0), "2h 01m");
+    assert_eq!(
+        ferris_swarm_video::utils::frame_rate_value("25"),
+        Some(25.0)
+    );
+    assert_eq!(ferris_swarm_video::utils::frame_rate_value("0/0"), None);
+}
@@ -1,5 +1,16 @@
 // Configuration unit tests
+use std::path::Path;
+
 use ferris_swarm_config::{Settings, TempConfig};
+use ferris_swarm_core::{
+    AudioEncodeLocation,
+    EncoderProfile,
+    PassMode,
+    RateControl,
+    Rendition,
+    TargetSize,
+    TimeRange,
+};
 
 use crate::common::{create_temp_dir, init_test_logging};
 
@@ -30,3 +41,276 @@ fn test_temp_config_creation() {
     // TempConfig::new returns TempConfig directly, not Result
     assert!(config.temp_dir.exists());
 }
+
+#[test]
+fn test_settings_pass_mode_from_file() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        "[client]\nnode_addresses = []\nencoder_params = [\"-c:v\", \"libx264\"]\npass_mode = \
+         \"two_pass\"\n",
+    )
+    .unwrap();
+
+    let settings = Settings::from_file(&config_path).unwrap();
+    assert_eq!(settings.client.pass_mode, PassMode::TwoPass);
+    assert_eq!(Settings::default().client.pass_mode, PassMode::OnePass);
+}
+
+#[test]
+fn test_encoder_profile_presets_from_file() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        r#"
+[client]
+node_addresses = []
+encoder_params = []
+profile = "av1-hq"
+
+[profiles.av1-hq]
+codec = "libsvtav1"
+rate_control = { mode = "crf", value = 30 }
+preset = "4"
+pixel_format = "yuv420p10le"
+keyint = 240
+
+[profiles.h264-web]
+codec = "libx264"
+rate_control = { mode = "bitrate", value = 4500 }
+extra_args = ["-tune", "film"]
+"#,
+    )
+    .unwrap();
+
+    let mut settings = Settings::from_file(&config_path).unwrap();
+    let profile = settings.selected_profile().unwrap().unwrap();
+    assert_eq!(profile.codec, "libsvtav1");
+    assert_eq!(profile.rate_control, RateControl::Crf(30.0));
+    assert_eq!(profile.keyint, Some(240));
+    assert_eq!(
+        settings.profiles["h264-web"].rate_control,
+        RateControl::Bitrate(4500)
+    );
+
+    settings.client.profile = Some("missing".to_string());
+    assert!(settings.selected_profile().is_err());
+}
+
+#[test]
+fn test_audio_settings_from_file() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        r#"
+[client]
+node_addresses = []
+encoder_params = []
+
+[audio]
+codec = "libopus"
+bitrate = "128k"
+channel_layout = "stereo"
+tracks = [0, 2]
+location = "node"
+
+[audio.loudness]
+integrated = -24.0
+"#,
+    )
+    .unwrap();
+
+    let settings = Settings::from_file(&config_path).unwrap();
+    assert!(settings.audio.reencodes());
+    assert_eq!(settings.audio.bitrate.as_deref(), Some("128k"));
+    assert_eq!(settings.audio.tracks, vec![0, 2]);
+    assert_eq!(settings.audio.location, AudioEncodeLocation::Node);
+    let loudness = settings.audio.loudness.unwrap();
+    assert_eq!(loudness.integrated, -24.0);
+    assert_eq!(loudness.true_peak, -1.0);
+    assert!(settings.audio.validate().is_ok());
+    assert!(!Settings::default().audio.reencodes());
+    assert_eq!(settings.audio.track_bitrates(2), vec![
+        Some("128k"),
+        Some("128k")
+    ]);
+
+    // One bitrate per track, the last one for any further tracks
+    let mut audio = settings.audio.clone();
+    audio.bitrate = Some("192k, 96k".to_string());
+    assert!(audio.validate().is_ok());
+    assert_eq!(audio.track_bitrates(3), vec![
+        Some("192k"),
+        Some("96k"),
+        Some("96k")
+    ]);
+    audio.bitrate = Some("192k,loud".to_string());
+    assert!(audio.validate().is_err());
+    assert_eq!(Settings::default().audio.track_bitrates(1), vec![None]);
+}
+
+#[test]
+fn test_target_size_from_file() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        r#"
+[client]
+node_addresses = []
+encoder_params = []
+
+[processing]
+segment_duration = 10.0
+temp_dir = "/tmp"
+
+[processing.target_size]
+bytes = "700MB"
+"#,
+    )
+    .unwrap();
+
+    let mut settings = Settings::from_file(&config_path).unwrap();
+    let target = settings.processing.target_size.unwrap();
+    assert_eq!(target.bytes, 700_000_000);
+    assert_eq!(target.tolerance, 0.05);
+    assert!(target.validate().is_ok());
+    assert!(settings.validate().is_ok());
+    assert!(Settings::default().processing.target_size.is_none());
+
+    // Checked with the rest of the settings
+    settings.processing.target_size = Some(TargetSize::new(0));
+    assert!(settings.validate().is_err());
+    settings.processing.target_size = None;
+    settings.processing.ranges = vec![TimeRange::new(10.0, Some(5.0))];
+    assert!(settings.validate().is_err());
+}
+
+#[test]
+fn test_zones_from_file() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let zones_path = temp_dir.path().join("zones.toml");
+    std::fs::write(
+        &zones_path,
+        r#"
+[[zones]]
+name = "credits"
+start = 90.0
+rate_control = { mode = "crf", value = 40 }
+
+[[zones]]
+chunks = [0]
+profile = "hq"
+"#,
+    )
+    .unwrap();
+
+    let mut settings = Settings {
+        zones: Settings::zones_from_file(&zones_path).unwrap(),
+        ..Default::default()
+    };
+    assert_eq!(settings.zones.len(), 2);
+    assert_eq!(settings.zones[0].rate_control, Some(RateControl::Crf(40.0)));
+    assert_eq!(settings.zones[1].chunks, vec![0]);
+    // Neither the named profile nor one to override exists
+    assert!(settings.validate_zones().is_err());
+
+    settings.profiles.insert("hq".to_string(), EncoderProfile {
+        codec:        "libx264".to_string(),
+        rate_control: RateControl::Crf(18.0),
+        preset:       None,
+        pixel_format: None,
+        keyint:       None,
+        extra_args:   Vec::new(),
+    });
+    settings.client.profile = Some("hq".to_string());
+    assert!(settings.validate_zones().is_ok());
+
+    // Raw parameters would be dropped from chunks encoded with a profile
+    settings.zones[1].encoder_params = vec!["-tune".to_string(), "grain".to_string()];
+    assert!(settings.validate_zones().is_err());
+    settings.zones[1].encoder_params.clear();
+
+    // The target size sets every chunk's bitrate, the zone's included
+    settings.processing.target_size = Some(TargetSize::new(100_000_000));
+    assert!(settings.validate_zones().is_err());
+    settings.processing.target_size = None;
+
+    settings.zones[0].start = None;
+    assert!(settings.validate_zones().is_err());
+}
+
+#[test]
+fn test_rendition_ladder_from_file() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        r#"
+[client]
+node_addresses = []
+encoder_params = []
+profile = "base"
+
+[profiles.base]
+codec = "libx264"
+rate_control = { mode = "crf", value = 23 }
+
+[profiles.small]
+codec = "libx264"
+rate_control = { mode = "bitrate", value = 800 }
+
+[[renditions]]
+name = "1080p"
+filter = "scale=-2:1080"
+
+[[renditions]]
+name = "480p"
+filter = "scale=-2:480"
+profile = "small"
+output = "/videos/small.mp4"
+"#,
+    )
+    .unwrap();
+
+    let settings = Settings::from_file(&config_path).unwrap();
+    assert_eq!(settings.renditions.len(), 2);
+    let job_output = Path::new("/videos/movie.mkv");
+    assert_eq!(
+        settings.renditions[0].output_path(job_output),
+        Path::new("/videos/movie.1080p.mkv")
+    );
+    assert_eq!(
+        settings.renditions[1].output_path(job_output),
+        Path::new("/videos/small.mp4")
+    );
+    assert!(Rendition::validate_ladder(&settings.renditions, job_output).is_ok());
+
+    // Without a profile of its own a rendition uses the job's
+    let first = settings.rendition_profile(&settings.renditions[0]).unwrap().unwrap();
+    assert_eq!(first.rate_control, RateControl::Crf(23.0));
+    let second = settings.rendition_profile(&settings.renditions[1]).unwrap().unwrap();
+    assert_eq!(second.rate_control, RateControl::Bitrate(800));
+
+    let mut duplicate = settings.renditions.clone();
+    duplicate[1].name = "1080p".to_string();
+    assert!(Rendition::validate_ladder(&duplicate, job_output).is_err());
+    let mut unsafe_name = settings.renditions.clone();
+    unsafe_name[0].name = "../1080p".to_string();
+    assert!(Rendition::validate_ladder(&unsafe_name, job_output).is_err());
+}
@@ -1,7 +1,31 @@
 // Core types unit tests
-use ferris_swarm_core::{Chunk, VideoEncodeError};
+use ferris_swarm_core::{
+    allocate_bitrates,
+    chunk::convert_files_to_chunks,
+    chunk_spans,
+    parse_bitrate,
+    parse_size,
+    parse_time,
+    reencode_plan,
+    substitute_attachments,
+    zone_assignments,
+    zones_covering,
+    AttachmentSource,
+    Chunk,
+    ChunkAttachment,
+    EncoderProfile,
+    PassMode,
+    RateControl,
+    StreamKind,
+    StreamTiming,
+    SyncAudit,
+    TargetSize,
+    TimeRange,
+    VideoEncodeError,
+    Zone,
+};
 
-use crate::common::{init_test_logging, mock_data};
+use crate::common::{create_temp_dir, init_test_logging, mock_data};
 
 #[test]
 fn test_chunk_creation_with_existing_file() {
@@ -45,3 +69,259 @@ fn test_video_encode_error_display() {
     let error = VideoEncodeError::Encoding("test error".to_string());
     assert!(error.to_string().contains("test error"));
 }
+
+#[test]
+fn test_chunks_carry_pass_mode() {
+    init_test_logging();
+
+    let temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+    let temp_path = temp_file.path().to_path_buf();
+
+    let single = Chunk::new(temp_path.clone(), 0, Vec::new()).unwrap();
+    assert_eq!(single.pass_mode, PassMode::OnePass);
+
+    let chunks = convert_files_to_chunks(
+        vec![temp_path],
+        Vec::new(),
+        PassMode::TwoPass,
+        "ffmpeg",
+        None,
+    )
+    .unwrap();
+    assert_eq!(chunks[0].pass_mode, PassMode::TwoPass);
+    assert_eq!(
+        chunks[0].with_encoded_path("encoded.mkv".into()).pass_mode,
+        PassMode::TwoPass
+    );
+}
+
+#[test]
+fn test_sync_audit_drift() {
+    init_test_logging();
+
+    let timing = |index, kind, start_time, duration, frames| StreamTiming {
+        index,
+        kind,
+        start_time: Some(start_time),
+        duration: Some(duration),
+        frames,
+    };
+    // The source audio starts 50 ms late and ends 10 ms early
+    let source = [
+        timing(0, StreamKind::Video, 0.0, 60.0, Some(1440)),
+        timing(1, StreamKind::Audio, 0.05, 59.99, None),
+    ];
+
+    let in_sync = [
+        timing(0, StreamKind::Video, 0.0, 60.02, Some(1440)),
+        timing(1, StreamKind::Audio, 0.05, 60.0, None),
+    ];
+    let audit = SyncAudit::from_timing(&source, &in_sync);
+    assert_eq!(audit.frame_difference(), Some(0));
+    assert!((audit.duration_drift().unwrap() - 0.02).abs() < 1e-9);
+    assert!(audit.problems(0.1).is_empty());
+
+    let drifted = [
+        timing(0, StreamKind::Video, 0.0, 60.5, Some(1452)),
+        timing(2, StreamKind::Audio, 0.3, 59.99, None),
+    ];
+    let audit = SyncAudit::from_timing(&source, &drifted);
+    assert_eq!(audit.frame_difference(), Some(12));
+    assert_eq!(audit.output_video_index, Some(0));
+    assert_eq!(audit.streams.len(), 1);
+    assert_eq!(audit.streams[0].index, 2);
+    assert!((audit.streams[0].start_offset.unwrap() - 0.25).abs() < 1e-9);
+    assert!((audit.streams[0].duration_drift.unwrap() + 0.5).abs() < 1e-9);
+    assert_eq!(audit.problems(0.1).len(), 3);
+    assert!(audit.problems(1.0).is_empty());
+
+    // A rendition at half the frame rate lasts as long as the source
+    let half_rate = [
+        timing(0, StreamKind::Video, 0.0, 60.02, Some(720)),
+        timing(1, StreamKind::Audio, 0.05, 60.0, None),
+    ];
+    let audit = SyncAudit::from_timing(&source, &half_rate);
+    assert_eq!(audit.frame_difference(), Some(-720));
+    assert!(audit.problems(0.1).is_empty());
+}
+
+#[test]
+fn test_time_range_parsing() {
+    init_test_logging();
+
+    assert_eq!(parse_time("90.5").unwrap(), 90.5);
+    assert_eq!(parse_time("1:30").unwrap(), 90.0);
+    assert_eq!(parse_time("01:02:03.25").unwrap(), 3723.25);
+    assert!(parse_time("1:75").is_err());
+    assert!(parse_time("1.5:00").is_err());
+    assert!(parse_time("-3").is_err());
+
+    assert_eq!(
+        TimeRange::parse("0:30-1:10").unwrap(),
+        TimeRange::new(30.0, Some(70.0))
+    );
+    assert_eq!(
+        TimeRange::parse("5:00-").unwrap(),
+        TimeRange::new(300.0, None)
+    );
+    assert_eq!(
+        TimeRange::parse("-10").unwrap(),
+        TimeRange::new(0.0, Some(10.0))
+    );
+    assert_eq!(TimeRange::new(30.0, Some(70.0)).duration(), Some(40.0));
+    assert!(TimeRange::parse("30").is_err());
+
+    let ranges = [TimeRange::new(0.0, Some(10.0)), TimeRange::new(20.0, None)];
+    assert!(TimeRange::validate_ranges(&ranges).is_ok());
+    assert!(TimeRange::validate_ranges(&[TimeRange::new(10.0, Some(5.0))]).is_err());
+    assert!(TimeRange::validate_ranges(&[ranges[1], ranges[0]]).is_err());
+    assert!(TimeRange::validate_ranges(&[
+        TimeRange::new(0.0, Some(10.0)),
+        TimeRange::new(5.0, Some(15.0))
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_target_size_budget() {
+    init_test_logging();
+
+    assert_eq!(parse_size("700MB").unwrap(), 700_000_000);
+    assert_eq!(parse_size("1.5 GB").unwrap(), 1_500_000_000);
+    assert_eq!(parse_size("4GiB").unwrap(), 4 * 1024 * 1024 * 1024);
+    assert_eq!(parse_size("1024 KiB").unwrap(), 1024 * 1024);
+    assert_eq!(parse_size("123").unwrap(), 123);
+    assert!(parse_size("10XB").is_err());
+    assert!(parse_size("MB").is_err());
+    assert_eq!(parse_bitrate("128k"), Some(128_000));
+    assert_eq!(parse_bitrate("1.5M"), Some(1_500_000));
+    assert_eq!(parse_bitrate("fast"), None);
+
+    let target = TargetSize::new(1_000_000);
+    assert_eq!(target.video_bytes(200_000).unwrap(), 795_000);
+    assert!(target.video_bytes(995_000).is_err());
+
+    // The unmeasured last chunk counts as average
+    let budgets = allocate_bitrates(1_000_000, &[10.0, 10.0, 20.0], &[1.0, 3.0, 0.0]);
+    let planned: Vec<(u64, u32)> =
+        budgets.iter().map(|budget| (budget.planned_bytes, budget.bitrate)).collect();
+    assert_eq!(planned, vec![
+        (125_000, 100),
+        (375_000, 300),
+        (500_000, 200)
+    ]);
+
+    // The outlier alone recovers the overshoot
+    assert_eq!(
+        reencode_plan(&budgets, &[125_000, 450_000, 500_000], 50_000, 0.05),
+        vec![(1, 250)]
+    );
+    // It doesn't, so the others are scaled down too
+    assert_eq!(
+        reencode_plan(&budgets, &[125_000, 400_000, 520_000], 100_000, 0.05),
+        vec![(0, 88), (1, 281), (2, 176)]
+    );
+}
+
+#[test]
+fn test_zone_assignments() {
+    init_test_logging();
+
+    let spans = chunk_spans(&[10.0, 10.0, 10.0, 10.0]);
+    assert_eq!(spans, vec![
+        (0.0, 10.0),
+        (10.0, 20.0),
+        (20.0, 30.0),
+        (30.0, 40.0)
+    ]);
+
+    let credits = Zone {
+        name: Some("credits".to_string()),
+        start: Some(25.0),
+        rate_control: Some(RateControl::Crf(40.0)),
+        extra_args: vec!["-tune".to_string(), "grain".to_string()],
+        ..Default::default()
+    };
+    let intro = Zone {
+        chunks: vec![0],
+        start: Some(5.0),
+        end: Some(10.0),
+        preset: Some("slow".to_string()),
+        encoder_params: vec!["-g".to_string(), "48".to_string()],
+        ..Default::default()
+    };
+    // A chunk only touching a zone's edge isn't covered
+    assert_eq!(
+        zone_assignments(&[credits.clone(), intro.clone()], &spans),
+        vec![vec![1], vec![], vec![0], vec![0]]
+    );
+    // Chunks assigned one at a time as segments are split off
+    assert_eq!(
+        zones_covering(&[credits.clone(), intro.clone()], 3, spans[3]),
+        vec![0]
+    );
+    assert!(credits.validate(0).is_ok());
+    assert!(Zone::default().validate(0).is_err());
+
+    let mut chunk = mock_data::create_test_chunk();
+    chunk.profile = Some(EncoderProfile {
+        codec:        "libx264".to_string(),
+        rate_control: RateControl::Crf(23.0),
+        preset:       Some("medium".to_string()),
+        pixel_format: None,
+        keyint:       None,
+        extra_args:   vec!["-bf".to_string(), "3".to_string()],
+    });
+    chunk.apply_zone(&credits, None);
+    chunk.apply_zone(&intro, None);
+    let profile = chunk.profile.as_ref().unwrap();
+    assert_eq!(profile.rate_control, RateControl::Crf(40.0));
+    assert_eq!(profile.preset.as_deref(), Some("slow"));
+    assert_eq!(profile.extra_args, vec!["-bf", "3", "-tune", "grain"]);
+    assert!(chunk.encoder_parameters.ends_with(&["-g".to_string(), "48".to_string()]));
+}
+
+#[test]
+fn test_attachment_sources() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    std::fs::write(temp_dir.path().join("grain_2.tbl"), "filmgrn1").unwrap();
+    let source = AttachmentSource::parse(&format!(
+        "grain={}",
+        temp_dir.path().join("grain_{index}.tbl").display()
+    ))
+    .unwrap();
+    assert_eq!(source.name, "grain");
+    let attachment = source.for_chunk(2).unwrap().unwrap();
+    assert_eq!(attachment.path, temp_dir.path().join("grain_2.tbl"));
+    // Chunk 3 has no table of its own
+    assert!(source.for_chunk(3).is_err());
+    let only_two = AttachmentSource {
+        chunks: vec![2],
+        ..source.clone()
+    };
+    assert_eq!(only_two.for_chunk(3).unwrap(), None);
+
+    assert!(AttachmentSource::parse("grain").is_err());
+    assert!(AttachmentSource::validate_all(&[source.clone(), source.clone()]).is_err());
+    assert!(AttachmentSource::validate_all(&[AttachmentSource {
+        name: "../grain".to_string(),
+        ..source
+    }])
+    .is_err());
+
+    let attachments = [ChunkAttachment {
+        name: "grain".to_string(),
+        path: "/node/chunk_2/grain".into(),
+    }];
+    assert_eq!(
+        substitute_attachments("--fgs-table={attachment:grain}", &attachments).unwrap(),
+        "--fgs-table=/node/chunk_2/grain"
+    );
+    assert_eq!(
+        substitute_attachments("-vf {not-a-placeholder}", &attachments).unwrap(),
+        "-vf {not-a-placeholder}"
+    );
+    assert!(substitute_attachments("{attachment:zones}", &attachments).is_err());
+}
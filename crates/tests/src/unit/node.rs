@@ -1,5 +1,18 @@
 // Node service unit tests
-use crate::common::init_test_logging;
+use std::{path::Path, time::Duration};
+
+use ferris_swarm_core::NodeCapabilities;
+use ferris_swarm_node::NodeEncodingService;
+use ferris_swarm_proto::{
+    video_encoding_service_client::VideoEncodingServiceClient,
+    video_encoding_service_server::VideoEncodingServiceServer,
+    EncodeChunkPart,
+    EncodeChunkRequest,
+};
+use futures::channel::mpsc;
+use tonic::transport::{Endpoint, Server};
+
+use crate::common::{create_temp_dir, init_test_logging};
 
 #[test]
 fn test_node_service_placeholder() {
@@ -8,3 +21,84 @@ fn test_node_service_placeholder() {
     // For example: service registration, chunk processing, etc.
     assert!(true);
 }
+
+#[tokio::test]
+async fn test_same_chunk_of_two_jobs_at_once() {
+    init_test_logging();
+
+    let temp_dir = create_temp_dir();
+    let service = NodeEncodingService::new(temp_dir.path().to_path_buf(), NodeCapabilities {
+        max_concurrent_chunks: 2,
+        supported_encoders:    Vec::new(),
+        supported_backends:    Vec::new(),
+        cpu_cores:             1,
+        memory_gb:             1,
+    });
+    let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let server = tokio::spawn(
+        Server::builder()
+            .add_service(VideoEncodingServiceServer::new(service))
+            .serve(address),
+    );
+    let channel = Endpoint::from_shared(format!("http://{}", address)).unwrap().connect_lazy();
+
+    // Both jobs send their chunk 0 and keep streaming
+    let mut senders = Vec::new();
+    let mut calls = Vec::new();
+    for data in [b"first job".to_vec(), b"second job".to_vec()] {
+        let (sender, parts) = mpsc::unbounded();
+        let request = EncodeChunkRequest {
+            chunk_index: 0,
+            ..Default::default()
+        };
+        sender
+            .unbounded_send(EncodeChunkPart {
+                request: Some(request),
+                data:    Vec::new(),
+            })
+            .unwrap();
+        sender
+            .unbounded_send(EncodeChunkPart {
+                request: None,
+                data,
+            })
+            .unwrap();
+        senders.push(sender);
+        let mut client = VideoEncodingServiceClient::new(channel.clone());
+        calls.push(tokio::spawn(async move {
+            client.encode_chunk_stream(parts).await
+        }));
+    }
+
+    // Each is received into a file of its own
+    let received_dir = temp_dir.path().join("received_chunks");
+    let received = |dir: &Path| {
+        let mut contents: Vec<Vec<u8>> = std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter_map(|e| std::fs::read(e.path()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        contents.sort();
+        contents
+    };
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while received(&received_dir) != vec![b"first job".to_vec(), b"second job".to_vec()] {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the chunks were not received side by side");
+
+    // One finishing doesn't take the other's input with it: both get an
+    // answer from the encoder, a failure as the data isn't video
+    for (sender, call) in senders.into_iter().zip(calls) {
+        drop(sender);
+        let response = call.await.unwrap().unwrap().into_inner();
+        assert!(!response.success);
+    }
+    assert!(received(&received_dir).is_empty());
+    server.abort();
+}
@@ -1,8 +1,68 @@
 // Video processing unit tests
-use ferris_swarm_core::VideoEncodeError;
-use ferris_swarm_video::{verify_ffmpeg, verify_mkvmerge};
+use std::{collections::HashMap, path::Path, process::Command, time::Duration};
 
-use crate::common::init_test_logging;
+use ferris_swarm_core::{
+    ChunkAttachment,
+    ChunkTransport,
+    ContentLightLevel,
+    EncoderProfile,
+    LoudnessMeasurement,
+    LoudnessTarget,
+    NodeCapabilities,
+    RateControl,
+    StreamKind,
+    StreamMetadata,
+    StreamSelection,
+    SyncAudit,
+    TimeRange,
+    VideoEncodeError,
+};
+use ferris_swarm_video::{
+    chunk_encoder_arguments,
+    concatenate_ivf,
+    concatenator,
+    container_accepts_codec,
+    encoder_backend,
+    encoder_backends,
+    ffmpeg_metadata_arguments,
+    is_keyframe_flags,
+    loudnorm_filter,
+    loudnorm_stats,
+    mkvmerge_color_arguments,
+    mkvmerge_metadata_arguments,
+    mkvmerge_sync_argument,
+    package_ffmpeg,
+    packaging_arguments,
+    parse_color_metadata,
+    parse_ffprobe_streams,
+    parse_mkvmerge_video_track,
+    parse_quality_metrics,
+    parse_stream_timing,
+    progressive_accepts_codec,
+    progressive_chunk_arguments,
+    progressive_muxer_arguments,
+    quality_filter,
+    range_input_arguments,
+    select_concatenator,
+    stream_map_arguments,
+    transport_arguments,
+    transport_command,
+    validate_profile_for_node,
+    verify_ffmpeg,
+    verify_mkvmerge,
+    Chapter,
+    ContainerMetadata,
+    EncoderArguments,
+    InOrderQueue,
+    KillableCommand,
+    MuxInputs,
+    PackageFormat,
+    PackageVariant,
+    ProcessScope,
+    ProgressiveFormat,
+};
+
+use crate::common::{create_temp_dir, init_test_logging, mock_data};
 
 #[test]
 fn test_ffmpeg_verification() {
@@ -39,3 +99,793 @@ fn test_mkvmerge_verification() {
         },
     }
 }
+
+#[test]
+fn test_encoder_backend_lookup() {
+    init_test_logging();
+
+    let names: Vec<&str> = encoder_backends().iter().map(|backend| backend.name()).collect();
+    assert_eq!(names, vec![
+        "ffmpeg", "svt-av1", "aomenc", "rav1e", "x264", "x265"
+    ]);
+
+    assert_eq!(encoder_backend("svt-av1").unwrap().name(), "svt-av1");
+    match encoder_backend("handbrake") {
+        Err(VideoEncodeError::UnsupportedBackend(name)) => assert_eq!(name, "handbrake"),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Unknown backend must not resolve"),
+    }
+}
+
+#[test]
+fn test_profile_rendering_per_backend() {
+    init_test_logging();
+
+    let profile = EncoderProfile {
+        codec:        "libsvtav1".to_string(),
+        rate_control: RateControl::Crf(30.0),
+        preset:       Some("4".to_string()),
+        pixel_format: Some("yuv420p10le".to_string()),
+        keyint:       Some(240),
+        extra_args:   vec!["-svtav1-params".to_string(), "tune=0".to_string()],
+    };
+
+    let ffmpeg = encoder_backend("ffmpeg").unwrap().render_profile(&profile);
+    assert!(ffmpeg.decoder_args.is_empty());
+    assert_eq!(ffmpeg.encoder_args, vec![
+        "-c:v",
+        "libsvtav1",
+        "-crf",
+        "30",
+        "-preset",
+        "4",
+        "-pix_fmt",
+        "yuv420p10le",
+        "-g",
+        "240",
+        "-svtav1-params",
+        "tune=0"
+    ]);
+
+    let svt = encoder_backend("svt-av1").unwrap().render_profile(&profile);
+    assert_eq!(svt.decoder_args, vec!["-pix_fmt", "yuv420p10le"]);
+    assert_eq!(&svt.encoder_args[..6], &[
+        "--crf", "30", "--preset", "4", "--keyint", "240"
+    ]);
+}
+
+#[test]
+fn test_profile_validation_against_node() {
+    init_test_logging();
+
+    let profile = EncoderProfile {
+        codec:        "libsvtav1".to_string(),
+        rate_control: RateControl::Crf(30.0),
+        preset:       None,
+        pixel_format: None,
+        keyint:       None,
+        extra_args:   Vec::new(),
+    };
+    let capabilities = NodeCapabilities {
+        max_concurrent_chunks: 1,
+        supported_encoders:    vec!["av1".to_string(), "libaom-av1".to_string()],
+        supported_backends:    vec!["ffmpeg".to_string(), "svt-av1".to_string()],
+        cpu_cores:             4,
+        memory_gb:             8,
+    };
+
+    // The node only has libaom among its av1 encoders
+    assert!(validate_profile_for_node(&profile, "ffmpeg", Some(&capabilities)).is_err());
+    // Standalone backends are matched by codec family
+    assert!(validate_profile_for_node(&profile, "svt-av1", Some(&capabilities)).is_ok());
+    assert!(validate_profile_for_node(&profile, "x265", Some(&capabilities)).is_err());
+    // Nodes that only list families are matched by family
+    let legacy = NodeCapabilities {
+        supported_encoders: vec!["av1".to_string()],
+        ..capabilities
+    };
+    assert!(validate_profile_for_node(&profile, "ffmpeg", Some(&legacy)).is_ok());
+}
+
+#[test]
+fn test_stream_selection_arguments() {
+    init_test_logging();
+
+    let streams = parse_ffprobe_streams(mock_data::PROBED_STREAMS_JSON).unwrap();
+    assert_eq!(streams.len(), 7);
+    assert_eq!(streams[2].title.as_deref(), Some("Commentary"));
+    assert!(streams[2].has_disposition("comment"));
+    assert_eq!(streams[6].kind, StreamKind::Attachment);
+
+    // Without filters every non-video stream is kept
+    let all = StreamSelection::default().select(&streams, &[]);
+    assert_eq!(stream_map_arguments(&all).len(), 12);
+
+    let mut selection = StreamSelection::default();
+    selection.audio.exclude_dispositions = vec!["comment".to_string()];
+    selection.subtitle.languages = vec!["eng".to_string()];
+    let selected = selection.select(&streams, &[]);
+    assert_eq!(stream_map_arguments(&selected), vec![
+        "-map", "0:1", "-map", "0:3", "-map", "0:4", "-map", "0:6"
+    ]);
+
+    // Audio track indices count audio streams only
+    let selected = selection.select(&streams, &[2]);
+    assert_eq!(selected.iter().map(|s| s.index).collect::<Vec<_>>(), vec![
+        3, 4, 6
+    ]);
+}
+
+#[test]
+fn test_stream_metadata_arguments() {
+    init_test_logging();
+
+    let metadata: Vec<StreamMetadata> = vec![
+        "a:0,title=Main,language=eng,default".parse().unwrap(),
+        "s:0,default=false,forced".parse().unwrap(),
+    ];
+    assert_eq!(ffmpeg_metadata_arguments(&metadata), vec![
+        "-metadata:s:a:0",
+        "title=Main",
+        "-metadata:s:a:0",
+        "language=eng",
+        "-disposition:a:0",
+        "+default",
+        "-disposition:s:0",
+        "-default+forced",
+    ]);
+
+    // Tracks of the non-video file: a:0 is its first audio, s:0 its subtitle.
+    // The streams keep their source indices, one past the track IDs mkvmerge
+    // gives them in a file without the video.
+    let streams = parse_ffprobe_streams(mock_data::PROBED_STREAMS_JSON).unwrap();
+    let file_streams: Vec<_> = streams.into_iter().skip(1).collect();
+    let args = mkvmerge_metadata_arguments(&metadata, &file_streams, &mut HashMap::new());
+    assert_eq!(args, vec![
+        "--track-name",
+        "0:Main",
+        "--language",
+        "0:eng",
+        "--default-track-flag",
+        "0:1",
+        "--default-track-flag",
+        "3:0",
+        "--forced-display-flag",
+        "3:1",
+    ]);
+}
+
+#[test]
+fn test_loudnorm_two_pass_filters() {
+    init_test_logging();
+
+    let target = LoudnessTarget::default();
+    assert_eq!(
+        loudnorm_filter(&target, None),
+        "loudnorm=I=-23:TP=-1:LRA=7:print_format=json"
+    );
+
+    let measured = LoudnessMeasurement {
+        integrated:     -27.61,
+        true_peak:      -4.47,
+        loudness_range: 18.06,
+        threshold:      -39.2,
+        target_offset:  0.58,
+    };
+    let second_pass = loudnorm_filter(&target, Some(&measured));
+    assert!(second_pass.contains("measured_I=-27.61:measured_TP=-4.47"));
+    assert!(second_pass.contains("offset=0.58:linear=true"));
+    assert!(!measured.is_silent());
+
+    // loudnorm measures a silent track at -inf, which isn't normalized
+    let silent = LoudnessMeasurement {
+        integrated:     "-inf".parse().unwrap(),
+        true_peak:      "-inf".parse().unwrap(),
+        loudness_range: 0.0,
+        threshold:      -70.0,
+        target_offset:  "inf".parse().unwrap(),
+    };
+    assert!(silent.is_silent());
+}
+
+#[test]
+fn test_loudnorm_stats_parsing() {
+    init_test_logging();
+
+    let stderr = r#"size=N/A time=00:00:10.00 bitrate=N/A speed= 200x
+[Parsed_loudnorm_0 @ 0x55d0c8a7a0c0]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-23.01",
+	"output_tp" : "-1.00",
+	"output_lra" : "7.00",
+	"output_thresh" : "-34.60",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.01"
+}
+"#;
+    let stats = loudnorm_stats(stderr);
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0]["input_i"], "-27.61");
+    assert_eq!(stats[0]["normalization_type"], "dynamic");
+}
+
+#[test]
+fn test_container_metadata_rendering() {
+    init_test_logging();
+
+    let probe = r#"{
+        "streams": [
+            { "index": 0, "codec_type": "video" },
+            { "index": 1, "codec_type": "attachment",
+              "tags": { "filename": "font.ttf", "mimetype": "font/ttf" } }
+        ],
+        "chapters": [
+            { "id": 0, "start_time": "0.000000", "end_time": "61.500000",
+              "tags": { "title": "Intro; Part=1" } }
+        ],
+        "format": { "tags": { "title": "Feature", "ENCODER": "Lavf60.3.100",
+                              "COMMENT": "a & b" } }
+    }"#;
+    let (metadata, attachments) = ContainerMetadata::from_probe(probe).unwrap();
+    assert_eq!(metadata.title(), Some("Feature"));
+    assert_eq!(metadata.tags.len(), 2, "muxer tags are dropped");
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments[0].mimetype, "font/ttf");
+
+    let ffmetadata = metadata.to_ffmetadata();
+    assert!(ffmetadata.starts_with(";FFMETADATA1\n"));
+    assert!(ffmetadata.contains("START=0\nEND=61500\ntitle=Intro\\; Part\\=1\n"));
+
+    let chapters = metadata.to_mkvmerge_chapters();
+    assert!(chapters.contains("<ChapterTimeEnd>00:01:01.500000000</ChapterTimeEnd>"));
+    let tags = metadata.to_mkvmerge_tags();
+    assert!(tags.contains("<String>a &amp; b</String>"));
+    assert!(!tags.contains("Feature"), "the title goes to --title");
+}
+
+#[test]
+fn test_color_metadata_parsing() {
+    init_test_logging();
+
+    let color = parse_color_metadata(mock_data::PROBED_HDR10_JSON).unwrap();
+    assert_eq!(color.color_primaries.as_deref(), Some("bt2020"));
+    assert_eq!(color.transfer_code(), Some(16));
+    assert_eq!(color.matrix_code(), Some(9));
+    assert_eq!(
+        color.full_range(),
+        Some(false),
+        "range falls back to the frame"
+    );
+    assert_eq!(
+        color.content_light,
+        Some(ContentLightLevel {
+            max_cll:  1000,
+            max_fall: 400,
+        })
+    );
+    let display = color.mastering_display.unwrap();
+    assert_eq!(display.green, (0.265, 0.69));
+    assert_eq!(display.min_luminance, 0.005);
+    assert_eq!(display.max_luminance, 1000.0);
+
+    let sdr = parse_color_metadata(r#"{ "streams": [ { "color_space": "unknown" } ] }"#).unwrap();
+    assert!(sdr.is_empty());
+}
+
+#[test]
+fn test_color_metadata_arguments() {
+    init_test_logging();
+
+    let color = parse_color_metadata(mock_data::PROBED_HDR10_JSON).unwrap();
+
+    // x265 gets HDR10 merged into the caller's -x265-params
+    let mut arguments = EncoderArguments::from_parameters(
+        &["-c:v", "libx265", "-x265-params", "aq-mode=3", "-color_range", "pc"].map(String::from),
+    );
+    let unsupported =
+        encoder_backend("ffmpeg").unwrap().apply_color_metadata(&color, &mut arguments);
+    assert!(unsupported.is_empty());
+    assert_eq!(
+        arguments.encoder_args[3],
+        "aq-mode=3:master-display=G(13250,34500)B(7500,3000)R(34000,16000)WP(15635,\
+         16450)L(10000000,50):max-cll=1000,400"
+    );
+    assert!(arguments
+        .encoder_args
+        .ends_with(&["-colorspace".to_string(), "bt2020nc".to_string()]));
+    assert_eq!(
+        arguments.encoder_args.iter().filter(|arg| *arg == "-color_range").count(),
+        1,
+        "options set by the caller are kept"
+    );
+
+    let mut arguments =
+        EncoderArguments::from_parameters(&["-c:v", "libvpx-vp9"].map(String::from));
+    let unsupported =
+        encoder_backend("ffmpeg").unwrap().apply_color_metadata(&color, &mut arguments);
+    assert_eq!(unsupported, vec![
+        "mastering display",
+        "content light level"
+    ]);
+
+    let mut arguments = EncoderArguments::default();
+    let unsupported =
+        encoder_backend("svt-av1").unwrap().apply_color_metadata(&color, &mut arguments);
+    assert!(unsupported.is_empty());
+    assert!(arguments.encoder_args.windows(2).any(|pair| pair
+        == [
+            "--mastering-display",
+            "G(0.2650,0.6900)B(0.1500,0.0600)R(0.6800,0.3200)WP(0.3127,0.3290)L(1000.0000,0.0050)"
+        ]));
+
+    let mut arguments = EncoderArguments::default();
+    let unsupported =
+        encoder_backend("aomenc").unwrap().apply_color_metadata(&color, &mut arguments);
+    assert_eq!(arguments.encoder_args, vec![
+        "--color-primaries=bt2020",
+        "--transfer-characteristics=smpte2084",
+        "--matrix-coefficients=bt2020ncl",
+    ]);
+    assert_eq!(unsupported, vec![
+        "mastering display",
+        "content light level"
+    ]);
+
+    let mkvmerge = mkvmerge_color_arguments(&color, 0);
+    assert_eq!(&mkvmerge[..8], [
+        "--colour-primaries",
+        "0:9",
+        "--colour-transfer-characteristics",
+        "0:16",
+        "--colour-matrix-coefficients",
+        "0:9",
+        "--colour-range",
+        "0:1",
+    ]);
+    assert!(mkvmerge.contains(&"0:0.68,0.32,0.265,0.69,0.15,0.06".to_string()));
+    assert!(mkvmerge.ends_with(&["--max-frame-light".to_string(), "0:400".to_string()]));
+}
+
+#[test]
+fn test_video_filter_prepended() {
+    init_test_logging();
+
+    let mut arguments = EncoderArguments::default();
+    arguments.prepend_video_filter("scale=-2:720");
+    assert_eq!(arguments.decoder_args, vec!["-vf", "scale=-2:720"]);
+
+    // Filters the encoder parameters already set run after the rendition's
+    let mut arguments =
+        EncoderArguments::from_parameters(&["-c:v", "libx264", "-vf", "hqdn3d"].map(String::from));
+    arguments.prepend_video_filter("scale=-2:720");
+    assert!(arguments.decoder_args.is_empty());
+    assert_eq!(arguments.encoder_args[3], "scale=-2:720,hqdn3d");
+}
+
+#[test]
+fn test_packaging_arguments() {
+    init_test_logging();
+
+    let variants: Vec<PackageVariant> = ["1080p", "720p"]
+        .into_iter()
+        .map(|name| PackageVariant {
+            name:          name.to_string(),
+            segment_paths: Vec::new(),
+        })
+        .collect();
+    let streams = parse_ffprobe_streams(mock_data::PROBED_STREAMS_JSON).unwrap();
+    let audio: Vec<_> = streams.iter().filter(|s| s.kind == StreamKind::Audio).take(1).collect();
+
+    let hls = packaging_arguments(
+        PackageFormat::Hls,
+        &variants,
+        &audio,
+        Path::new("out/master.m3u8"),
+        6.0,
+    );
+    let value = |args: &[String], option: &str| {
+        let i = args.iter().position(|arg| arg == option).unwrap();
+        args[i + 1].clone()
+    };
+    assert_eq!(
+        value(&hls, "-var_stream_map"),
+        "a:0,agroup:audio,name:audio_0,language:eng v:0,agroup:audio,name:1080p \
+         v:1,agroup:audio,name:720p"
+    );
+    assert_eq!(value(&hls, "-master_pl_name"), "master.m3u8");
+    assert_eq!(
+        value(&hls, "-hls_segment_filename"),
+        "out/master_%v_%05d.m4s"
+    );
+    assert_eq!(hls.last().unwrap(), "out/master_%v.m3u8");
+
+    let dash = packaging_arguments(
+        PackageFormat::Dash,
+        &variants[..1],
+        &[],
+        Path::new("out/stream.mpd"),
+        6.0,
+    );
+    assert_eq!(value(&dash, "-adaptation_sets"), "id=0,streams=v");
+    assert_eq!(dash.last().unwrap(), "out/stream.mpd");
+
+    // The output must name the manifest of the chosen format
+    let error = package_ffmpeg(
+        PackageFormat::Hls,
+        &variants,
+        &MuxInputs::default(),
+        Path::new("out/output.mkv"),
+        Path::new("out"),
+        0,
+        6.0,
+    )
+    .unwrap_err();
+    assert!(matches!(error, VideoEncodeError::Concatenation(_)));
+    assert!(PackageFormat::Dash.validate_output(Path::new("out/stream.MPD")).is_ok());
+    assert!(PackageFormat::Dash.validate_output(Path::new("out/master.m3u8")).is_err());
+}
+
+#[test]
+fn test_concatenator_output_validation() {
+    init_test_logging();
+
+    assert!(container_accepts_codec("mkv", "h264"));
+    assert!(container_accepts_codec("webm", "av1"));
+    assert!(!container_accepts_codec("webm", "h264"));
+    assert!(!container_accepts_codec("MP4", "vp8"));
+    assert!(container_accepts_codec("webm", "prores"));
+
+    let ffmpeg = concatenator("ffmpeg").unwrap();
+    assert!(ffmpeg.validate_output(Path::new("out.mp4"), Some("hevc")).is_ok());
+    assert!(ffmpeg.validate_output(Path::new("out.webm"), Some("h264")).is_err());
+    assert!(ffmpeg.validate_output(Path::new("out.ivf"), Some("av1")).is_err());
+
+    let native = concatenator("native").unwrap();
+    assert!(native.validate_output(Path::new("out.ivf"), Some("av1")).is_ok());
+    assert!(native.validate_output(Path::new("out.obu"), Some("vp9")).is_err());
+    assert!(native.validate_output(Path::new("out.mkv"), None).is_err());
+
+    let mkvmerge = concatenator("mkvmerge").unwrap();
+    assert!(mkvmerge.validate_output(Path::new("out.mp4"), None).is_err());
+
+    assert!(matches!(
+        concatenator("cat"),
+        Err(VideoEncodeError::Config(_))
+    ));
+    assert!(select_concatenator(None, Path::new("out.webm"), Some("h264")).is_err());
+    assert!(select_concatenator(Some("mkvmerge"), Path::new("out.mov"), None).is_err());
+
+    if verify_ffmpeg().is_err() {
+        println!("Skipping automatic selection checks - FFmpeg not available");
+        return;
+    }
+    let selected = select_concatenator(None, Path::new("out.mkv"), Some("av1")).unwrap();
+    assert_eq!(selected.name(), "ffmpeg");
+    let selected = select_concatenator(None, Path::new("out.ivf"), Some("av1")).unwrap();
+    assert_eq!(selected.name(), "native");
+}
+
+#[test]
+fn test_ivf_concatenation() {
+    init_test_logging();
+    let temp_dir = create_temp_dir();
+    let first = temp_dir.path().join("first.ivf");
+    let second = temp_dir.path().join("second.ivf");
+    let output = temp_dir.path().join("joined.ivf");
+    mock_data::write_test_ivf(&first, b"AV01", &[0, 1, 2]);
+    // Every segment restarts its timestamps
+    mock_data::write_test_ivf(&second, b"AV01", &[10, 11]);
+
+    let frames = concatenate_ivf(&[first.clone(), second.clone()], &output).unwrap();
+    assert_eq!(frames, 5);
+
+    let data = std::fs::read(&output).unwrap();
+    assert_eq!(&data[0..4], b"DKIF");
+    assert_eq!(&data[8..12], b"AV01");
+    assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 5);
+    let timestamps: Vec<u64> = (0..5)
+        .map(|i| {
+            let offset = 32 + i * 13 + 4;
+            u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+        })
+        .collect();
+    assert_eq!(timestamps, vec![0, 1, 2, 3, 4]);
+
+    let vp9 = temp_dir.path().join("vp9.ivf");
+    mock_data::write_test_ivf(&vp9, b"VP90", &[0]);
+    assert!(concatenate_ivf(&[first, vp9], &output).is_err());
+}
+
+#[test]
+fn test_stream_timing_and_sync_fix() {
+    init_test_logging();
+
+    let timing = parse_stream_timing(mock_data::PROBED_TIMING_JSON).unwrap();
+    assert_eq!(timing.len(), 3);
+    assert_eq!(timing[0].kind, StreamKind::Video);
+    assert!((timing[0].duration.unwrap() - 60.04).abs() < 1e-9);
+    assert_eq!(timing[0].frames, Some(1500));
+    assert!((timing[1].duration.unwrap() - 60.02).abs() < 1e-9);
+    assert_eq!(timing[1].start_time, Some(0.02));
+    // Without a stream duration the container's is used
+    assert!((timing[2].duration.unwrap() - 60.04).abs() < 1e-9);
+
+    // Against itself nothing drifts
+    let audit = SyncAudit::from_timing(&timing, &timing);
+    assert!(audit.problems(0.1).is_empty());
+    assert_eq!(mkvmerge_sync_argument(&audit, 0, 0.1), None);
+
+    let mut drifted = timing.clone();
+    drifted[0].duration = Some(60.54);
+    drifted[1].start_time = Some(0.32);
+    let audit = SyncAudit::from_timing(&timing, &drifted);
+    // mkvmerge's track ID, not ffprobe's stream index, names the video
+    let track_id = parse_mkvmerge_video_track(mock_data::MKVMERGE_IDENTIFY_JSON).unwrap();
+    assert_eq!(track_id, Some(1));
+    assert_eq!(audit.output_video_index, Some(0));
+    assert_eq!(
+        mkvmerge_sync_argument(&audit, 1, 0.1).as_deref(),
+        Some("1:300,60.04/60.54")
+    );
+    // Drift within the tolerance is left alone
+    assert_eq!(mkvmerge_sync_argument(&audit, 1, 1.0), None);
+}
+
+#[test]
+fn test_chunk_boundary_arguments() {
+    init_test_logging();
+
+    let apply = |backend: &str, parameters: &[&str]| {
+        let mut arguments = EncoderArguments::from_parameters(
+            &parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>(),
+        );
+        encoder_backend(backend).unwrap().apply_chunk_boundaries(&mut arguments);
+        arguments.encoder_args
+    };
+
+    assert_eq!(
+        apply("ffmpeg", &["-c:v", "libx265", "-x265-params", "aq-mode=3"]),
+        vec![
+            "-c:v",
+            "libx265",
+            "-x265-params",
+            "aq-mode=3:open-gop=0",
+            "-force_key_frames",
+            "expr:eq(n,0)",
+            "-forced-idr",
+            "1"
+        ]
+    );
+    assert_eq!(apply("ffmpeg", &["-c:v", "libsvtav1"])[4..], [
+        "-svtav1-params",
+        "irefresh-type=2"
+    ]);
+    // The caller's choice wins
+    assert_eq!(
+        apply("ffmpeg", &["-c:v", "libx264", "-x264-params", "open-gop=1"])[3],
+        "open-gop=1"
+    );
+    assert_eq!(apply("ffmpeg", &["-c:v", "libvpx-vp9"]).len(), 4);
+
+    assert_eq!(apply("x265", &["--crf", "20"]), vec![
+        "--crf",
+        "20",
+        "--no-open-gop"
+    ]);
+    assert_eq!(apply("x265", &["--open-gop"]), vec!["--open-gop"]);
+    assert_eq!(apply("svt-av1", &[]), vec!["--irefresh-type", "2"]);
+    assert_eq!(apply("aomenc", &[]), vec!["--enable-fwd-kf=0"]);
+    assert!(apply("x264", &[]).is_empty());
+    assert!(apply("rav1e", &[]).is_empty());
+
+    assert!(is_keyframe_flags("K__\n"));
+    assert!(is_keyframe_flags("K_"));
+    assert!(!is_keyframe_flags("___"));
+    assert!(!is_keyframe_flags(""));
+}
+
+#[test]
+fn test_time_range_trimming() {
+    init_test_logging();
+
+    assert!(range_input_arguments(None).is_empty());
+    assert_eq!(
+        range_input_arguments(Some(&TimeRange::new(12.5, Some(20.0)))),
+        vec!["-ss", "12.5", "-t", "7.5"]
+    );
+    assert_eq!(
+        range_input_arguments(Some(&TimeRange::new(0.0, None))),
+        Vec::<String>::new()
+    );
+
+    let chapter = |start, end, title: &str| Chapter {
+        start,
+        end,
+        title: Some(title.to_string()),
+    };
+    let mut metadata = ContainerMetadata {
+        chapters: vec![
+            chapter(0.0, 30.0, "Opening"),
+            chapter(30.0, 90.0, "Middle"),
+            chapter(90.0, 120.0, "Credits"),
+        ],
+        ..Default::default()
+    };
+    let original = metadata.clone();
+    metadata.trim_to_ranges(
+        &[TimeRange::new(20.0, Some(40.0)), TimeRange::new(100.0, None)],
+        120.0,
+    );
+    assert_eq!(metadata.chapters, vec![
+        chapter(0.0, 10.0, "Opening"),
+        chapter(10.0, 20.0, "Middle"),
+        chapter(20.0, 40.0, "Credits"),
+    ]);
+
+    // Neither a range nor a chapter runs past the end of the source
+    let mut metadata = original;
+    metadata.chapters[2].end = 130.0;
+    metadata.trim_to_ranges(
+        &[TimeRange::new(0.0, Some(20.0)), TimeRange::new(100.0, Some(150.0))],
+        120.0,
+    );
+    assert_eq!(metadata.chapters, vec![
+        chapter(0.0, 20.0, "Opening"),
+        chapter(20.0, 40.0, "Credits"),
+    ]);
+}
+
+#[test]
+fn test_quality_metrics_parsing() {
+    init_test_logging();
+
+    assert_eq!(
+        quality_filter(None),
+        "[0:v][1:v]scale2ref[d][r];[d]split[d0][d1];[r]split[r0][r1];[d0][r0]ssim;[d1][r1]psnr"
+    );
+    // The encode is scaled to the filtered source, whatever its encoder did
+    assert_eq!(
+        quality_filter(Some("scale=-2:720")),
+        "[1:v]scale=-2:720[ref];[0:v][ref]scale2ref[d][r];[d]split[d0][d1];[r]split[r0][r1];\
+         [d0][r0]ssim;[d1][r1]psnr"
+    );
+
+    let metrics = parse_quality_metrics(mock_data::QUALITY_LOG);
+    assert_eq!(metrics.ssim, Some(0.982345));
+    assert_eq!(metrics.psnr, Some(41.234567));
+    // Identical frames have infinite PSNR, which is left out
+    let identical = parse_quality_metrics(
+        "[Parsed_psnr_5 @ 0x1] PSNR y:inf u:inf v:inf average:inf min:inf max:inf",
+    );
+    assert_eq!(identical.psnr, None);
+    assert_eq!(identical.ssim, None);
+}
+
+#[test]
+fn test_attachment_substitution() {
+    init_test_logging();
+
+    let mut chunk = mock_data::create_test_chunk();
+    chunk.encoder_parameters = ["-c:v", "libx265", "-x265-params", "qpfile={attachment:qp}"]
+        .map(String::from)
+        .to_vec();
+    // A placeholder without its attachment is refused
+    assert!(chunk_encoder_arguments(&chunk).is_err());
+
+    let chunk = chunk.with_attachments(vec![ChunkAttachment {
+        name: "qp".to_string(),
+        path: "/work/chunk_3/qp".into(),
+    }]);
+    let (_, arguments, _) = chunk_encoder_arguments(&chunk).unwrap();
+    assert!(arguments
+        .encoder_args
+        .contains(&"qpfile=/work/chunk_3/qp:open-gop=0".to_string()));
+}
+
+#[test]
+fn test_chunk_transport() {
+    init_test_logging();
+
+    assert_eq!(transport_arguments(ChunkTransport::Source), None);
+    assert!(transport_command(Path::new("segment.mkv"), ChunkTransport::Source).is_none());
+
+    let ffv1 = transport_arguments(ChunkTransport::Ffv1).unwrap();
+    assert!(ffv1.windows(2).any(|pair| pair == ["-c:v", "ffv1"]));
+    assert!(ffv1.windows(2).any(|pair| pair == ["-f", "matroska"]));
+    assert_eq!(ChunkTransport::Ffv1.extension(), "mkv");
+
+    let y4m = transport_arguments(ChunkTransport::Y4m).unwrap();
+    assert!(y4m.windows(2).any(|pair| pair == ["-f", "yuv4mpegpipe"]));
+    assert_eq!(ChunkTransport::Y4m.extension(), "y4m");
+
+    let command = transport_command(Path::new("segment.mkv"), ChunkTransport::Y4m).unwrap();
+    let arguments: Vec<_> = command.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+    assert_eq!(arguments.last().map(String::as_str), Some("-"));
+    assert!(arguments.contains(&"segment.mkv".to_string()));
+
+    // A chunk keeps its transport once encoded
+    let chunk = mock_data::create_test_chunk().with_transport(ChunkTransport::Ffv1);
+    let encoded = chunk.with_encoded_path("encoded.mkv".into());
+    assert_eq!(encoded.transport, ChunkTransport::Ffv1);
+}
+
+#[test]
+fn test_process_scope_kills_on_cancel() {
+    init_test_logging();
+
+    let scope = ProcessScope::default();
+    let worker = {
+        let scope = scope.clone();
+        std::thread::spawn(move || scope.run(|| Command::new("sleep").arg("30").killable_output()))
+    };
+    std::thread::sleep(Duration::from_millis(200));
+    scope.cancel();
+    // Killed, or never started if cancelled first
+    let output = worker.join().unwrap();
+    assert!(output.map_or(true, |output| !output.status.success()));
+    scope.wait_idle();
+    // Nothing starts in a cancelled scope
+    assert!(scope.run(|| Command::new("true").killable_output()).is_err());
+    // Threads the work starts are handed its scope
+    let handed = scope.run(|| {
+        let current = ProcessScope::current().unwrap();
+        std::thread::spawn(move || current.run(|| Command::new("true").killable_output()))
+            .join()
+            .unwrap()
+    });
+    assert!(handed.is_err());
+    assert!(ProcessScope::current().is_none());
+    // Outside a scope, commands run as usual
+    assert!(Command::new("true").killable_output().unwrap().status.success());
+}
+
+#[test]
+fn test_progressive_output() {
+    init_test_logging();
+
+    // Chunks completing out of order are appended in index order
+    let mut queue = InOrderQueue::default();
+    assert!(queue.push(1, "1.mkv".into()).is_empty());
+    assert!(queue.push(2, "2.mkv".into()).is_empty());
+    assert_eq!(queue.push(0, "0.mkv".into()), vec![
+        Path::new("0.mkv"),
+        Path::new("1.mkv"),
+        Path::new("2.mkv")
+    ]);
+    // Chunks already appended are not appended again
+    assert!(queue.push(1, "1.mkv".into()).is_empty());
+    assert_eq!(queue.push(3, "3.mkv".into()), vec![Path::new("3.mkv")]);
+    assert_eq!(queue.next_index(), 4);
+
+    assert_eq!(
+        ProgressiveFormat::from_path(Path::new("watch.MP4")).unwrap(),
+        ProgressiveFormat::FragmentedMp4
+    );
+    assert_eq!(
+        ProgressiveFormat::from_path(Path::new("watch.mkv")).unwrap(),
+        ProgressiveFormat::Matroska
+    );
+    assert!(ProgressiveFormat::from_path(Path::new("watch.webm")).is_err());
+
+    assert!(progressive_accepts_codec("h264"));
+    assert!(progressive_accepts_codec("hevc"));
+    assert!(!progressive_accepts_codec("vp9"));
+    assert!(ProgressiveFormat::Matroska.check_codec("hevc").is_ok());
+    let av1 = ProgressiveFormat::Matroska.check_codec("av1").unwrap_err().to_string();
+    assert!(
+        av1.contains("Matroska") && av1.contains("MPEG-TS"),
+        "{}",
+        av1
+    );
+
+    let muxer = progressive_muxer_arguments(ProgressiveFormat::FragmentedMp4, Path::new("w.mp4"));
+    assert!(muxer.contains(&"frag_keyframe+empty_moov+default_base_moof".to_string()));
+    assert_eq!(muxer.last().map(String::as_str), Some("w.mp4"));
+    let chunk = progressive_chunk_arguments(Path::new("chunk_0002.mkv"), 20.5);
+    assert!(chunk.windows(2).any(|pair| pair == ["-output_ts_offset", "20.5"]));
+    assert_eq!(chunk.last().map(String::as_str), Some("pipe:1"));
+}
@@ -0,0 +1,338 @@
+/// Audio track extraction and re-encoding, run as a task of its own next to
+/// the video chunks.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use ferris_swarm_core::{
+    error::VideoEncodeError,
+    AudioOptions,
+    LoudnessMeasurement,
+    LoudnessTarget,
+    StreamInfo,
+    StreamKind,
+    StreamSelection,
+    TimeRange,
+    TrackLoudness,
+};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{process::KillableCommand, segmenter::copy_streams_in_ranges, streams::probe_streams};
+
+/// Stream copies the selected audio tracks of `input_path` into a Matroska
+/// audio file, which is what gets encoded locally or sent to a node. Tracks
+/// are selected by `options.tracks` and the audio filter of `selection`, and
+/// cut to `ranges` like the video. Returns `None` when no audio track is
+/// selected.
+#[instrument(skip(options, selection))]
+pub fn extract_audio_streams(
+    input_path: &Path,
+    temp_dir: &Path,
+    options: &AudioOptions,
+    selection: &StreamSelection,
+    ranges: &[TimeRange],
+) -> Result<Option<PathBuf>, VideoEncodeError> {
+    debug!("Extracting audio tracks from: {:?}", input_path);
+
+    let streams = probe_streams(input_path)?;
+    let audio_streams: Vec<&StreamInfo> = selection
+        .select(&streams, &options.tracks)
+        .into_iter()
+        .filter(|stream| stream.kind == StreamKind::Audio)
+        .collect();
+    if audio_streams.is_empty() {
+        info!("No audio tracks selected in {:?}", input_path);
+        return Ok(None);
+    }
+
+    std::fs::create_dir_all(temp_dir)?;
+
+    let audio_path = temp_dir.join("audio_source.mka");
+    copy_streams_in_ranges(input_path, &audio_streams, ranges, &audio_path, temp_dir)?;
+    info!(
+        "Extracted {} audio tracks to {:?}",
+        audio_streams.len(),
+        audio_path
+    );
+    Ok(Some(audio_path))
+}
+
+/// Encodes every audio track of `input_path` with the codec, bitrate and
+/// channel layout in `options`.
+///
+/// With a loudness target set, each track is first measured with `loudnorm`
+/// in analysis mode and then normalized in a second, linear pass using the
+/// measured values. The per-track results are returned; they are empty when
+/// no normalization was requested.
+#[instrument(skip(options))]
+pub fn encode_audio(
+    input_path: &Path,
+    output_path: &Path,
+    options: &AudioOptions,
+) -> Result<Vec<TrackLoudness>, VideoEncodeError> {
+    let codec = options.codec.as_deref().ok_or_else(|| {
+        VideoEncodeError::Config("Audio re-encoding requested without a codec".to_string())
+    })?;
+
+    let mut args =
+        vec!["-map".to_string(), "0:a".to_string(), "-c:a".to_string(), codec.to_string()];
+    match &options.bitrate {
+        Some(bitrate) if bitrate.contains(',') => {
+            let track_bitrates = options.track_bitrates(count_audio_streams(input_path)?);
+            for (track, bitrate) in track_bitrates.into_iter().enumerate() {
+                if let Some(bitrate) = bitrate {
+                    args.extend([format!("-b:a:{}", track), bitrate.to_string()]);
+                }
+            }
+        },
+        Some(bitrate) => args.extend(["-b:a".to_string(), bitrate.clone()]),
+        None => {},
+    }
+
+    // aformat up/downmixes to the requested layout. It runs ahead of loudnorm
+    // so the measurement sees the downmix that is actually delivered.
+    let layout_filter = options
+        .channel_layout
+        .as_ref()
+        .map(|channel_layout| format!("aformat=channel_layouts={}", channel_layout));
+
+    let mut measurements = Vec::new();
+    match &options.loudness {
+        Some(target) => {
+            let track_count = count_audio_streams(input_path)?;
+            for track in 0..track_count {
+                let measured =
+                    measure_loudness(input_path, track, target, layout_filter.as_deref())?;
+                let mut filters: Vec<String> = layout_filter.iter().cloned().collect();
+                if measured.is_silent() {
+                    // -inf measured values make an invalid second pass
+                    warn!(
+                        "Audio track {} is silent; it is not loudness normalized",
+                        track
+                    );
+                } else {
+                    info!(
+                        "Audio track {} measures {:.2} LUFS, {:.2} dBTP, LRA {:.2} LU",
+                        track, measured.integrated, measured.true_peak, measured.loudness_range
+                    );
+                    filters.push(loudnorm_filter(target, Some(&measured)));
+                    // loudnorm works at 192 kHz internally
+                    filters.push("aresample=48000".to_string());
+                }
+                if !filters.is_empty() {
+                    args.extend([format!("-filter:a:{}", track), filters.join(",")]);
+                }
+                measurements.push(measured);
+            }
+        },
+        None => {
+            if let Some(layout_filter) = &layout_filter {
+                args.extend(["-af".to_string(), layout_filter.clone()]);
+            }
+        },
+    }
+    debug!("Encoding audio {:?} with {:?}", input_path, args);
+
+    let output = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-i")
+        .arg(input_path)
+        .args(&args)
+        .arg("-y")
+        .arg(output_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        let error_msg = format!(
+            "Failed to encode audio with ffmpeg. Stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        error!("{}", error_msg);
+        return Err(VideoEncodeError::Encoding(error_msg));
+    }
+
+    let mut results = Vec::with_capacity(measurements.len());
+    if !measurements.is_empty() {
+        let stats = loudnorm_stats(&String::from_utf8_lossy(&output.stderr));
+        // Silent tracks ran no loudnorm, so print no statistics
+        let normalized = measurements.iter().filter(|input| !input.is_silent()).count();
+        if stats.len() != normalized {
+            warn!(
+                "Expected loudnorm statistics for {} tracks, ffmpeg reported {}",
+                normalized,
+                stats.len()
+            );
+        }
+        let mut stats = stats.iter();
+        for (track, input) in measurements.into_iter().enumerate() {
+            if input.is_silent() {
+                results.push(TrackLoudness {
+                    track,
+                    input,
+                    output: None,
+                    normalization_type: "none".to_string(),
+                });
+                continue;
+            }
+            let stats = stats.next();
+            let output = stats.map(|stats| loudness_from_stats(stats, "output")).transpose()?;
+            if let Some(output) = &output {
+                info!(
+                    "Audio track {} normalized to {:.2} LUFS, {:.2} dBTP",
+                    track, output.integrated, output.true_peak
+                );
+            }
+            results.push(TrackLoudness {
+                track,
+                input,
+                output,
+                normalization_type: stats
+                    .and_then(|stats| stats.get("normalization_type"))
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+    }
+
+    info!("Encoded audio {:?} to {:?}", input_path, output_path);
+    Ok(results)
+}
+
+/// Runs `loudnorm` in analysis mode over one audio track.
+#[instrument(skip(target))]
+pub fn measure_loudness(
+    input_path: &Path,
+    track: usize,
+    target: &LoudnessTarget,
+    pre_filter: Option<&str>,
+) -> Result<LoudnessMeasurement, VideoEncodeError> {
+    let filter = match pre_filter {
+        Some(pre_filter) => format!("{},{}", pre_filter, loudnorm_filter(target, None)),
+        None => loudnorm_filter(target, None),
+    };
+    let output = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-i")
+        .arg(input_path)
+        .args(["-map", &format!("0:a:{}", track), "-af", &filter, "-f", "null", "-"])
+        .killable_output()?;
+
+    if !output.status.success() {
+        let error_msg = format!(
+            "Failed to measure loudness of audio track {}. Stderr: {}",
+            track,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        error!("{}", error_msg);
+        return Err(VideoEncodeError::Encoding(error_msg));
+    }
+
+    let stats = loudnorm_stats(&String::from_utf8_lossy(&output.stderr));
+    let stats = stats.first().ok_or_else(|| {
+        VideoEncodeError::Encoding(format!(
+            "ffmpeg printed no loudnorm statistics for audio track {}",
+            track
+        ))
+    })?;
+    loudness_from_stats(stats, "input")
+}
+
+/// `loudnorm` filter for `target`. With a measurement the filter runs in
+/// linear (second pass) mode, without it only analyses.
+pub fn loudnorm_filter(target: &LoudnessTarget, measured: Option<&LoudnessMeasurement>) -> String {
+    let mut filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}",
+        target.integrated, target.true_peak, target.loudness_range
+    );
+    if let Some(measured) = measured {
+        filter.push_str(&format!(
+            ":measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:\
+             linear=true",
+            measured.integrated,
+            measured.true_peak,
+            measured.loudness_range,
+            measured.threshold,
+            measured.target_offset
+        ));
+    }
+    filter.push_str(":print_format=json");
+    filter
+}
+
+/// Collects the JSON statistics blocks `loudnorm` prints to stderr, one per
+/// filter instance.
+pub fn loudnorm_stats(stderr: &str) -> Vec<serde_json::Value> {
+    let mut blocks = Vec::new();
+    let mut current: Option<String> = None;
+    for line in stderr.lines() {
+        let trimmed = line.trim();
+        if trimmed == "{" {
+            current = Some(String::from("{"));
+        } else if let Some(block) = current.as_mut() {
+            block.push_str(trimmed);
+            if trimmed == "}" {
+                if let Ok(value) = serde_json::from_str(block) {
+                    blocks.push(value);
+                }
+                current = None;
+            }
+        }
+    }
+    blocks
+}
+
+/// Reads the `input_*` or `output_*` values of a `loudnorm` statistics block.
+fn loudness_from_stats(
+    stats: &serde_json::Value,
+    prefix: &str,
+) -> Result<LoudnessMeasurement, VideoEncodeError> {
+    // loudnorm prints every number as a string
+    let value = |key: String| -> Result<f64, VideoEncodeError> {
+        stats
+            .get(&key)
+            .and_then(|value| value.as_str())
+            .and_then(|value| value.trim().parse().ok())
+            .ok_or_else(|| {
+                VideoEncodeError::Encoding(format!("Missing or invalid loudnorm value '{}'", key))
+            })
+    };
+    Ok(LoudnessMeasurement {
+        integrated:     value(format!("{}_i", prefix))?,
+        true_peak:      value(format!("{}_tp", prefix))?,
+        loudness_range: value(format!("{}_lra", prefix))?,
+        threshold:      value(format!("{}_thresh", prefix))?,
+        target_offset:  value("target_offset".to_string())?,
+    })
+}
+
+fn count_audio_streams(input_path: &Path) -> Result<usize, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "a",
+            "-show_entries",
+            "stream=index",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to list audio streams of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count())
+}
@@ -0,0 +1,1037 @@
+/// Encoder backends a chunk can be encoded with.
+///
+/// `ffmpeg` runs the whole encode inside ffmpeg. The standalone backends
+/// decode the chunk with ffmpeg, pipe it as y4m into the encoder's own CLI and
+/// mux the resulting elementary stream back into Matroska.
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use ferris_swarm_core::{
+    error::VideoEncodeError,
+    profile::codec_family,
+    substitute_attachments,
+    ChunkAttachment,
+    ColorMetadata,
+    EncoderProfile,
+    MasteringDisplay,
+    NodeCapabilities,
+    PassMode,
+    RateControl,
+    DEFAULT_ENCODER_BACKEND,
+};
+use tracing::{debug, error, instrument};
+
+use crate::{
+    encoder::{encode_with_ffmpeg, encode_with_ffmpeg_two_pass, strip_pass_arguments},
+    utils::probe_frame_rate,
+};
+
+/// Arguments for one encode, split by the process that consumes them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EncoderArguments {
+    /// ffmpeg output options applied while decoding the chunk (pixel format,
+    /// filters). The ffmpeg backend places them ahead of `encoder_args`.
+    pub decoder_args: Vec<String>,
+    /// Options understood by the encoder itself
+    pub encoder_args: Vec<String>,
+}
+
+impl EncoderArguments {
+    /// Wraps raw, backend specific encoder parameters.
+    pub fn from_parameters(encoder_parameters: &[String]) -> Self {
+        Self {
+            decoder_args: Vec::new(),
+            encoder_args: encoder_parameters.to_vec(),
+        }
+    }
+
+    /// Replaces attachment placeholders in every argument with the paths of
+    /// `attachments`.
+    pub fn substitute_attachments(
+        &mut self,
+        attachments: &[ChunkAttachment],
+    ) -> Result<(), VideoEncodeError> {
+        for argument in self.decoder_args.iter_mut().chain(self.encoder_args.iter_mut()) {
+            *argument = substitute_attachments(argument, attachments)?;
+        }
+        Ok(())
+    }
+
+    /// Runs `filter` ahead of any video filter the arguments already set.
+    pub fn prepend_video_filter(&mut self, filter: &str) {
+        for args in [&mut self.decoder_args, &mut self.encoder_args] {
+            if let Some(i) = args
+                .iter()
+                .position(|arg| matches!(arg.as_str(), "-vf" | "-filter:v"))
+                .filter(|i| i + 1 < args.len())
+            {
+                args[i + 1] = format!("{},{}", filter, args[i + 1]);
+                return;
+            }
+        }
+        self.decoder_args.extend(["-vf".to_string(), filter.to_string()]);
+    }
+}
+
+/// A video encoder a node can run a chunk through.
+pub trait Encoder: Send + Sync {
+    /// Name jobs use to select this backend
+    fn name(&self) -> &'static str;
+
+    /// Codec family the backend always produces, `None` if it depends on the
+    /// profile's codec
+    fn codec_family(&self) -> Option<&'static str>;
+
+    /// Whether the executables this backend needs are installed
+    fn is_available(&self) -> bool;
+
+    /// Renders a typed encoder profile into arguments for this backend.
+    fn render_profile(&self, profile: &EncoderProfile) -> EncoderArguments;
+
+    /// Adds the source's color description and HDR10 metadata to
+    /// `arguments`, leaving options the caller already set alone. Returns the
+    /// parts of `color` this backend can't carry.
+    fn apply_color_metadata(
+        &self,
+        color: &ColorMetadata,
+        arguments: &mut EncoderArguments,
+    ) -> Vec<&'static str>;
+
+    /// Adds the options that make a chunk start with an IDR/key frame and
+    /// keep its GOPs closed, so that encoded chunks join without references
+    /// across the border. Options the caller already set are left alone.
+    fn apply_chunk_boundaries(&self, arguments: &mut EncoderArguments);
+
+    /// Encodes `input_path` into `output_path`.
+    fn encode(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        arguments: &EncoderArguments,
+        pass_mode: PassMode,
+    ) -> Result<(), VideoEncodeError>;
+}
+
+/// Formats a rate control value without a trailing `.0` for whole numbers.
+fn format_quality(value: f32) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Whether `args` already sets `flag`, as `flag value` or `flag=value`
+fn has_argument(args: &[String], flag: &str) -> bool {
+    args.iter()
+        .any(|arg| arg == flag || arg.strip_prefix(flag).is_some_and(|rest| rest.starts_with('=')))
+}
+
+/// Appends `option value` (or `option=value` when `inline`) unless the caller
+/// already set `option`.
+fn push_missing(args: &mut Vec<String>, option: &str, value: String, inline: bool) {
+    if has_argument(args, option) {
+        return;
+    }
+    if inline {
+        args.push(format!("{}={}", option, value));
+    } else {
+        args.extend([option.to_string(), value]);
+    }
+}
+
+/// Merges `params` into a `key=value:key=value` ffmpeg option such as
+/// `-x265-params`, skipping keys the caller already set.
+fn merge_codec_params(args: &mut Vec<String>, option: &str, params: Vec<(&str, String)>) {
+    let position = args.iter().position(|arg| arg == option).filter(|i| i + 1 < args.len());
+    let existing = position.map(|i| args[i + 1].clone()).unwrap_or_default();
+    let added: Vec<String> = params
+        .into_iter()
+        .filter(|(key, _)| !existing.split(':').any(|param| param.split('=').next() == Some(*key)))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    if added.is_empty() {
+        return;
+    }
+
+    let added = added.join(":");
+    match position {
+        Some(i) if !existing.is_empty() => args[i + 1] = format!("{}:{}", existing, added),
+        Some(i) => args[i + 1] = added,
+        None => args.extend([option.to_string(), added]),
+    }
+}
+
+/// Video codec selected by ffmpeg output options
+fn ffmpeg_video_codec(args: &[String]) -> Option<&str> {
+    args.iter()
+        .position(|arg| matches!(arg.as_str(), "-c:v" | "-vcodec" | "-codec:v"))
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// `G(x,y)B(x,y)R(x,y)WP(x,y)L(max,min)` in the integer units x264 and x265
+/// use: 0.00002 for chromaticity and 0.0001 cd/m² for luminance
+fn x26x_master_display(display: &MasteringDisplay) -> String {
+    let xy = |(x, y): (f64, f64)| {
+        format!(
+            "{},{}",
+            (x * 50000.0).round() as u32,
+            (y * 50000.0).round() as u32
+        )
+    };
+    format!(
+        "G({})B({})R({})WP({})L({},{})",
+        xy(display.green),
+        xy(display.blue),
+        xy(display.red),
+        xy(display.white_point),
+        (display.max_luminance * 10000.0).round() as u64,
+        (display.min_luminance * 10000.0).round() as u64
+    )
+}
+
+/// The same layout in plain chromaticity and cd/m², as SVT-AV1 and rav1e
+/// read it
+fn decimal_master_display(display: &MasteringDisplay) -> String {
+    let xy = |(x, y): (f64, f64)| format!("{:.4},{:.4}", x, y);
+    format!(
+        "G({})B({})R({})WP({})L({:.4},{:.4})",
+        xy(display.green),
+        xy(display.blue),
+        xy(display.red),
+        xy(display.white_point),
+        display.max_luminance,
+        display.min_luminance
+    )
+}
+
+/// HDR10 parts of `color`, for encoders that can't signal them
+fn hdr10_fields(color: &ColorMetadata) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if color.mastering_display.is_some() {
+        fields.push("mastering display");
+    }
+    if color.content_light.is_some() {
+        fields.push("content light level");
+    }
+    fields
+}
+
+/// x264/x265 option values for `color`. Both use ffmpeg's names apart from
+/// the two gamma transfer curves.
+fn x26x_transfer(color: &ColorMetadata) -> Option<String> {
+    color.transfer.as_deref().map(|transfer| {
+        match transfer {
+            "gamma22" => "bt470m",
+            "gamma28" => "bt470bg",
+            other => other,
+        }
+        .to_string()
+    })
+}
+
+/// aomenc and rav1e names for the H.273 codes, in that order
+fn av1_primaries_names(code: u8) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        1 => ("bt709", "BT709"),
+        4 => ("bt470m", "BT470M"),
+        5 => ("bt470bg", "BT470BG"),
+        6 => ("bt601", "BT601"),
+        7 => ("smpte240", "SMPTE240"),
+        8 => ("film", "GenericFilm"),
+        9 => ("bt2020", "BT2020"),
+        10 => ("xyz", "XYZ"),
+        11 => ("smpte431", "SMPTE431"),
+        12 => ("smpte432", "SMPTE432"),
+        22 => ("ebu3213", "EBU3213"),
+        _ => return None,
+    })
+}
+
+fn av1_transfer_names(code: u8) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        1 => ("bt709", "BT709"),
+        4 => ("bt470m", "BT470M"),
+        5 => ("bt470bg", "BT470BG"),
+        6 => ("bt601", "BT601"),
+        7 => ("smpte240", "SMPTE240"),
+        8 => ("lin", "Linear"),
+        9 => ("log100", "Log100"),
+        10 => ("log100sq10", "Log100Sqrt10"),
+        11 => ("iec61966", "IEC61966"),
+        12 => ("bt1361", "BT1361"),
+        13 => ("srgb", "SRGB"),
+        14 => ("bt2020-10bit", "BT2020_10Bit"),
+        15 => ("bt2020-12bit", "BT2020_12Bit"),
+        16 => ("smpte2084", "SMPTE2084"),
+        17 => ("smpte428", "SMPTE428"),
+        18 => ("hlg", "HLG"),
+        _ => return None,
+    })
+}
+
+fn av1_matrix_names(code: u8) -> Option<(&'static str, &'static str)> {
+    Some(match code {
+        0 => ("identity", "Identity"),
+        1 => ("bt709", "BT709"),
+        4 => ("fcc73", "FCC"),
+        5 => ("bt470bg", "BT470BG"),
+        6 => ("bt601", "BT601"),
+        7 => ("smpte240", "SMPTE240"),
+        8 => ("ycgco", "YCgCo"),
+        9 => ("bt2020ncl", "BT2020NCL"),
+        10 => ("bt2020cl", "BT2020CL"),
+        11 => ("smpte2085", "SMPTE2085"),
+        12 => ("chromncl", "ChromatNCL"),
+        13 => ("chromcl", "ChromatCL"),
+        14 => ("ictcp", "ICtCp"),
+        _ => return None,
+    })
+}
+
+/// Encodes entirely within ffmpeg; parameters are ffmpeg output options.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FfmpegEncoder;
+
+impl Encoder for FfmpegEncoder {
+    fn name(&self) -> &'static str {
+        DEFAULT_ENCODER_BACKEND
+    }
+
+    fn codec_family(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn is_available(&self) -> bool {
+        which::which("ffmpeg").is_ok()
+    }
+
+    fn render_profile(&self, profile: &EncoderProfile) -> EncoderArguments {
+        let codec = profile.codec.as_str();
+        let mut args = vec!["-c:v".to_string(), codec.to_string()];
+
+        match profile.rate_control {
+            RateControl::Crf(value) => {
+                args.extend(["-crf".to_string(), format_quality(value)]);
+                if matches!(codec, "libaom-av1" | "libvpx-vp9" | "libvpx") {
+                    // Constant quality mode in libaom/libvpx needs a zero bitrate
+                    args.extend(["-b:v".to_string(), "0".to_string()]);
+                }
+            },
+            RateControl::Qp(value) => args.extend(["-qp".to_string(), value.to_string()]),
+            RateControl::Bitrate(kbps) => args.extend(["-b:v".to_string(), format!("{}k", kbps)]),
+        }
+
+        if let Some(preset) = &profile.preset {
+            let preset_flag = match codec {
+                "libaom-av1" | "libvpx-vp9" | "libvpx" => "-cpu-used",
+                "librav1e" => "-speed",
+                _ => "-preset",
+            };
+            args.extend([preset_flag.to_string(), preset.clone()]);
+        }
+        if let Some(pixel_format) = &profile.pixel_format {
+            args.extend(["-pix_fmt".to_string(), pixel_format.clone()]);
+        }
+        if let Some(keyint) = profile.keyint {
+            args.extend(["-g".to_string(), keyint.to_string()]);
+        }
+        args.extend(profile.extra_args.iter().cloned());
+
+        EncoderArguments {
+            decoder_args: Vec::new(),
+            encoder_args: args,
+        }
+    }
+
+    fn apply_color_metadata(
+        &self,
+        color: &ColorMetadata,
+        arguments: &mut EncoderArguments,
+    ) -> Vec<&'static str> {
+        let args = &mut arguments.encoder_args;
+        for (option, value) in [
+            ("-color_primaries", &color.color_primaries),
+            ("-color_trc", &color.transfer),
+            ("-colorspace", &color.matrix),
+            ("-color_range", &color.range),
+        ] {
+            if let Some(value) = value {
+                push_missing(args, option, value.clone(), false);
+            }
+        }
+        if !color.has_hdr10() {
+            return Vec::new();
+        }
+
+        // ffmpeg has no generic HDR10 options, they go through the wrapper's
+        // private parameter string
+        let (option, display_key, light_key) = match ffmpeg_video_codec(args) {
+            Some("libx265") => ("-x265-params", "master-display", "max-cll"),
+            Some("libx264") => ("-x264-params", "mastering-display", "cll"),
+            Some("libsvtav1") => ("-svtav1-params", "mastering-display", "content-light"),
+            _ => return hdr10_fields(color),
+        };
+        let mut params = Vec::new();
+        if let Some(mastering_display) = &color.mastering_display {
+            let display = match option {
+                "-svtav1-params" => decimal_master_display(mastering_display),
+                _ => x26x_master_display(mastering_display),
+            };
+            params.push((display_key, display));
+        }
+        if let Some(light) = &color.content_light {
+            params.push((light_key, format!("{},{}", light.max_cll, light.max_fall)));
+        }
+        merge_codec_params(args, option, params);
+        Vec::new()
+    }
+
+    fn apply_chunk_boundaries(&self, arguments: &mut EncoderArguments) {
+        let args = &mut arguments.encoder_args;
+        push_missing(args, "-force_key_frames", "expr:eq(n,0)".to_string(), false);
+        let codec = ffmpeg_video_codec(args).map(str::to_string);
+        match codec.as_deref() {
+            Some(codec @ ("libx264" | "libx265")) => {
+                // Forced keyframes are only IDR frames on request
+                push_missing(args, "-forced-idr", "1".to_string(), false);
+                let option = match codec {
+                    "libx264" => "-x264-params",
+                    _ => "-x265-params",
+                };
+                merge_codec_params(args, option, vec![("open-gop", "0".to_string())]);
+            },
+            Some("libsvtav1") => {
+                // Key frames rather than CRA as intra refresh points
+                merge_codec_params(args, "-svtav1-params", vec![(
+                    "irefresh-type",
+                    "2".to_string(),
+                )]);
+            },
+            Some("libaom-av1") => {
+                merge_codec_params(args, "-aom-params", vec![(
+                    "enable-fwd-kf",
+                    "0".to_string(),
+                )]);
+            },
+            // libvpx and rav1e only reference across keyframes when asked to
+            _ => {},
+        }
+    }
+
+    fn encode(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        arguments: &EncoderArguments,
+        pass_mode: PassMode,
+    ) -> Result<(), VideoEncodeError> {
+        let parameters: Vec<String> = arguments
+            .decoder_args
+            .iter()
+            .chain(arguments.encoder_args.iter())
+            .cloned()
+            .collect();
+        match pass_mode {
+            PassMode::OnePass => encode_with_ffmpeg(input_path, output_path, &parameters),
+            PassMode::TwoPass => encode_with_ffmpeg_two_pass(input_path, output_path, &parameters),
+        }
+    }
+}
+
+/// Standalone encoder CLIs that read y4m from stdin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StandaloneKind {
+    SvtAv1,
+    Aomenc,
+    Rav1e,
+    X264,
+    X265,
+}
+
+/// Encodes by piping y4m from ffmpeg into a standalone encoder CLI.
+#[derive(Debug, Clone, Copy)]
+pub struct StandaloneEncoder {
+    kind: StandaloneKind,
+}
+
+impl StandaloneEncoder {
+    pub fn new(kind: StandaloneKind) -> Self {
+        Self {
+            kind,
+        }
+    }
+
+    pub fn kind(&self) -> StandaloneKind {
+        self.kind
+    }
+
+    fn binary(&self) -> &'static str {
+        match self.kind {
+            StandaloneKind::SvtAv1 => "SvtAv1EncApp",
+            StandaloneKind::Aomenc => "aomenc",
+            StandaloneKind::Rav1e => "rav1e",
+            StandaloneKind::X264 => "x264",
+            StandaloneKind::X265 => "x265",
+        }
+    }
+
+    /// Extension of the elementary stream the encoder writes
+    fn bitstream_extension(&self) -> &'static str {
+        match self.kind {
+            StandaloneKind::SvtAv1 | StandaloneKind::Aomenc | StandaloneKind::Rav1e => "ivf",
+            StandaloneKind::X264 => "264",
+            StandaloneKind::X265 => "hevc",
+        }
+    }
+
+    /// Arguments that read y4m from stdin and write the bitstream to `output`
+    fn io_arguments(&self, output: &Path) -> Vec<String> {
+        let output = output.to_string_lossy().into_owned();
+        let args: Vec<&str> = match self.kind {
+            StandaloneKind::SvtAv1 => vec!["-i", "stdin", "-b", &output],
+            StandaloneKind::Aomenc => vec!["--ivf", "-o", &output, "-"],
+            StandaloneKind::Rav1e => vec!["-", "-o", &output],
+            StandaloneKind::X264 => vec!["--demuxer", "y4m", "-o", &output, "-"],
+            StandaloneKind::X265 => vec!["--y4m", "--input", "-", "-o", &output],
+        };
+        args.into_iter().map(str::to_string).collect()
+    }
+
+    /// Arguments owned by the pass driver, stripped from caller parameters
+    fn pass_argument_names(&self) -> &'static [&'static str] {
+        match self.kind {
+            StandaloneKind::SvtAv1 | StandaloneKind::X264 | StandaloneKind::X265 => {
+                &["--pass", "--passes", "--stats"]
+            },
+            StandaloneKind::Aomenc => &["--pass", "--passes", "--fpf"],
+            StandaloneKind::Rav1e => &["--first-pass", "--second-pass"],
+        }
+    }
+
+    /// Arguments selecting `pass` (1 or 2) of a two-pass encode with the
+    /// given stats file. `None` selects a single pass encode.
+    fn pass_arguments(&self, pass: Option<u8>, stats_file: &Path) -> Vec<String> {
+        let stats = stats_file.to_string_lossy().into_owned();
+        match (self.kind, pass) {
+            (StandaloneKind::Aomenc, None) => vec!["--passes=1".to_string()],
+            (_, None) => Vec::new(),
+            (StandaloneKind::Aomenc, Some(pass)) => vec![
+                "--passes=2".to_string(),
+                format!("--pass={}", pass),
+                format!("--fpf={}", stats),
+            ],
+            (StandaloneKind::Rav1e, Some(1)) => vec!["--first-pass".to_string(), stats],
+            (StandaloneKind::Rav1e, Some(_)) => vec!["--second-pass".to_string(), stats],
+            (_, Some(pass)) => {
+                vec!["--pass".to_string(), pass.to_string(), "--stats".to_string(), stats]
+            },
+        }
+    }
+
+    /// Decodes `input_path` to y4m and pipes it into one encoder run.
+    fn run_pipeline(
+        &self,
+        input_path: &Path,
+        decoder_args: &[String],
+        encoder_args: &[String],
+    ) -> Result<(), VideoEncodeError> {
+        debug!(
+            "Piping y4m from {:?} into {} {:?}",
+            input_path,
+            self.binary(),
+            encoder_args
+        );
+
+        let mut decoder = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-i"])
+            .arg(input_path)
+            .args(decoder_args)
+            .args(["-f", "yuv4mpegpipe", "-strict", "-1", "-"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let decoded = decoder.stdout.take().ok_or_else(|| {
+            VideoEncodeError::Encoding("Failed to capture ffmpeg y4m output".to_string())
+        })?;
+
+        let encoder_output = Command::new(self.binary())
+            .args(encoder_args)
+            .stdin(Stdio::from(decoded))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?
+            .wait_with_output()?;
+        let decoder_output = decoder.wait_with_output()?;
+
+        // An encoder that exits early breaks the pipe and fails the decoder
+        // too, so its failure is the one to report
+        if !encoder_output.status.success() {
+            let mut error_msg = format!(
+                "Failed to encode with {}. Stderr: {}",
+                self.binary(),
+                String::from_utf8_lossy(&encoder_output.stderr)
+            );
+            if !decoder_output.status.success() {
+                error_msg += &format!(
+                    "; ffmpeg decoder stderr: {}",
+                    String::from_utf8_lossy(&decoder_output.stderr)
+                );
+            }
+            error!("{}", error_msg);
+            return Err(VideoEncodeError::Encoding(error_msg));
+        }
+        if !decoder_output.status.success() {
+            let error_msg = format!(
+                "ffmpeg failed to decode {:?} to y4m. Stderr: {}",
+                input_path,
+                String::from_utf8_lossy(&decoder_output.stderr)
+            );
+            error!("{}", error_msg);
+            return Err(VideoEncodeError::Encoding(error_msg));
+        }
+        Ok(())
+    }
+
+    /// Muxes the elementary stream into the container chosen by `output_path`.
+    fn mux_bitstream(
+        &self,
+        input_path: &Path,
+        bitstream: &Path,
+        output_path: &Path,
+    ) -> Result<(), VideoEncodeError> {
+        let mut command = Command::new("ffmpeg");
+        command.args(["-hide_banner", "-y"]);
+        if self.bitstream_extension() != "ivf" {
+            // Annex-B streams carry no timing, take it from the source chunk
+            command.arg("-framerate").arg(probe_frame_rate(input_path)?);
+        }
+        let output = command
+            .arg("-i")
+            .arg(bitstream)
+            .args(["-c", "copy"])
+            .arg(output_path)
+            .output()?;
+
+        if !output.status.success() {
+            let error_msg = format!(
+                "Failed to mux {:?} into {:?}. Stderr: {}",
+                bitstream,
+                output_path,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            error!("{}", error_msg);
+            return Err(VideoEncodeError::Encoding(error_msg));
+        }
+        Ok(())
+    }
+}
+
+impl Encoder for StandaloneEncoder {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            StandaloneKind::SvtAv1 => "svt-av1",
+            StandaloneKind::Aomenc => "aomenc",
+            StandaloneKind::Rav1e => "rav1e",
+            StandaloneKind::X264 => "x264",
+            StandaloneKind::X265 => "x265",
+        }
+    }
+
+    fn codec_family(&self) -> Option<&'static str> {
+        Some(match self.kind {
+            StandaloneKind::SvtAv1 | StandaloneKind::Aomenc | StandaloneKind::Rav1e => "av1",
+            StandaloneKind::X264 => "h264",
+            StandaloneKind::X265 => "hevc",
+        })
+    }
+
+    fn is_available(&self) -> bool {
+        which::which("ffmpeg").is_ok() && which::which(self.binary()).is_ok()
+    }
+
+    fn render_profile(&self, profile: &EncoderProfile) -> EncoderArguments {
+        let mut decoder_args = Vec::new();
+        if let Some(pixel_format) = &profile.pixel_format {
+            // y4m carries the pixel format, so conversion happens while decoding
+            decoder_args.extend(["-pix_fmt".to_string(), pixel_format.clone()]);
+        }
+
+        let mut args: Vec<String> = Vec::new();
+        match (self.kind, profile.rate_control) {
+            (StandaloneKind::Aomenc, RateControl::Crf(value)) => args.extend([
+                "--end-usage=q".to_string(),
+                format!("--cq-level={}", format_quality(value)),
+            ]),
+            (StandaloneKind::Aomenc, RateControl::Qp(value)) => args.extend([
+                "--end-usage=q".to_string(),
+                format!("--min-q={}", value),
+                format!("--max-q={}", value),
+            ]),
+            (StandaloneKind::Aomenc, RateControl::Bitrate(kbps)) => {
+                args.extend(["--end-usage=vbr".to_string(), format!("--target-bitrate={}", kbps)])
+            },
+            (StandaloneKind::Rav1e, RateControl::Crf(value)) => {
+                args.extend(["--quantizer".to_string(), format_quality(value)])
+            },
+            (StandaloneKind::Rav1e, RateControl::Qp(value)) => {
+                args.extend(["--quantizer".to_string(), value.to_string()])
+            },
+            (StandaloneKind::Rav1e, RateControl::Bitrate(kbps)) => {
+                args.extend(["--bitrate".to_string(), kbps.to_string()])
+            },
+            (StandaloneKind::SvtAv1, RateControl::Bitrate(kbps)) => args.extend([
+                "--rc".to_string(),
+                "1".to_string(),
+                "--tbr".to_string(),
+                kbps.to_string(),
+            ]),
+            (_, RateControl::Crf(value)) => {
+                args.extend(["--crf".to_string(), format_quality(value)])
+            },
+            (_, RateControl::Qp(value)) => args.extend(["--qp".to_string(), value.to_string()]),
+            (_, RateControl::Bitrate(kbps)) => {
+                args.extend(["--bitrate".to_string(), kbps.to_string()])
+            },
+        }
+
+        if let Some(preset) = &profile.preset {
+            match self.kind {
+                StandaloneKind::Aomenc => args.push(format!("--cpu-used={}", preset)),
+                StandaloneKind::Rav1e => args.extend(["--speed".to_string(), preset.clone()]),
+                _ => args.extend(["--preset".to_string(), preset.clone()]),
+            }
+        }
+        if let Some(keyint) = profile.keyint {
+            match self.kind {
+                StandaloneKind::Aomenc => args.push(format!("--kf-max-dist={}", keyint)),
+                _ => args.extend(["--keyint".to_string(), keyint.to_string()]),
+            }
+        }
+        args.extend(profile.extra_args.iter().cloned());
+
+        EncoderArguments {
+            decoder_args,
+            encoder_args: args,
+        }
+    }
+
+    fn apply_color_metadata(
+        &self,
+        color: &ColorMetadata,
+        arguments: &mut EncoderArguments,
+    ) -> Vec<&'static str> {
+        let args = &mut arguments.encoder_args;
+        let mut unsupported = Vec::new();
+        let mut push =
+            |option: &str, value: Option<String>, field: &'static str, present: bool| match value {
+                Some(value) => {
+                    push_missing(args, option, value, self.kind == StandaloneKind::Aomenc)
+                },
+                None if present => unsupported.push(field),
+                None => {},
+            };
+
+        let full_range = color.full_range();
+        let primaries = color.primaries_code();
+        let transfer = color.transfer_code();
+        let matrix = color.matrix_code();
+        match self.kind {
+            StandaloneKind::X264 | StandaloneKind::X265 => {
+                push(
+                    "--colorprim",
+                    color.color_primaries.clone(),
+                    "color primaries",
+                    false,
+                );
+                push(
+                    "--transfer",
+                    x26x_transfer(color),
+                    "transfer characteristics",
+                    false,
+                );
+                push(
+                    "--colormatrix",
+                    color.matrix.clone(),
+                    "matrix coefficients",
+                    false,
+                );
+                let range = match (self.kind, full_range) {
+                    (_, None) => None,
+                    (StandaloneKind::X264, Some(full)) => Some(if full { "pc" } else { "tv" }),
+                    (_, Some(full)) => Some(if full { "full" } else { "limited" }),
+                };
+                push("--range", range.map(str::to_string), "color range", false);
+
+                let (display_option, light_option) = match self.kind {
+                    StandaloneKind::X264 => ("--mastering-display", "--cll"),
+                    _ => ("--master-display", "--max-cll"),
+                };
+                push(
+                    display_option,
+                    color.mastering_display.as_ref().map(x26x_master_display),
+                    "mastering display",
+                    false,
+                );
+                push(
+                    light_option,
+                    color
+                        .content_light
+                        .map(|light| format!("{},{}", light.max_cll, light.max_fall)),
+                    "content light level",
+                    false,
+                );
+            },
+            StandaloneKind::SvtAv1 => {
+                push(
+                    "--color-primaries",
+                    primaries.map(|code| code.to_string()),
+                    "color primaries",
+                    color.color_primaries.is_some(),
+                );
+                push(
+                    "--transfer-characteristics",
+                    transfer.map(|code| code.to_string()),
+                    "transfer characteristics",
+                    color.transfer.is_some(),
+                );
+                push(
+                    "--matrix-coefficients",
+                    matrix.map(|code| code.to_string()),
+                    "matrix coefficients",
+                    color.matrix.is_some(),
+                );
+                push(
+                    "--color-range",
+                    full_range.map(|full| u8::from(full).to_string()),
+                    "color range",
+                    false,
+                );
+                push(
+                    "--mastering-display",
+                    color.mastering_display.as_ref().map(decimal_master_display),
+                    "mastering display",
+                    false,
+                );
+                push(
+                    "--content-light",
+                    color
+                        .content_light
+                        .map(|light| format!("{},{}", light.max_cll, light.max_fall)),
+                    "content light level",
+                    false,
+                );
+            },
+            StandaloneKind::Rav1e => {
+                let name = |names: Option<(&'static str, &'static str)>| {
+                    names.map(|(_, name)| name.to_string())
+                };
+                push(
+                    "--primaries",
+                    name(primaries.and_then(av1_primaries_names)),
+                    "color primaries",
+                    color.color_primaries.is_some(),
+                );
+                push(
+                    "--transfer",
+                    name(transfer.and_then(av1_transfer_names)),
+                    "transfer characteristics",
+                    color.transfer.is_some(),
+                );
+                push(
+                    "--matrix",
+                    name(matrix.and_then(av1_matrix_names)),
+                    "matrix coefficients",
+                    color.matrix.is_some(),
+                );
+                push(
+                    "--range",
+                    full_range.map(|full| if full { "Full" } else { "Limited" }.to_string()),
+                    "color range",
+                    false,
+                );
+                push(
+                    "--mastering-display",
+                    color.mastering_display.as_ref().map(decimal_master_display),
+                    "mastering display",
+                    false,
+                );
+                push(
+                    "--content-light",
+                    color
+                        .content_light
+                        .map(|light| format!("{},{}", light.max_cll, light.max_fall)),
+                    "content light level",
+                    false,
+                );
+            },
+            StandaloneKind::Aomenc => {
+                let name = |names: Option<(&'static str, &'static str)>| {
+                    names.map(|(name, _)| name.to_string())
+                };
+                push(
+                    "--color-primaries",
+                    name(primaries.and_then(av1_primaries_names)),
+                    "color primaries",
+                    color.color_primaries.is_some(),
+                );
+                push(
+                    "--transfer-characteristics",
+                    name(transfer.and_then(av1_transfer_names)),
+                    "transfer characteristics",
+                    color.transfer.is_some(),
+                );
+                push(
+                    "--matrix-coefficients",
+                    name(matrix.and_then(av1_matrix_names)),
+                    "matrix coefficients",
+                    color.matrix.is_some(),
+                );
+                // aomenc always signals limited range and has no HDR10 options
+                if full_range == Some(true) {
+                    unsupported.push("color range");
+                }
+                unsupported.extend(hdr10_fields(color));
+            },
+        }
+        unsupported
+    }
+
+    fn apply_chunk_boundaries(&self, arguments: &mut EncoderArguments) {
+        let args = &mut arguments.encoder_args;
+        // Every encoder starts its output with a keyframe; these keep later
+        // frames from referencing across GOPs. x264 and rav1e only write open
+        // GOPs when asked to.
+        match self.kind {
+            StandaloneKind::X265
+                if !has_argument(args, "--open-gop") && !has_argument(args, "--no-open-gop") =>
+            {
+                args.push("--no-open-gop".to_string());
+            },
+            StandaloneKind::SvtAv1 => {
+                push_missing(args, "--irefresh-type", "2".to_string(), false);
+            },
+            StandaloneKind::Aomenc => {
+                push_missing(args, "--enable-fwd-kf", "0".to_string(), true);
+            },
+            _ => {},
+        }
+    }
+
+    #[instrument(skip(self, arguments), fields(backend = self.name()))]
+    fn encode(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        arguments: &EncoderArguments,
+        pass_mode: PassMode,
+    ) -> Result<(), VideoEncodeError> {
+        let parameters = strip_pass_arguments(&arguments.encoder_args, self.pass_argument_names());
+
+        let work_parent = output_path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let work_dir = tempfile::Builder::new()
+            .prefix(&format!("{}_", self.name()))
+            .tempdir_in(work_parent)?;
+        let bitstream: PathBuf =
+            work_dir.path().join(format!("bitstream.{}", self.bitstream_extension()));
+        let stats_file = work_dir.path().join("passlog");
+
+        let passes: &[Option<u8>] = match pass_mode {
+            PassMode::OnePass => &[None],
+            PassMode::TwoPass => &[Some(1), Some(2)],
+        };
+        for pass in passes {
+            let mut args = self.io_arguments(&bitstream);
+            args.extend(self.pass_arguments(*pass, &stats_file));
+            args.extend(parameters.iter().cloned());
+            self.run_pipeline(input_path, &arguments.decoder_args, &args)?;
+        }
+
+        self.mux_bitstream(input_path, &bitstream, output_path)?;
+        debug!("Successfully encoded {:?} to {:?}", input_path, output_path);
+        Ok(())
+    }
+}
+
+/// All encoder backends known to this build, available or not.
+pub fn encoder_backends() -> Vec<Box<dyn Encoder>> {
+    vec![
+        Box::new(FfmpegEncoder),
+        Box::new(StandaloneEncoder::new(StandaloneKind::SvtAv1)),
+        Box::new(StandaloneEncoder::new(StandaloneKind::Aomenc)),
+        Box::new(StandaloneEncoder::new(StandaloneKind::Rav1e)),
+        Box::new(StandaloneEncoder::new(StandaloneKind::X264)),
+        Box::new(StandaloneEncoder::new(StandaloneKind::X265)),
+    ]
+}
+
+/// Looks up an encoder backend by name.
+pub fn encoder_backend(name: &str) -> Result<Box<dyn Encoder>, VideoEncodeError> {
+    encoder_backends()
+        .into_iter()
+        .find(|backend| backend.name() == name)
+        .ok_or_else(|| VideoEncodeError::UnsupportedBackend(name.to_string()))
+}
+
+/// Names of the encoder backends whose executables are installed.
+pub fn available_encoder_backends() -> Vec<String> {
+    encoder_backends()
+        .into_iter()
+        .filter(|backend| backend.is_available())
+        .map(|backend| backend.name().to_string())
+        .collect()
+}
+
+/// Codec family a job produces: fixed by standalone backends, otherwise taken
+/// from the profile or the `-c:v` of raw ffmpeg parameters. `None` when
+/// neither names a codec.
+pub fn output_codec_family(
+    backend: &dyn Encoder,
+    profile: Option<&EncoderProfile>,
+    encoder_parameters: &[String],
+) -> Option<String> {
+    if let Some(family) = backend.codec_family() {
+        return Some(family.to_string());
+    }
+    match profile {
+        Some(profile) => Some(profile.codec_family().to_string()),
+        None => ffmpeg_video_codec(encoder_parameters).map(|codec| codec_family(codec).to_string()),
+    }
+}
+
+/// Checks that a node can encode `profile` with `backend` before chunks are
+/// dispatched to it. Nodes that don't report capabilities can't be checked
+/// and are accepted.
+pub fn validate_profile_for_node(
+    profile: &EncoderProfile,
+    backend: &str,
+    capabilities: Option<&NodeCapabilities>,
+) -> Result<(), VideoEncodeError> {
+    profile.validate()?;
+
+    let backend_impl = encoder_backend(backend)?;
+    if let Some(family) = backend_impl.codec_family() {
+        if profile.codec_family() != family {
+            return Err(VideoEncodeError::Config(format!(
+                "Encoder backend '{}' produces {}, but the profile asks for '{}' ({})",
+                backend,
+                family,
+                profile.codec,
+                profile.codec_family()
+            )));
+        }
+        // Standalone encoders are advertised as backends, not ffmpeg encoders
+        return Ok(());
+    }
+
+    match capabilities {
+        Some(capabilities) => profile.validate_against(capabilities),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,187 @@
+/// Raw elementary streams (IVF, AV1 OBU, H.264/HEVC Annex-B) and joining them
+/// without a container.
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use ferris_swarm_core::error::VideoEncodeError;
+use tracing::{debug, error};
+
+use crate::process::KillableCommand;
+
+const IVF_SIGNATURE: &[u8; 4] = b"DKIF";
+const IVF_HEADER_LEN: usize = 32;
+const IVF_FRAME_HEADER_LEN: usize = 12;
+/// Offset of the frame count in the IVF file header
+const IVF_FRAME_COUNT_OFFSET: u64 = 24;
+
+/// Raw stream layouts, told apart by output extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawStreamFormat {
+    /// IVF frames with a file header, for AV1, VP8 and VP9
+    Ivf,
+    /// AV1 low overhead bitstream
+    Obu,
+    /// H.264 byte stream
+    H264,
+    /// HEVC byte stream
+    Hevc,
+}
+
+impl RawStreamFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "ivf" => Some(RawStreamFormat::Ivf),
+            "obu" => Some(RawStreamFormat::Obu),
+            "h264" | "264" => Some(RawStreamFormat::H264),
+            "hevc" | "h265" | "265" => Some(RawStreamFormat::Hevc),
+            _ => None,
+        }
+    }
+
+    /// ffmpeg muxer writing this format
+    pub fn muxer(&self) -> &'static str {
+        match self {
+            RawStreamFormat::Ivf => "ivf",
+            RawStreamFormat::Obu => "obu",
+            RawStreamFormat::H264 => "h264",
+            RawStreamFormat::Hevc => "hevc",
+        }
+    }
+}
+
+/// Copies the first video stream of `input_path` into `output_path` as a raw
+/// `format` stream.
+pub fn extract_elementary_stream(
+    input_path: &Path,
+    output_path: &Path,
+    format: RawStreamFormat,
+) -> Result<(), VideoEncodeError> {
+    debug!(
+        "Extracting {} stream from {:?} to {:?}",
+        format.muxer(),
+        input_path,
+        output_path
+    );
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(input_path)
+        .args(["-map", "0:v:0", "-c:v", "copy", "-f", format.muxer()])
+        .arg(output_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        let error_msg = format!(
+            "Failed to extract the {} stream of {:?}. Stderr: {}",
+            format.muxer(),
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        error!("{}", error_msg);
+        return Err(VideoEncodeError::Concatenation(error_msg));
+    }
+    Ok(())
+}
+
+/// Joins IVF files into `output_path`. The first file's header is kept,
+/// frame timestamps continue across files and the frame count is rewritten.
+/// Returns the number of frames written.
+pub fn concatenate_ivf(inputs: &[PathBuf], output_path: &Path) -> Result<u32, VideoEncodeError> {
+    let invalid = |path: &Path, reason: &str| {
+        VideoEncodeError::Concatenation(format!("Invalid IVF file {:?}: {}", path, reason))
+    };
+
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    let mut first_header: Option<[u8; IVF_HEADER_LEN]> = None;
+    let mut frame_count: u32 = 0;
+    // Timestamp the next file starts at
+    let mut offset: u64 = 0;
+
+    for input in inputs {
+        let mut reader = BufReader::new(File::open(input)?);
+        let mut header = [0u8; IVF_HEADER_LEN];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| invalid(input, "truncated file header"))?;
+        if &header[0..4] != IVF_SIGNATURE {
+            return Err(invalid(input, "missing DKIF signature"));
+        }
+        let header_len = u16::from_le_bytes([header[6], header[7]]) as usize;
+        if header_len > IVF_HEADER_LEN {
+            // Skip header fields this reader doesn't know
+            std::io::copy(
+                &mut (&mut reader).take((header_len - IVF_HEADER_LEN) as u64),
+                &mut std::io::sink(),
+            )?;
+        }
+        match &first_header {
+            None => {
+                writer.write_all(&header)?;
+                first_header = Some(header);
+            },
+            Some(first) if first[8..12] != header[8..12] => {
+                return Err(invalid(input, "codec differs from the first segment"));
+            },
+            Some(_) => {},
+        }
+
+        let mut first_timestamp = None;
+        let mut previous: Option<u64> = None;
+        let mut last: Option<u64> = None;
+        loop {
+            let mut frame_header = [0u8; IVF_FRAME_HEADER_LEN];
+            match reader.read_exact(&mut frame_header) {
+                Ok(()) => {},
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            let size = u32::from_le_bytes(frame_header[0..4].try_into().unwrap());
+            let timestamp = u64::from_le_bytes(frame_header[4..12].try_into().unwrap());
+            let first = *first_timestamp.get_or_insert(timestamp);
+            let shifted = offset + timestamp.saturating_sub(first);
+
+            let mut frame = vec![0u8; size as usize];
+            reader.read_exact(&mut frame).map_err(|_| invalid(input, "truncated frame"))?;
+            writer.write_all(&size.to_le_bytes())?;
+            writer.write_all(&shifted.to_le_bytes())?;
+            writer.write_all(&frame)?;
+
+            frame_count += 1;
+            previous = last;
+            last = Some(shifted);
+        }
+
+        // The next file starts one frame duration after this one's last frame
+        if let Some(last) = last {
+            let duration = previous.map_or(1, |previous| last.saturating_sub(previous).max(1));
+            offset = last + duration;
+        }
+    }
+
+    if first_header.is_none() {
+        return Err(VideoEncodeError::Concatenation(
+            "No IVF files provided for concatenation.".to_string(),
+        ));
+    }
+    let mut file = writer.into_inner().map_err(|e| VideoEncodeError::Io(e.into_error()))?;
+    file.seek(SeekFrom::Start(IVF_FRAME_COUNT_OFFSET))?;
+    file.write_all(&frame_count.to_le_bytes())?;
+    // The output header is always IVF_HEADER_LEN long
+    file.seek(SeekFrom::Start(6))?;
+    file.write_all(&(IVF_HEADER_LEN as u16).to_le_bytes())?;
+    Ok(frame_count)
+}
+
+/// Joins streams that need no rewriting, such as Annex-B byte streams and
+/// AV1 OBU streams whose segments each start with their parameter sets.
+pub fn concatenate_bytes(inputs: &[PathBuf], output_path: &Path) -> Result<(), VideoEncodeError> {
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    for input in inputs {
+        std::io::copy(&mut BufReader::new(File::open(input)?), &mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+/// Quick per-segment complexity analysis: each segment is encoded small and
+/// fast at a constant quality, and the bitrate that takes stands for how hard
+/// it is to encode.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use ferris_swarm_core::error::VideoEncodeError;
+use tracing::{debug, info, instrument};
+
+use crate::{
+    process::{KillableCommand, ProcessScope},
+    utils::probe_duration,
+};
+
+/// Arguments of the analysis encode, between input and output
+pub const COMPLEXITY_ANALYSIS_ARGUMENTS: &[&str] = &[
+    "-map",
+    "0:v:0",
+    "-vf",
+    "scale=-2:240",
+    "-c:v",
+    "libx264",
+    "-preset",
+    "ultrafast",
+    "-crf",
+    "28",
+    "-an",
+    "-sn",
+    "-dn",
+];
+
+/// Bits per second the analysis encode of `segment` takes.
+#[instrument]
+pub fn analyze_complexity(segment: &Path, temp_dir: &Path) -> Result<f64, VideoEncodeError> {
+    let stem = segment.file_stem().unwrap_or_default().to_string_lossy();
+    let analysis_path = temp_dir.join(format!("{}_complexity.mkv", stem));
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-y", "-i"])
+        .arg(segment)
+        .args(COMPLEXITY_ANALYSIS_ARGUMENTS)
+        .arg(&analysis_path)
+        .killable_output()?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&analysis_path);
+        return Err(VideoEncodeError::Encoding(format!(
+            "Complexity analysis of {:?} failed: {}",
+            segment,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let bytes = fs::metadata(&analysis_path)?.len();
+    let duration = probe_duration(segment)?;
+    fs::remove_file(&analysis_path)?;
+    let complexity = if duration > 0.0 {
+        bytes as f64 * 8.0 / duration
+    } else {
+        0.0
+    };
+    debug!("Complexity of {:?}: {:.0} bit/s", segment, complexity);
+    Ok(complexity)
+}
+
+/// Analyzes `segments` on all local cores, returning their complexity in
+/// order.
+pub fn analyze_segments(
+    segments: &[PathBuf],
+    temp_dir: &Path,
+) -> Result<Vec<f64>, VideoEncodeError> {
+    fs::create_dir_all(temp_dir)?;
+    let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(segments.len());
+    info!(
+        "Analyzing the complexity of {} segments on {} threads...",
+        segments.len(),
+        workers
+    );
+    let next = AtomicUsize::new(0);
+    // The analysis encodes belong to the job that started them
+    let processes = ProcessScope::current();
+    let mut results: Vec<(usize, Result<f64, VideoEncodeError>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let analyze = || {
+                        let mut results = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(segment) = segments.get(index) else {
+                                return results;
+                            };
+                            results.push((index, analyze_complexity(segment, temp_dir)));
+                        }
+                    };
+                    match &processes {
+                        Some(processes) => processes.run(analyze),
+                        None => analyze(),
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Complexity analysis thread panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, complexity)| complexity).collect()
+}
@@ -1,19 +1,111 @@
 use std::{
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
     process::Command,
 };
 
-use ferris_swarm_core::error::VideoEncodeError;
-use tracing::{debug, error, info, instrument};
+use ferris_swarm_core::{error::VideoEncodeError, ColorMetadata, StreamMetadata};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{
+    bitstream::{concatenate_bytes, concatenate_ivf, extract_elementary_stream, RawStreamFormat},
+    metadata::ContainerMetadata,
+    process::KillableCommand,
+    streams::{
+        ffmpeg_metadata_arguments,
+        mkvmerge_color_arguments,
+        mkvmerge_metadata_arguments,
+        probe_streams,
+    },
+};
+
+/// Everything muxed into the output next to the concatenated video segments
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MuxInputs<'a> {
+    /// Audio, subtitles and other streams from `extract_non_video_streams`
+    pub non_video_stream_file: Option<&'a Path>,
+    /// Separately encoded audio, muxed ahead of the non-video streams
+    pub audio_file:            Option<&'a Path>,
+    /// Metadata overrides for output streams
+    pub stream_metadata:       &'a [StreamMetadata],
+    /// Global tags, chapters and attachments of the source. When set, they
+    /// replace whatever the other inputs carry.
+    pub container_metadata:    Option<&'a ContainerMetadata>,
+    /// Color description and HDR10 metadata of the source video. mkvmerge
+    /// writes it to the video track; ffmpeg takes it from the encoded
+    /// bitstream.
+    pub color_metadata:        Option<&'a ColorMetadata>,
+}
+
+impl MuxInputs<'_> {
+    /// Files muxed after the segments, in output order
+    fn extra_inputs(&self) -> Vec<&Path> {
+        self.audio_file.into_iter().chain(self.non_video_stream_file).collect()
+    }
+
+    pub(crate) fn check_files_exist(&self, tool: &str) -> Result<(), VideoEncodeError> {
+        if let Some(non_video_stream_file) =
+            self.non_video_stream_file.filter(|path| !path.exists())
+        {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "{}: Non-video stream file not found: {:?}",
+                tool, non_video_stream_file
+            )));
+        }
+        if let Some(audio_file) = self.audio_file.filter(|path| !path.exists()) {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "{}: Encoded audio file not found: {:?}",
+                tool, audio_file
+            )));
+        }
+        let attachments =
+            self.container_metadata.map(|m| m.attachments.as_slice()).unwrap_or_default();
+        if let Some(attachment) = attachments.iter().find(|a| !a.path.exists()) {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "{}: Attachment file not found: {:?}",
+                tool, attachment.path
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` names a Matroska file, the only output that keeps
+/// attachments
+fn is_matroska(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            ["mkv", "mka", "mks"].iter().any(|mkv| extension.eq_ignore_ascii_case(mkv))
+        })
+}
+
+/// Writes the list file of ffmpeg's concat demuxer for `segment_paths`.
+pub(crate) fn write_concat_list(
+    segment_paths: &[PathBuf],
+    list_path: &Path,
+) -> Result<(), VideoEncodeError> {
+    let file_list_content: String = segment_paths
+        .iter()
+        .map(|path| {
+            let abs_path = path.canonicalize().map_err(|e| VideoEncodeError::Io(e))?;
+            Ok(format!("file '{}'\n", abs_path.to_string_lossy()))
+        })
+        .collect::<Result<String, VideoEncodeError>>()?;
+
+    fs::write(list_path, file_list_content).map_err(VideoEncodeError::Io)
+}
 
 /// Concatenates video segments using FFmpeg and adds back non-video streams.
-#[instrument(skip(segment_paths, non_video_stream_file))]
+/// A separately encoded audio file, if any, is muxed ahead of them. Stream
+/// and container metadata from `mux_inputs` are applied to the output.
+#[instrument(skip(segment_paths, mux_inputs))]
 pub fn concatenate_videos_ffmpeg(
     segment_paths: Vec<PathBuf>,
-    non_video_stream_file: &Path,
+    mux_inputs: &MuxInputs,
     output_file: &Path,
-    temp_dir: &PathBuf, // Used for file_list.txt
+    temp_dir: &Path, // Used for file_list.txt and ffmetadata.txt
     expected_segments: usize,
 ) -> Result<(), VideoEncodeError> {
     if segment_paths.is_empty() {
@@ -37,54 +129,84 @@ pub fn concatenate_videos_ffmpeg(
             )));
         }
     }
-    if !non_video_stream_file.exists() {
-        return Err(VideoEncodeError::Concatenation(format!(
-            "FFmpeg: Non-video stream file not found: {:?}",
-            non_video_stream_file
-        )));
-    }
+    mux_inputs.check_files_exist("FFmpeg")?;
 
     fs::create_dir_all(temp_dir).map_err(VideoEncodeError::Io)?;
     let temp_file_list_path = temp_dir.join("ffmpeg_concat_list.txt");
+    write_concat_list(&segment_paths, &temp_file_list_path)?;
 
-    let file_list_content: String = segment_paths
-        .iter()
-        .map(|path| {
-            let abs_path = path.canonicalize().map_err(|e| VideoEncodeError::Io(e))?;
-            Ok(format!("file '{}'\n", abs_path.to_string_lossy()))
-        })
-        .collect::<Result<String, VideoEncodeError>>()?;
-
-    fs::write(&temp_file_list_path, file_list_content).map_err(VideoEncodeError::Io)?;
-
-    let temp_file_list_str = temp_file_list_path.to_string_lossy();
-    let non_video_stream_file_str = non_video_stream_file.to_string_lossy();
-    let output_file_str = output_file.to_string_lossy();
-
-    let ffmpeg_args = vec![
-        "-f",
-        "concat",
-        "-safe",
-        "0",
-        "-i",
-        &temp_file_list_str,
-        "-i",
-        &non_video_stream_file_str,
-        "-map",
-        "0:v?", // Map video from first input (concatenated segments), if present
-        "-map",
-        "1?", // Map all streams from second input (non-video streams), if present
-        "-c",
-        "copy",
-        "-y", // Overwrite output file if it exists
-        &output_file_str,
+    let mut ffmpeg_args: Vec<String> = vec![
+        "-f".to_string(),
+        "concat".to_string(),
+        "-safe".to_string(),
+        "0".to_string(),
+        "-i".to_string(),
+        temp_file_list_path.to_string_lossy().into_owned(),
     ];
+    // Inputs after the concatenated segments: re-encoded audio, then the
+    // remaining non-video streams
+    let extra_inputs = mux_inputs.extra_inputs();
+    for input in &extra_inputs {
+        ffmpeg_args.extend(["-i".to_string(), input.to_string_lossy().into_owned()]);
+    }
+    // Global tags and chapters come in as an FFMETADATA input of their own
+    if let Some(container_metadata) = mux_inputs.container_metadata {
+        let ffmetadata_path = temp_dir.join("ffmetadata.txt");
+        fs::write(&ffmetadata_path, container_metadata.to_ffmetadata())
+            .map_err(VideoEncodeError::Io)?;
+        ffmpeg_args.extend([
+            "-f".to_string(),
+            "ffmetadata".to_string(),
+            "-i".to_string(),
+            ffmetadata_path.to_string_lossy().into_owned(),
+        ]);
+    }
+    // Map video from first input (concatenated segments), if present
+    ffmpeg_args.extend(["-map".to_string(), "0:v?".to_string()]);
+    for input_index in 1..=extra_inputs.len() {
+        // Map all streams from each additional input, if present
+        ffmpeg_args.extend(["-map".to_string(), format!("{}?", input_index)]);
+    }
+    if let Some(container_metadata) = mux_inputs.container_metadata {
+        let ffmetadata_index = (extra_inputs.len() + 1).to_string();
+        ffmpeg_args.extend([
+            "-map_metadata".to_string(),
+            ffmetadata_index.clone(),
+            "-map_chapters".to_string(),
+            ffmetadata_index,
+        ]);
+        if is_matroska(output_file) {
+            for (i, attachment) in container_metadata.attachments.iter().enumerate() {
+                ffmpeg_args.extend([
+                    "-attach".to_string(),
+                    attachment.path.to_string_lossy().into_owned(),
+                    format!("-metadata:s:t:{}", i),
+                    format!("mimetype={}", attachment.mimetype),
+                    format!("-metadata:s:t:{}", i),
+                    format!("filename={}", attachment.filename),
+                ]);
+            }
+        } else if !container_metadata.attachments.is_empty() {
+            warn!(
+                "FFmpeg: {:?} is not a Matroska file; dropping {} attachments",
+                output_file,
+                container_metadata.attachments.len()
+            );
+        }
+    }
+    ffmpeg_args.extend(ffmpeg_metadata_arguments(mux_inputs.stream_metadata));
+    ffmpeg_args.extend([
+        "-c".to_string(),
+        "copy".to_string(),
+        "-y".to_string(), // Overwrite output file if it exists
+        output_file.to_string_lossy().into_owned(),
+    ]);
 
     debug!("FFmpeg command: ffmpeg {:?}", ffmpeg_args);
     let output = Command::new("ffmpeg")
         .arg("-hide_banner")
         .args(&ffmpeg_args)
-        .output()
+        .killable_output()
         .map_err(VideoEncodeError::Io)?;
 
     if !output.status.success() {
@@ -99,21 +221,23 @@ pub fn concatenate_videos_ffmpeg(
     }
 
     info!(
-        "FFmpeg: Successfully concatenated {} video segments and copied streams to {}",
+        "FFmpeg: Successfully concatenated {} video segments and copied streams to {:?}",
         segment_paths.len(),
-        output_file_str
+        output_file
     );
     fs::remove_file(temp_file_list_path).map_err(VideoEncodeError::Io)?;
     Ok(())
 }
 
 /// Concatenates video segments using mkvmerge and adds back non-video streams.
-#[instrument(skip(segment_paths, non_video_stream_file))]
+/// A separately encoded audio file, if any, is muxed ahead of them. Stream
+/// and container metadata from `mux_inputs` are applied to the output.
+#[instrument(skip(segment_paths, mux_inputs))]
 pub fn concatenate_videos_mkvmerge(
     segment_paths: Vec<PathBuf>,
-    non_video_stream_file: &Path,
+    mux_inputs: &MuxInputs,
     output_file: &Path,
-    _temp_dir: &PathBuf, // Not used by mkvmerge for this concatenation strategy
+    temp_dir: &Path, // Used for the chapters and tags files
     expected_segments: usize,
 ) -> Result<(), VideoEncodeError> {
     if segment_paths.is_empty() {
@@ -137,12 +261,12 @@ pub fn concatenate_videos_mkvmerge(
             )));
         }
     }
-    if !non_video_stream_file.exists() {
-        return Err(VideoEncodeError::Concatenation(format!(
-            "Mkvmerge: Non-video stream file not found: {:?}",
-            non_video_stream_file
-        )));
-    }
+    mux_inputs.check_files_exist("Mkvmerge")?;
+    // With the source's container metadata at hand, none is taken from inputs
+    let skip_input_metadata: &[&str] = match mux_inputs.container_metadata {
+        Some(_) => &["--no-chapters", "--no-attachments", "--no-global-tags"],
+        None => &[],
+    };
 
     let mut mkvmerge_args: Vec<String> = Vec::new();
     mkvmerge_args.push("-o".to_string());
@@ -154,22 +278,74 @@ pub fn concatenate_videos_mkvmerge(
         let canonical_seg_path = seg_path.canonicalize().map_err(VideoEncodeError::Io)?;
         if i > 0 {
             mkvmerge_args.push("+".to_string());
+        } else {
+            mkvmerge_args.extend(skip_input_metadata.iter().map(|arg| arg.to_string()));
+            if let Some(color_metadata) = mux_inputs.color_metadata {
+                // Encoded segments hold the video as their only track
+                mkvmerge_args.extend(mkvmerge_color_arguments(color_metadata, 0));
+            }
         }
         mkvmerge_args.push(canonical_seg_path.to_string_lossy().into_owned());
     }
 
-    // Add the non-video streams file as a separate input for muxing.
-    // If `extract_non_video_streams` produced it with `ffmpeg -vn`, it won't have
-    // video. mkvmerge will then mux its audio/subtitle streams with the video
-    // from segments.
-    let canonical_non_video_path =
-        non_video_stream_file.canonicalize().map_err(VideoEncodeError::Io)?;
-    mkvmerge_args.push(canonical_non_video_path.to_string_lossy().into_owned());
+    // Add the re-encoded audio, if any, ahead of the remaining streams, then
+    // the non-video streams file as a separate input for muxing. Neither has
+    // video, so mkvmerge muxes their streams with the video from segments.
+    // Track options precede the file they apply to.
+    let mut preceding_tracks = HashMap::new();
+    for extra_input in mux_inputs.extra_inputs() {
+        if !mux_inputs.stream_metadata.is_empty() {
+            let file_streams = probe_streams(extra_input)?;
+            mkvmerge_args.extend(mkvmerge_metadata_arguments(
+                mux_inputs.stream_metadata,
+                &file_streams,
+                &mut preceding_tracks,
+            ));
+        }
+        mkvmerge_args.extend(skip_input_metadata.iter().map(|arg| arg.to_string()));
+        let canonical_path = extra_input.canonicalize().map_err(VideoEncodeError::Io)?;
+        mkvmerge_args.push(canonical_path.to_string_lossy().into_owned());
+    }
+
+    if let Some(container_metadata) = mux_inputs.container_metadata {
+        fs::create_dir_all(temp_dir).map_err(VideoEncodeError::Io)?;
+        if let Some(title) = container_metadata.title() {
+            mkvmerge_args.extend(["--title".to_string(), title.to_string()]);
+        }
+        if !container_metadata.chapters.is_empty() {
+            let chapters_path = temp_dir.join("chapters.xml");
+            fs::write(&chapters_path, container_metadata.to_mkvmerge_chapters())
+                .map_err(VideoEncodeError::Io)?;
+            mkvmerge_args
+                .extend(["--chapters".to_string(), chapters_path.to_string_lossy().into_owned()]);
+        }
+        if container_metadata
+            .tags
+            .iter()
+            .any(|(key, _)| !key.eq_ignore_ascii_case("title"))
+        {
+            let tags_path = temp_dir.join("global_tags.xml");
+            fs::write(&tags_path, container_metadata.to_mkvmerge_tags())
+                .map_err(VideoEncodeError::Io)?;
+            mkvmerge_args
+                .extend(["--global-tags".to_string(), tags_path.to_string_lossy().into_owned()]);
+        }
+        for attachment in &container_metadata.attachments {
+            mkvmerge_args.extend([
+                "--attachment-name".to_string(),
+                attachment.filename.clone(),
+                "--attachment-mime-type".to_string(),
+                attachment.mimetype.clone(),
+                "--attach-file".to_string(),
+                attachment.path.to_string_lossy().into_owned(),
+            ]);
+        }
+    }
 
     debug!("mkvmerge command: mkvmerge {:?}", mkvmerge_args);
     let output = Command::new("mkvmerge")
         .args(&mkvmerge_args)
-        .output()
+        .killable_output()
         .map_err(VideoEncodeError::Io)?;
 
     if !output.status.success() {
@@ -194,3 +370,338 @@ pub fn concatenate_videos_mkvmerge(
     );
     Ok(())
 }
+
+/// Joins encoded segments into the final output file. Implementations
+/// declare the output containers and codec families they can write, so a
+/// job can be checked before any chunk is encoded.
+pub trait Concatenator: Send + Sync {
+    /// Name jobs use to select this concatenator
+    fn name(&self) -> &'static str;
+
+    /// Output file extensions this concatenator writes
+    fn containers(&self) -> &'static [&'static str];
+
+    /// Codec families it can join, `None` if any codec the container holds
+    fn codecs(&self) -> Option<&'static [&'static str]>;
+
+    /// Whether the executables this concatenator needs are installed
+    fn is_available(&self) -> bool;
+
+    /// Concatenates `segment_paths` into `output_file` and muxes in
+    /// `mux_inputs` as far as the output format allows.
+    fn concatenate(
+        &self,
+        segment_paths: Vec<PathBuf>,
+        mux_inputs: &MuxInputs,
+        output_file: &Path,
+        temp_dir: &Path,
+        expected_segments: usize,
+    ) -> Result<(), VideoEncodeError>;
+
+    /// Checks that `output_file` can be written by this concatenator and can
+    /// hold `codec_family`. Unknown codec families are accepted.
+    fn validate_output(
+        &self,
+        output_file: &Path,
+        codec_family: Option<&str>,
+    ) -> Result<(), VideoEncodeError> {
+        let extension = output_file
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        if !self.containers().contains(&extension.as_str()) {
+            return Err(VideoEncodeError::Config(format!(
+                "Concatenator '{}' can't write {:?}; supported extensions: {}",
+                self.name(),
+                output_file,
+                self.containers().join(", ")
+            )));
+        }
+        let Some(family) = codec_family else {
+            return Ok(());
+        };
+        if self.codecs().is_some_and(|codecs| !codecs.contains(&family)) {
+            return Err(VideoEncodeError::Config(format!(
+                "Concatenator '{}' can't join {} video",
+                self.name(),
+                family
+            )));
+        }
+        if !container_accepts_codec(&extension, family) {
+            return Err(VideoEncodeError::Config(format!(
+                "A .{} output can't hold {} video",
+                extension, family
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether a container with file extension `extension` can hold video of
+/// `codec_family`. Only the families this crate knows are judged, anything
+/// else is left for the muxer to decide.
+pub fn container_accepts_codec(extension: &str, codec_family: &str) -> bool {
+    if !["h264", "hevc", "av1", "vp8", "vp9"].contains(&codec_family) {
+        return true;
+    }
+    let accepted: &[&str] = match extension.to_ascii_lowercase().as_str() {
+        "webm" => &["vp8", "vp9", "av1"],
+        "mp4" | "m4v" | "mov" => &["h264", "hevc", "av1", "vp9"],
+        "ivf" => &["av1", "vp8", "vp9"],
+        "obu" => &["av1"],
+        "h264" | "264" => &["h264"],
+        "hevc" | "h265" | "265" => &["hevc"],
+        "ts" | "m2ts" => &["h264", "hevc"],
+        _ => return true,
+    };
+    accepted.contains(&codec_family)
+}
+
+/// Concatenation with ffmpeg's concat demuxer, see
+/// [`concatenate_videos_ffmpeg`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FfmpegConcatenator;
+
+impl Concatenator for FfmpegConcatenator {
+    fn name(&self) -> &'static str {
+        "ffmpeg"
+    }
+
+    fn containers(&self) -> &'static [&'static str] {
+        &["mkv", "webm", "mp4", "m4v", "mov", "ts", "m2ts"]
+    }
+
+    fn codecs(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    fn is_available(&self) -> bool {
+        which::which("ffmpeg").is_ok()
+    }
+
+    fn concatenate(
+        &self,
+        segment_paths: Vec<PathBuf>,
+        mux_inputs: &MuxInputs,
+        output_file: &Path,
+        temp_dir: &Path,
+        expected_segments: usize,
+    ) -> Result<(), VideoEncodeError> {
+        concatenate_videos_ffmpeg(
+            segment_paths,
+            mux_inputs,
+            output_file,
+            temp_dir,
+            expected_segments,
+        )
+    }
+}
+
+/// Concatenation with mkvmerge's append mode, see
+/// [`concatenate_videos_mkvmerge`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MkvmergeConcatenator;
+
+impl Concatenator for MkvmergeConcatenator {
+    fn name(&self) -> &'static str {
+        "mkvmerge"
+    }
+
+    fn containers(&self) -> &'static [&'static str] {
+        &["mkv", "mk3d", "webm"]
+    }
+
+    fn codecs(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    fn is_available(&self) -> bool {
+        which::which("mkvmerge").is_ok()
+    }
+
+    fn concatenate(
+        &self,
+        segment_paths: Vec<PathBuf>,
+        mux_inputs: &MuxInputs,
+        output_file: &Path,
+        temp_dir: &Path,
+        expected_segments: usize,
+    ) -> Result<(), VideoEncodeError> {
+        concatenate_videos_mkvmerge(
+            segment_paths,
+            mux_inputs,
+            output_file,
+            temp_dir,
+            expected_segments,
+        )
+    }
+}
+
+/// Joins the video bitstreams themselves into a raw IVF, OBU or Annex-B
+/// file. Nothing but the video survives: audio, other streams and container
+/// metadata are dropped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeConcatenator;
+
+impl Concatenator for NativeConcatenator {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn containers(&self) -> &'static [&'static str] {
+        &["ivf", "obu", "h264", "264", "hevc", "h265", "265"]
+    }
+
+    fn codecs(&self) -> Option<&'static [&'static str]> {
+        Some(&["av1", "vp8", "vp9", "h264", "hevc"])
+    }
+
+    fn is_available(&self) -> bool {
+        // Only needed to pull the bitstream out of Matroska chunks
+        which::which("ffmpeg").is_ok()
+    }
+
+    #[instrument(skip(self, segment_paths, mux_inputs))]
+    fn concatenate(
+        &self,
+        segment_paths: Vec<PathBuf>,
+        mux_inputs: &MuxInputs,
+        output_file: &Path,
+        temp_dir: &Path,
+        expected_segments: usize,
+    ) -> Result<(), VideoEncodeError> {
+        let format = output_file
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(RawStreamFormat::from_extension)
+            .ok_or_else(|| {
+                VideoEncodeError::Concatenation(format!(
+                    "Native: {:?} is not a raw video stream file",
+                    output_file
+                ))
+            })?;
+        if segment_paths.is_empty() {
+            return Err(VideoEncodeError::Concatenation(
+                "No video segments provided for native concatenation.".to_string(),
+            ));
+        }
+        if segment_paths.len() != expected_segments {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "Native: Mismatch in segment count. Expected: {}, Found: {}",
+                expected_segments,
+                segment_paths.len()
+            )));
+        }
+        if let Some(path) = segment_paths.iter().find(|path| !path.exists()) {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "Native: Segment file not found: {:?}",
+                path
+            )));
+        }
+        if mux_inputs.non_video_stream_file.is_some()
+            || mux_inputs.audio_file.is_some()
+            || mux_inputs.container_metadata.is_some()
+        {
+            warn!(
+                "Native: {:?} holds only the video bitstream; audio, other streams and container \
+                 metadata are dropped",
+                output_file
+            );
+        }
+
+        fs::create_dir_all(temp_dir).map_err(VideoEncodeError::Io)?;
+        // Segments already in the output format are joined as they are
+        let mut extracted = Vec::new();
+        let mut streams = Vec::with_capacity(segment_paths.len());
+        for (i, path) in segment_paths.iter().enumerate() {
+            let in_format = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(RawStreamFormat::from_extension);
+            if in_format == Some(format) {
+                streams.push(path.clone());
+                continue;
+            }
+            let stream_path = temp_dir.join(format!("native_segment_{}.{}", i, format.muxer()));
+            extract_elementary_stream(path, &stream_path, format)?;
+            extracted.push(stream_path.clone());
+            streams.push(stream_path);
+        }
+
+        let result = match format {
+            RawStreamFormat::Ivf => concatenate_ivf(&streams, output_file).map(|_| ()),
+            _ => concatenate_bytes(&streams, output_file),
+        };
+        for path in &extracted {
+            let _ = fs::remove_file(path);
+        }
+        result?;
+
+        info!(
+            "Native: Successfully concatenated {} video segments into {:?}",
+            segment_paths.len(),
+            output_file
+        );
+        Ok(())
+    }
+}
+
+/// All concatenators, in the order automatic selection tries them.
+pub fn concatenators() -> Vec<Box<dyn Concatenator>> {
+    vec![
+        Box::new(FfmpegConcatenator),
+        Box::new(MkvmergeConcatenator),
+        Box::new(NativeConcatenator),
+    ]
+}
+
+/// Looks up a concatenator by name.
+pub fn concatenator(name: &str) -> Result<Box<dyn Concatenator>, VideoEncodeError> {
+    concatenators()
+        .into_iter()
+        .find(|concatenator| concatenator.name() == name)
+        .ok_or_else(|| VideoEncodeError::Config(format!("Unknown concatenator '{}'", name)))
+}
+
+/// Picks the concatenator for `output_file`. A `preferred` concatenator is
+/// checked against the output and must be installed; otherwise the first
+/// installed one that can write the output is used.
+pub fn select_concatenator(
+    preferred: Option<&str>,
+    output_file: &Path,
+    codec_family: Option<&str>,
+) -> Result<Box<dyn Concatenator>, VideoEncodeError> {
+    if let Some(name) = preferred {
+        let concatenator = concatenator(name)?;
+        concatenator.validate_output(output_file, codec_family)?;
+        if !concatenator.is_available() {
+            return Err(VideoEncodeError::Config(format!(
+                "Concatenator '{}' is not installed",
+                name
+            )));
+        }
+        return Ok(concatenator);
+    }
+
+    let mut last_error = None;
+    for concatenator in concatenators() {
+        match concatenator.validate_output(output_file, codec_family) {
+            Ok(()) if concatenator.is_available() => return Ok(concatenator),
+            Ok(()) => {
+                debug!("Concatenator '{}' is not installed", concatenator.name());
+                last_error = Some(VideoEncodeError::Config(format!(
+                    "Concatenator '{}' can write {:?} but is not installed",
+                    concatenator.name(),
+                    output_file
+                )));
+            },
+            Err(e) => {
+                last_error.get_or_insert(e);
+            },
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        VideoEncodeError::Config(format!("No concatenator can write {:?}", output_file))
+    }))
+}
@@ -1,7 +1,11 @@
 use std::{path::Path, process::Command};
 
 use ferris_swarm_core::error::VideoEncodeError;
-use tracing::{debug, error, instrument};
+use tracing::{debug, error, instrument, warn};
+
+/// Arguments that select or locate a pass. These are owned by the two-pass
+/// driver and are stripped from caller supplied parameters.
+const FFMPEG_PASS_ARGS: &[&str] = &["-pass", "-passlogfile", "-passes"];
 
 #[instrument(skip(encoder_parameters))]
 pub fn encode_with_ffmpeg(
@@ -18,7 +22,8 @@ pub fn encode_with_ffmpeg(
         .arg("-hide_banner")
         .arg("-i")
         .arg(input_path)
-        .args(encoder_parameters) // These should already include -y if needed
+        .args(encoder_parameters)
+        .arg("-y") // Output is always a scratch file owned by the caller
         .arg(output_path)
         .output()?;
 
@@ -34,3 +39,78 @@ pub fn encode_with_ffmpeg(
     debug!("Successfully encoded {:?} to {:?}", input_path, output_path);
     Ok(())
 }
+
+/// Encodes with ffmpeg in two passes.
+///
+/// The first pass only writes encoder statistics (`-pass 1 -f null`), the
+/// second pass produces `output_path`. The passlog lives in a temporary
+/// directory next to the output, so concurrent chunks never share stats files.
+#[instrument(skip(encoder_parameters))]
+pub fn encode_with_ffmpeg_two_pass(
+    input_path: &Path,
+    output_path: &Path,
+    encoder_parameters: &[String],
+) -> Result<(), VideoEncodeError> {
+    let parameters = strip_pass_arguments(encoder_parameters, FFMPEG_PASS_ARGS);
+
+    let passlog_parent = output_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let passlog_dir = tempfile::Builder::new().prefix("passlog_").tempdir_in(passlog_parent)?;
+    let passlog_prefix = passlog_dir.path().join("ffmpeg2pass");
+    debug!("Using passlog prefix {:?}", passlog_prefix);
+
+    let mut first_pass = parameters.clone();
+    first_pass.extend([
+        "-pass".to_string(),
+        "1".to_string(),
+        "-passlogfile".to_string(),
+        passlog_prefix.to_string_lossy().into_owned(),
+        "-an".to_string(),
+        "-f".to_string(),
+        "null".to_string(),
+    ]);
+    debug!("Running first pass for {:?}", input_path);
+    encode_with_ffmpeg(input_path, Path::new("-"), &first_pass)?;
+
+    let mut second_pass = parameters;
+    second_pass.extend([
+        "-pass".to_string(),
+        "2".to_string(),
+        "-passlogfile".to_string(),
+        passlog_prefix.to_string_lossy().into_owned(),
+    ]);
+    debug!("Running second pass for {:?}", input_path);
+    encode_with_ffmpeg(input_path, output_path, &second_pass)
+}
+
+/// Removes pass selection arguments (and their values) from encoder
+/// parameters. Both `--arg value` and `--arg=value` forms are recognised.
+pub(crate) fn strip_pass_arguments(
+    encoder_parameters: &[String],
+    pass_args: &[&str],
+) -> Vec<String> {
+    let mut stripped = Vec::with_capacity(encoder_parameters.len());
+    let mut iter = encoder_parameters.iter();
+    while let Some(arg) = iter.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if pass_args.contains(&name) {
+            let value = match inline_value {
+                Some(value) => Some(value),
+                None => iter.next().map(String::as_str),
+            };
+            warn!(
+                "Ignoring '{} {}' in encoder parameters; passes are managed by the node",
+                name,
+                value.unwrap_or_default()
+            );
+            continue;
+        }
+        stripped.push(arg.clone());
+    }
+    stripped
+}
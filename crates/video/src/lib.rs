@@ -1,14 +1,39 @@
+pub mod audio;
+pub mod backend;
+pub mod bitstream;
+pub mod complexity;
 pub mod concatenator;
 pub mod encoder;
+pub mod metadata;
+pub mod packager;
+pub mod process;
+pub mod progressive;
+pub mod quality;
 pub mod segmenter;
+pub mod streams;
+pub mod sync;
+pub mod transport;
 pub mod utils;
 
 use std::path::PathBuf;
 
+pub use audio::*;
+pub use backend::*;
+pub use bitstream::*;
+pub use complexity::*;
 pub use concatenator::*;
 pub use encoder::*;
 use ferris_swarm_core::{Chunk, VideoEncodeError};
+pub use metadata::*;
+pub use packager::*;
+pub use process::*;
+pub use progressive::*;
+pub use quality::*;
 pub use segmenter::*;
+pub use streams::*;
+pub use sync::*;
+use tracing::warn;
+pub use transport::*;
 pub use utils::*;
 
 /// Extension trait for Chunk to add encoding functionality
@@ -16,9 +41,50 @@ pub trait ChunkEncoder {
     fn encode(&self, output_path: PathBuf) -> Result<Chunk, VideoEncodeError>;
 }
 
+/// Backend, arguments and unsupported color metadata of a chunk encode
+pub type ResolvedEncoder = (Box<dyn Encoder>, EncoderArguments, Vec<&'static str>);
+
+/// Resolves the backend and arguments `chunk` is encoded with, including its
+/// video filter, color metadata and closed-GOP options. Also returns the parts
+/// of that metadata the backend can't carry.
+pub fn chunk_encoder_arguments(chunk: &Chunk) -> Result<ResolvedEncoder, VideoEncodeError> {
+    let backend = encoder_backend(&chunk.encoder_backend)?;
+    let mut arguments = match &chunk.profile {
+        Some(profile) => backend.render_profile(profile),
+        None => EncoderArguments::from_parameters(&chunk.encoder_parameters),
+    };
+    if let Some(filter) = &chunk.video_filter {
+        arguments.prepend_video_filter(filter);
+    }
+    let unsupported = match &chunk.color {
+        Some(color) => backend.apply_color_metadata(color, &mut arguments),
+        None => Vec::new(),
+    };
+    backend.apply_chunk_boundaries(&mut arguments);
+    arguments.substitute_attachments(&chunk.attachments)?;
+    Ok((backend, arguments, unsupported))
+}
+
 impl ChunkEncoder for Chunk {
     fn encode(&self, output_path: PathBuf) -> Result<Chunk, VideoEncodeError> {
-        encode_with_ffmpeg(&self.source_path, &output_path, &self.encoder_parameters)?;
+        let (backend, arguments, unsupported) = chunk_encoder_arguments(self)?;
+        if !unsupported.is_empty() {
+            warn!(
+                "Encoder backend '{}' can't carry the source's {}, chunk {} is encoded without it",
+                backend.name(),
+                unsupported.join(", "),
+                self.index
+            );
+        }
+        backend.encode(&self.source_path, &output_path, &arguments, self.pass_mode)?;
+        // A chunk opening on a non-key frame would glitch at its border once
+        // concatenated
+        if !starts_with_keyframe(&output_path)? {
+            return Err(VideoEncodeError::Encoding(format!(
+                "Encoded chunk {} does not start with a keyframe",
+                self.index
+            )));
+        }
 
         Ok(self.with_encoded_path(output_path))
     }
@@ -0,0 +1,248 @@
+/// Container level metadata (global tags, chapters, attachments) carried from
+/// the source to the output, past the per-segment encode.
+use std::path::PathBuf;
+
+use ferris_swarm_core::{error::VideoEncodeError, TimeRange};
+
+/// Global tags that describe the writing muxer rather than the content
+const MUXER_TAGS: &[&str] = &["encoder", "major_brand", "minor_version", "compatible_brands"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    /// Start in seconds
+    pub start: f64,
+    /// End in seconds
+    pub end:   f64,
+    pub title: Option<String>,
+}
+
+/// A file attached to the container, e.g. a font used by ASS subtitles
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    /// Where the attachment was extracted to
+    pub path:     PathBuf,
+    pub filename: String,
+    pub mimetype: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContainerMetadata {
+    /// Global tags in source order
+    pub tags:        Vec<(String, String)>,
+    pub chapters:    Vec<Chapter>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// Attachment as listed by ffprobe, before it is extracted
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbedAttachment {
+    pub filename: String,
+    pub mimetype: String,
+}
+
+impl ContainerMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.chapters.is_empty() && self.attachments.is_empty()
+    }
+
+    /// Parses `ffprobe -show_chapters -show_entries format_tags:stream=...
+    /// -of json` output into tags and chapters, plus the attachments to
+    /// extract.
+    pub fn from_probe(json: &str) -> Result<(Self, Vec<ProbedAttachment>), VideoEncodeError> {
+        let probe: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| VideoEncodeError::Encoding(format!("Invalid ffprobe output: {}", e)))?;
+        let text = |value: Option<&serde_json::Value>| {
+            value.and_then(|value| value.as_str()).map(str::to_string)
+        };
+
+        let tags = probe
+            .pointer("/format/tags")
+            .and_then(|tags| tags.as_object())
+            .map(|tags| {
+                tags.iter()
+                    .filter(|(key, _)| {
+                        !MUXER_TAGS.iter().any(|muxer_tag| key.eq_ignore_ascii_case(muxer_tag))
+                    })
+                    .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let chapters = probe
+            .get("chapters")
+            .and_then(|chapters| chapters.as_array())
+            .map(|chapters| {
+                chapters
+                    .iter()
+                    .filter_map(|chapter| {
+                        let time = |key| text(chapter.get(key))?.parse::<f64>().ok();
+                        Some(Chapter {
+                            start: time("start_time")?,
+                            end:   time("end_time")?,
+                            title: text(chapter.pointer("/tags/title")),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let attachments = probe
+            .get("streams")
+            .and_then(|streams| streams.as_array())
+            .map(|streams| {
+                streams
+                    .iter()
+                    .filter(|stream| {
+                        text(stream.get("codec_type")).as_deref() == Some("attachment")
+                    })
+                    .map(|stream| ProbedAttachment {
+                        filename: text(stream.pointer("/tags/filename"))
+                            .unwrap_or_else(|| "attachment".to_string()),
+                        mimetype: text(stream.pointer("/tags/mimetype"))
+                            .unwrap_or_else(|| "application/octet-stream".to_string()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok((
+            Self {
+                tags,
+                chapters,
+                attachments: Vec::new(),
+            },
+            attachments,
+        ))
+    }
+
+    /// Keeps the chapters, or their parts, that fall within `ranges` and
+    /// moves them to where the ranges land in an output made of just those
+    /// ranges played back to back. Ranges end at the source's `duration`
+    /// at the latest, as the output does.
+    pub fn trim_to_ranges(&mut self, ranges: &[TimeRange], duration: f64) {
+        let mut chapters = Vec::new();
+        let mut output_start = 0.0;
+        for range in ranges {
+            let range_end = range.end.unwrap_or(duration).min(duration);
+            if range_end <= range.start {
+                continue;
+            }
+            for chapter in &self.chapters {
+                let start = chapter.start.max(range.start);
+                let end = chapter.end.min(range_end);
+                if end > start {
+                    chapters.push(Chapter {
+                        start: output_start + start - range.start,
+                        end:   output_start + end - range.start,
+                        title: chapter.title.clone(),
+                    });
+                }
+            }
+            output_start += range_end - range.start;
+        }
+        self.chapters = chapters;
+    }
+
+    /// The `title` global tag, which Matroska keeps in the segment info
+    pub fn title(&self) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("title"))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Global tags and chapters in ffmpeg's FFMETADATA format
+    pub fn to_ffmetadata(&self) -> String {
+        let mut ffmetadata = String::from(";FFMETADATA1\n");
+        for (key, value) in &self.tags {
+            ffmetadata.push_str(&format!(
+                "{}={}\n",
+                escape_ffmetadata(key),
+                escape_ffmetadata(value)
+            ));
+        }
+        for chapter in &self.chapters {
+            ffmetadata.push_str(&format!(
+                "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\n",
+                (chapter.start * 1000.0).round() as i64,
+                (chapter.end * 1000.0).round() as i64
+            ));
+            if let Some(title) = &chapter.title {
+                ffmetadata.push_str(&format!("title={}\n", escape_ffmetadata(title)));
+            }
+        }
+        ffmetadata
+    }
+
+    /// Chapters as a Matroska chapters XML file for `mkvmerge --chapters`
+    pub fn to_mkvmerge_chapters(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\"?>\n<Chapters>\n  <EditionEntry>\n");
+        for chapter in &self.chapters {
+            xml.push_str("    <ChapterAtom>\n");
+            xml.push_str(&format!(
+                "      <ChapterTimeStart>{}</ChapterTimeStart>\n      \
+                 <ChapterTimeEnd>{}</ChapterTimeEnd>\n",
+                matroska_timestamp(chapter.start),
+                matroska_timestamp(chapter.end)
+            ));
+            if let Some(title) = &chapter.title {
+                xml.push_str(&format!(
+                    "      <ChapterDisplay>\n        <ChapterString>{}</ChapterString>\n      \
+                     </ChapterDisplay>\n",
+                    escape_xml(title)
+                ));
+            }
+            xml.push_str("    </ChapterAtom>\n");
+        }
+        xml.push_str("  </EditionEntry>\n</Chapters>\n");
+        xml
+    }
+
+    /// Global tags other than the title as a Matroska tags XML file for
+    /// `mkvmerge --global-tags`
+    pub fn to_mkvmerge_tags(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\"?>\n<Tags>\n  <Tag>\n    <Targets/>\n");
+        for (key, value) in self.tags.iter().filter(|(key, _)| !key.eq_ignore_ascii_case("title")) {
+            xml.push_str(&format!(
+                "    <Simple>\n      <Name>{}</Name>\n      <String>{}</String>\n    </Simple>\n",
+                escape_xml(key),
+                escape_xml(value)
+            ));
+        }
+        xml.push_str("  </Tag>\n</Tags>\n");
+        xml
+    }
+}
+
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `HH:MM:SS.nnnnnnnnn` as Matroska chapter files use it
+fn matroska_timestamp(seconds: f64) -> String {
+    let nanos = (seconds.max(0.0) * 1e9).round() as u64;
+    let secs = nanos / 1_000_000_000;
+    format!(
+        "{:02}:{:02}:{:02}.{:09}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        nanos % 1_000_000_000
+    )
+}
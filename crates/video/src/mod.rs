@@ -1,4 +1,16 @@
+pub mod audio;
+pub mod backend;
+pub mod bitstream;
+pub mod complexity;
 pub mod concatenator;
 pub mod encoder;
+pub mod metadata;
+pub mod packager;
+pub mod process;
+pub mod progressive;
+pub mod quality;
 pub mod segmenter;
+pub mod streams;
+pub mod sync;
+pub mod transport;
 pub mod utils;
@@ -0,0 +1,297 @@
+/// Adaptive streaming output: the encoded chunks packaged as fragmented MP4
+/// segments with HLS playlists or a DASH manifest, in place of a single
+/// concatenated file.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use ferris_swarm_core::{error::VideoEncodeError, StreamInfo, StreamKind};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::{
+    concatenator::{write_concat_list, MuxInputs},
+    process::KillableCommand,
+    streams::{ffmpeg_metadata_arguments, probe_streams},
+};
+
+/// Streaming format the chunks are packaged in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageFormat {
+    Hls,
+    Dash,
+}
+
+impl PackageFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            PackageFormat::Hls => "HLS",
+            PackageFormat::Dash => "DASH",
+        }
+    }
+
+    /// Extension of the master playlist or manifest
+    pub fn manifest_extension(&self) -> &'static str {
+        match self {
+            PackageFormat::Hls => "m3u8",
+            PackageFormat::Dash => "mpd",
+        }
+    }
+
+    /// Checks that `output_file` names a manifest of this format
+    pub fn validate_output(&self, output_file: &Path) -> Result<(), VideoEncodeError> {
+        let extension = output_file.extension().and_then(|extension| extension.to_str());
+        if !extension
+            .is_some_and(|extension| extension.eq_ignore_ascii_case(self.manifest_extension()))
+        {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "{}: Output {:?} must be a .{} file",
+                self.name(),
+                output_file,
+                self.manifest_extension()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// One video variant of the package, e.g. a rendition of an ABR ladder
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageVariant {
+    /// Name used in media playlist and segment file names
+    pub name:          String,
+    /// Encoded chunks in playback order
+    pub segment_paths: Vec<PathBuf>,
+}
+
+/// ffmpeg muxer options writing `variants` video streams and `audio` tracks
+/// as `format` next to `manifest`. Media playlists and segments are named
+/// after the manifest's file stem.
+pub fn packaging_arguments(
+    format: PackageFormat,
+    variants: &[PackageVariant],
+    audio: &[&StreamInfo],
+    manifest: &Path,
+    segment_duration: f64,
+) -> Vec<String> {
+    let output_dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+    let stem = manifest.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let in_output_dir = |name: String| output_dir.join(name).to_string_lossy().into_owned();
+
+    let mut args: Vec<String> = Vec::new();
+    match format {
+        PackageFormat::Hls => {
+            // Audio tracks form one rendition group every video variant refers to
+            let group = if audio.is_empty() {
+                ""
+            } else {
+                ",agroup:audio"
+            };
+            let mut stream_map: Vec<String> = audio
+                .iter()
+                .enumerate()
+                .map(|(i, track)| {
+                    let mut entry = format!("a:{},agroup:audio,name:audio_{}", i, i);
+                    if let Some(language) = &track.language {
+                        entry.push_str(&format!(",language:{}", language));
+                    }
+                    entry
+                })
+                .collect();
+            stream_map.extend(
+                variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, variant)| format!("v:{}{},name:{}", i, group, variant.name)),
+            );
+
+            args.extend(
+                [
+                    "-f",
+                    "hls",
+                    "-hls_time",
+                    &segment_duration.to_string(),
+                    "-hls_playlist_type",
+                    "vod",
+                    "-hls_segment_type",
+                    "fmp4",
+                    "-hls_flags",
+                    "independent_segments",
+                    "-hls_fmp4_init_filename",
+                    &format!("{}_%v_init.mp4", stem),
+                    "-hls_segment_filename",
+                    &in_output_dir(format!("{}_%v_%05d.m4s", stem)),
+                    "-master_pl_name",
+                    &manifest.file_name().unwrap_or_default().to_string_lossy(),
+                    "-var_stream_map",
+                    &stream_map.join(" "),
+                ]
+                .map(str::to_string),
+            );
+            args.push(in_output_dir(format!("{}_%v.m3u8", stem)));
+        },
+        PackageFormat::Dash => {
+            let adaptation_sets = if audio.is_empty() {
+                "id=0,streams=v"
+            } else {
+                "id=0,streams=v id=1,streams=a"
+            };
+            args.extend(
+                [
+                    "-f",
+                    "dash",
+                    "-seg_duration",
+                    &segment_duration.to_string(),
+                    "-use_template",
+                    "1",
+                    "-use_timeline",
+                    "1",
+                    "-adaptation_sets",
+                    adaptation_sets,
+                    "-init_seg_name",
+                    &format!("{}_init_$RepresentationID$.m4s", stem),
+                    "-media_seg_name",
+                    &format!("{}_$RepresentationID$_$Number%05d$.m4s", stem),
+                ]
+                .map(str::to_string),
+            );
+            args.push(manifest.to_string_lossy().into_owned());
+        },
+    }
+    args
+}
+
+/// Packages the encoded chunks of each variant as `format` with FFmpeg.
+/// `output_file` is the master playlist or manifest; segments and media
+/// playlists are written next to it. Audio from `mux_inputs` is packaged
+/// alongside the video, subtitles, chapters and attachments are not.
+#[instrument(skip(variants, mux_inputs))]
+pub fn package_ffmpeg(
+    format: PackageFormat,
+    variants: &[PackageVariant],
+    mux_inputs: &MuxInputs,
+    output_file: &Path,
+    temp_dir: &Path, // Used for the concat list files
+    expected_segments: usize,
+    segment_duration: f64,
+) -> Result<(), VideoEncodeError> {
+    let tool = format.name();
+    if variants.is_empty() {
+        return Err(VideoEncodeError::Concatenation(format!(
+            "{}: No variants provided for packaging.",
+            tool
+        )));
+    }
+    format.validate_output(output_file)?;
+    for variant in variants {
+        if variant.segment_paths.len() != expected_segments {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "{}: Mismatch in segment count for '{}'. Expected: {}, Found: {}",
+                tool,
+                variant.name,
+                expected_segments,
+                variant.segment_paths.len()
+            )));
+        }
+        if let Some(path) = variant.segment_paths.iter().find(|path| !path.exists()) {
+            return Err(VideoEncodeError::Concatenation(format!(
+                "{}: Segment file not found: {:?}",
+                tool, path
+            )));
+        }
+    }
+    mux_inputs.check_files_exist(tool)?;
+
+    fs::create_dir_all(temp_dir).map_err(VideoEncodeError::Io)?;
+    if let Some(output_dir) = output_file.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(output_dir).map_err(VideoEncodeError::Io)?;
+    }
+
+    // One concatenated input per variant, then the audio source
+    let mut ffmpeg_args: Vec<String> = Vec::new();
+    let mut list_paths = Vec::new();
+    for (i, variant) in variants.iter().enumerate() {
+        let list_path = temp_dir.join(format!("package_list_{}.txt", i));
+        write_concat_list(&variant.segment_paths, &list_path)?;
+        ffmpeg_args.extend(
+            ["-f", "concat", "-safe", "0", "-i", &list_path.to_string_lossy()].map(str::to_string),
+        );
+        list_paths.push(list_path);
+    }
+
+    let mut audio_streams = Vec::new();
+    if let Some(non_video_stream_file) = mux_inputs.non_video_stream_file {
+        let dropped = probe_streams(non_video_stream_file)?
+            .into_iter()
+            .filter(|stream| matches!(stream.kind, StreamKind::Subtitle | StreamKind::Data))
+            .count();
+        if dropped > 0 {
+            warn!(
+                "{}: fMP4 segments can't carry subtitle or data streams; dropping {}",
+                tool, dropped
+            );
+        }
+    }
+    if mux_inputs
+        .container_metadata
+        .is_some_and(|metadata| !metadata.chapters.is_empty() || !metadata.attachments.is_empty())
+    {
+        warn!("{}: Chapters and attachments are not packaged", tool);
+    }
+    // Re-encoded audio replaces the audio of the non-video file
+    if let Some(audio_source) = mux_inputs.audio_file.or(mux_inputs.non_video_stream_file) {
+        audio_streams = probe_streams(audio_source)?
+            .into_iter()
+            .filter(|stream| stream.kind == StreamKind::Audio)
+            .collect();
+        if !audio_streams.is_empty() {
+            ffmpeg_args.extend(["-i".to_string(), audio_source.to_string_lossy().into_owned()]);
+        }
+    }
+
+    for i in 0..variants.len() {
+        ffmpeg_args.extend(["-map".to_string(), format!("{}:v:0", i)]);
+    }
+    for i in 0..audio_streams.len() {
+        ffmpeg_args.extend(["-map".to_string(), format!("{}:a:{}", variants.len(), i)]);
+    }
+    ffmpeg_args.extend(ffmpeg_metadata_arguments(mux_inputs.stream_metadata));
+    ffmpeg_args.extend(["-c".to_string(), "copy".to_string(), "-y".to_string()]);
+    let audio: Vec<&StreamInfo> = audio_streams.iter().collect();
+    ffmpeg_args.extend(packaging_arguments(
+        format,
+        variants,
+        &audio,
+        output_file,
+        segment_duration,
+    ));
+
+    debug!("FFmpeg command: ffmpeg {:?}", ffmpeg_args);
+    let output = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .args(&ffmpeg_args)
+        .killable_output()
+        .map_err(VideoEncodeError::Io)?;
+    for list_path in &list_paths {
+        let _ = fs::remove_file(list_path);
+    }
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("Failed to package {} with FFmpeg. Stderr: {}", tool, stderr);
+        return Err(VideoEncodeError::Concatenation(format!(
+            "{} packaging failed. FFmpeg stderr: {}",
+            tool, stderr
+        )));
+    }
+
+    info!(
+        "{}: Successfully packaged {} variants of {} segments into {:?}",
+        tool,
+        variants.len(),
+        expected_segments,
+        output_file
+    );
+    Ok(())
+}
@@ -0,0 +1,207 @@
+/// Cancellable child processes: blocking work run inside a `ProcessScope`
+/// starts its ffmpeg processes through `KillableCommand`, so cancelling the
+/// scope kills them instead of leaving them writing to a job's temporary
+/// files.
+use std::{
+    cell::RefCell,
+    io::{self, Read},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Condvar,
+        Mutex,
+        Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use tracing::{debug, warn};
+
+/// How often a scoped process is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(25);
+
+thread_local! {
+    static CURRENT_SCOPE: RefCell<Option<ProcessScope>> = const { RefCell::new(None) };
+}
+
+/// The processes started by the blocking work of a job
+#[derive(Debug, Clone, Default)]
+pub struct ProcessScope(Arc<ScopeState>);
+
+#[derive(Debug, Default)]
+struct ScopeState {
+    cancelled: AtomicBool,
+    children:  Mutex<Vec<Weak<Mutex<Child>>>>,
+    /// Calls of `run` that have not returned
+    running:   Mutex<usize>,
+    idle:      Condvar,
+}
+
+impl ProcessScope {
+    /// Runs `work` on this thread, its processes belonging to the scope
+    pub fn run<T>(&self, work: impl FnOnce() -> T) -> T {
+        *self.0.running.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        let previous = CURRENT_SCOPE.with(|current| current.replace(Some(self.clone())));
+        // Restores the thread and counts the work done even if it panics
+        struct Leave<'a>(&'a ScopeState, Option<ProcessScope>);
+        impl Drop for Leave<'_> {
+            fn drop(&mut self) {
+                let previous = self.1.take();
+                CURRENT_SCOPE.with(|current| current.replace(previous));
+                let mut running = self.0.running.lock().unwrap_or_else(|e| e.into_inner());
+                *running -= 1;
+                if *running == 0 {
+                    self.0.idle.notify_all();
+                }
+            }
+        }
+        let _leave = Leave(&self.0, previous);
+        work()
+    }
+
+    /// Kills the processes of the scope; processes started later fail to
+    /// start
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        let children =
+            std::mem::take(&mut *self.0.children.lock().unwrap_or_else(|e| e.into_inner()));
+        for child in children.iter().filter_map(Weak::upgrade) {
+            let mut child = child.lock().unwrap_or_else(|e| e.into_inner());
+            debug!("Killing process {}", child.id());
+            if let Err(e) = child.kill() {
+                warn!("Failed to kill process {}: {}", child.id(), e);
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until no work runs in the scope
+    pub fn wait_idle(&self) {
+        let mut running = self.0.running.lock().unwrap_or_else(|e| e.into_inner());
+        while *running > 0 {
+            running = self.0.idle.wait(running).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// The scope the thread's work runs in, for handing it to threads the
+    /// work starts
+    pub fn current() -> Option<ProcessScope> {
+        CURRENT_SCOPE.with(|current| current.borrow().clone())
+    }
+}
+
+/// A child process, killed when the scope it was started in is cancelled
+pub struct KillableChild {
+    pub stdin:  Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+    child:      Arc<Mutex<Child>>,
+    scoped:     bool,
+}
+
+impl KillableChild {
+    pub fn id(&self) -> u32 {
+        self.child.lock().unwrap_or_else(|e| e.into_inner()).id()
+    }
+
+    /// Waits for the process to exit. In a scope it is polled, so that
+    /// cancelling can kill it meanwhile.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        drop(self.stdin.take());
+        if !self.scoped {
+            return self.child.lock().unwrap_or_else(|e| e.into_inner()).wait();
+        }
+        loop {
+            let status = self.child.lock().unwrap_or_else(|e| e.into_inner()).try_wait()?;
+            if let Some(status) = status {
+                return Ok(status);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Waits for the process to exit, collecting its stdout and stderr
+    pub fn wait_with_output(mut self) -> io::Result<Output> {
+        let stdout = self.stdout.take();
+        let stderr = self.stderr.take();
+        let read = |pipe: Option<Box<dyn Read + Send>>| {
+            thread::spawn(move || {
+                let mut buffer = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    pipe.read_to_end(&mut buffer)?;
+                }
+                Ok::<_, io::Error>(buffer)
+            })
+        };
+        let stdout = read(stdout.map(|pipe| Box::new(pipe) as Box<dyn Read + Send>));
+        let stderr = read(stderr.map(|pipe| Box::new(pipe) as Box<dyn Read + Send>));
+        let status = self.wait()?;
+        let joined = |reader: thread::JoinHandle<io::Result<Vec<u8>>>| {
+            reader.join().unwrap_or_else(|_| Err(io::Error::other("Pipe reader panicked")))
+        };
+        Ok(Output {
+            status,
+            stdout: joined(stdout)?,
+            stderr: joined(stderr)?,
+        })
+    }
+}
+
+/// Starting commands as processes of the current scope
+pub trait KillableCommand {
+    /// Like `spawn`, registering the process with the thread's scope
+    fn spawn_killable(&mut self) -> io::Result<KillableChild>;
+
+    /// Like `output`, registering the process with the thread's scope
+    fn killable_output(&mut self) -> io::Result<Output>;
+}
+
+impl KillableCommand for Command {
+    fn spawn_killable(&mut self) -> io::Result<KillableChild> {
+        let scope = ProcessScope::current();
+        if scope.as_ref().is_some_and(ProcessScope::is_cancelled) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "The job was cancelled",
+            ));
+        }
+        let mut child = self.spawn()?;
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let child = Arc::new(Mutex::new(child));
+        if let Some(scope) = &scope {
+            let mut children = scope.0.children.lock().unwrap_or_else(|e| e.into_inner());
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Arc::downgrade(&child));
+            drop(children);
+            // Cancelled while the process started
+            if scope.is_cancelled() {
+                child.lock().unwrap_or_else(|e| e.into_inner()).kill().ok();
+            }
+        }
+        Ok(KillableChild {
+            stdin,
+            stdout,
+            stderr,
+            child,
+            scoped: scope.is_some(),
+        })
+    }
+
+    fn killable_output(&mut self) -> io::Result<Output> {
+        if ProcessScope::current().is_none() {
+            return self.output();
+        }
+        self.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn_killable()?
+            .wait_with_output()
+    }
+}
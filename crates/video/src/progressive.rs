@@ -0,0 +1,255 @@
+/// Progressive output: encoded chunks are appended to a growing file in
+/// index order while the job runs, so its beginning can be watched before
+/// the final mux exists.
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+use ferris_swarm_core::VideoEncodeError;
+use tracing::{debug, error, info, instrument};
+
+use crate::{
+    concatenator::container_accepts_codec,
+    process::KillableCommand,
+    utils::probe_duration,
+};
+
+/// Container of a progressive output, picked from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressiveFormat {
+    /// Fragmented MP4 with an empty moov, playable while it grows
+    FragmentedMp4,
+    Matroska,
+}
+
+impl ProgressiveFormat {
+    pub fn from_path(path: &Path) -> Result<Self, VideoEncodeError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "mp4" | "m4v" | "mov" => Ok(ProgressiveFormat::FragmentedMp4),
+            "mkv" => Ok(ProgressiveFormat::Matroska),
+            _ => Err(VideoEncodeError::Config(format!(
+                "A progressive output must be .mp4, .m4v, .mov or .mkv, got {:?}",
+                path
+            ))),
+        }
+    }
+
+    /// ffmpeg output options of the muxer writing the growing file
+    pub fn muxer_arguments(&self) -> &'static [&'static str] {
+        match self {
+            ProgressiveFormat::FragmentedMp4 => {
+                &["-f", "mp4", "-movflags", "frag_keyframe+empty_moov+default_base_moof"]
+            },
+            ProgressiveFormat::Matroska => &["-f", "matroska", "-live", "1"],
+        }
+    }
+
+    /// Rejects video the growing file can't be fed: whatever its own
+    /// container, every chunk passes through an MPEG-TS intermediate
+    pub fn check_codec(&self, codec_family: &str) -> Result<(), VideoEncodeError> {
+        if progressive_accepts_codec(codec_family) {
+            return Ok(());
+        }
+        let container = match self {
+            ProgressiveFormat::FragmentedMp4 => "fragmented MP4",
+            ProgressiveFormat::Matroska => "Matroska",
+        };
+        Err(VideoEncodeError::Config(format!(
+            "A progressive {} output can't carry {} video: chunks are appended through an MPEG-TS \
+             intermediate, which only takes H.264 and HEVC. Drop the progressive output or pick \
+             an H.264 or HEVC profile.",
+            container, codec_family
+        )))
+    }
+}
+
+/// Chunks reach the growing file as MPEG-TS, which can be appended to
+pub fn progressive_accepts_codec(codec_family: &str) -> bool {
+    container_accepts_codec("ts", codec_family)
+}
+
+/// ffmpeg arguments of the process muxing the MPEG-TS on its stdin into
+/// `output_path`
+pub fn progressive_muxer_arguments(format: ProgressiveFormat, output_path: &Path) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-f",
+        "mpegts",
+        "-i",
+        "pipe:0",
+        "-map",
+        "0",
+        "-c",
+        "copy",
+        "-y",
+    ]
+    .map(str::to_string)
+    .to_vec();
+    args.extend(format.muxer_arguments().iter().map(|arg| arg.to_string()));
+    args.push(output_path.to_string_lossy().into_owned());
+    args
+}
+
+/// ffmpeg arguments writing the video of `chunk_path` to stdout as MPEG-TS,
+/// shifted to start `offset` seconds into the output
+pub fn progressive_chunk_arguments(chunk_path: &Path, offset: f64) -> Vec<String> {
+    let mut args: Vec<String> =
+        ["-hide_banner", "-loglevel", "error", "-i"].map(str::to_string).to_vec();
+    args.push(chunk_path.to_string_lossy().into_owned());
+    args.extend(
+        [
+            "-map",
+            "0:v:0",
+            "-c",
+            "copy",
+            "-output_ts_offset",
+            &offset.to_string(),
+            // No start delay, so chunks follow each other seamlessly
+            "-muxdelay",
+            "0",
+            "-muxpreload",
+            "0",
+            "-f",
+            "mpegts",
+            "pipe:1",
+        ]
+        .map(str::to_string),
+    );
+    args
+}
+
+/// Orders chunks completing in any order by index
+#[derive(Debug, Default)]
+pub struct InOrderQueue {
+    next:  usize,
+    ready: BTreeMap<usize, PathBuf>,
+}
+
+impl InOrderQueue {
+    /// Queues chunk `index`, returning the chunks now due in order: none
+    /// while an earlier chunk is missing
+    pub fn push(&mut self, index: usize, path: PathBuf) -> Vec<PathBuf> {
+        if index >= self.next {
+            self.ready.insert(index, path);
+        }
+        let mut due = Vec::new();
+        while let Some(path) = self.ready.remove(&self.next) {
+            due.push(path);
+            self.next += 1;
+        }
+        due
+    }
+
+    /// Index of the next chunk due
+    pub fn next_index(&self) -> usize {
+        self.next
+    }
+}
+
+/// A growing output file that encoded chunks are appended to as soon as
+/// every earlier chunk is in
+pub struct ProgressiveWriter {
+    path:     PathBuf,
+    muxer:    Child,
+    input:    Option<ChildStdin>,
+    queue:    InOrderQueue,
+    /// Where the next chunk starts on the output's timeline
+    position: f64,
+}
+
+impl ProgressiveWriter {
+    #[instrument]
+    pub fn start(path: &Path) -> Result<Self, VideoEncodeError> {
+        let format = ProgressiveFormat::from_path(path)?;
+        let args = progressive_muxer_arguments(format, path);
+        debug!("Progressive muxer command: ffmpeg {:?}", args);
+        let mut muxer = Command::new("ffmpeg")
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        let input = muxer.stdin.take();
+        info!("Writing progressive output to {:?}", path);
+        Ok(Self {
+            path: path.to_path_buf(),
+            muxer,
+            input,
+            queue: InOrderQueue::default(),
+            position: 0.0,
+        })
+    }
+
+    /// Adds encoded chunk `index`, appending it and the queued chunks after
+    /// it once every earlier chunk is in. Returns how many were appended.
+    pub fn add(&mut self, index: usize, encoded_path: PathBuf) -> Result<usize, VideoEncodeError> {
+        let due = self.queue.push(index, encoded_path);
+        for path in &due {
+            self.append(path)?;
+        }
+        if !due.is_empty() {
+            info!(
+                "Progressive output {:?} holds {} chunks ({:.1}s)",
+                self.path,
+                self.queue.next_index(),
+                self.position
+            );
+        }
+        Ok(due.len())
+    }
+
+    fn append(&mut self, chunk_path: &Path) -> Result<(), VideoEncodeError> {
+        let duration = probe_duration(chunk_path)?;
+        let args = progressive_chunk_arguments(chunk_path, self.position);
+        let mut remux = Command::new("ffmpeg")
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn_killable()?;
+        let input = self.input.as_mut().ok_or_else(|| {
+            VideoEncodeError::Encoding("Progressive output is already finished".to_string())
+        })?;
+        if let Some(mut output) = remux.stdout.take() {
+            io::copy(&mut output, input)?;
+        }
+        let status = remux.wait()?;
+        if !status.success() {
+            let error_msg = format!(
+                "Failed to append {:?} to progressive output {:?}: ffmpeg exit status {}",
+                chunk_path, self.path, status
+            );
+            error!("{}", error_msg);
+            return Err(VideoEncodeError::Encoding(error_msg));
+        }
+        self.position += duration;
+        Ok(())
+    }
+
+    /// Closes the growing file. Returns the number of chunks it holds.
+    pub fn finish(mut self) -> Result<usize, VideoEncodeError> {
+        // The muxer finalizes the file once its input ends
+        drop(self.input.take());
+        let status = self.muxer.wait()?;
+        if !status.success() {
+            return Err(VideoEncodeError::Encoding(format!(
+                "Progressive muxer for {:?} failed: ffmpeg exit status {}",
+                self.path, status
+            )));
+        }
+        info!(
+            "Progressive output {:?} finished with {} chunks",
+            self.path,
+            self.queue.next_index()
+        );
+        Ok(self.queue.next_index())
+    }
+}
@@ -0,0 +1,66 @@
+/// SSIM and PSNR of encoded chunks against their source segments, measured
+/// with ffmpeg's own filters.
+use std::{path::Path, process::Command};
+
+use ferris_swarm_core::{error::VideoEncodeError, QualityMetrics};
+use tracing::{debug, instrument};
+
+/// Filter graph comparing input 0 (the encode) against input 1 (the source).
+/// `reference_filter` is run on the source first, e.g. the scaling of a
+/// rendition. The encode is then scaled to the reference's size, as encoder
+/// parameters or profile arguments may have scaled it too.
+pub fn quality_filter(reference_filter: Option<&str>) -> String {
+    let inputs = match reference_filter {
+        Some(filter) => format!("[1:v]{}[ref];[0:v][ref]", filter),
+        None => "[0:v][1:v]".to_string(),
+    };
+    format!(
+        "{}scale2ref[d][r];[d]split[d0][d1];[r]split[r0][r1];[d0][r0]ssim;[d1][r1]psnr",
+        inputs
+    )
+}
+
+/// Measures `distorted` against `reference`.
+#[instrument]
+pub fn measure_quality(
+    distorted: &Path,
+    reference: &Path,
+    reference_filter: Option<&str>,
+) -> Result<QualityMetrics, VideoEncodeError> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(distorted)
+        .arg("-i")
+        .arg(reference)
+        .args(["-lavfi", &quality_filter(reference_filter), "-f", "null", "-"])
+        .output()?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffmpeg failed to compare {:?} against {:?}: {}",
+            distorted, reference, stderr
+        )));
+    }
+
+    let metrics = parse_quality_metrics(&stderr);
+    debug!("Quality of {:?}: {:?}", distorted, metrics);
+    Ok(metrics)
+}
+
+/// Reads the summaries the `ssim` and `psnr` filters log when they finish.
+pub fn parse_quality_metrics(log: &str) -> QualityMetrics {
+    let value = |marker: &str, key: &str| {
+        log.lines()
+            .filter(|line| line.contains(marker))
+            .find_map(|line| {
+                let value = line.split_whitespace().find_map(|field| field.strip_prefix(key))?;
+                value.parse::<f64>().ok()
+            })
+            .filter(|value| value.is_finite())
+    };
+    QualityMetrics {
+        ssim: value("SSIM", "All:"),
+        psnr: value("PSNR", "average:"),
+    }
+}
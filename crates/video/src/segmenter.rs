@@ -1,14 +1,28 @@
 /// This module is responsible for segmenting input file
 /// into multiple independent files which are ready for processing
 use std::{
+    io::{BufRead, BufReader},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Stdio},
 };
 
-use ferris_swarm_core::error::VideoEncodeError;
+use ferris_swarm_core::{
+    error::VideoEncodeError,
+    AudioOptions,
+    StreamInfo,
+    StreamKind,
+    StreamSelection,
+    TimeRange,
+};
 use tracing::{debug, error, info, instrument};
 
-use crate::utils::verify_ffmpeg;
+use crate::{
+    concatenator::write_concat_list,
+    metadata::{Attachment, ContainerMetadata},
+    process::KillableCommand,
+    streams::{probe_streams, stream_map_arguments},
+    utils::verify_ffmpeg,
+};
 
 /// Due to the nature of method -segment_time
 /// Getting expected number of segments is not
@@ -21,9 +35,40 @@ pub fn segment_video(
     segment_duration: f64,
     segment_dir: &Path,
 ) -> Result<Vec<PathBuf>, VideoEncodeError> {
+    segment_video_ranges(input_path, segment_duration, segment_dir, &[])
+}
+
+/// Segments only the parts of the input within `ranges`, in range order.
+/// The video is stream copied, so each range begins on the keyframe at or
+/// before its start; see `probe_keyframe_before`. No ranges segment the
+/// whole input.
+#[instrument]
+pub fn segment_video_ranges(
+    input_path: &Path,
+    segment_duration: f64,
+    segment_dir: &Path,
+    ranges: &[TimeRange],
+) -> Result<Vec<PathBuf>, VideoEncodeError> {
+    segment_video_streaming(input_path, segment_duration, segment_dir, ranges, |_| {})
+}
+
+/// Segments like `segment_video_ranges`, handing each segment to
+/// `on_segment` as soon as ffmpeg has finished writing it, so segments can
+/// be encoded while the rest of the input is still being split.
+#[instrument(skip(on_segment))]
+pub fn segment_video_streaming<F>(
+    input_path: &Path,
+    segment_duration: f64,
+    segment_dir: &Path,
+    ranges: &[TimeRange],
+    mut on_segment: F,
+) -> Result<Vec<PathBuf>, VideoEncodeError>
+where
+    F: FnMut(&Path),
+{
     debug!(
-        "Starting video segmentation: input={:?}, duration={}, segment_dir={:?}",
-        input_path, segment_duration, segment_dir
+        "Starting video segmentation: input={:?}, duration={}, segment_dir={:?}, ranges={:?}",
+        input_path, segment_duration, segment_dir, ranges
     );
 
     verify_ffmpeg()?; // Call the moved function
@@ -32,37 +77,104 @@ pub fn segment_video(
     std::fs::create_dir_all(segment_dir)?;
     debug!("Created segment directory: {:?}", segment_dir);
 
-    let output_pattern = segment_dir.join("chunk_%04d.mp4");
+    if ranges.is_empty() {
+        return segment_part(
+            input_path,
+            segment_duration,
+            segment_dir,
+            "chunk",
+            None,
+            &mut on_segment,
+        );
+    }
+    let mut segmented_files = Vec::new();
+    for (i, range) in ranges.iter().enumerate() {
+        let prefix = format!("range_{:03}_chunk", i);
+        segmented_files.extend(segment_part(
+            input_path,
+            segment_duration,
+            segment_dir,
+            &prefix,
+            Some(range),
+            &mut on_segment,
+        )?);
+    }
+    info!(
+        "{} ranges split into {} segments",
+        ranges.len(),
+        segmented_files.len()
+    );
+    Ok(segmented_files)
+}
+
+/// Segments `range` of the input, or all of it, into `{prefix}_NNNN.mp4`
+/// files. ffmpeg lists each segment on stdout once it is complete; every
+/// one is handed to `on_segment` right away. Returns them in playback order.
+fn segment_part(
+    input_path: &Path,
+    segment_duration: f64,
+    segment_dir: &Path,
+    prefix: &str,
+    range: Option<&TimeRange>,
+    on_segment: &mut dyn FnMut(&Path),
+) -> Result<Vec<PathBuf>, VideoEncodeError> {
+    let output_pattern = segment_dir.join(format!("{}_%04d.mp4", prefix));
     debug!("Output pattern: {:?}", output_pattern);
 
-    let binding = segment_duration.to_string();
-    let inp = input_path.to_string_lossy();
-    let output_pattern_str = output_pattern.to_string_lossy(); // Renamed for clarity
-
-    let ffmpeg_args = vec![
-        "-hide_banner",
-        "-i",
-        &inp,
-        "-y",
-        "-an", // don't copy audio
-        "-sn", // don't copy subtitles
-        "-dn", // don't copy other data
-        "-c",
-        "copy",
-        "-map",
-        "0",
-        "-segment_time",
-        &binding,
-        "-f",
-        "segment",
-        "-reset_timestamps",
-        "1",
-        &output_pattern_str,
-    ];
+    let mut ffmpeg_args: Vec<String> = vec!["-hide_banner".to_string()];
+    ffmpeg_args.extend(range_input_arguments(range));
+    ffmpeg_args.extend(
+        [
+            "-i",
+            &input_path.to_string_lossy(),
+            "-y",
+            "-an", // don't copy audio
+            "-sn", // don't copy subtitles
+            "-dn", // don't copy other data
+            "-c",
+            "copy",
+            "-map",
+            "0",
+            "-segment_time",
+            &segment_duration.to_string(),
+            "-f",
+            "segment",
+            "-reset_timestamps",
+            "1",
+            // Names of finished segments, one per line
+            "-segment_list",
+            "pipe:1",
+            "-segment_list_type",
+            "flat",
+            &output_pattern.to_string_lossy(),
+        ]
+        .map(str::to_string),
+    );
 
     debug!("FFmpeg command: ffmpeg {:?}", ffmpeg_args);
 
-    let status = Command::new("ffmpeg").args(&ffmpeg_args).status()?; // Removed -hide_banner from here as it's in args
+    let mut child = Command::new("ffmpeg")
+        .args(&ffmpeg_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn_killable()?;
+
+    let mut segmented_files = Vec::new();
+    if let Some(segment_list) = child.stdout.take() {
+        for line in BufReader::new(segment_list).lines() {
+            let line = line?;
+            let name = line.trim();
+            if name.is_empty() {
+                continue;
+            }
+            // Entries are relative to the directory of the output pattern
+            let segment = segment_dir.join(name);
+            debug!("Segment ready: {:?}", segment);
+            on_segment(&segment);
+            segmented_files.push(segment);
+        }
+    }
+    let status = child.wait()?;
 
     if !status.success() {
         error!("Failed to split video. FFmpeg exit status: {}", status);
@@ -71,14 +183,6 @@ pub fn segment_video(
         ));
     }
 
-    debug!("Video segmentation completed successfully");
-
-    let segmented_files: Vec<PathBuf> = std::fs::read_dir(segment_dir)?
-        .filter_map(Result::ok)
-        .filter(|entry| entry.path().extension().and_then(|s| s.to_str()) == Some("mp4"))
-        .map(|entry| entry.path())
-        .collect();
-
     debug!(
         "Segmented files: count={}, files={:?}",
         segmented_files.len(),
@@ -90,39 +194,211 @@ pub fn segment_video(
     Ok(segmented_files)
 }
 
+/// ffmpeg input options reading only `range` of the next input. Seeking on
+/// the input resets timestamps to zero, so the end is given as a duration.
+pub fn range_input_arguments(range: Option<&TimeRange>) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(range) = range {
+        if range.start > 0.0 {
+            args.extend(["-ss".to_string(), range.start.to_string()]);
+        }
+        if let Some(duration) = range.duration() {
+            args.extend(["-t".to_string(), duration.to_string()]);
+        }
+    }
+    args
+}
+
 /// Extracts audio and other non-video streams from the input file.
-/// Returns paths to the extracted files.
-#[instrument]
+/// Audio is left out when it is re-encoded as a separate task, otherwise only
+/// the audio tracks selected in `audio` are kept. Audio and subtitles are
+/// further filtered by `selection`. With `ranges`, only those parts are
+/// extracted and joined back to back, matching `segment_video_ranges`.
+/// Attachments are left to `extract_container_metadata`.
+/// Returns the path to the extracted file, `None` if no stream is left.
+#[instrument(skip(audio, selection))]
 pub fn extract_non_video_streams(
     input_path: &Path,
     temp_dir: &Path,
-) -> Result<PathBuf, VideoEncodeError> {
+    audio: &AudioOptions,
+    selection: &StreamSelection,
+    ranges: &[TimeRange],
+) -> Result<Option<PathBuf>, VideoEncodeError> {
     debug!("Extracting non-video streams from: {:?}", input_path);
 
+    let streams = probe_streams(input_path)?;
+    let selected: Vec<&StreamInfo> = selection
+        .select(&streams, &audio.tracks)
+        .into_iter()
+        .filter(|stream| !(audio.reencodes() && stream.kind == StreamKind::Audio))
+        // Attachments are re-applied from `extract_container_metadata`
+        .filter(|stream| stream.kind != StreamKind::Attachment)
+        .collect();
+    debug!("Selected non-video streams: {:?}", selected);
+    if selected.is_empty() {
+        info!("No non-video streams selected in {:?}", input_path);
+        return Ok(None);
+    }
+
     std::fs::create_dir_all(temp_dir)?;
 
     let streams_path = temp_dir.join("non_video_streams.mkv"); // Changed extension for clarity
-    let status = Command::new("ffmpeg")
-        .arg("-hide_banner")
-        .args(&[
-            "-i",
-            input_path.to_str().unwrap(),
-            "-y",
-            "-map",
-            "0",
-            "-vn", // no video
-            "-c",  // copy all other streams
-            "copy",
-            streams_path.to_str().unwrap(),
+    copy_streams_in_ranges(input_path, &selected, ranges, &streams_path, temp_dir)?;
+    info!("Extracted non-video streams to {:?}", streams_path);
+    Ok(Some(streams_path))
+}
+
+/// Stream copies `streams` of `input_path` into `output_path`. With
+/// `ranges`, only those parts are copied and joined back to back, matching
+/// `segment_video_ranges`.
+pub(crate) fn copy_streams_in_ranges(
+    input_path: &Path,
+    streams: &[&StreamInfo],
+    ranges: &[TimeRange],
+    output_path: &Path,
+    temp_dir: &Path,
+) -> Result<(), VideoEncodeError> {
+    let copy = |range: Option<&TimeRange>, output: &Path| {
+        let output = Command::new("ffmpeg")
+            .arg("-hide_banner")
+            .args(range_input_arguments(range))
+            .arg("-i")
+            .arg(input_path)
+            .arg("-y")
+            .args(stream_map_arguments(streams))
+            .args(["-c", "copy"])
+            .arg(output)
+            .killable_output()?;
+
+        if !output.status.success() {
+            let error_msg = format!(
+                "Failed to copy streams of {:?}. Stderr: {}",
+                input_path,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            error!("{}", error_msg);
+            return Err(VideoEncodeError::Encoding(error_msg));
+        }
+        Ok(())
+    };
+
+    let ranges = match ranges {
+        [] => return copy(None, output_path),
+        [range] => return copy(Some(range), output_path),
+        ranges => ranges,
+    };
+    // One file per range, then joined like the video segments
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let extension = output_path.extension().unwrap_or_default().to_string_lossy().into_owned();
+    let mut range_paths = Vec::new();
+    for (i, range) in ranges.iter().enumerate() {
+        let range_path = temp_dir.join(format!("{}_range_{:03}.{}", stem, i, extension));
+        copy(Some(range), &range_path)?;
+        range_paths.push(range_path);
+    }
+    let list_path = temp_dir.join(format!("{}_concat_list.txt", stem));
+    write_concat_list(&range_paths, &list_path)?;
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-f", "concat", "-safe", "0", "-i"])
+        .arg(&list_path)
+        .args(["-y", "-map", "0", "-c", "copy"])
+        .arg(output_path)
+        .killable_output()?;
+    for path in range_paths.iter().chain([&list_path]) {
+        let _ = std::fs::remove_file(path);
+    }
+    if !output.status.success() {
+        let error_msg = format!(
+            "Failed to join {} time ranges into {:?}. Stderr: {}",
+            ranges.len(),
+            output_path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        error!("{}", error_msg);
+        return Err(VideoEncodeError::Encoding(error_msg));
+    }
+    Ok(())
+}
+
+/// Extracts global tags, chapters and attachments of the input file, so the
+/// concatenators can re-apply them to the output. Attachments are written to
+/// `temp_dir/attachments`.
+#[instrument]
+pub fn extract_container_metadata(
+    input_path: &Path,
+    temp_dir: &Path,
+) -> Result<ContainerMetadata, VideoEncodeError> {
+    debug!("Extracting container metadata from: {:?}", input_path);
+
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_chapters",
+            "-show_entries",
+            "format_tags:stream=index,codec_type:stream_tags=filename,mimetype",
+            "-of",
+            "json",
         ])
-        .status()?;
+        .arg(input_path)
+        .killable_output()?;
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to read container metadata of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    let (mut metadata, attachments) =
+        ContainerMetadata::from_probe(&String::from_utf8_lossy(&output.stdout))?;
 
-    if !status.success() {
-        error!("Failed to extract non-video streams");
-        return Err(VideoEncodeError::Encoding(
-            "Failed to extract non-video streams".to_string(),
-        ));
+    if !attachments.is_empty() {
+        let attachments_dir = temp_dir.join("attachments");
+        std::fs::create_dir_all(&attachments_dir)?;
+
+        let mut dump_args = Vec::new();
+        for (i, attachment) in attachments.into_iter().enumerate() {
+            // Prefixed with the index, attachment names need not be unique
+            let file_name = Path::new(&attachment.filename)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "attachment".to_string());
+            let path = attachments_dir.join(format!("{}_{}", i, file_name));
+            dump_args.push(format!("-dump_attachment:t:{}", i));
+            dump_args.push(path.to_string_lossy().into_owned());
+            metadata.attachments.push(Attachment {
+                path,
+                filename: attachment.filename,
+                mimetype: attachment.mimetype,
+            });
+        }
+
+        // Attachments are dumped while the input is opened; nothing is encoded
+        let output = Command::new("ffmpeg")
+            .arg("-hide_banner")
+            .arg("-y")
+            .args(&dump_args)
+            .arg("-i")
+            .arg(input_path)
+            .args(["-t", "0", "-f", "null", "-"])
+            .killable_output()?;
+        if let Some(missing) = metadata.attachments.iter().find(|a| !a.path.exists()) {
+            let error_msg = format!(
+                "Failed to extract attachment {:?}. Stderr: {}",
+                missing.filename,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            error!("{}", error_msg);
+            return Err(VideoEncodeError::Encoding(error_msg));
+        }
     }
-    info!("Extracted non-video streams to {:?}", streams_path);
-    Ok(streams_path)
+
+    info!(
+        "Extracted {} global tags, {} chapters and {} attachments from {:?}",
+        metadata.tags.len(),
+        metadata.chapters.len(),
+        metadata.attachments.len(),
+        input_path
+    );
+    Ok(metadata)
 }
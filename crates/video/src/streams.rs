@@ -0,0 +1,326 @@
+/// Stream probing and the ffmpeg/mkvmerge arguments that select streams and
+/// set their metadata in the output mux.
+use std::{collections::HashMap, path::Path, process::Command};
+
+use ferris_swarm_core::{
+    error::VideoEncodeError,
+    ColorMetadata,
+    ContentLightLevel,
+    MasteringDisplay,
+    StreamInfo,
+    StreamKind,
+    StreamMetadata,
+};
+use tracing::{debug, instrument};
+
+use crate::process::KillableCommand;
+
+/// Lists the streams of `input_path` with ffprobe.
+#[instrument]
+pub fn probe_streams(input_path: &Path) -> Result<Vec<StreamInfo>, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=index,codec_type,codec_name:stream_tags=language,title:stream_disposition",
+            "-of",
+            "json",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to list streams of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let streams = parse_ffprobe_streams(&String::from_utf8_lossy(&output.stdout))?;
+    debug!("Streams of {:?}: {:?}", input_path, streams);
+    Ok(streams)
+}
+
+/// Parses the JSON `ffprobe -show_entries stream=... -of json` prints.
+pub fn parse_ffprobe_streams(json: &str) -> Result<Vec<StreamInfo>, VideoEncodeError> {
+    let probe: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| VideoEncodeError::Encoding(format!("Invalid ffprobe output: {}", e)))?;
+    let streams = probe
+        .get("streams")
+        .and_then(|streams| streams.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    streams
+        .iter()
+        .map(|stream| {
+            let index = stream.get("index").and_then(|index| index.as_u64()).ok_or_else(|| {
+                VideoEncodeError::Encoding("ffprobe stream without an index".to_string())
+            })?;
+            let text = |value: Option<&serde_json::Value>| {
+                value.and_then(|value| value.as_str()).map(str::to_string)
+            };
+            let tags = stream.get("tags");
+            let dispositions = stream
+                .get("disposition")
+                .and_then(|disposition| disposition.as_object())
+                .map(|disposition| {
+                    disposition
+                        .iter()
+                        .filter(|(_, set)| set.as_i64() == Some(1))
+                        .map(|(name, _)| name.clone())
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(StreamInfo {
+                index: index as usize,
+                kind: StreamKind::from_codec_type(
+                    stream.get("codec_type").and_then(|t| t.as_str()).unwrap_or_default(),
+                ),
+                codec: text(stream.get("codec_name")).unwrap_or_default(),
+                language: text(tags.and_then(|tags| tags.get("language"))),
+                title: text(tags.and_then(|tags| tags.get("title"))),
+                dispositions,
+            })
+        })
+        .collect()
+}
+
+/// Reads the color description and HDR10 static metadata of the first video
+/// stream. Side data is looked up on the stream and on its first frame, where
+/// HEVC and AV1 sources carry it.
+#[instrument]
+pub fn probe_color_metadata(input_path: &Path) -> Result<ColorMetadata, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_streams",
+            "-show_frames",
+            "-read_intervals",
+            "%+#1",
+            "-of",
+            "json",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to read color metadata of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let color = parse_color_metadata(&String::from_utf8_lossy(&output.stdout))?;
+    debug!("Color metadata of {:?}: {:?}", input_path, color);
+    Ok(color)
+}
+
+/// Parses `ffprobe -show_streams -show_frames -of json` output of a single
+/// video stream into its color metadata.
+pub fn parse_color_metadata(json: &str) -> Result<ColorMetadata, VideoEncodeError> {
+    let probe: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| VideoEncodeError::Encoding(format!("Invalid ffprobe output: {}", e)))?;
+    let stream = probe.pointer("/streams/0");
+    let frame = probe.pointer("/frames/0");
+
+    // Stream fields first, the frame fills in what the container lacks
+    let field = |key: &str| {
+        [stream, frame]
+            .into_iter()
+            .flatten()
+            .filter_map(|section| section.get(key)?.as_str())
+            .find(|value| !matches!(*value, "unknown" | "unspecified" | "reserved"))
+            .map(str::to_string)
+    };
+    let side_data = |side_data_type: &str| {
+        [stream, frame]
+            .into_iter()
+            .flatten()
+            .filter_map(|section| section.get("side_data_list")?.as_array())
+            .flatten()
+            .find(|data| {
+                data.get("side_data_type").and_then(|t| t.as_str()) == Some(side_data_type)
+            })
+    };
+
+    let mastering_display = side_data("Mastering display metadata").and_then(|data| {
+        let value = |key: &str| data.get(key).and_then(parse_rational);
+        let point = |x: &str, y: &str| Some((value(x)?, value(y)?));
+        Some(MasteringDisplay {
+            red:           point("red_x", "red_y")?,
+            green:         point("green_x", "green_y")?,
+            blue:          point("blue_x", "blue_y")?,
+            white_point:   point("white_point_x", "white_point_y")?,
+            min_luminance: value("min_luminance")?,
+            max_luminance: value("max_luminance")?,
+        })
+    });
+    let content_light = side_data("Content light level metadata").and_then(|data| {
+        let value = |key: &str| data.get(key).and_then(parse_rational).map(|v| v as u32);
+        Some(ContentLightLevel {
+            max_cll:  value("max_content")?,
+            max_fall: value("max_average")?,
+        })
+    });
+
+    Ok(ColorMetadata {
+        color_primaries: field("color_primaries"),
+        transfer: field("color_transfer"),
+        matrix: field("color_space"),
+        range: field("color_range"),
+        mastering_display,
+        content_light,
+    })
+}
+
+/// ffprobe prints side data values as numbers or `num/den` strings
+fn parse_rational(value: &serde_json::Value) -> Option<f64> {
+    if let Some(number) = value.as_f64() {
+        return Some(number);
+    }
+    let text = value.as_str()?;
+    match text.split_once('/') {
+        Some((num, den)) => {
+            let den: f64 = den.trim().parse().ok()?;
+            (den != 0.0).then_some(num.trim().parse::<f64>().ok()? / den)
+        },
+        None => text.trim().parse().ok(),
+    }
+}
+
+/// `-map` arguments selecting exactly `streams` from the first input.
+pub fn stream_map_arguments(streams: &[&StreamInfo]) -> Vec<String> {
+    streams
+        .iter()
+        .flat_map(|stream| ["-map".to_string(), format!("0:{}", stream.index)])
+        .collect()
+}
+
+/// ffmpeg output options applying `metadata` to the output streams.
+pub fn ffmpeg_metadata_arguments(metadata: &[StreamMetadata]) -> Vec<String> {
+    let mut args = Vec::new();
+    for entry in metadata {
+        let specifier = format!("{}:{}", entry.kind.specifier(), entry.index);
+        if let Some(title) = &entry.title {
+            args.extend([format!("-metadata:s:{}", specifier), format!("title={}", title)]);
+        }
+        if let Some(language) = &entry.language {
+            args.extend([format!("-metadata:s:{}", specifier), format!("language={}", language)]);
+        }
+        // `+flag`/`-flag` changes single flags and keeps the others
+        let dispositions: String = [("default", entry.default), ("forced", entry.forced)]
+            .into_iter()
+            .filter_map(|(flag, set)| {
+                set.map(|set| format!("{}{}", if set { '+' } else { '-' }, flag))
+            })
+            .collect();
+        if !dispositions.is_empty() {
+            args.extend([format!("-disposition:{}", specifier), dispositions]);
+        }
+    }
+    args
+}
+
+/// mkvmerge options applying `metadata` to the tracks of one input file.
+///
+/// `file_streams` are the streams of that file, in order, and `preceding`
+/// counts the output streams per kind contributed by earlier inputs, so that
+/// `a:1` can be resolved to a track ID of this file.
+pub fn mkvmerge_metadata_arguments(
+    metadata: &[StreamMetadata],
+    file_streams: &[StreamInfo],
+    preceding: &mut HashMap<StreamKind, usize>,
+) -> Vec<String> {
+    let mut args = Vec::new();
+    // mkvmerge numbers the tracks of each input file from 0 in stream order,
+    // whatever the streams' indices were in the source
+    for (track_id, stream) in file_streams.iter().enumerate() {
+        let counter = preceding.entry(stream.kind).or_insert(0);
+        let output_index = *counter;
+        *counter += 1;
+
+        for entry in metadata
+            .iter()
+            .filter(|entry| entry.kind == stream.kind && entry.index == output_index)
+        {
+            if let Some(title) = &entry.title {
+                args.extend(["--track-name".to_string(), format!("{}:{}", track_id, title)]);
+            }
+            if let Some(language) = &entry.language {
+                args.extend(["--language".to_string(), format!("{}:{}", track_id, language)]);
+            }
+            if let Some(default) = entry.default {
+                args.extend([
+                    "--default-track-flag".to_string(),
+                    format!("{}:{}", track_id, default as u8),
+                ]);
+            }
+            if let Some(forced) = entry.forced {
+                args.extend([
+                    "--forced-display-flag".to_string(),
+                    format!("{}:{}", track_id, forced as u8),
+                ]);
+            }
+        }
+    }
+    args
+}
+
+/// mkvmerge options writing `color` to the Matroska Colour element of track
+/// `track_id` in the following input file.
+pub fn mkvmerge_color_arguments(color: &ColorMetadata, track_id: usize) -> Vec<String> {
+    let mut options: Vec<(&str, String)> = Vec::new();
+    if let Some(code) = color.primaries_code() {
+        options.push(("--colour-primaries", code.to_string()));
+    }
+    if let Some(code) = color.transfer_code() {
+        options.push(("--colour-transfer-characteristics", code.to_string()));
+    }
+    if let Some(code) = color.matrix_code() {
+        options.push(("--colour-matrix-coefficients", code.to_string()));
+    }
+    if let Some(full) = color.full_range() {
+        // 1 is broadcast range, 2 full range
+        options.push(("--colour-range", if full { "2" } else { "1" }.to_string()));
+    }
+    if let Some(display) = &color.mastering_display {
+        options.extend([
+            (
+                "--chromaticity-coordinates",
+                format!(
+                    "{},{},{},{},{},{}",
+                    display.red.0,
+                    display.red.1,
+                    display.green.0,
+                    display.green.1,
+                    display.blue.0,
+                    display.blue.1
+                ),
+            ),
+            (
+                "--white-colour-coordinates",
+                format!("{},{}", display.white_point.0, display.white_point.1),
+            ),
+            ("--max-luminance", display.max_luminance.to_string()),
+            ("--min-luminance", display.min_luminance.to_string()),
+        ]);
+    }
+    if let Some(light) = &color.content_light {
+        options.extend([
+            ("--max-content-light", light.max_cll.to_string()),
+            ("--max-frame-light", light.max_fall.to_string()),
+        ]);
+    }
+
+    options
+        .into_iter()
+        .flat_map(|(option, value)| [option.to_string(), format!("{}:{}", track_id, value)])
+        .collect()
+}
@@ -0,0 +1,218 @@
+/// Post-concatenation audit of the output's timing against the source, and
+/// the mkvmerge remux that corrects drifted video timestamps.
+use std::{fs, path::Path, process::Command};
+
+use ferris_swarm_core::{error::VideoEncodeError, StreamKind, StreamTiming, SyncAudit};
+use tracing::{debug, error, info, instrument};
+
+use crate::process::KillableCommand;
+
+/// Reads start time, duration and packet count of every stream in
+/// `input_path`. Counting packets demuxes the whole file.
+#[instrument]
+pub fn probe_stream_timing(input_path: &Path) -> Result<Vec<StreamTiming>, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-count_packets",
+            "-show_entries",
+            "stream=index,codec_type,start_time,duration,nb_read_packets:stream_tags:\
+             format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to read stream timing of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let timing = parse_stream_timing(&String::from_utf8_lossy(&output.stdout))?;
+    debug!("Stream timing of {:?}: {:?}", input_path, timing);
+    Ok(timing)
+}
+
+/// Parses `ffprobe -count_packets -show_entries stream=...:format=duration`
+/// JSON. Matroska streams carry their duration in a `DURATION` tag; streams
+/// without any duration fall back to the container's.
+pub fn parse_stream_timing(json: &str) -> Result<Vec<StreamTiming>, VideoEncodeError> {
+    let probe: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| VideoEncodeError::Encoding(format!("Invalid ffprobe output: {}", e)))?;
+    let seconds = |value: Option<&serde_json::Value>| {
+        value.and_then(|value| value.as_str()?.trim().parse::<f64>().ok())
+    };
+    let format_duration = seconds(probe.pointer("/format/duration"));
+    let streams = probe
+        .get("streams")
+        .and_then(|streams| streams.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    streams
+        .iter()
+        .map(|stream| {
+            let index = stream.get("index").and_then(|index| index.as_u64()).ok_or_else(|| {
+                VideoEncodeError::Encoding("ffprobe stream without an index".to_string())
+            })?;
+            let tagged_duration =
+                stream.get("tags").and_then(|tags| tags.as_object()).and_then(|tags| {
+                    tags.iter()
+                        .find(|(key, _)| key.to_ascii_uppercase().starts_with("DURATION"))
+                        .and_then(|(_, value)| parse_timestamp(value.as_str()?))
+                });
+            Ok(StreamTiming {
+                index:      index as usize,
+                kind:       StreamKind::from_codec_type(
+                    stream.get("codec_type").and_then(|t| t.as_str()).unwrap_or_default(),
+                ),
+                start_time: seconds(stream.get("start_time")),
+                duration:   seconds(stream.get("duration")).or(tagged_duration).or(format_duration),
+                frames:     stream
+                    .get("nb_read_packets")
+                    .and_then(|frames| frames.as_str()?.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+/// Parses `HH:MM:SS.fraction` into seconds
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let mut parts = timestamp.trim().splitn(3, ':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// mkvmerge track ID of the first video track, from the JSON
+/// `mkvmerge -J` prints. mkvmerge numbers tracks on its own, e.g. without
+/// the attachments ffprobe counts as streams.
+pub fn parse_mkvmerge_video_track(json: &str) -> Result<Option<usize>, VideoEncodeError> {
+    let identification: serde_json::Value = serde_json::from_str(json).map_err(|e| {
+        VideoEncodeError::Concatenation(format!("Invalid mkvmerge identification: {}", e))
+    })?;
+    Ok(identification
+        .get("tracks")
+        .and_then(|tracks| tracks.as_array())
+        .and_then(|tracks| {
+            tracks
+                .iter()
+                .find(|track| track.get("type").and_then(|t| t.as_str()) == Some("video"))
+        })
+        .and_then(|track| track.get("id")?.as_u64())
+        .map(|id| id as usize))
+}
+
+/// Identifies the tracks of `input_path` with mkvmerge and returns the ID of
+/// its first video track.
+fn probe_mkvmerge_video_track(input_path: &Path) -> Result<Option<usize>, VideoEncodeError> {
+    let output = Command::new("mkvmerge").arg("-J").arg(input_path).killable_output()?;
+    if !output.status.success() {
+        return Err(VideoEncodeError::Concatenation(format!(
+            "mkvmerge failed to identify {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stdout)
+        )));
+    }
+    parse_mkvmerge_video_track(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// mkvmerge `--sync` value retiming video track `track_id` of the output so
+/// that it lasts as long as the source and lines up with the first audio
+/// stream. `None` when the audit found nothing beyond `tolerance` seconds to
+/// correct.
+pub fn mkvmerge_sync_argument(
+    audit: &SyncAudit,
+    track_id: usize,
+    tolerance: f64,
+) -> Option<String> {
+    // Delaying the video by the audio's offset puts them back together
+    let delay = audit
+        .streams
+        .first()
+        .and_then(|stream| stream.start_offset)
+        .filter(|offset| offset.abs() > tolerance)
+        .unwrap_or(0.0);
+    let ratio = audit
+        .duration_drift()
+        .filter(|drift| drift.abs() > tolerance)
+        .and_then(|_| Some((audit.source_duration?, audit.output_duration?)))
+        .filter(|(_, output)| *output > 0.0);
+
+    match ratio {
+        Some((source, output)) => Some(format!(
+            "{}:{},{}/{}",
+            track_id,
+            (delay * 1000.0).round() as i64,
+            source,
+            output
+        )),
+        None if delay != 0.0 => Some(format!("{}:{}", track_id, (delay * 1000.0).round() as i64)),
+        None => None,
+    }
+}
+
+/// Remuxes the Matroska `output_file` with mkvmerge, retiming its video track
+/// as [`mkvmerge_sync_argument`] describes. Returns whether anything was
+/// corrected.
+#[instrument(skip(audit))]
+pub fn fix_sync_mkvmerge(
+    output_file: &Path,
+    audit: &SyncAudit,
+    tolerance: f64,
+    temp_dir: &Path,
+) -> Result<bool, VideoEncodeError> {
+    let extension = output_file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    if !["mkv", "mk3d", "webm"].contains(&extension.as_str()) {
+        return Err(VideoEncodeError::Concatenation(format!(
+            "mkvmerge can only fix the timing of Matroska outputs, not {:?}",
+            output_file
+        )));
+    }
+    let track_id = probe_mkvmerge_video_track(output_file)?.ok_or_else(|| {
+        VideoEncodeError::Concatenation(format!("{:?} has no video track to retime", output_file))
+    })?;
+    let Some(sync) = mkvmerge_sync_argument(audit, track_id, tolerance) else {
+        return Ok(false);
+    };
+
+    fs::create_dir_all(temp_dir)?;
+    let fixed_path = temp_dir.join(format!("sync_fixed.{}", extension));
+    let mkvmerge_args: Vec<String> = vec![
+        "-o".to_string(),
+        fixed_path.to_string_lossy().into_owned(),
+        "--sync".to_string(),
+        sync,
+        output_file.to_string_lossy().into_owned(),
+    ];
+    debug!("mkvmerge command: mkvmerge {:?}", mkvmerge_args);
+    let output = Command::new("mkvmerge").args(&mkvmerge_args).killable_output()?;
+    // mkvmerge exits with 1 for warnings and 2 for errors
+    if output.status.code().is_none_or(|code| code > 1) {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        error!("mkvmerge failed to retime {:?}: {}", output_file, stdout);
+        let _ = fs::remove_file(&fixed_path);
+        return Err(VideoEncodeError::Concatenation(format!(
+            "mkvmerge failed to retime {:?}: {}",
+            output_file, stdout
+        )));
+    }
+
+    // The temp dir may be on another file system
+    if fs::rename(&fixed_path, output_file).is_err() {
+        fs::copy(&fixed_path, output_file)?;
+        fs::remove_file(&fixed_path)?;
+    }
+    info!("Retimed the video track of {:?}", output_file);
+    Ok(true)
+}
@@ -0,0 +1,50 @@
+/// Client-side decoding of segments into intermediates any node can read,
+/// so nodes only need the target encoder.
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use ferris_swarm_core::ChunkTransport;
+
+/// ffmpeg output options writing the first video stream as `transport`,
+/// `None` for segments sent as they are
+pub fn transport_arguments(transport: ChunkTransport) -> Option<Vec<&'static str>> {
+    match transport {
+        ChunkTransport::Source => None,
+        // Intra only, so every frame decodes on its own
+        ChunkTransport::Ffv1 => Some(vec![
+            "-map",
+            "0:v:0",
+            "-c:v",
+            "ffv1",
+            "-level",
+            "3",
+            "-g",
+            "1",
+            "-slices",
+            "4",
+            "-slicecrc",
+            "1",
+            "-f",
+            "matroska",
+        ]),
+        // Pixel formats beyond 8-bit 4:2:0 are y4m extensions
+        ChunkTransport::Y4m => Some(vec!["-map", "0:v:0", "-f", "yuv4mpegpipe", "-strict", "-1"]),
+    }
+}
+
+/// ffmpeg decoding `segment` to `transport` on stdout, `None` for segments
+/// sent as they are
+pub fn transport_command(segment: &Path, transport: ChunkTransport) -> Option<Command> {
+    let arguments = transport_arguments(transport)?;
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-nostats", "-loglevel", "error", "-i"])
+        .arg(segment)
+        .args(arguments)
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    Some(command)
+}
@@ -1,6 +1,10 @@
+use std::{path::Path, process::Command};
+
 use ferris_swarm_core::error::VideoEncodeError;
 use tracing::{debug, error, info, instrument};
 
+use crate::process::KillableCommand;
+
 #[instrument]
 pub fn verify_ffmpeg() -> Result<(), VideoEncodeError> {
     debug!("Verifying FFmpeg installation");
@@ -30,3 +34,161 @@ pub fn verify_mkvmerge() -> Result<(), VideoEncodeError> {
         },
     }
 }
+
+/// Returns the frame rate of the first video stream as reported by ffprobe
+/// (e.g. `24000/1001`).
+#[instrument]
+pub fn probe_frame_rate(input_path: &Path) -> Result<String, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=r_frame_rate",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to read frame rate of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let frame_rate = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    debug!("Frame rate of {:?}: {}", input_path, frame_rate);
+    Ok(frame_rate)
+}
+
+/// Frames per second of a frame rate as ffprobe reports it, e.g. `24000/1001`
+pub fn frame_rate_value(frame_rate: &str) -> Option<f64> {
+    let value = match frame_rate.split_once('/') {
+        Some((numerator, denominator)) => {
+            numerator.trim().parse::<f64>().ok()? / denominator.trim().parse::<f64>().ok()?
+        },
+        None => frame_rate.trim().parse().ok()?,
+    };
+    (value.is_finite() && value > 0.0).then_some(value)
+}
+
+/// Whether the first video packet of `input_path` is a keyframe, so that the
+/// file can be appended to another without references across the join.
+#[instrument]
+pub fn starts_with_keyframe(input_path: &Path) -> Result<bool, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            "%+#1",
+            "-show_entries",
+            "packet=flags",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to read the first packet of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let flags = String::from_utf8_lossy(&output.stdout);
+    debug!("First packet flags of {:?}: {}", input_path, flags.trim());
+    Ok(is_keyframe_flags(&flags))
+}
+
+/// Whether ffprobe packet `flags` (e.g. `K__`) mark a keyframe
+pub fn is_keyframe_flags(flags: &str) -> bool {
+    flags.lines().next().is_some_and(|flags| flags.trim_start().starts_with('K'))
+}
+
+/// Time of the last video keyframe at or before `time` seconds, where a
+/// stream copied cut starting at `time` really begins. Falls back to `time`
+/// when the first packet after seeking is no keyframe.
+#[instrument]
+pub fn probe_keyframe_before(input_path: &Path, time: f64) -> Result<f64, VideoEncodeError> {
+    if time <= 0.0 {
+        return Ok(0.0);
+    }
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-read_intervals",
+            &format!("{}%+#1", time),
+            "-show_entries",
+            "packet=pts_time,flags",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to seek {:?} to {}s: {}",
+            input_path,
+            time,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let packet = String::from_utf8_lossy(&output.stdout);
+    let keyframe = packet.lines().next().and_then(|line| {
+        let (pts_time, flags) = line.split_once(',')?;
+        is_keyframe_flags(flags).then(|| pts_time.trim().parse::<f64>().ok()).flatten()
+    });
+    debug!(
+        "Keyframe of {:?} at or before {}s: {:?}",
+        input_path, time, keyframe
+    );
+    Ok(keyframe.filter(|keyframe| *keyframe <= time).unwrap_or(time))
+}
+
+/// Container duration of `input_path` in seconds, as ffprobe reports it
+/// without reading the streams.
+#[instrument]
+pub fn probe_duration(input_path: &Path) -> Result<f64, VideoEncodeError> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(input_path)
+        .killable_output()?;
+
+    let duration = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        return Err(VideoEncodeError::Encoding(format!(
+            "ffprobe failed to read the duration of {:?}: {}",
+            input_path,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    duration.trim().parse().map_err(|_| {
+        VideoEncodeError::Encoding(format!(
+            "ffprobe reported no duration for {:?}: '{}'",
+            input_path,
+            duration.trim()
+        ))
+    })
+}